@@ -3,6 +3,7 @@ extern crate cc;
 use cc::Build;
 
 fn main() {
+    println!("cargo:rerun-if-changed=src/tuntap.c");
     Build::new()
         .file("src/tuntap.c")
         .warnings(true)
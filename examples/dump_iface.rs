@@ -1,43 +1,37 @@
 //! An example of reading from tun
 //!
-//! It creates a tun device, sets it up (using shell commands) for local use and then prints the
-//! raw data of the packets that arrive.
+//! It creates a tun device, sets it up for local use and then prints the raw data of the packets
+//! that arrive. Pings are answered.
 //!
 //! You really do want better error handling than all these unwraps.
 extern crate tun_tap;
 
-use std::process::Command;
+use std::net::Ipv4Addr;
 
+use tun_tap::icmp::EchoResponder;
+use tun_tap::packet::PacketDisplay;
 use tun_tap::{Iface, Mode};
 
-/// Run a shell command. Panic if it fails in any way.
-fn cmd(cmd: &str, args: &[&str]) {
-    let ecode = Command::new("ip")
-        .args(args)
-        .spawn()
-        .unwrap()
-        .wait()
-        .unwrap();
-    assert!(ecode.success(), "Failed to execte {}", cmd);
-}
-
 fn main() {
     // Create the tun interface.
     let iface = Iface::new("testtun%d", Mode::Tun).unwrap();
     eprintln!("Iface: {:?}", iface);
     // Configure the „local“ (kernel) endpoint.
-    cmd("ip", &["addr", "add", "dev", iface.name(), "10.107.1.2/24"]);
-    cmd("ip", &["link", "set", "up", "dev", iface.name()]);
+    iface.set_ipv4(Ipv4Addr::new(10, 107, 1, 2), 24).unwrap();
+    iface.set_up(true).unwrap();
     println!("Created interface {}. Send some packets into it and see they're printed here",
              iface.name());
-    println!("You can for example ping 10.107.1.3 (it won't answer)");
-    // That 1500 is a guess for the IFace's MTU (we probably could configure it explicitly). 4 more
-    // for TUN's „header“.
-    let mut buffer = vec![0; 1504];
+    println!("You can for example ping 10.107.1.3");
+    // The MTU (which may be raised for jumbo frames) and 4 more for TUN's „header“.
+    let mut buffer = vec![0; iface.max_frame_size().unwrap()];
+    let echo = EchoResponder::new();
     loop {
         // Every read is one packet. If the buffer is too small, bad luck, it gets truncated.
-        let size = iface.recv(&mut buffer).unwrap();
-        assert!(size >= 4);
-        println!("Packet: {:?}", &buffer[4..size]);
+        let (info, packet) = iface.recv_parsed(&mut buffer).unwrap();
+        let proto = info.expect("Created with packet info").proto;
+        println!("Packet ({:?}):\n{}", proto, PacketDisplay::new(packet));
+        if let Some(reply) = echo.reply(packet) {
+            iface.send_with_proto(proto, &reply).unwrap();
+        }
     }
 }
@@ -0,0 +1,178 @@
+//! A multi-queue benchmark.
+//!
+//! This creates a multi-queue TUN device with offloads enabled, configures the kernel endpoint
+//! and reads each queue in its own thread pinned to its own CPU. Meanwhile, it blasts UDP
+//! datagrams from the kernel side into the device (several flows, so the kernel spreads them
+//! between the queues) and prints how many packets and bits per second make it through.
+//!
+//! Takes two optional parameters ‒ the number of queues (one per CPU by default) and the number
+//! of seconds to run (10 by default). Needs to run as root.
+//!
+//! The sockets use UDP segmentation offload, so with a recent enough kernel, each write sends a
+//! single large packet through the device, described by the virtio net header. The packets are
+//! counted as the segments they stand for.
+//!
+//! Note that the `workers::WorkerPool` would do for the reading threads if the offloads were not
+//! in use, but it sizes its buffers by the MTU.
+
+extern crate libc;
+extern crate tun_tap;
+
+use std::env;
+use std::mem;
+use std::net::{Ipv4Addr, UdpSocket};
+use std::os::unix::io::AsRawFd;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tun_tap::packet::VirtioNetHeader;
+use tun_tap::workers::pin_to_cpu;
+use tun_tap::{Iface, OffloadFlags, Shutdown, TunFlags};
+
+/// The payload of each datagram (segment).
+const SEGMENT: usize = 1400;
+/// How many segments are sent by a single write.
+const SEGMENTS: usize = 40;
+/// The largest packet with offloads, plus the header.
+const BUFSIZE: usize = 65536 + VirtioNetHeader::SIZE;
+
+#[derive(Default)]
+struct Stats {
+    packets: AtomicU64,
+    bytes: AtomicU64,
+}
+
+fn read_queue(queue: &Iface, stats: &Stats) {
+    let mut buffer = vec![0; BUFSIZE];
+    loop {
+        let size = match queue.recv(&mut buffer) {
+            Ok(size) => size,
+            Err(ref e) if Shutdown::is_shutdown(e) => return,
+            Err(e) => panic!("Failed to read {}: {}", queue.name(), e),
+        };
+        let mut header = [0; VirtioNetHeader::SIZE];
+        header.copy_from_slice(&buffer[..VirtioNetHeader::SIZE]);
+        let header = VirtioNetHeader::parse(&header);
+        let len = size - VirtioNetHeader::SIZE;
+        let segments = if header.gso_type == VirtioNetHeader::GSO_NONE {
+            1
+        } else {
+            let payload = len.saturating_sub(usize::from(header.hdr_len));
+            payload.div_ceil(usize::from(header.gso_size).max(1)) as u64
+        };
+        stats.packets.fetch_add(segments, Ordering::Relaxed);
+        stats.bytes.fetch_add(len as u64, Ordering::Relaxed);
+    }
+}
+
+/// Turns on UDP segmentation offload on the socket, if the kernel has it.
+fn segment(socket: &UdpSocket) -> bool {
+    let size = SEGMENT as libc::c_int;
+    let result = unsafe {
+        libc::setsockopt(
+            socket.as_raw_fd(),
+            libc::SOL_UDP,
+            libc::UDP_SEGMENT,
+            &size as *const _ as *const libc::c_void,
+            mem::size_of::<libc::c_int>() as libc::socklen_t,
+        )
+    };
+    result == 0
+}
+
+fn generate(stop: &AtomicBool) {
+    let socket = UdpSocket::bind("10.108.0.1:0").unwrap();
+    socket.connect("10.108.0.2:9").unwrap();
+    let payload = if segment(&socket) {
+        vec![0; SEGMENT * SEGMENTS]
+    } else {
+        vec![0; SEGMENT]
+    };
+    while !stop.load(Ordering::Relaxed) {
+        // Drops (eg. a full queue) are fine, the benchmark measures what gets through
+        let _ = socket.send(&payload);
+    }
+}
+
+fn main() {
+    let mut args = env::args().skip(1);
+    let queues = args
+        .next()
+        .map(|queues| queues.parse().unwrap())
+        .unwrap_or_else(|| thread::available_parallelism().unwrap().get());
+    let duration = Duration::from_secs(args.next().map(|d| d.parse().unwrap()).unwrap_or(10));
+    let cpus = thread::available_parallelism().unwrap().get();
+
+    let flags = TunFlags::TUN | TunFlags::NO_PI | TunFlags::MULTI_QUEUE | TunFlags::VNET_HDR;
+    let first = Iface::with_flags("mq%d", flags).unwrap();
+    let name = first.name().to_owned();
+    let offloads = OffloadFlags::CSUM | OffloadFlags::TSO4 | OffloadFlags::TSO6;
+    // The UDP segmentation offload is fairly new, older kernels don't know it
+    if first
+        .set_offload(offloads | OffloadFlags::USO4 | OffloadFlags::USO6)
+        .is_err()
+    {
+        eprintln!("No UDP segmentation offload, the packets come one by one");
+        first.set_offload(offloads).unwrap();
+    }
+    let mut ifaces = vec![first];
+    for _ in 1..queues {
+        ifaces.push(Iface::with_flags(&name, flags).unwrap());
+    }
+    ifaces[0].set_ipv4(Ipv4Addr::new(10, 108, 0, 1), 24).unwrap();
+    ifaces[0].set_up(true).unwrap();
+    println!("Created {} with {} queues", name, queues);
+
+    let stats = Arc::new(Stats::default());
+    let mut shutdowns = Vec::new();
+    let mut readers = Vec::new();
+    for (i, queue) in ifaces.into_iter().enumerate() {
+        shutdowns.push(queue.shutdown_handle().unwrap());
+        let stats = Arc::clone(&stats);
+        readers.push(thread::spawn(move || {
+            pin_to_cpu(i % cpus).unwrap();
+            read_queue(&queue, &stats);
+        }));
+    }
+    let stop = Arc::new(AtomicBool::new(false));
+    let generators = (0..queues)
+        .map(|_| {
+            let stop = Arc::clone(&stop);
+            thread::spawn(move || generate(&stop))
+        })
+        .collect::<Vec<_>>();
+
+    let start = Instant::now();
+    let (mut packets, mut bytes) = (0, 0);
+    while start.elapsed() < duration {
+        thread::sleep(Duration::from_secs(1));
+        let now_packets = stats.packets.load(Ordering::Relaxed);
+        let now_bytes = stats.bytes.load(Ordering::Relaxed);
+        println!(
+            "{:>10} pps {:>8.3} Gbps",
+            now_packets - packets,
+            (now_bytes - bytes) as f64 * 8.0 / 1e9
+        );
+        packets = now_packets;
+        bytes = now_bytes;
+    }
+    let elapsed = start.elapsed().as_secs_f64();
+    println!(
+        "Average: {:.0} pps {:.3} Gbps",
+        packets as f64 / elapsed,
+        bytes as f64 * 8.0 / 1e9 / elapsed
+    );
+
+    stop.store(true, Ordering::Relaxed);
+    for generator in generators {
+        generator.join().unwrap();
+    }
+    for shutdown in shutdowns {
+        shutdown.shutdown().unwrap();
+    }
+    for reader in readers {
+        reader.join().unwrap();
+    }
+}
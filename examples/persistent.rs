@@ -0,0 +1,94 @@
+//! Using a device without root privileges.
+//!
+//! Creating a device needs the `CAP_NET_ADMIN` capability (usually root), but the application
+//! using it doesn't have to run as root. The recommended way is to create a persistent device
+//! owned by the user of the application in advance, from a root setup step, and let the
+//! application attach to it later.
+//!
+//! The setup (as root) creates the device, lets the user (by the numeric UID) use it and
+//! configures the kernel endpoint (10.110.0.1):
+//!
+//! ```sh
+//! sudo ./persistent setup mytun 1000
+//! ```
+//!
+//! Then the application (as the user 1000, no privileges needed) attaches to the device, prints
+//! the packets that come through and answers pings to 10.110.0.2:
+//!
+//! ```sh
+//! ./persistent run mytun
+//! ping 10.110.0.2
+//! ```
+//!
+//! The device stays there until removed, once the application stopped (the owner may do it too):
+//!
+//! ```sh
+//! ./persistent teardown mytun
+//! ```
+//!
+//! The setup is the same as `ip tuntap add mytun mode tun user 1000` or `tuntap add mytun tun
+//! --user 1000` (with the binary built with the `cli` feature). The device must be attached to
+//! the same way it was created ‒ here in the TUN mode without the packet info.
+//!
+//! The user also needs to be able to open `/dev/net/tun`, which most distributions allow to
+//! everyone (mode `0666`).
+//!
+//! You really do want better error handling than all these unwraps.
+
+extern crate tun_tap;
+
+use std::env;
+use std::net::Ipv4Addr;
+use std::process;
+
+use tun_tap::icmp::EchoResponder;
+use tun_tap::packet::PacketDisplay;
+use tun_tap::{Iface, Mode};
+
+/// The privileged part, creating the device for the user.
+fn setup(name: &str, uid: u32) {
+    let iface = Iface::without_packet_info(name, Mode::Tun).unwrap();
+    iface.set_owner(uid).unwrap();
+    iface.set_persistent(true).unwrap();
+    iface.set_ipv4(Ipv4Addr::new(10, 110, 0, 1), 24).unwrap();
+    iface.set_up(true).unwrap();
+    println!("Created {} for the user {}", iface.name(), uid);
+    // The device outlives the file descriptor closed here
+}
+
+/// The unprivileged part, attaching to the existing device.
+fn run(name: &str) {
+    let iface = Iface::without_packet_info(name, Mode::Tun)
+        .unwrap_or_else(|e| panic!("Can't attach to {} (is it set up for us?): {}", name, e));
+    println!("Attached to {}, ping 10.110.0.2", iface.name());
+    let echo = EchoResponder::new();
+    let mut buffer = vec![0; iface.max_frame_size().unwrap()];
+    loop {
+        let size = iface.recv(&mut buffer).unwrap();
+        let packet = &buffer[..size];
+        println!("{}", PacketDisplay::new(packet));
+        if echo.respond(&iface, packet).unwrap() {
+            println!("Answered a ping");
+        }
+    }
+}
+
+fn teardown(name: &str) {
+    let iface = Iface::without_packet_info(name, Mode::Tun).unwrap();
+    // Removed once this last file descriptor is closed
+    iface.set_persistent(false).unwrap();
+    println!("Removed {}", name);
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["setup", name, uid] => setup(name, uid.parse().unwrap()),
+        ["run", name] => run(name),
+        ["teardown", name] => teardown(name),
+        _ => {
+            eprintln!("Usage: persistent setup NAME UID | run NAME | teardown NAME");
+            process::exit(1);
+        },
+    }
+}
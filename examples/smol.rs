@@ -0,0 +1,57 @@
+//! The pingpong example, but asynchronous and without tokio.
+//!
+//! This runs on the reactor of `async-io` (the one smol uses), so it works with any executor. For
+//! simplicity, it uses `async_io::block_on` in two threads, in a real application you'd likely
+//! spawn two tasks instead.
+//!
+//! Needs the `smol` feature.
+
+extern crate async_io;
+extern crate tun_tap;
+
+use std::net::Ipv4Addr;
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use async_io::Timer;
+use tun_tap::agnostic::AsyncIface;
+use tun_tap::packet::PacketDisplay;
+use tun_tap::{Iface, Mode};
+
+/// The packet data. Note that it is prefixed by 4 bytes ‒ two bytes are flags, another two are
+/// protocol. 8, 0 is IPv4, 134, 221 is IPv6. <https://en.wikipedia.org/wiki/EtherType#Examples>.
+const PING: &[u8] = &[0, 0, 8, 0, 69, 0, 0, 84, 44, 166, 64, 0, 64, 1, 247, 40, 10, 107, 1, 2, 10,
+    107, 1, 3, 8, 0, 62, 248, 19, 160, 0, 2, 232, 228, 34, 90, 0, 0, 0, 0, 216, 83, 3, 0, 0, 0, 0,
+    0, 16, 17, 18, 19, 20, 21, 22, 23, 24, 25, 26, 27, 28, 29, 30, 31, 32, 33, 34, 35, 36, 37, 38,
+    39, 40, 41, 42, 43, 44, 45, 46, 47, 48, 49, 50, 51, 52, 53, 54, 55];
+
+fn main() {
+    let iface = Iface::new("testtun%d", Mode::Tun).unwrap();
+    eprintln!("Iface: {:?}", iface);
+    iface.set_ipv4(Ipv4Addr::new(10, 107, 1, 3), 24).unwrap();
+    iface.set_up(true).unwrap();
+    // MTU + TUN header
+    let bufsize = iface.max_frame_size().unwrap();
+    let iface = Arc::new(AsyncIface::new(iface).unwrap());
+    let iface_writer = Arc::clone(&iface);
+    let writer = thread::spawn(move || {
+        async_io::block_on(async {
+            loop {
+                Timer::after(Duration::from_secs(1)).await;
+                println!("Sending a ping");
+                let amount = iface_writer.send(PING).await.unwrap();
+                assert!(amount == PING.len());
+            }
+        })
+    });
+    async_io::block_on(async {
+        let mut buffer = vec![0; bufsize];
+        loop {
+            let size = iface.recv(&mut buffer).await.unwrap();
+            println!("{}", PacketDisplay::with_packet_info(&buffer[..size]));
+        }
+    });
+    writer.join()
+        .unwrap();
+}
@@ -0,0 +1,95 @@
+//! A TCP echo server on a userspace network stack.
+//!
+//! This creates a TAP device, configures the kernel endpoint (10.109.0.1) and runs the
+//! [`smoltcp`](https://docs.rs/smoltcp) stack on the other side of it, as if it was another
+//! machine (10.109.0.2) plugged into the device. The stack answers ARP and pings on its own and
+//! runs an echo server on the TCP port 7. Try it by:
+//!
+//! ```sh
+//! nc 10.109.0.2 7
+//! ```
+//!
+//! Needs the `smoltcp` feature and root.
+//!
+//! You really do want better error handling than all these unwraps.
+
+extern crate smoltcp;
+extern crate tun_tap;
+
+use std::net::Ipv4Addr;
+use std::time::Duration;
+
+use smoltcp::iface::{Config, Interface, SocketSet};
+use smoltcp::socket::tcp;
+use smoltcp::time::Instant;
+use smoltcp::wire::{EthernetAddress, IpAddress, IpCidr};
+use tun_tap::packet::MacAddr;
+use tun_tap::phy::SmolIface;
+use tun_tap::poll_set::{Interest, PollSet};
+use tun_tap::{Iface, Mode};
+
+/// The MAC address of the stack.
+const MAC: MacAddr = MacAddr([0x02, 0, 0, 0, 0x01, 0x09]);
+const PORT: u16 = 7;
+
+fn main() {
+    // The stack takes care of the ethernet frames, the packet info would be in the way.
+    let tap = Iface::without_packet_info("tap%d", Mode::Tap).unwrap();
+    tap.set_ipv4(Ipv4Addr::new(10, 109, 0, 1), 24).unwrap();
+    tap.set_up(true).unwrap();
+    let mut device = SmolIface::new(tap).unwrap();
+
+    let config = Config::new(EthernetAddress(MAC.0).into());
+    let mut iface = Interface::new(config, &mut device, Instant::now());
+    iface.update_ip_addrs(|addrs| {
+        addrs
+            .push(IpCidr::new(IpAddress::v4(10, 109, 0, 2), 24))
+            .unwrap();
+    });
+    let mut sockets = SocketSet::new(Vec::new());
+    let socket = tcp::Socket::new(
+        tcp::SocketBuffer::new(vec![0; 4096]),
+        tcp::SocketBuffer::new(vec![0; 4096]),
+    );
+    let handle = sockets.add(socket);
+    println!(
+        "Created {}, the stack is at 10.109.0.2 ({}), echoing on port {}",
+        device.iface().name(),
+        MAC,
+        PORT
+    );
+
+    let poll = PollSet::new().unwrap();
+    poll.add(device.iface(), 0, Interest::Readable).unwrap();
+    let mut events = Vec::new();
+    loop {
+        let now = Instant::now();
+        iface.poll(now, &mut device, &mut sockets);
+
+        let socket = sockets.get_mut::<tcp::Socket>(handle);
+        if !socket.is_open() {
+            // Waiting for the next client (one at a time)
+            socket.listen(PORT).unwrap();
+        }
+        if socket.can_recv() && socket.can_send() {
+            // Take only as much as fits into the send buffer, the rest waits
+            let room = socket.send_capacity() - socket.send_queue();
+            let data = socket
+                .recv(|buffer| {
+                    let len = buffer.len().min(room);
+                    (len, buffer[..len].to_vec())
+                })
+                .unwrap();
+            socket.send_slice(&data).unwrap();
+        } else if !socket.may_recv() && socket.may_send() {
+            // The client closed its side, we are done too
+            socket.close();
+        }
+
+        // Sleep until a packet arrives or some timer of the stack needs to run
+        let timeout = iface
+            .poll_delay(now, &sockets)
+            .map(|delay| Duration::from_micros(delay.total_micros()));
+        poll.wait(&mut events, timeout).unwrap();
+    }
+}
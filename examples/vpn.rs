@@ -1,69 +1,143 @@
-//! An VPN example
+//! A VPN example
 //!
-//! This creates one endpoint of a VPN. It takes two parameters ‒ local address and the address
-//! address of the other endpoint, and sends all packets there, encapsulated in UDP. Packets
-//! received from the other side are put to the kernel from the other side.
+//! This creates one endpoint of a VPN. It takes two parameters ‒ local address and the address of
+//! the other endpoint ‒ and sends all packets there, encrypted and encapsulated in UDP. Packets
+//! received from the other side are decrypted and put to the kernel through the TUN device.
+//!
+//! Both endpoints need the same key, 32 bytes written as 64 hex digits in the `VPN_KEY`
+//! environment variable. One can be generated by `openssl rand -hex 32`.
 //!
 //! Unlike the other examples, this doesn't configure the kernel endpoint and it is left up for the
-//! caller to bring the interface up and add an address to it (or possibly some routes).
+//! caller to bring the interface up and add an address to it (or possibly some routes). For
+//! example, on two machines:
+//!
+//! ```sh
+//! VPN_KEY=... cargo run --example vpn 192.0.2.1:4242 192.0.2.2:4242 &
+//! ip addr add 10.107.1.1/24 dev vpn0 && ip link set up dev vpn0
+//! ```
+//!
+//! The device is split into a sink and a stream of `TunPacket`s and so is the UDP socket, with a
+//! codec doing the encryption. Each packet (together with its protocol) is sealed by
+//! ChaCha20-Poly1305 with a random nonce, which is sent in front of it. Packets that fail to
+//! authenticate or come from elsewhere are dropped.
 //!
 //! # Warning
 //!
-//! Do not use as a VPN in any real-life situation. There's no authentication, encryption, nearly
-//! no error handling, etc.
+//! This still is not a VPN to be used in any real-life situation. There's no key exchange (and
+//! therefore no forward secrecy), no protection against replayed packets and no rekeying.
 
 extern crate futures;
+extern crate ring;
 extern crate tokio_core;
 extern crate tun_tap;
 
 use std::env;
-use std::io::Result;
+use std::error::Error;
+use std::io::Result as IoResult;
 use std::net::SocketAddr;
 
 use futures::{Future, Stream};
+use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, CHACHA20_POLY1305, NONCE_LEN};
+use ring::rand::{SecureRandom, SystemRandom};
 use tokio_core::net::{UdpCodec, UdpSocket};
 use tokio_core::reactor::Core;
+use tun_tap::codec::TunPacket;
+use tun_tap::r#async::Async;
+use tun_tap::{EtherType, IfaceConfig, Mode};
 
-use tun_tap::{Iface, Mode};
-use tun_tap::async::Async;
+/// The protocol, sent encrypted in front of the packet.
+const PROTO_LEN: usize = 2;
 
-struct VecCodec(SocketAddr);
+type Res<T> = Result<T, Box<dyn Error>>;
 
-impl UdpCodec for VecCodec {
-    type In = Vec<u8>;
-    type Out = Vec<u8>;
-    fn decode(&mut self, _src: &SocketAddr, buf: &[u8]) -> Result<Self::In> {
-        Ok(buf.to_owned())
+fn parse_key(hex: &str) -> Res<LessSafeKey> {
+    let hex = hex.trim();
+    if hex.len() != 64 || !hex.is_ascii() {
+        return Err("The key needs to be 64 hex digits".into());
     }
-    fn encode(&mut self, msg: Self::Out, buf: &mut Vec<u8>) -> SocketAddr {
-        buf.extend(&msg);
-        self.0
+    let key = (0..32)
+        .map(|i| u8::from_str_radix(&hex[i * 2..i * 2 + 2], 16))
+        .collect::<Result<Vec<u8>, _>>()?;
+    let key = UnboundKey::new(&CHACHA20_POLY1305, &key).map_err(|_| "Invalid key")?;
+    Ok(LessSafeKey::new(key))
+}
+
+/// Encrypts the outgoing packets and decrypts the incoming ones.
+struct SealCodec {
+    remote: SocketAddr,
+    key: LessSafeKey,
+    random: SystemRandom,
+}
+
+impl UdpCodec for SealCodec {
+    /// The packets that can't be decrypted are `None`.
+    type In = Option<TunPacket>;
+    type Out = TunPacket;
+    fn decode(&mut self, src: &SocketAddr, buf: &[u8]) -> IoResult<Self::In> {
+        if *src != self.remote {
+            eprintln!("Dropping a packet from {}", src);
+            return Ok(None);
+        }
+        if buf.len() < NONCE_LEN {
+            eprintln!("Dropping a runt packet");
+            return Ok(None);
+        }
+        let (nonce, message) = buf.split_at(NONCE_LEN);
+        let nonce = Nonce::try_assume_unique_for_key(nonce).expect("Nonce has the right size");
+        let mut message = message.to_owned();
+        match self.key.open_in_place(nonce, Aad::empty(), &mut message) {
+            Ok(packet) if packet.len() >= PROTO_LEN => {
+                let proto = u16::from_be_bytes([packet[0], packet[1]]);
+                let packet = TunPacket::new(EtherType::from(proto), &packet[PROTO_LEN..]);
+                Ok(Some(packet))
+            },
+            _ => {
+                eprintln!("Dropping a packet failing authentication");
+                Ok(None)
+            },
+        }
+    }
+    fn encode(&mut self, packet: TunPacket, buf: &mut Vec<u8>) -> SocketAddr {
+        // The codec can't fail here. But without randomness the nonces could repeat, which would
+        // give the key away, so better not to go on.
+        let mut nonce = [0; NONCE_LEN];
+        self.random.fill(&mut nonce).expect("No randomness");
+        buf.extend_from_slice(&nonce);
+        buf.extend_from_slice(&u16::from(packet.proto).to_be_bytes());
+        buf.extend_from_slice(&packet.payload);
+        let nonce = Nonce::assume_unique_for_key(nonce);
+        let tag = self
+            .key
+            .seal_in_place_separate_tag(nonce, Aad::empty(), &mut buf[NONCE_LEN..])
+            .expect("A packet is never too large to encrypt");
+        buf.extend_from_slice(tag.as_ref());
+        self.remote
     }
 }
 
-fn main() {
-    let mut core = Core::new().unwrap();
-    let loc_address = env::args()
-        .nth(1)
-        .unwrap()
-        .parse()
-        .unwrap();
-    let rem_address = env::args()
-        .nth(2)
-        .unwrap()
-        .parse()
-        .unwrap();
-    let socket = UdpSocket::bind(&loc_address, &core.handle())
-        .unwrap();
-    let (sender, receiver) = socket.framed(VecCodec(rem_address))
-        .split();
-    let tun = Iface::new("vpn%d", Mode::Tun)
-        .unwrap();
-    let (sink, stream) = Async::new(tun, &core.handle())
-        .unwrap()
-        .split();
-    let reader = stream.forward(sender);
-    let writer = receiver.forward(sink);
-    core.run(reader.join(writer))
-        .unwrap();
+fn main() -> Res<()> {
+    let mut args = env::args().skip(1);
+    let usage = "Usage: vpn LOCAL_ADDRESS:PORT REMOTE_ADDRESS:PORT";
+    let local: SocketAddr = args.next().ok_or(usage)?.parse()?;
+    let remote: SocketAddr = args.next().ok_or(usage)?.parse()?;
+    let key = parse_key(&env::var("VPN_KEY").map_err(|_| "VPN_KEY is not set")?)?;
+
+    let mut core = Core::new()?;
+    let socket = UdpSocket::bind(&local, &core.handle())?;
+    let codec = SealCodec {
+        remote,
+        key,
+        random: SystemRandom::new(),
+    };
+    let (to_remote, from_remote) = socket.framed(codec).split();
+
+    let iface = IfaceConfig::new("vpn%d", Mode::Tun).create()?;
+    eprintln!("Created {}, tunneling from {} to {}", iface.name(), local, remote);
+    let (to_iface, from_iface) = Async::new(iface, &core.handle())?.framed().split();
+
+    let outbound = from_iface.forward(to_remote);
+    let inbound = from_remote.filter_map(|packet| packet).forward(to_iface);
+    // Both run until an error, the streams and sinks themselves are of no further use
+    core.run(outbound.join(inbound).map(|_| ()))?;
+    Ok(())
 }
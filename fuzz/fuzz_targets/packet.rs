@@ -0,0 +1,30 @@
+//! The header parsers, on whatever the fuzzer comes up with.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use tun_tap::packet::{
+    inner_ether_type, strip_vlan_tag, vlan_tags, EthernetHeader, PacketDisplay, VirtioNetHeader,
+};
+use tun_tap::PacketInfo;
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() >= VirtioNetHeader::SIZE {
+        let mut header = [0; VirtioNetHeader::SIZE];
+        header.copy_from_slice(&data[..VirtioNetHeader::SIZE]);
+        assert_eq!(header, VirtioNetHeader::parse(&header).to_bytes());
+    }
+    if data.len() >= PacketInfo::SIZE {
+        let mut header = [0; PacketInfo::SIZE];
+        header.copy_from_slice(&data[..PacketInfo::SIZE]);
+        assert_eq!(header, PacketInfo::parse(&header).to_bytes());
+    }
+    if let Some((header, rest)) = EthernetHeader::parse(data) {
+        assert_eq!(&data[..EthernetHeader::SIZE], &header.to_bytes()[..]);
+        assert_eq!(data.len() - EthernetHeader::SIZE, rest.len());
+    }
+    let _ = vlan_tags(data).count();
+    let _ = inner_ether_type(data);
+    let _ = strip_vlan_tag(&mut data.to_vec());
+    let _ = PacketDisplay::with_packet_info(data).to_string();
+});
@@ -0,0 +1,23 @@
+//! The responders, which parse the frames all the way to the application protocols.
+
+#![no_main]
+
+use std::net::Ipv4Addr;
+
+use libfuzzer_sys::fuzz_target;
+use tun_tap::arp::ArpResponder;
+use tun_tap::dhcp::DhcpServer;
+use tun_tap::icmp::EchoResponder;
+use tun_tap::packet::MacAddr;
+
+const ADDRESS: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+const MAC: MacAddr = MacAddr([2, 0, 0, 0, 0, 1]);
+
+fuzz_target!(|data: &[u8]| {
+    let mut arp = ArpResponder::new();
+    arp.insert(ADDRESS, MAC);
+    let _ = arp.reply(data);
+    let _ = EchoResponder::new().reply(data);
+    let range = Ipv4Addr::new(10, 0, 0, 100)..=Ipv4Addr::new(10, 0, 0, 101);
+    let _ = DhcpServer::new(ADDRESS, MAC, range).reply(data);
+});
@@ -0,0 +1,160 @@
+//! Runtime-agnostic asynchronous interface.
+//!
+//! See the [`AsyncIface`](struct.AsyncIface.html) structure. This module is available only with
+//! the `futures-io` feature (or its `smol` alias).
+
+use std::future;
+use std::io::{ErrorKind, Result};
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use async_io::Async;
+use futures_io::{AsyncRead, AsyncWrite};
+
+use crate::Iface;
+
+/// A wrapper around [`Iface`](../struct.Iface.html) implementing the `futures-io` traits.
+///
+/// Unlike the [`Async`](../async/struct.Async.html), this is not bound to tokio. The
+/// interface is watched by the reactor of the [`async-io`](https://docs.rs/async-io) crate,
+/// which runs on its own, so this works with any executor (eg. smol, async-std or just
+/// `block_on`).
+///
+/// Each `poll_read` receives a single packet and each `poll_write` sends a single packet, so
+/// the usual adapters working with byte streams (eg. buffered readers) are not a good fit.
+///
+/// A triggered [`ShutdownHandle`](../struct.ShutdownHandle.html) makes the reads fail with the
+/// [`Shutdown`](../struct.Shutdown.html) error, but it doesn't wake up a task already waiting
+/// for a packet.
+#[derive(Debug)]
+pub struct AsyncIface {
+    inner: Async<Iface>,
+}
+
+impl AsyncIface {
+    /// Wraps the interface.
+    ///
+    /// The interface is switched to the non-blocking mode.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn new(iface: Iface) -> Result<Self> {
+        Ok(AsyncIface {
+            inner: Async::new(iface)?,
+        })
+    }
+    /// Accesses the wrapped interface.
+    pub fn iface(&self) -> &Iface {
+        self.inner.get_ref()
+    }
+    /// Unwraps the interface.
+    ///
+    /// The interface stays in the non-blocking mode.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn into_inner(self) -> Result<Iface> {
+        self.inner.into_inner()
+    }
+    /// Receives a single packet.
+    ///
+    /// The asynchronous counterpart of [`Iface::recv`](../struct.Iface.html#method.recv).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// # use tun_tap::agnostic::AsyncIface;
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let iface = AsyncIface::new(iface).unwrap();
+    /// let mut buffer = vec![0; 1504];
+    /// // Or smol::block_on, or inside any other executor.
+    /// let size = async_io::block_on(iface.recv(&mut buffer)).unwrap();
+    /// println!("Packet: {:?}", &buffer[..size]);
+    /// ```
+    pub async fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        future::poll_fn(|cx| self.poll_recv(cx, buf)).await
+    }
+    /// Sends a single packet.
+    ///
+    /// The asynchronous counterpart of [`Iface::send`](../struct.Iface.html#method.send).
+    pub async fn send(&self, buf: &[u8]) -> Result<usize> {
+        future::poll_fn(|cx| self.poll_send(cx, buf)).await
+    }
+    /// Waits until the interface is readable.
+    pub async fn readable(&self) -> Result<()> {
+        self.inner.readable().await
+    }
+    /// Waits until the interface is writable.
+    pub async fn writable(&self) -> Result<()> {
+        self.inner.writable().await
+    }
+    /// Receives one packet, registering the waker if none is available.
+    pub fn poll_recv(&self, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize>> {
+        loop {
+            match self.inner.get_ref().recv(buf) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    match self.inner.poll_readable(cx) {
+                        Poll::Ready(Ok(())) => (),
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                },
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+    /// Sends one packet, registering the waker if it is not possible now.
+    pub fn poll_send(&self, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        loop {
+            match self.inner.get_ref().send(buf) {
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                    match self.inner.poll_writable(cx) {
+                        Poll::Ready(Ok(())) => (),
+                        Poll::Ready(Err(e)) => return Poll::Ready(Err(e)),
+                        Poll::Pending => return Poll::Pending,
+                    }
+                },
+                result => return Poll::Ready(result),
+            }
+        }
+    }
+}
+
+impl AsyncRead for AsyncIface {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize>> {
+        self.poll_recv(cx, buf)
+    }
+}
+
+impl AsyncRead for &AsyncIface {
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut [u8]) -> Poll<Result<usize>> {
+        self.poll_recv(cx, buf)
+    }
+}
+
+impl AsyncWrite for AsyncIface {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        self.poll_send(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl AsyncWrite for &AsyncIface {
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<Result<usize>> {
+        self.poll_send(cx, buf)
+    }
+    fn poll_flush(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+    fn poll_close(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<Result<()>> {
+        Poll::Ready(Ok(()))
+    }
+}
@@ -0,0 +1,147 @@
+//! Answering ARP requests on a TAP device.
+//!
+//! Before the kernel sends an IPv4 packet into a TAP device, it asks for the MAC address of the
+//! destination by an ARP request. Without an answer, no traffic comes. The
+//! [`ArpResponder`](struct.ArpResponder.html) answers these, so tests and userspace stacks don't
+//! have to implement ARP themselves.
+
+use std::collections::HashMap;
+use std::io::Result;
+use std::net::Ipv4Addr;
+
+use crate::packet::{inner_ether_type, vlan_tags, EthernetHeader, MacAddr, VlanTag};
+use crate::{Device, EtherType, Mode, PacketInfo};
+
+/// Size of the ARP payload for IPv4 over ethernet.
+const ARP_SIZE: usize = 28;
+const REQUEST: u16 = 1;
+const REPLY: u16 = 2;
+/// Hardware type ethernet, protocol IPv4, the address lengths.
+const IPV4_OVER_ETHERNET: [u8; 6] = [0, 1, 0x08, 0x00, 6, 4];
+
+/// Answers ARP requests for the configured addresses.
+///
+/// Feed it the frames received from a TAP device by [`respond`](#method.respond). It answers the
+/// requests for the IPv4 addresses in its table and leaves everything else to the caller. The
+/// VLAN tags of the request are kept in the reply.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::net::Ipv4Addr;
+/// # use tun_tap::*;
+/// # use tun_tap::arp::ArpResponder;
+/// # use tun_tap::packet::MacAddr;
+/// let iface = Iface::without_packet_info("mytap", Mode::Tap).expect("Failed to create a device");
+/// let mut arp = ArpResponder::new();
+/// arp.insert(Ipv4Addr::new(10, 0, 0, 2), MacAddr([0x02, 0, 0, 0, 0, 2]));
+/// let mut buffer = vec![0; 1518];
+/// loop {
+///     let size = iface.recv(&mut buffer).unwrap();
+///     if !arp.respond(&iface, &buffer[..size]).unwrap() {
+///         // Handle the IP traffic
+///     }
+/// }
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ArpResponder {
+    table: HashMap<Ipv4Addr, MacAddr>,
+}
+
+impl ArpResponder {
+    /// Creates a responder with an empty table.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Answers the requests for the `ip` with the `mac`.
+    ///
+    /// Returns the previous MAC address of the `ip`, if any.
+    pub fn insert(&mut self, ip: Ipv4Addr, mac: MacAddr) -> Option<MacAddr> {
+        self.table.insert(ip, mac)
+    }
+    /// Stops answering for the `ip`.
+    pub fn remove(&mut self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.table.remove(&ip)
+    }
+    /// Returns the MAC address answered for the `ip`.
+    pub fn get(&self, ip: Ipv4Addr) -> Option<MacAddr> {
+        self.table.get(&ip).cloned()
+    }
+    /// Builds the reply to the frame (without the packet info).
+    ///
+    /// Returns `None` if the frame is not an ARP request for an address in the table.
+    pub fn reply(&self, frame: &[u8]) -> Option<Vec<u8>> {
+        if inner_ether_type(frame)? != EtherType::Arp {
+            return None;
+        }
+        let offset = 12 + vlan_tags(frame).count() * VlanTag::SIZE + 2;
+        let arp = frame.get(offset..offset + ARP_SIZE)?;
+        if arp[..6] != IPV4_OVER_ETHERNET || u16::from_be_bytes([arp[6], arp[7]]) != REQUEST {
+            return None;
+        }
+        let sender_mac = &arp[8..14];
+        let sender_ip = &arp[14..18];
+        let target_ip = &arp[24..28];
+        // A gratuitous ARP, announcing the address instead of asking for it
+        if sender_ip == target_ip {
+            return None;
+        }
+        let mac = self.get(Ipv4Addr::new(
+            target_ip[0],
+            target_ip[1],
+            target_ip[2],
+            target_ip[3],
+        ))?;
+        let mut reply = Vec::with_capacity(offset + ARP_SIZE);
+        reply.extend_from_slice(sender_mac);
+        reply.extend_from_slice(&mac.0);
+        // The VLAN tags and the ether type
+        reply.extend_from_slice(&frame[12..offset]);
+        reply.extend_from_slice(&IPV4_OVER_ETHERNET);
+        reply.extend_from_slice(&REPLY.to_be_bytes());
+        reply.extend_from_slice(&mac.0);
+        reply.extend_from_slice(target_ip);
+        reply.extend_from_slice(sender_mac);
+        reply.extend_from_slice(sender_ip);
+        debug_assert!(reply.len() >= EthernetHeader::SIZE + ARP_SIZE);
+        Some(reply)
+    }
+    /// Answers the packet received from the device, if it is a request for a known address.
+    ///
+    /// The `packet` is as received, including the packet info if the device has it. Returns
+    /// whether the packet was answered (and the caller may ignore it). Packets from devices in
+    /// the TUN mode are never answered.
+    ///
+    /// # Errors
+    ///
+    /// If sending the reply fails.
+    pub fn respond<D: Device>(&self, device: &D, packet: &[u8]) -> Result<bool> {
+        if device.mode() != Mode::Tap {
+            return Ok(false);
+        }
+        let info = device.packet_info();
+        let frame = if info {
+            match packet.get(PacketInfo::SIZE..) {
+                Some(frame) => frame,
+                None => return Ok(false),
+            }
+        } else {
+            packet
+        };
+        let reply = match self.reply(frame) {
+            Some(reply) => reply,
+            None => return Ok(false),
+        };
+        trace!("Answering an ARP request on {}", device.name());
+        if info {
+            let header = PacketInfo::new(EtherType::Arp).to_bytes();
+            let mut with_info = Vec::with_capacity(PacketInfo::SIZE + reply.len());
+            with_info.extend_from_slice(&header);
+            with_info.extend_from_slice(&reply);
+            device.send(&with_info)?;
+        } else {
+            device.send(&reply)?;
+        }
+        Ok(true)
+    }
+}
@@ -6,21 +6,32 @@ extern crate libc;
 extern crate mio;
 extern crate tokio_core;
 
+use std::fs::File;
 use std::io::{Error, ErrorKind, Read, Result, Write};
 use std::os::unix::io::AsRawFd;
+use std::time::Duration;
 
-use self::futures::{Async as FAsync, AsyncSink, Sink, StartSend, Stream, Poll as FPoll};
-use self::mio::{Evented, Poll as MPoll, PollOpt, Ready, Token};
+use self::futures::sync::mpsc;
+use self::futures::{try_ready, Async as FAsync, AsyncSink, Future, Sink, StartSend, Stream, Poll as FPoll};
+use self::mio::{Evented, Poll as MPoll, PollOpt, Token};
 use self::mio::unix::EventedFd;
-use self::tokio_core::reactor::{Handle, PollEvented};
+use self::tokio_core::reactor::{Handle, PollEvented, Timeout};
 
-use super::Iface;
+use crate::codec::{Framed, TunPacket, TunPacketCodec};
+use crate::device;
+use crate::pool::{PacketPool, PooledPacket};
+use crate::resilient::is_lost;
+use super::{
+    Device, Error as TunError, Iface, IfaceConfig, Shutdown, ShutdownHandle, Timestamp, TunFlags,
+};
 
-struct MioWrapper {
-    iface: Iface,
+pub use self::mio::Ready;
+
+struct MioWrapper<D> {
+    iface: D,
 }
 
-impl Evented for MioWrapper {
+impl<D: Device> Evented for MioWrapper<D> {
     fn register(&self, poll: &MPoll, token: Token, events: Ready, opts: PollOpt) -> Result<()> {
         EventedFd(&self.iface.as_raw_fd()).register(poll, token, events, opts)
     }
@@ -32,13 +43,30 @@ impl Evented for MioWrapper {
     }
 }
 
-impl Read for MioWrapper {
+/// The reading end of the shutdown pipe, becoming readable on shutdown.
+struct ShutdownWrapper {
+    read: File,
+}
+
+impl Evented for ShutdownWrapper {
+    fn register(&self, poll: &MPoll, token: Token, events: Ready, opts: PollOpt) -> Result<()> {
+        EventedFd(&self.read.as_raw_fd()).register(poll, token, events, opts)
+    }
+    fn reregister(&self, poll: &MPoll, token: Token, events: Ready, opts: PollOpt) -> Result<()> {
+        EventedFd(&self.read.as_raw_fd()).reregister(poll, token, events, opts)
+    }
+    fn deregister(&self, poll: &MPoll) -> Result<()> {
+        EventedFd(&self.read.as_raw_fd()).deregister(poll)
+    }
+}
+
+impl<D: Device> Read for MioWrapper<D> {
     fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
         self.iface.recv(buf)
     }
 }
 
-impl Write for MioWrapper {
+impl<D: Device> Write for MioWrapper<D> {
     fn write(&mut self, buf: &[u8]) -> Result<usize> {
         self.iface.send(buf)
     }
@@ -50,13 +78,45 @@ impl Write for MioWrapper {
 /// A wrapper around [`Iface`](../struct.Iface.html) for use in connection with tokio.
 ///
 /// This turns the synchronous `Iface` into an asynchronous `Sink + Stream` of packets.
-pub struct Async {
-    mio: PollEvented<MioWrapper>,
+///
+/// Other [`Device`](../trait.Device.html)s can be wrapped too (eg. a mock one in tests).
+///
+/// # Cancel safety
+///
+/// Every packet is received and sent by a single syscall, so no partial state is ever left
+/// behind. Therefore dropping any of the futures returned by the methods here (eg. because
+/// another branch of a `select` won) loses no packets ‒ either the packet has already been
+/// received or sent (and the future resolved) or it has not been touched at all. The `Stream`
+/// and the `Sink` of `Async` itself behave the same way.
+///
+/// The only exception is the buffered `Sink` of the [`framed`](#method.framed) interface (and
+/// the [`PacketSink`](struct.PacketSink.html)), which may hold one accepted, but not yet sent,
+/// packet. It is sent by the next `poll_complete` or lost if the sink is dropped.
+///
+/// # Shutdown
+///
+/// The [`ShutdownHandle`](../struct.ShutdownHandle.html) of the interface works here too. Once
+/// it is triggered, all the pending receiving and sending futures are woken up and fail with the
+/// [`Shutdown`](../struct.Shutdown.html) error and the streams (both the one of `Async` itself
+/// and the [`packets`](#method.packets)) end. Sending packets that don't need to wait still
+/// succeeds, as with [`Iface::send`](../struct.Iface.html#method.send).
+pub struct Async<D = Iface> {
+    mio: PollEvented<MioWrapper<D>>,
+    handle: Handle,
+    shutdown: Option<PollEvented<ShutdownWrapper>>,
     recv_bufsize: usize,
+    auto_bufsize: bool,
+    recv_pool_size: usize,
 }
 
-impl Async {
-    /// Consumes an `Iface` and wraps it in a new `Async`.
+type Setup = dyn Fn(&Iface) -> Result<()>;
+
+// The delays between the attempts to create a lost device again.
+const REATTACH_DELAY: Duration = Duration::from_millis(100);
+const MAX_REATTACH_DELAY: Duration = Duration::from_secs(10);
+
+impl<D: Device> Async<D> {
+    /// Consumes an `Iface` (or other device) and wraps it in a new `Async`.
     ///
     /// # Parameters
     ///
@@ -75,65 +135,1009 @@ impl Async {
     /// # extern crate tun_tap;
     /// # use futures::Stream;
     /// # use tun_tap::*;
-    /// # use tun_tap::async::*;
+    /// # use tun_tap::r#async::*;
     /// # use tokio_core::reactor::Core;
     /// # fn main() {
     /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
     /// let name = iface.name().to_owned();
     /// // Bring the interface up by `ip addr add IP dev $name; ip link set up dev $name`
     /// let core = Core::new().unwrap();
-    /// let async = Async::new(iface, &core.handle()).unwrap();
-    /// let (sink, stream) = async.split();
+    /// let iface = Async::new(iface, &core.handle()).unwrap();
+    /// let (sink, stream) = iface.split();
     /// # }
     /// ```
-    pub fn new(iface: Iface, handle: &Handle) -> Result<Self> {
+    pub fn new(iface: D, handle: &Handle) -> Result<Self> {
         iface.set_non_blocking()?;
-        Ok(Async {
+        let recv_bufsize = iface.max_frame_size().unwrap_or(device::BUFSIZE);
+        let mut result = Async {
             mio: PollEvented::new(MioWrapper { iface }, handle)?,
-            recv_bufsize: 1542,
-        })
+            handle: handle.clone(),
+            shutdown: None,
+            recv_bufsize,
+            auto_bufsize: true,
+            recv_pool_size: 1,
+        };
+        result.register_shutdown()?;
+        Ok(result)
+    }
+    /// Starts watching the shutdown pipe of the interface, if it has one.
+    fn register_shutdown(&mut self) -> Result<()> {
+        if self.shutdown.is_none() {
+            if let Some(fd) = self.iface().shutdown_fd() {
+                // A copy, as one fd can't be registered multiple times (eg. by clones)
+                let read = fd.try_clone_to_owned()?;
+                let read = File::from(read);
+                self.shutdown = Some(PollEvented::new(ShutdownWrapper { read }, &self.handle)?);
+            }
+        }
+        Ok(())
+    }
+    /// Called instead of going to sleep, fails if the interface has been shut down.
+    ///
+    /// Registers the current task to be woken up on the shutdown.
+    fn check_shutdown(&mut self) -> Result<()> {
+        self.register_shutdown()?;
+        match self.shutdown {
+            Some(ref shutdown) if shutdown.poll_read().is_ready() => Err(Error::other(Shutdown)),
+            _ => Ok(()),
+        }
     }
     /// Sets the receive buffer size.
     ///
     /// When receiving a packet, a buffer of this size is allocated and the packet read into it.
     /// This configures the size of the buffer.
     ///
-    /// This needs to be called when the interface's MTU is changed from the default 1500. The
-    /// default should be enough otherwise.
+    /// By default, the size is derived from the MTU of the device (see
+    /// [`Device::max_frame_size`](../trait.Device.html#method.max_frame_size), it leaves room for
+    /// a single VLAN tag in the TAP mode). The MTU is read again whenever a packet fills the
+    /// whole buffer, as that may be a sign the MTU was raised (the packet itself may already be
+    /// truncated, though). Setting the size explicitly turns this off, which is needed eg. for
+    /// more VLAN tags (add 4 bytes for each additional one).
     pub fn set_recv_bufsize(&mut self, bufsize: usize) {
         self.recv_bufsize = bufsize;
+        self.auto_bufsize = false;
+    }
+    /// Returns the configured receive buffer size.
+    pub fn recv_bufsize(&self) -> usize {
+        self.recv_bufsize
+    }
+    /// Re-reads the MTU if a received packet filled the whole buffer.
+    pub(crate) fn update_bufsize(&mut self, received: usize) {
+        if self.auto_bufsize {
+            self.recv_bufsize = device::refresh_bufsize(self.iface(), self.recv_bufsize, received);
+        }
+    }
+    /// Sets for how many packets the receive buffer is allocated at once.
+    ///
+    /// This applies to the decoded packets (eg. [`packets`](#method.packets) and
+    /// [`framed`](#method.framed)), not the raw `Stream` of `Vec<u8>`. The packets are received
+    /// into a shared buffer with room for this many packets and handed out as slices of it. Once
+    /// all the packets from the buffer are dropped, the buffer is reused without allocating.
+    ///
+    /// The default of 1 is enough if each packet is dropped before the next one is received. If
+    /// the packets are kept around for longer (eg. queued for further processing), a larger
+    /// value saves allocations, at the cost that a single outstanding packet keeps the whole
+    /// buffer alive.
+    ///
+    /// # Panics
+    ///
+    /// If the `packets` is 0.
+    pub fn set_recv_pool_size(&mut self, packets: usize) {
+        assert!(packets > 0, "The receive pool needs room for at least one packet");
+        self.recv_pool_size = packets;
+    }
+    /// Returns the configured receive pool size.
+    pub fn recv_pool_size(&self) -> usize {
+        self.recv_pool_size
+    }
+    /// Accesses the wrapped interface.
+    pub fn iface(&self) -> &D {
+        &self.mio.get_ref().iface
+    }
+
+    /// Wraps the interface into a [`Framed`](../codec/struct.Framed.html) with the
+    /// [`TunPacketCodec`](../codec/struct.TunPacketCodec.html).
+    ///
+    /// The codec is configured according to the interface (if it has the packet info and its
+    /// mode).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::Stream;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let core = Core::new().unwrap();
+    /// let (sink, stream) = Async::new(iface, &core.handle()).unwrap().framed().split();
+    /// # }
+    /// ```
+    pub fn framed(self) -> Framed<TunPacketCodec, D> {
+        let codec = TunPacketCodec::new(self.iface().packet_info(), self.iface().mode());
+        Framed::new(self, codec)
+    }
+    /// Turns the interface into a stream of decoded packets.
+    ///
+    /// Unlike the raw `Stream` implementation of `Async`, the packet info header (if any) is
+    /// stripped and turned into the [`proto`](../codec/struct.TunPacket.html#structfield.proto)
+    /// of the packet (or it is guessed from the packet if the interface runs without the packet
+    /// info). There's no need to choose and configure a codec.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::Stream;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let packets = Async::new(iface, &core.handle()).unwrap().packets();
+    /// core.run(packets.for_each(|packet| {
+    ///     println!("{:?}: {:?}", packet.proto, packet.payload);
+    ///     Ok(())
+    /// })).unwrap();
+    /// # }
+    /// ```
+    pub fn packets(self) -> PacketStream<D> {
+        PacketStream {
+            inner: self.framed(),
+        }
+    }
+    /// Turns the interface into a stream of raw packets in buffers from the pool.
+    ///
+    /// This is like the `Stream` implementation of `Async` itself, but the buffers are recycled
+    /// instead of allocating one for each packet. The stream ends once the interface is shut
+    /// down.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::Stream;
+    /// # use tun_tap::*;
+    /// # use tun_tap::pool::PacketPool;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let pool = PacketPool::for_device(&iface).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let packets = Async::new(iface, &core.handle()).unwrap().pooled(pool);
+    /// core.run(packets.for_each(|packet| {
+    ///     println!("{:?}", &packet[..]);
+    ///     Ok(())
+    /// })).unwrap();
+    /// # }
+    /// ```
+    pub fn pooled(self, pool: PacketPool) -> PooledStream<D> {
+        PooledStream { iface: self, pool }
+    }
+    /// Turns the interface into a stream of raw packets with the times they were received.
+    ///
+    /// This is like the `Stream` implementation of `Async` itself, but each packet comes with its
+    /// [`Timestamp`](../struct.Timestamp.html), for measuring latencies or replaying the traffic
+    /// later. The stream ends once the interface is shut down.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::Stream;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let packets = Async::new(iface, &core.handle()).unwrap().timestamped();
+    /// core.run(packets.for_each(|(packet, timestamp)| {
+    ///     println!("{:?}: {:?}", timestamp.realtime, packet);
+    ///     Ok(())
+    /// })).unwrap();
+    /// # }
+    /// ```
+    pub fn timestamped(self) -> TimestampedStream<D> {
+        TimestampedStream { iface: self }
+    }
+    /// Turns the interface into a sink of packets.
+    ///
+    /// The counterpart of [`packets`](#method.packets) ‒ the packet info header is added when
+    /// the interface has one. To both send and receive, either split the
+    /// [`framed`](#method.framed) interface or make a second handle with
+    /// [`try_clone`](#method.try_clone).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::Stream;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let iface = Async::new(iface, &core.handle()).unwrap();
+    /// let sink = iface.try_clone(&core.handle()).unwrap().packet_sink();
+    /// // Reflect all the packets back
+    /// core.run(iface.packets().forward(sink)).unwrap();
+    /// # }
+    /// ```
+    pub fn packet_sink(self) -> PacketSink<D> {
+        PacketSink {
+            inner: self.framed(),
+        }
+    }
+    /// Turns the interface into a pair of channels.
+    ///
+    /// Two tasks are spawned on the core the interface runs on. One passes the received packets
+    /// into the returned receiver, the other one sends the packets put into the returned sender.
+    /// Both channels are bounded by `buffer` (see `futures::sync::mpsc::channel`), so a slow
+    /// consumer slows the receiving down. The sender can be cloned, to let many tasks send
+    /// packets.
+    ///
+    /// The receiving task terminates once the interface is [shut down](#method.shutdown_handle)
+    /// or on the first packet after the receiver is dropped. The sending one terminates once
+    /// all the senders are dropped. An error terminates the respective task (it is logged).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::{Sink, Stream};
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let (sender, receiver) = Async::new(iface, &core.handle()).unwrap().into_channels(16);
+    /// // Reflect all the packets back
+    /// core.run(receiver.forward(sender.sink_map_err(|_| ()))).map(|_| ()).unwrap();
+    /// # }
+    /// ```
+    pub fn into_channels(
+        self,
+        buffer: usize,
+    ) -> (mpsc::Sender<TunPacket>, mpsc::Receiver<TunPacket>)
+    where
+        D: 'static,
+    {
+        let handle = self.handle.clone();
+        let recv_name = self.iface().name().to_owned();
+        let send_name = recv_name.clone();
+        let (sink, stream) = self.framed().split();
+        let (to_iface, from_user) = mpsc::channel(buffer);
+        let (to_user, from_iface) = mpsc::channel(buffer);
+        let recv = stream
+            .map_err(move |e| error!("Receiving from {} failed: {}", recv_name, e))
+            .forward(to_user.sink_map_err(|_| ()))
+            .map(|_| ());
+        let send = from_user
+            .forward(sink.sink_map_err(move |e| error!("Sending to {} failed: {}", send_name, e)))
+            .map(|_| ());
+        handle.spawn(recv);
+        handle.spawn(send);
+        (to_iface, from_iface)
+    }
+    /// Checks if the interface is readable, registering the current task for wake up if not.
+    ///
+    /// The readiness is cached and cleared once a receive hits `WouldBlock` (or by
+    /// [`clear_read_ready`](#method.clear_read_ready)).
+    pub fn poll_read_ready(&self) -> FAsync<()> {
+        self.mio.poll_read()
+    }
+    /// Checks if the interface is writable, registering the current task for wake up if not.
+    pub fn poll_write_ready(&self) -> FAsync<()> {
+        self.mio.poll_write()
+    }
+    /// Checks for any of the readiness events in the `mask`.
+    ///
+    /// Returns the ones that are ready or registers the current task for wake up if none is.
+    pub fn poll_ready(&self, mask: Ready) -> FAsync<Ready> {
+        self.mio.poll_ready(mask)
+    }
+    /// Marks the interface as no longer readable.
+    ///
+    /// This is needed only when draining the packets through the inner
+    /// [`iface`](#method.iface) directly, as [`poll_recv`](#method.poll_recv) (and the `Stream`)
+    /// does it on `WouldBlock` by itself. It must be called from within a task.
+    pub fn clear_read_ready(&self) {
+        self.mio.need_read()
+    }
+    /// Marks the interface as no longer writable.
+    ///
+    /// The counterpart of [`clear_read_ready`](#method.clear_read_ready).
+    pub fn clear_write_ready(&self) {
+        self.mio.need_write()
+    }
+    /// Returns a future resolving once the interface is readable.
+    ///
+    /// This allows draining all the available packets on a single wake up, eg. by calling
+    /// [`poll_recv`](#method.poll_recv) until it returns `NotReady`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::Async as FAsync;
+    /// # use futures::future::poll_fn;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let mut iface = Async::new(iface, &core.handle()).unwrap();
+    /// core.run(iface.readable()).unwrap();
+    /// let mut buffer = vec![0; 1504];
+    /// let batch = poll_fn(|| {
+    ///     let mut cnt = 0;
+    ///     while let FAsync::Ready(_size) = iface.poll_recv(&mut buffer)? {
+    ///         cnt += 1;
+    ///     }
+    ///     Ok::<_, std::io::Error>(FAsync::Ready(cnt))
+    /// });
+    /// println!("Received {} packets", core.run(batch).unwrap());
+    /// # }
+    /// ```
+    pub fn readable(&self) -> Readiness<'_, D> {
+        self.ready(Ready::readable())
+    }
+    /// Returns a future resolving once the interface is writable.
+    pub fn writable(&self) -> Readiness<'_, D> {
+        self.ready(Ready::writable())
+    }
+    /// Returns a future resolving once any of the readiness events in the `mask` happens.
+    ///
+    /// The future resolves to the events that are ready.
+    pub fn ready(&self, mask: Ready) -> Readiness<'_, D> {
+        Readiness { iface: self, mask }
+    }
+
+    /// Receives one packet, registering the current task for wake up if none is available.
+    ///
+    /// This is the building block of the `Stream` implementation, usable directly in hand-written
+    /// futures (eg. to receive into a reused buffer). Like any other `poll` method, it must be
+    /// called from within a task.
+    ///
+    /// Returns the size of the received packet.
+    pub fn poll_recv(&mut self, buf: &mut [u8]) -> FPoll<usize, Error> {
+        match self.mio.read(buf) {
+            Ok(size) => Ok(FAsync::Ready(size)),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                self.check_shutdown()?;
+                Ok(FAsync::NotReady)
+            },
+            Err(e) => Err(e),
+        }
+    }
+    /// Receives one packet and tells when it was received.
+    ///
+    /// This is like [`poll_recv`](#method.poll_recv), with the
+    /// [`Timestamp`](../struct.Timestamp.html) taken right after the read. The time the task
+    /// waited for the wake up is therefore not included.
+    pub fn poll_recv_timestamped(&mut self, buf: &mut [u8]) -> FPoll<(usize, Timestamp), Error> {
+        let size = try_ready!(self.poll_recv(buf));
+        Ok(FAsync::Ready((size, Timestamp::now())))
+    }
+    /// Returns a future receiving a single packet into the buffer.
+    ///
+    /// The future resolves to the size of the packet. It is cancel safe ‒ dropping it before it
+    /// resolves doesn't lose any packet.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let mut iface = Async::new(iface, &core.handle()).unwrap();
+    /// let mut buffer = vec![0; 1504];
+    /// let size = core.run(iface.recv_packet(&mut buffer)).unwrap();
+    /// println!("Packet: {:?}", &buffer[..size]);
+    /// # }
+    /// ```
+    pub fn recv_packet<'a>(&'a mut self, buf: &'a mut [u8]) -> RecvPacket<'a, D> {
+        RecvPacket { iface: self, buf }
+    }
+    /// Returns a future sending a single packet.
+    ///
+    /// The future resolves to the number of bytes sent. It is cancel safe ‒ if dropped before it
+    /// resolves, the packet has not been sent (not even partially).
+    pub fn send_packet<'a>(&'a mut self, buf: &'a [u8]) -> SendPacket<'a, D> {
+        SendPacket { iface: self, buf }
+    }
+    /// Receives all the immediately available packets, up to `max` of them.
+    ///
+    /// The packets are appended to `packets`. Returns the number of received packets or
+    /// `NotReady` (registering the current task for wake up) if there's none. This saves task
+    /// wake ups at high packet rates, as a single readiness notification is used for the whole
+    /// batch. Like any other `poll` method, it must be called from within a task.
+    ///
+    /// In case of an error, the packets received before it stay in `packets`.
+    pub fn poll_recv_many(&mut self, packets: &mut Vec<Vec<u8>>, max: usize) -> FPoll<usize, Error> {
+        let mut cnt = 0;
+        while cnt < max {
+            let mut buffer = vec![0; self.recv_bufsize];
+            match self.poll_recv(&mut buffer)? {
+                FAsync::Ready(size) => {
+                    self.update_bufsize(size);
+                    buffer.truncate(size);
+                    packets.push(buffer);
+                    cnt += 1;
+                },
+                FAsync::NotReady if cnt == 0 => return Ok(FAsync::NotReady),
+                FAsync::NotReady => break,
+            }
+        }
+        Ok(FAsync::Ready(cnt))
+    }
+    /// Returns a future receiving a batch of packets.
+    ///
+    /// The future waits for at least one packet and then receives all the immediately available
+    /// ones, up to `max`. It resolves to the number of received packets. See
+    /// [`poll_recv_many`](#method.poll_recv_many). It is cancel safe, as the whole batch is
+    /// received within a single `poll`.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let mut iface = Async::new(iface, &core.handle()).unwrap();
+    /// let mut packets = Vec::new();
+    /// loop {
+    ///     core.run(iface.recv_many(&mut packets, 32)).unwrap();
+    ///     for packet in packets.drain(..) {
+    ///         println!("Packet: {:?}", packet);
+    ///     }
+    /// }
+    /// # }
+    /// ```
+    pub fn recv_many<'a>(
+        &'a mut self,
+        packets: &'a mut Vec<Vec<u8>>,
+        max: usize,
+    ) -> RecvMany<'a, D> {
+        RecvMany {
+            iface: self,
+            packets,
+            max,
+        }
+    }
+    /// Sends one packet, registering the current task for wake up if it is not possible now.
+    ///
+    /// This is the building block of the `Sink` implementation. Like any other `poll` method, it
+    /// must be called from within a task.
+    ///
+    /// Returns the number of bytes sent.
+    pub fn poll_send(&mut self, buf: &[u8]) -> FPoll<usize, Error> {
+        match self.mio.write(buf) {
+            Ok(size) => Ok(FAsync::Ready(size)),
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                self.check_shutdown()?;
+                Ok(FAsync::NotReady)
+            },
+            Err(e) => Err(e),
+        }
     }
 }
 
-impl Stream for Async {
+impl Async {
+    /// Returns a handle to shut the interface down.
+    ///
+    /// This is the same as [`Iface::shutdown_handle`](../struct.Iface.html#method.shutdown_handle)
+    /// on the wrapped interface. To wake up the futures already waiting, the handle needs to be
+    /// obtained before they start waiting (which is the case of the ones started after this
+    /// call).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use std::thread;
+    /// # use futures::Stream;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let mut iface = Async::new(iface, &core.handle()).unwrap();
+    /// let handle = iface.shutdown_handle().unwrap();
+    /// thread::spawn(move || {
+    ///     // Eg. on SIGTERM
+    ///     handle.shutdown().unwrap();
+    /// });
+    /// // Ends once the interface is shut down
+    /// core.run(iface.packets().for_each(|packet| {
+    ///     println!("{:?}", packet);
+    ///     Ok(())
+    /// })).unwrap();
+    /// # }
+    /// ```
+    pub fn shutdown_handle(&mut self) -> Result<ShutdownHandle> {
+        let handle = self.iface().shutdown_handle()?;
+        self.register_shutdown()?;
+        Ok(handle)
+    }
+    /// Creates another handle to the same interface, registered with the given handle.
+    ///
+    /// This allows several tasks (possibly on different cores) to each own a handle to the
+    /// interface. See [`Iface::try_clone`](../struct.Iface.html#method.try_clone) for the details.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (eg. running out of file
+    /// descriptors).
+    pub fn try_clone(&self, handle: &Handle) -> Result<Self> {
+        self.replaced(self.iface().try_clone()?, handle)
+    }
+    /// Creates a multi-queue device and wraps each of its queues.
+    ///
+    /// The [`MULTI_QUEUE`](../struct.TunFlags.html#associatedconstant.MULTI_QUEUE) flag is added
+    /// to the `flags` (see [`Iface::with_flags`](../struct.Iface.html#method.with_flags)). The
+    /// kernel spreads the flows between the queues. Each queue is a file descriptor of its own,
+    /// registered with the reactor separately, so each can be driven by its own task. Note that
+    /// this is unlike [`try_clone`](#method.try_clone), the clones share a single queue.
+    ///
+    /// All the queues are registered with the reactor of the `handle`. To run a reactor (thread)
+    /// per queue, create the queues by `Iface::with_flags` instead and wrap each of them by
+    /// [`new`](#method.new) in its own thread.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Iface::with_flags`](../struct.Iface.html#method.with_flags) (for any of
+    /// the queues, eg. when there are more than the kernel supports) and failures to register
+    /// with the reactor (as the [`Io`](../enum.Error.html#variant.Io) error).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::{Future, Stream};
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let mut core = Core::new().unwrap();
+    /// let queues = Async::new_queues("mytun%d", TunFlags::TUN, 4, &core.handle()).unwrap();
+    /// for queue in queues {
+    ///     let task = queue.packets().for_each(|packet| {
+    ///         println!("{:?}", packet);
+    ///         Ok(())
+    ///     });
+    ///     core.handle().spawn(task.map_err(|e| eprintln!("Queue failed: {}", e)));
+    /// }
+    /// core.run(futures::future::empty::<(), ()>()).unwrap();
+    /// # }
+    /// ```
+    pub fn new_queues(
+        ifname: &str,
+        flags: TunFlags,
+        queues: usize,
+        handle: &Handle,
+    ) -> std::result::Result<Vec<Self>, TunError> {
+        let flags = flags | TunFlags::MULTI_QUEUE;
+        let mut result = Vec::with_capacity(queues);
+        let mut name = ifname.to_owned();
+        for _ in 0..queues {
+            let queue = Iface::with_flags(&name, flags)?;
+            // The name is fixed now, so the others attach to the same device.
+            name = queue.name().to_owned();
+            result.push(Async::new(queue, handle)?);
+        }
+        debug!("Opened {} asynchronous queues of {}", queues, name);
+        Ok(result)
+    }
+    /// Wraps another interface, with the same settings as this one.
+    fn replaced(&self, iface: Iface, handle: &Handle) -> Result<Self> {
+        let mut result = Async::new(iface, handle)?;
+        result.recv_bufsize = self.recv_bufsize;
+        result.auto_bufsize = self.auto_bufsize;
+        result.recv_pool_size = self.recv_pool_size;
+        Ok(result)
+    }
+    /// Turns the interface into a stream of decoded packets, surviving the loss of the device.
+    ///
+    /// This is like [`packets`](#method.packets), but if the device stops working (eg. someone
+    /// deleted it by `ip link del`), the stream doesn't end with an error. It produces an
+    /// [`Interrupted`](enum.StreamEvent.html#variant.Interrupted) item, creates the device again
+    /// by the `config` and continues with it. The `setup` configures each new device (the
+    /// configuration of the old one is lost with it), its failure (or failure to create the
+    /// device) is retried after a delay, from 100 milliseconds doubling up to 10 seconds.
+    ///
+    /// Packets in flight at the time are lost. Other errors end the stream as usual.
+    ///
+    /// See also the [`ResilientIface`](../resilient/struct.ResilientIface.html) for the
+    /// synchronous version.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::Stream;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::*;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let config = IfaceConfig::new("vpn0", Mode::Tun);
+    /// let iface = config.create().unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let setup = |iface: &Iface| iface.set_up(true);
+    /// setup(&iface).unwrap();
+    /// let packets = Async::new(iface, &core.handle()).unwrap().reattaching(config, setup);
+    /// core.run(packets.for_each(|event| {
+    ///     match event {
+    ///         StreamEvent::Packet(packet) => println!("{:?}", packet),
+    ///         StreamEvent::Interrupted(e) => eprintln!("Device lost, creating again: {}", e),
+    ///         _ => (),
+    ///     }
+    ///     Ok(())
+    /// })).unwrap();
+    /// # }
+    /// ```
+    pub fn reattaching<F>(self, config: IfaceConfig, setup: F) -> ReattachingStream
+    where
+        F: Fn(&Iface) -> Result<()> + 'static,
+    {
+        ReattachingStream {
+            handle: self.handle.clone(),
+            stream: Some(self.packets()),
+            lost: None,
+            config,
+            setup: Box::new(setup),
+            timeout: None,
+            delay: REATTACH_DELAY,
+        }
+    }
+}
+
+impl<D: Device> Stream for Async<D> {
     type Item = Vec<u8>;
     type Error = Error;
     fn poll(&mut self) -> FPoll<Option<Self::Item>, Self::Error> {
         // TODO Reuse buffer?
         let mut buffer = vec![0; self.recv_bufsize];
-        match self.mio.read(&mut buffer) {
-            Ok(size) => {
-                buffer.resize(size, 0);
-                Ok(FAsync::Ready(Some(buffer)))
-            },
-            Err(ref e) if e.kind() == ErrorKind::WouldBlock => Ok(FAsync::NotReady),
-            Err(e) => Err(e),
-        }
+        let size = match self.poll_recv(&mut buffer) {
+            Err(ref e) if Shutdown::is_shutdown(e) => return Ok(FAsync::Ready(None)),
+            result => try_ready!(result),
+        };
+        self.update_bufsize(size);
+        buffer.resize(size, 0);
+        Ok(FAsync::Ready(Some(buffer)))
     }
 }
 
-impl Sink for Async {
+impl<D: Device> Sink for Async<D> {
     type SinkItem = Vec<u8>;
     type SinkError = Error;
     fn start_send(&mut self, item: Self::SinkItem) -> StartSend<Self::SinkItem, Self::SinkError> {
-        match self.mio.write(&item) {
+        match self.poll_send(&item)? {
             // TODO What to do about short write? Can it happen?
-            Ok(_size) => Ok(AsyncSink::Ready),
-            Err(ref e) if e.kind() == ErrorKind::WouldBlock => Ok(AsyncSink::NotReady(item)),
-            Err(e) => Err(e),
+            FAsync::Ready(_size) => Ok(AsyncSink::Ready),
+            FAsync::NotReady => Ok(AsyncSink::NotReady(item)),
         }
     }
     fn poll_complete(&mut self) -> FPoll<(), Self::SinkError> {
         Ok(FAsync::Ready(()))
     }
 }
+
+/// A future waiting for readiness of an [`Async`](struct.Async.html).
+///
+/// Created by the [`readable`](struct.Async.html#method.readable),
+/// [`writable`](struct.Async.html#method.writable) and
+/// [`ready`](struct.Async.html#method.ready) methods.
+pub struct Readiness<'a, D = Iface> {
+    iface: &'a Async<D>,
+    mask: Ready,
+}
+
+impl<D: Device> Future for Readiness<'_, D> {
+    type Item = Ready;
+    type Error = Error;
+    fn poll(&mut self) -> FPoll<Ready, Error> {
+        Ok(self.iface.poll_ready(self.mask))
+    }
+}
+
+/// A future receiving a single packet.
+///
+/// Created by [`Async::recv_packet`](struct.Async.html#method.recv_packet).
+pub struct RecvPacket<'a, D = Iface> {
+    iface: &'a mut Async<D>,
+    buf: &'a mut [u8],
+}
+
+impl<D: Device> Future for RecvPacket<'_, D> {
+    type Item = usize;
+    type Error = Error;
+    fn poll(&mut self) -> FPoll<usize, Error> {
+        self.iface.poll_recv(self.buf)
+    }
+}
+
+/// A future sending a single packet.
+///
+/// Created by [`Async::send_packet`](struct.Async.html#method.send_packet).
+pub struct SendPacket<'a, D = Iface> {
+    iface: &'a mut Async<D>,
+    buf: &'a [u8],
+}
+
+impl<D: Device> Future for SendPacket<'_, D> {
+    type Item = usize;
+    type Error = Error;
+    fn poll(&mut self) -> FPoll<usize, Error> {
+        self.iface.poll_send(self.buf)
+    }
+}
+
+/// A future receiving a batch of packets.
+///
+/// Created by [`Async::recv_many`](struct.Async.html#method.recv_many).
+pub struct RecvMany<'a, D = Iface> {
+    iface: &'a mut Async<D>,
+    packets: &'a mut Vec<Vec<u8>>,
+    max: usize,
+}
+
+impl<D: Device> Future for RecvMany<'_, D> {
+    type Item = usize;
+    type Error = Error;
+    fn poll(&mut self) -> FPoll<usize, Error> {
+        self.iface.poll_recv_many(self.packets, self.max)
+    }
+}
+
+/// A stream of decoded packets.
+///
+/// Created by [`Async::packets`](struct.Async.html#method.packets).
+pub struct PacketStream<D = Iface> {
+    inner: Framed<TunPacketCodec, D>,
+}
+
+impl<D: Device> PacketStream<D> {
+    /// Accesses the interface.
+    pub fn get_ref(&self) -> &Async<D> {
+        self.inner.get_ref()
+    }
+    /// Unwraps the interface.
+    pub fn into_inner(self) -> Async<D> {
+        self.inner.into_inner()
+    }
+}
+
+impl<D: Device> Stream for PacketStream<D> {
+    type Item = TunPacket;
+    type Error = Error;
+    fn poll(&mut self) -> FPoll<Option<TunPacket>, Error> {
+        self.inner.poll()
+    }
+}
+
+/// A stream of packets in buffers from a pool.
+///
+/// Created by [`Async::pooled`](struct.Async.html#method.pooled).
+pub struct PooledStream<D = Iface> {
+    iface: Async<D>,
+    pool: PacketPool,
+}
+
+impl<D: Device> PooledStream<D> {
+    /// Accesses the interface.
+    pub fn get_ref(&self) -> &Async<D> {
+        &self.iface
+    }
+    /// Accesses the pool.
+    pub fn pool(&self) -> &PacketPool {
+        &self.pool
+    }
+    /// Unwraps the interface.
+    pub fn into_inner(self) -> Async<D> {
+        self.iface
+    }
+}
+
+impl<D: Device> Stream for PooledStream<D> {
+    type Item = PooledPacket;
+    type Error = Error;
+    fn poll(&mut self) -> FPoll<Option<PooledPacket>, Error> {
+        // If not ready, the buffer goes right back to the pool.
+        let mut packet = self.pool.get();
+        let size = match self.iface.poll_recv(&mut packet) {
+            Err(ref e) if Shutdown::is_shutdown(e) => return Ok(FAsync::Ready(None)),
+            result => try_ready!(result),
+        };
+        packet.truncate(size);
+        Ok(FAsync::Ready(Some(packet)))
+    }
+}
+
+/// A stream of packets with the times they were received.
+///
+/// Created by [`Async::timestamped`](struct.Async.html#method.timestamped).
+pub struct TimestampedStream<D = Iface> {
+    iface: Async<D>,
+}
+
+impl<D: Device> TimestampedStream<D> {
+    /// Accesses the interface.
+    pub fn get_ref(&self) -> &Async<D> {
+        &self.iface
+    }
+    /// Unwraps the interface.
+    pub fn into_inner(self) -> Async<D> {
+        self.iface
+    }
+}
+
+impl<D: Device> Stream for TimestampedStream<D> {
+    type Item = (Vec<u8>, Timestamp);
+    type Error = Error;
+    fn poll(&mut self) -> FPoll<Option<Self::Item>, Error> {
+        let mut buffer = vec![0; self.iface.recv_bufsize];
+        let (size, timestamp) = match self.iface.poll_recv_timestamped(&mut buffer) {
+            Err(ref e) if Shutdown::is_shutdown(e) => return Ok(FAsync::Ready(None)),
+            result => try_ready!(result),
+        };
+        self.iface.update_bufsize(size);
+        buffer.truncate(size);
+        Ok(FAsync::Ready(Some((buffer, timestamp))))
+    }
+}
+
+/// A sink of packets.
+///
+/// Created by [`Async::packet_sink`](struct.Async.html#method.packet_sink).
+pub struct PacketSink<D = Iface> {
+    inner: Framed<TunPacketCodec, D>,
+}
+
+impl<D: Device> PacketSink<D> {
+    /// Accesses the interface.
+    pub fn get_ref(&self) -> &Async<D> {
+        self.inner.get_ref()
+    }
+    /// Unwraps the interface.
+    ///
+    /// A packet waiting to be sent is lost.
+    pub fn into_inner(self) -> Async<D> {
+        self.inner.into_inner()
+    }
+}
+
+impl<D: Device> Sink for PacketSink<D> {
+    type SinkItem = TunPacket;
+    type SinkError = Error;
+    fn start_send(&mut self, item: TunPacket) -> StartSend<TunPacket, Error> {
+        self.inner.start_send(item)
+    }
+    fn poll_complete(&mut self) -> FPoll<(), Error> {
+        self.inner.poll_complete()
+    }
+}
+
+/// An item of the [`ReattachingStream`](struct.ReattachingStream.html).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum StreamEvent {
+    /// A received packet.
+    Packet(TunPacket),
+    /// The device stopped working, with the error it failed with.
+    ///
+    /// It is being created again, the packets continue once it is ready.
+    Interrupted(Error),
+}
+
+/// A stream of decoded packets, creating the device again if it is lost.
+///
+/// Created by [`Async::reattaching`](struct.Async.html#method.reattaching).
+pub struct ReattachingStream {
+    handle: Handle,
+    /// The current stream, none while the device is being created again.
+    stream: Option<PacketStream>,
+    /// The lost interface, kept for its settings.
+    lost: Option<Async>,
+    config: IfaceConfig,
+    setup: Box<Setup>,
+    timeout: Option<Timeout>,
+    delay: Duration,
+}
+
+impl ReattachingStream {
+    /// Accesses the current interface, if there's one.
+    pub fn get_ref(&self) -> Option<&Async> {
+        self.stream.as_ref().map(PacketStream::get_ref)
+    }
+    /// Creates the device again, with the settings of the lost one.
+    fn reattach(&mut self) -> FPoll<(), Error> {
+        loop {
+            if let Some(ref mut timeout) = self.timeout {
+                try_ready!(timeout.poll());
+                self.timeout = None;
+            }
+            let lost = self.lost.as_ref().expect("Reattaching without a lost interface");
+            let result = self.config.create().map_err(Error::from).and_then(|iface| {
+                (self.setup)(&iface)?;
+                lost.replaced(iface, &self.handle)
+            });
+            match result {
+                Ok(iface) => {
+                    debug!("Interface {} created again", iface.iface().name());
+                    self.stream = Some(iface.packets());
+                    self.lost = None;
+                    self.delay = REATTACH_DELAY;
+                    return Ok(FAsync::Ready(()));
+                },
+                Err(e) => {
+                    error!("Failed to create {} again: {}", self.config.name, e);
+                    self.timeout = Some(Timeout::new(self.delay, &self.handle)?);
+                    self.delay = (self.delay * 2).min(MAX_REATTACH_DELAY);
+                },
+            }
+        }
+    }
+}
+
+impl Stream for ReattachingStream {
+    type Item = StreamEvent;
+    type Error = Error;
+    fn poll(&mut self) -> FPoll<Option<StreamEvent>, Error> {
+        if self.stream.is_none() {
+            try_ready!(self.reattach());
+        }
+        let stream = self.stream.as_mut().expect("Missing stream after reattaching");
+        match stream.poll() {
+            Err(e) => {
+                if !is_lost(stream.get_ref().iface(), &e) {
+                    return Err(e);
+                }
+                let lost = self.stream.take().expect("Missing lost stream").into_inner();
+                error!("Interface {} lost: {}", lost.iface().name(), e);
+                self.lost = Some(lost);
+                Ok(FAsync::Ready(Some(StreamEvent::Interrupted(e))))
+            },
+            Ok(packet) => Ok(packet.map(|packet| packet.map(StreamEvent::Packet))),
+        }
+    }
+}
@@ -0,0 +1,7 @@
+//! The privileged helper creating the devices.
+//!
+//! See the `tun_tap::helper` module.
+
+fn main() {
+    std::process::exit(tun_tap::helper::run_helper());
+}
@@ -0,0 +1,168 @@
+//! Management of persistent TUN/TAP devices, similar to `ip tuntap`.
+//!
+//! Built with the `cli` feature. Run without arguments for the usage.
+
+use std::env;
+use std::fmt::Display;
+use std::fs;
+use std::process;
+
+use tun_tap::{doctor, Iface, Mode, TunFlags};
+
+const USAGE: &str = "\
+Usage:
+  tuntap add NAME tun|tap [--user UID] [--group GID] [--no-pi] [--multi-queue]
+  tuntap del NAME
+  tuntap set NAME [--user UID] [--group GID]
+  tuntap show NAME
+  tuntap doctor
+
+The add command creates a persistent device, del removes it. The user and group are numeric
+and allow them to attach to the device without privileges. They can be changed, but not removed.
+The doctor command checks if the system allows using the devices.";
+
+/// The flags that need to match (or are overwritten) when attaching to an existing device.
+const ATTACH_FLAGS: TunFlags = TunFlags::TUN
+    .union(TunFlags::TAP)
+    .union(TunFlags::NO_PI)
+    .union(TunFlags::ONE_QUEUE)
+    .union(TunFlags::VNET_HDR)
+    .union(TunFlags::MULTI_QUEUE)
+    .union(TunFlags::NAPI)
+    .union(TunFlags::NAPI_FRAGS);
+
+fn usage() -> ! {
+    eprintln!("{}", USAGE);
+    process::exit(2);
+}
+
+fn fail<E: Display>(what: &str, error: E) -> ! {
+    eprintln!("tuntap: {}: {}", what, error);
+    process::exit(1);
+}
+
+fn sysfs(name: &str, attr: &str) -> String {
+    let path = format!("/sys/class/net/{}/{}", name, attr);
+    match fs::read_to_string(&path) {
+        Ok(value) => value.trim().to_owned(),
+        Err(e) => fail(&path, e),
+    }
+}
+
+fn attach(name: &str) -> Iface {
+    let flags = sysfs(name, "tun_flags");
+    let flags = match u16::from_str_radix(flags.trim_start_matches("0x"), 16) {
+        Ok(flags) => TunFlags::from_bits_retain(flags) & ATTACH_FLAGS,
+        Err(e) => fail(&format!("Invalid flags of {}", name), e),
+    };
+    Iface::with_flags(name, flags).unwrap_or_else(|e| fail(&format!("Can't attach to {}", name), e))
+}
+
+/// Parses the value of `--user` or `--group`.
+fn id(value: Option<&String>) -> u32 {
+    match value {
+        Some(id) => id.parse().unwrap_or_else(|e| fail(&format!("Invalid id {}", id), e)),
+        None => usage(),
+    }
+}
+
+/// Parses the options, returning the owner and group (`None` if not present).
+fn options(args: &[String], flags: &mut TunFlags) -> (Option<u32>, Option<u32>) {
+    let mut owner = None;
+    let mut group = None;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--user" => owner = Some(id(args.next())),
+            "--group" => group = Some(id(args.next())),
+            "--no-pi" => *flags |= TunFlags::NO_PI,
+            "--multi-queue" => *flags |= TunFlags::MULTI_QUEUE,
+            _ => usage(),
+        }
+    }
+    (owner, group)
+}
+
+fn configure(iface: &Iface, owner: Option<u32>, group: Option<u32>) {
+    if let Some(owner) = owner {
+        iface
+            .set_owner(owner)
+            .unwrap_or_else(|e| fail("Can't set the owner", e));
+    }
+    if let Some(group) = group {
+        iface
+            .set_group(group)
+            .unwrap_or_else(|e| fail("Can't set the group", e));
+    }
+}
+
+fn add(name: &str, mode: &str, args: &[String]) {
+    let mode = mode.parse::<Mode>().unwrap_or_else(|_| usage());
+    let mut flags = TunFlags::from(mode) | TunFlags::TUN_EXCL;
+    let (owner, group) = options(args, &mut flags);
+    let iface = Iface::with_flags(name, flags)
+        .unwrap_or_else(|e| fail(&format!("Can't create {}", name), e));
+    configure(&iface, owner, group);
+    iface
+        .set_persistent(true)
+        .unwrap_or_else(|e| fail("Can't make the device persistent", e));
+    println!("{}", iface.name());
+}
+
+fn del(name: &str) {
+    attach(name)
+        .set_persistent(false)
+        .unwrap_or_else(|e| fail(&format!("Can't delete {}", name), e));
+}
+
+fn set(name: &str, args: &[String]) {
+    let iface = attach(name);
+    let mut flags = TunFlags::empty();
+    let (owner, group) = options(args, &mut flags);
+    if !flags.is_empty() {
+        usage();
+    }
+    configure(&iface, owner, group);
+}
+
+fn show(name: &str) {
+    let iface = attach(name);
+    let flags = iface
+        .tun_flags()
+        .unwrap_or_else(|e| fail("Can't read the flags", e));
+    let iff_flags = iface
+        .iff_flags()
+        .unwrap_or_else(|e| fail("Can't read the interface flags", e));
+    let mtu = iface.mtu().unwrap_or_else(|e| fail("Can't read the MTU", e));
+    let id = |attr| match sysfs(name, attr).as_str() {
+        "-1" => "none".to_owned(),
+        id => id.to_owned(),
+    };
+    println!("{}:", iface.name());
+    println!("\tmode: {:?}", iface.mode());
+    println!("\tflags: {:?}", flags);
+    println!("\tinterface flags: {:?}", iff_flags);
+    println!("\tmtu: {}", mtu);
+    println!("\towner: {}", id("owner"));
+    println!("\tgroup: {}", id("group"));
+}
+
+fn doctor() {
+    let report = doctor::diagnose();
+    println!("{}", report);
+    if !report.is_ok() {
+        process::exit(1);
+    }
+}
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["add", name, mode, ..] => add(name, mode, &args[3..]),
+        ["del", name] => del(name),
+        ["set", name, ..] => set(name, &args[2..]),
+        ["show", name] => show(name),
+        ["doctor"] => doctor(),
+        _ => usage(),
+    }
+}
@@ -0,0 +1,169 @@
+//! Forwarding packets between two devices.
+//!
+//! See the [`bridge`](fn.bridge.html) function.
+
+use std::io::{Error, ErrorKind, Result};
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use crate::shutdown::ShutdownPipe;
+use crate::{device, Device, ShutdownHandle};
+
+/// Which way a packet goes through the [`Bridge`](struct.Bridge.html).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Direction {
+    /// Received from the first device, to be sent to the second one.
+    AToB,
+    /// Received from the second device, to be sent to the first one.
+    BToA,
+}
+
+/// A filter forwarding all the packets unchanged.
+///
+/// To be passed to [`bridge`](fn.bridge.html) when no filtering is needed.
+pub fn forward_all(_: Direction, _: &mut Vec<u8>) -> bool {
+    true
+}
+
+/// Starts forwarding packets between the two devices.
+///
+/// Each packet received from one of the devices is passed to the `filter`, together with the
+/// direction it goes. If the filter returns `true`, the packet (possibly modified by the filter)
+/// is sent to the other device, otherwise it is dropped. This makes a simple userspace patch
+/// panel, or a man-in-the-middle rig for tests.
+///
+/// The packets are forwarded verbatim, including the packet info header, therefore both devices
+/// need to be in the same mode and with the same packet info setting. Each direction is served by
+/// its own thread. Packets that fail to be sent are dropped, like on any other network.
+///
+/// # Errors
+///
+/// If the devices don't match or the threads can't be started.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// # use tun_tap::bridge::{self, Direction};
+/// let a = Iface::new("tap-a", Mode::Tap).expect("Failed to create a device");
+/// let b = Iface::new("tap-b", Mode::Tap).expect("Failed to create a device");
+/// // Lose every packet going one way
+/// let bridge = bridge::bridge(a, b, |direction, _: &mut Vec<u8>| direction == Direction::AToB)
+///     .expect("Failed to start the bridge");
+/// // Later on
+/// let (a, b) = bridge.shutdown();
+/// ```
+pub fn bridge<D, F>(a: D, b: D, filter: F) -> Result<Bridge<D>>
+where
+    D: Device + Send + Sync + 'static,
+    F: Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync + 'static,
+{
+    if a.mode() != b.mode() || a.packet_info() != b.packet_info() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "Bridged devices need the same mode and packet info",
+        ));
+    }
+    let mut bridge = Bridge {
+        a: Arc::new(a),
+        b: Arc::new(b),
+        shutdown: ShutdownHandle {
+            pipe: Arc::new(ShutdownPipe::new()?),
+        },
+        threads: Vec::with_capacity(2),
+    };
+    let filter = Arc::new(filter);
+    bridge.start(Direction::AToB, &filter)?;
+    bridge.start(Direction::BToA, &filter)?;
+    Ok(bridge)
+}
+
+fn forward<D, F>(from: &D, to: &D, shutdown: &ShutdownPipe, direction: Direction, filter: &F)
+where
+    D: Device,
+    F: Fn(Direction, &mut Vec<u8>) -> bool,
+{
+    let forwarded = device::pump(from, Some(shutdown), |packet| {
+        if filter(direction, packet) {
+            if let Err(e) = to.send(packet) {
+                debug!("Dropped a packet bridged to {}: {}", to.name(), e);
+            }
+        }
+        true
+    });
+    if let Err(e) = forwarded {
+        error!("Bridging from {} failed: {}", from.name(), e);
+    }
+}
+
+/// Two devices connected together.
+///
+/// Created by [`bridge`](fn.bridge.html). Dropping it tells the threads to terminate, but
+/// doesn't wait for them, [`shutdown`](#method.shutdown) does (and returns the devices).
+///
+/// A direction stops forwarding on an error of its receiving device (it is logged), the other one
+/// keeps running.
+#[derive(Debug)]
+pub struct Bridge<D> {
+    a: Arc<D>,
+    b: Arc<D>,
+    shutdown: ShutdownHandle,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl<D> Bridge<D>
+where
+    D: Device + Send + Sync + 'static,
+{
+    fn start<F>(&mut self, direction: Direction, filter: &Arc<F>) -> Result<()>
+    where
+        F: Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync + 'static,
+    {
+        let (from, to) = match direction {
+            Direction::AToB => (Arc::clone(&self.a), Arc::clone(&self.b)),
+            Direction::BToA => (Arc::clone(&self.b), Arc::clone(&self.a)),
+        };
+        let shutdown = Arc::clone(&self.shutdown.pipe);
+        let filter = Arc::clone(filter);
+        let thread = thread::Builder::new()
+            .name(format!("{}-{}", from.name(), to.name()))
+            .spawn(move || forward(&*from, &*to, &shutdown, direction, &*filter))?;
+        self.threads.push(thread);
+        Ok(())
+    }
+    /// Returns the first device.
+    pub fn a(&self) -> &D {
+        &self.a
+    }
+    /// Returns the second device.
+    pub fn b(&self) -> &D {
+        &self.b
+    }
+    /// Stops the forwarding and returns the devices.
+    ///
+    /// A packet being forwarded is finished first.
+    ///
+    /// # Panics
+    ///
+    /// If the filter panicked.
+    pub fn shutdown(mut self) -> (D, D) {
+        let _ = self.shutdown.shutdown();
+        for thread in self.threads.drain(..) {
+            thread.join().expect("Bridge filter panicked");
+        }
+        let a = Arc::clone(&self.a);
+        let b = Arc::clone(&self.b);
+        drop(self);
+        let unwrap = |device| match Arc::try_unwrap(device) {
+            Ok(device) => device,
+            Err(_) => unreachable!("Bridge threads terminated"),
+        };
+        (unwrap(a), unwrap(b))
+    }
+}
+
+impl<D> Drop for Bridge<D> {
+    fn drop(&mut self) {
+        let _ = self.shutdown.shutdown();
+    }
+}
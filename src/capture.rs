@@ -0,0 +1,229 @@
+//! Recording the traffic into a pcapng file.
+//!
+//! See the [`CaptureIface`](struct.CaptureIface.html) structure. This module is available only
+//! with the `capture` feature.
+
+use std::fs::File;
+use std::io::{BufWriter, Error, Result, Write};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::tee::Direction;
+use crate::{Iface, Mode, PacketInfo};
+
+const SECTION_HEADER_BLOCK: u32 = 0x0A0D_0D0A;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 1;
+const ENHANCED_PACKET_BLOCK: u32 = 6;
+const BYTE_ORDER_MAGIC: u32 = 0x1A2B_3C4D;
+const LINKTYPE_ETHERNET: u16 = 1;
+const LINKTYPE_RAW: u16 = 101;
+const OPT_END: u16 = 0;
+const IF_NAME: u16 = 2;
+const EPB_FLAGS: u16 = 2;
+const EPB_INBOUND: u32 = 1;
+const EPB_OUTBOUND: u32 = 2;
+
+fn padding(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+/// Builds a block, filling in the type and both lengths around the body.
+fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+    let total = (12 + body.len()) as u32;
+    let mut result = Vec::with_capacity(total as usize);
+    result.extend_from_slice(&block_type.to_ne_bytes());
+    result.extend_from_slice(&total.to_ne_bytes());
+    result.extend_from_slice(body);
+    result.extend_from_slice(&total.to_ne_bytes());
+    result
+}
+
+fn option(body: &mut Vec<u8>, code: u16, value: &[u8]) {
+    body.extend_from_slice(&code.to_ne_bytes());
+    body.extend_from_slice(&(value.len() as u16).to_ne_bytes());
+    body.extend_from_slice(value);
+    body.resize(body.len() + padding(value.len()), 0);
+}
+
+fn header(iface: &Iface) -> Vec<u8> {
+    let mut shb = Vec::new();
+    shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_ne_bytes());
+    shb.extend_from_slice(&1u16.to_ne_bytes());
+    shb.extend_from_slice(&0u16.to_ne_bytes());
+    // Unknown section length
+    shb.extend_from_slice(&(-1i64).to_ne_bytes());
+
+    let linktype = match iface.mode() {
+        Mode::Tun => LINKTYPE_RAW,
+        Mode::Tap => LINKTYPE_ETHERNET,
+    };
+    let mut idb = Vec::new();
+    idb.extend_from_slice(&linktype.to_ne_bytes());
+    idb.extend_from_slice(&0u16.to_ne_bytes());
+    // No snap length limit
+    idb.extend_from_slice(&0u32.to_ne_bytes());
+    option(&mut idb, IF_NAME, iface.name().as_bytes());
+    option(&mut idb, OPT_END, &[]);
+
+    let mut result = block(SECTION_HEADER_BLOCK, &shb);
+    result.extend_from_slice(&block(INTERFACE_DESCRIPTION_BLOCK, &idb));
+    result
+}
+
+fn packet_block(direction: Direction, packet: &[u8]) -> Vec<u8> {
+    let micros = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_micros() as u64)
+        .unwrap_or(0);
+    let mut epb = Vec::with_capacity(packet.len() + 40);
+    // Interface ID
+    epb.extend_from_slice(&0u32.to_ne_bytes());
+    epb.extend_from_slice(&((micros >> 32) as u32).to_ne_bytes());
+    epb.extend_from_slice(&(micros as u32).to_ne_bytes());
+    epb.extend_from_slice(&(packet.len() as u32).to_ne_bytes());
+    epb.extend_from_slice(&(packet.len() as u32).to_ne_bytes());
+    epb.extend_from_slice(packet);
+    epb.resize(epb.len() + padding(packet.len()), 0);
+    // From the kernel's point of view ‒ what we receive, it sent out.
+    let flags = match direction {
+        Direction::Received => EPB_OUTBOUND,
+        Direction::Sent => EPB_INBOUND,
+    };
+    option(&mut epb, EPB_FLAGS, &flags.to_ne_bytes());
+    option(&mut epb, OPT_END, &[]);
+    block(ENHANCED_PACKET_BLOCK, &epb)
+}
+
+#[derive(Debug)]
+struct Recorder<W> {
+    writer: W,
+    error: Option<Error>,
+}
+
+/// A wrapper around [`Iface`](../struct.Iface.html) recording all the traffic into a pcapng file.
+///
+/// The result can be opened in Wireshark (or other tools understanding the format). Each packet
+/// is recorded with a timestamp and the direction (packets received from the interface are marked
+/// as outbound, as the kernel sent them out, sent packets are marked as inbound). The packet info
+/// header, if present, is not recorded.
+///
+/// Failure to record a packet doesn't fail the send or receive itself. The first such error is
+/// kept and can be retrieved by [`take_error`](#method.take_error).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// # use tun_tap::capture::CaptureIface;
+/// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+/// let iface = CaptureIface::create(iface, "/tmp/mytun.pcapng").unwrap();
+/// let mut buffer = vec![0; 1504];
+/// iface.recv(&mut buffer).unwrap();
+/// iface.flush().unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CaptureIface<W: Write = BufWriter<File>> {
+    iface: Iface,
+    recorder: Mutex<Recorder<W>>,
+}
+
+impl CaptureIface {
+    /// Wraps the interface, recording into a newly created file.
+    ///
+    /// # Errors
+    ///
+    /// If the file can't be created or written to.
+    pub fn create<P: AsRef<Path>>(iface: Iface, path: P) -> Result<Self> {
+        let file = File::create(path)?;
+        Self::new(iface, BufWriter::new(file))
+    }
+}
+
+impl<W: Write> CaptureIface<W> {
+    /// Wraps the interface, recording into the given writer.
+    ///
+    /// The pcapng header is written right away.
+    ///
+    /// # Errors
+    ///
+    /// If writing the header fails.
+    pub fn new(iface: Iface, mut writer: W) -> Result<Self> {
+        writer.write_all(&header(&iface))?;
+        Ok(CaptureIface {
+            iface,
+            recorder: Mutex::new(Recorder {
+                writer,
+                error: None,
+            }),
+        })
+    }
+    fn record(&self, direction: Direction, packet: &[u8]) {
+        let packet = if self.iface.packet_info && packet.len() >= PacketInfo::SIZE {
+            &packet[PacketInfo::SIZE..]
+        } else {
+            packet
+        };
+        let block = packet_block(direction, packet);
+        let mut recorder = self.recorder.lock().unwrap_or_else(|e| e.into_inner());
+        if let Err(e) = recorder.writer.write_all(&block) {
+            debug!("Failed to record packet on {}: {}", self.iface.name(), e);
+            recorder.error.get_or_insert(e);
+        }
+    }
+    /// Receives a packet from the interface and records it.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        let size = self.iface.recv(buf)?;
+        self.record(Direction::Received, &buf[..size]);
+        Ok(size)
+    }
+    /// Sends a packet into the interface and records it.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        let size = self.iface.send(buf)?;
+        self.record(Direction::Sent, &buf[..size]);
+        Ok(size)
+    }
+    /// Flushes the recorded packets to the writer.
+    pub fn flush(&self) -> Result<()> {
+        self.recorder
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .writer
+            .flush()
+    }
+    /// Returns the first error that happened while recording, if any.
+    pub fn take_error(&self) -> Option<Error> {
+        self.recorder
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .error
+            .take()
+    }
+    /// Accesses the wrapped interface.
+    ///
+    /// Note that traffic passing directly through the interface is not recorded.
+    pub fn iface(&self) -> &Iface {
+        &self.iface
+    }
+    /// Unwraps the interface and the writer.
+    ///
+    /// The writer is not flushed.
+    pub fn into_inner(self) -> (Iface, W) {
+        let recorder = self
+            .recorder
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner());
+        (self.iface, recorder.writer)
+    }
+}
+
+impl<W: Write> AsRawFd for CaptureIface<W> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.iface.as_raw_fd()
+    }
+}
@@ -0,0 +1,84 @@
+//! Threads pumping packets between an interface and channels.
+//!
+//! See the [`Iface::into_channels`](../struct.Iface.html#method.into_channels) method.
+
+use std::io::Result;
+use std::ops::Deref;
+use std::sync::mpsc::{self, Receiver, SyncSender};
+use std::sync::Arc;
+use std::thread;
+
+use crate::{device, Iface, ShutdownHandle};
+
+/// The ends of the channels returned by
+/// [`Iface::into_channels`](struct.Iface.html#method.into_channels).
+pub type PacketChannels = (SyncSender<Vec<u8>>, PacketReceiver);
+
+/// The receiving end of [`Iface::into_channels`](struct.Iface.html#method.into_channels).
+///
+/// It dereferences to the `Receiver` of the channel and iterates over the received packets.
+/// Dropping it stops the receiving thread, even if no more packets come.
+#[derive(Debug)]
+pub struct PacketReceiver {
+    receiver: Receiver<Vec<u8>>,
+    shutdown: ShutdownHandle,
+}
+
+impl Deref for PacketReceiver {
+    type Target = Receiver<Vec<u8>>;
+    fn deref(&self) -> &Receiver<Vec<u8>> {
+        &self.receiver
+    }
+}
+
+impl Iterator for PacketReceiver {
+    type Item = Vec<u8>;
+    fn next(&mut self) -> Option<Vec<u8>> {
+        self.receiver.recv().ok()
+    }
+}
+
+impl Drop for PacketReceiver {
+    fn drop(&mut self) {
+        // Fails only on weird OS errors, there's nothing better to do about them anyway.
+        let _ = self.shutdown.shutdown();
+    }
+}
+
+fn pump_recv(iface: &Iface, sender: SyncSender<Vec<u8>>) {
+    // An error is already logged by the recv
+    let _ = device::pump(iface, None, |packet| {
+        let sent = sender.send(packet.clone()).is_ok();
+        if !sent {
+            debug!("Receiver of {} dropped, terminating", iface.name());
+        }
+        sent
+    });
+}
+
+fn pump_send(iface: &Iface, receiver: Receiver<Vec<u8>>) {
+    for packet in receiver {
+        // A failed packet is dropped, like on any other network. It is logged inside.
+        let _ = iface.send(&packet);
+    }
+}
+
+pub(crate) fn channels(iface: Iface, buffer: usize) -> Result<PacketChannels> {
+    let (to_iface, from_user) = mpsc::sync_channel(buffer);
+    let (to_user, from_iface) = mpsc::sync_channel(buffer);
+    let shutdown = iface.shutdown_handle()?;
+    let iface = Arc::new(iface);
+    let recv_iface = Arc::clone(&iface);
+    thread::Builder::new()
+        .name(format!("{}-recv", iface.name()))
+        .spawn(move || pump_recv(&recv_iface, to_user))?;
+    // From now on, dropping the receiver (even on an error) stops the receiving thread
+    let receiver = PacketReceiver {
+        receiver: from_iface,
+        shutdown,
+    };
+    thread::Builder::new()
+        .name(format!("{}-send", iface.name()))
+        .spawn(move || pump_send(&iface, from_user))?;
+    Ok((to_iface, receiver))
+}
@@ -0,0 +1,31 @@
+//! The internet checksum (RFC 1071), used by the IP, UDP, TCP and ICMP headers.
+
+/// Sums the data as 16-bit big-endian words, adding to the accumulator.
+///
+/// The accumulator doesn't overflow for anything up to the size of the largest IP packet.
+pub(crate) fn sum(data: &[u8], mut acc: u32) -> u32 {
+    let mut words = data.chunks_exact(2);
+    for word in &mut words {
+        acc += u32::from(u16::from_be_bytes([word[0], word[1]]));
+    }
+    if let [last] = words.remainder() {
+        acc += u32::from(*last) << 8;
+    }
+    acc
+}
+
+/// Folds the carries of the sum back into 16 bits.
+///
+/// This is the sum as the offloads expect it in the header. The finished checksum is its
+/// complement.
+pub(crate) fn fold(mut acc: u32) -> u16 {
+    while acc > 0xffff {
+        acc = (acc & 0xffff) + (acc >> 16);
+    }
+    acc as u16
+}
+
+/// Computes the complete checksum of the data.
+pub(crate) fn checksum(data: &[u8]) -> u16 {
+    !fold(sum(data, 0))
+}
@@ -0,0 +1,129 @@
+//! Tidying up the device when the interface is dropped.
+//!
+//! See the [`Cleanup`](enum.Cleanup.html).
+
+use std::ffi::CString;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::File;
+use std::io::Result;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::AsRawFd;
+use std::str::FromStr;
+
+use crate::ParseError;
+
+extern "C" {
+    fn tuntap_set_up(name: *const c_char, up: c_int) -> c_int;
+    fn tuntap_flush_ipv4(name: *const c_char) -> c_int;
+    fn tuntap_set_persist(fd: c_int, persist: c_int) -> c_int;
+}
+
+/// What happens to the device when the [`Iface`](struct.Iface.html) is dropped.
+///
+/// Each level does everything the previous ones do. It matters mostly for persistent devices and
+/// devices someone else still holds open, the others disappear on their own once closed.
+///
+/// Set by [`Iface::set_cleanup`](struct.Iface.html#method.set_cleanup) or the
+/// [`IfaceConfig::cleanup`](struct.IfaceConfig.html#structfield.cleanup). With the `serde`
+/// feature, it is written in the kebab case (eg. `"link-down"`).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "kebab-case"))]
+pub enum Cleanup {
+    /// The device is left as it is.
+    #[default]
+    Nothing,
+    /// The device is brought down.
+    ///
+    /// With the default `keep_addr_on_down` sysctl, the kernel removes the IPv6 addresses too.
+    LinkDown,
+    /// The device is brought down and all its IPv4 addresses are removed.
+    FlushAddresses,
+    /// The device is brought down, the addresses are removed and it stops being persistent.
+    ///
+    /// This removes the device once the last file descriptor of it is closed.
+    RemovePersistence,
+}
+
+impl Cleanup {
+    const NAMES: [(Cleanup, &'static str); 4] = [
+        (Cleanup::Nothing, "nothing"),
+        (Cleanup::LinkDown, "link-down"),
+        (Cleanup::FlushAddresses, "flush-addresses"),
+        (Cleanup::RemovePersistence, "remove-persistence"),
+    ];
+}
+
+/// Formats the level in the kebab case, the same as with `serde`.
+impl Display for Cleanup {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let (_, name) = Self::NAMES
+            .iter()
+            .find(|(cleanup, _)| cleanup == self)
+            .expect("All levels have a name");
+        fmt.write_str(name)
+    }
+}
+
+/// Parses the level in the kebab case (eg. `link-down`).
+impl FromStr for Cleanup {
+    type Err = ParseError;
+    fn from_str(s: &str) -> std::result::Result<Self, ParseError> {
+        Self::NAMES
+            .iter()
+            .find(|(_, name)| name.eq_ignore_ascii_case(s))
+            .map(|(cleanup, _)| *cleanup)
+            .ok_or_else(|| ParseError::new("cleanup", s))
+    }
+}
+
+/// Performs the cleanup once the last handle of the interface is dropped.
+///
+/// It holds its own copy of the file descriptor, to be able to remove the persistence even after
+/// the handle it was created with is gone.
+#[derive(Debug)]
+pub(crate) struct CleanupGuard {
+    fd: File,
+    name: String,
+    cleanup: Cleanup,
+}
+
+impl CleanupGuard {
+    pub(crate) fn new(fd: &File, name: &str, cleanup: Cleanup) -> Result<Self> {
+        Ok(CleanupGuard {
+            fd: fd.try_clone()?,
+            name: name.to_owned(),
+            cleanup,
+        })
+    }
+    pub(crate) fn cleanup(&self) -> Cleanup {
+        self.cleanup
+    }
+    fn step(&self, what: &str, result: c_int) {
+        if result < 0 {
+            let error = std::io::Error::last_os_error();
+            error!("Cleaning up {} failed at {}: {}", self.name, what, error);
+        }
+    }
+}
+
+impl Drop for CleanupGuard {
+    fn drop(&mut self) {
+        debug!("Cleaning up {} ({:?})", self.name, self.cleanup);
+        let name = match CString::new(self.name.as_str()) {
+            Ok(name) => name,
+            // Can't happen, the kernel accepted the name
+            Err(_) => return,
+        };
+        if self.cleanup >= Cleanup::LinkDown {
+            self.step("SIOCSIFFLAGS", unsafe { tuntap_set_up(name.as_ptr(), 0) });
+        }
+        if self.cleanup >= Cleanup::FlushAddresses {
+            self.step("SIOCSIFADDR", unsafe { tuntap_flush_ipv4(name.as_ptr()) });
+        }
+        if self.cleanup >= Cleanup::RemovePersistence {
+            let fd = self.fd.as_raw_fd();
+            self.step("TUNSETPERSIST", unsafe { tuntap_set_persist(fd, 0) });
+        }
+    }
+}
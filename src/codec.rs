@@ -0,0 +1,313 @@
+//! Codecs for the asynchronous interface.
+//!
+//! The [`TunPacketCodec`](struct.TunPacketCodec.html) turns the raw packets into
+//! [`TunPacket`](struct.TunPacket.html)s (and back), the
+//! [`EthernetFrameCodec`](struct.EthernetFrameCodec.html) does the same with
+//! [`EthernetFrame`](struct.EthernetFrame.html)s in the TAP mode. They implement the usual
+//! `Decoder` and `Encoder` traits, so they can be used with other codec-based code too.
+//!
+//! However, the generic framing adapters (like `tokio_codec::Framed`) treat the I/O object as a
+//! byte stream and may glue several encoded packets into a single write, which the interface
+//! then interprets as one (broken) packet. Therefore, use the [`Framed`](struct.Framed.html)
+//! provided here, which preserves the packet boundaries.
+extern crate bytes;
+extern crate futures;
+extern crate tokio_codec;
+
+use std::io::{Error, ErrorKind};
+
+use self::bytes::{Bytes, BytesMut};
+use self::futures::{try_ready, Async as FAsync, AsyncSink, Poll, Sink, StartSend, Stream};
+pub use self::tokio_codec::{Decoder, Encoder};
+
+use crate::packet::EthernetHeader;
+use crate::r#async::Async;
+use crate::{Device, EtherType, Iface, Mode, PacketInfo, Shutdown};
+
+fn strip_packet_info(buf: &mut BytesMut) -> Result<PacketInfo, Error> {
+    if buf.len() < PacketInfo::SIZE {
+        return Err(Error::new(
+            ErrorKind::InvalidData,
+            "Packet too short to contain the packet info",
+        ));
+    }
+    let header = buf.split_to(PacketInfo::SIZE);
+    Ok(PacketInfo::parse(&[header[0], header[1], header[2], header[3]]))
+}
+
+/// A packet together with its protocol.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TunPacket {
+    /// The protocol of the packet.
+    ///
+    /// If the interface runs without the packet info, this is guessed from the packet itself when
+    /// decoding and ignored when encoding.
+    pub proto: EtherType,
+    /// The packet itself, without the packet info header.
+    pub payload: Bytes,
+}
+
+impl TunPacket {
+    /// Creates a packet of the given protocol.
+    pub fn new<P: Into<Bytes>>(proto: EtherType, payload: P) -> Self {
+        TunPacket {
+            proto,
+            payload: payload.into(),
+        }
+    }
+}
+
+/// A codec between raw packets and [`TunPacket`](struct.TunPacket.html)s.
+///
+/// Each call to `decode` consumes the whole buffer as a single packet.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TunPacketCodec {
+    packet_info: bool,
+    mode: Mode,
+}
+
+impl TunPacketCodec {
+    /// Creates the codec.
+    ///
+    /// # Parameters
+    ///
+    /// * `packet_info`: If the interface has been created with the packet info (eg. by
+    ///   [`Iface::new`](../struct.Iface.html#method.new)).
+    /// * `mode`: The mode of the interface. It is used to guess the protocol when there's no
+    ///   packet info.
+    pub fn new(packet_info: bool, mode: Mode) -> Self {
+        TunPacketCodec { packet_info, mode }
+    }
+    fn guess_proto(&self, packet: &[u8]) -> EtherType {
+        match self.mode {
+            Mode::Tun => match packet.first().map(|b| b >> 4) {
+                Some(4) => EtherType::Ipv4,
+                Some(6) => EtherType::Ipv6,
+                _ => EtherType::Other(0),
+            },
+            Mode::Tap if packet.len() >= 14 => u16::from_be_bytes([packet[12], packet[13]]).into(),
+            Mode::Tap => EtherType::Other(0),
+        }
+    }
+}
+
+impl Decoder for TunPacketCodec {
+    type Item = TunPacket;
+    type Error = Error;
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<TunPacket>, Error> {
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        let proto = if self.packet_info {
+            strip_packet_info(buf)?.proto
+        } else {
+            self.guess_proto(buf)
+        };
+        Ok(Some(TunPacket::new(proto, buf.take().freeze())))
+    }
+}
+
+impl Encoder for TunPacketCodec {
+    type Item = TunPacket;
+    type Error = Error;
+    fn encode(&mut self, item: TunPacket, dst: &mut BytesMut) -> Result<(), Error> {
+        if self.packet_info {
+            dst.reserve(PacketInfo::SIZE + item.payload.len());
+            dst.extend_from_slice(&PacketInfo::new(item.proto).to_bytes());
+        }
+        dst.extend_from_slice(&item.payload);
+        Ok(())
+    }
+}
+
+/// An ethernet frame, split into the header and payload.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EthernetFrame {
+    /// The header of the frame.
+    pub header: EthernetHeader,
+    /// The payload (eg. an IP packet), without the header.
+    pub payload: Bytes,
+}
+
+/// A codec between raw frames of a TAP interface and [`EthernetFrame`](struct.EthernetFrame.html)s.
+///
+/// Each call to `decode` consumes the whole buffer as a single frame.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EthernetFrameCodec {
+    packet_info: bool,
+}
+
+impl EthernetFrameCodec {
+    /// Creates the codec.
+    ///
+    /// The `packet_info` is if the interface has been created with the packet info (eg. by
+    /// [`Iface::new`](../struct.Iface.html#method.new)).
+    pub fn new(packet_info: bool) -> Self {
+        EthernetFrameCodec { packet_info }
+    }
+}
+
+impl Decoder for EthernetFrameCodec {
+    type Item = EthernetFrame;
+    type Error = Error;
+    fn decode(&mut self, buf: &mut BytesMut) -> Result<Option<EthernetFrame>, Error> {
+        if buf.is_empty() {
+            return Ok(None);
+        }
+        if self.packet_info {
+            strip_packet_info(buf)?;
+        }
+        let header = match EthernetHeader::parse(buf) {
+            Some((header, _)) => header,
+            None => {
+                return Err(Error::new(
+                    ErrorKind::InvalidData,
+                    "Frame too short to contain the ethernet header",
+                ))
+            },
+        };
+        buf.advance(EthernetHeader::SIZE);
+        Ok(Some(EthernetFrame {
+            header,
+            payload: buf.take().freeze(),
+        }))
+    }
+}
+
+impl Encoder for EthernetFrameCodec {
+    type Item = EthernetFrame;
+    type Error = Error;
+    fn encode(&mut self, item: EthernetFrame, dst: &mut BytesMut) -> Result<(), Error> {
+        dst.reserve(PacketInfo::SIZE + EthernetHeader::SIZE + item.payload.len());
+        if self.packet_info {
+            let info = PacketInfo::new(item.header.ether_type);
+            dst.extend_from_slice(&info.to_bytes());
+        }
+        dst.extend_from_slice(&item.header.to_bytes());
+        dst.extend_from_slice(&item.payload);
+        Ok(())
+    }
+}
+
+/// A `Stream + Sink` of packets, encoded and decoded with a codec.
+///
+/// Unlike the generic framing adapters, this makes sure each decoded item comes from exactly one
+/// packet and each encoded item is sent as exactly one packet.
+///
+/// The stream ends once the interface is [shut down](../struct.ShutdownHandle.html).
+///
+/// For the common case of [`TunPacket`](struct.TunPacket.html)s, there's the shorthand of
+/// [`Async::framed`](../async/struct.Async.html#method.framed).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate futures;
+/// # extern crate tokio_core;
+/// # extern crate tun_tap;
+/// # use futures::Stream;
+/// # use tun_tap::*;
+/// # use tun_tap::codec::*;
+/// # use tun_tap::r#async::Async;
+/// # use tokio_core::reactor::Core;
+/// # fn main() {
+/// let iface = Iface::new("mytap%d", Mode::Tap).unwrap();
+/// let core = Core::new().unwrap();
+/// let iface = Async::new(iface, &core.handle()).unwrap();
+/// let framed = Framed::new(iface, EthernetFrameCodec::new(true));
+/// let (sink, stream) = framed.split();
+/// # }
+/// ```
+pub struct Framed<C, D = Iface> {
+    iface: Async<D>,
+    codec: C,
+    read_buf: BytesMut,
+    write_buf: BytesMut,
+}
+
+impl<C, D: Device> Framed<C, D> {
+    /// Wraps the interface with the codec.
+    pub fn new(iface: Async<D>, codec: C) -> Self {
+        Framed {
+            iface,
+            codec,
+            read_buf: BytesMut::new(),
+            write_buf: BytesMut::new(),
+        }
+    }
+    /// Accesses the interface.
+    pub fn get_ref(&self) -> &Async<D> {
+        &self.iface
+    }
+    /// Accesses the interface mutably.
+    ///
+    /// Reading or writing directly through it may confuse the codec.
+    pub fn get_mut(&mut self) -> &mut Async<D> {
+        &mut self.iface
+    }
+    /// Accesses the codec.
+    pub fn codec(&self) -> &C {
+        &self.codec
+    }
+    /// Accesses the codec mutably.
+    pub fn codec_mut(&mut self) -> &mut C {
+        &mut self.codec
+    }
+    /// Unwraps the interface.
+    ///
+    /// A packet waiting to be sent is lost.
+    pub fn into_inner(self) -> Async<D> {
+        self.iface
+    }
+    fn poll_flush(&mut self) -> Poll<(), Error> {
+        if !self.write_buf.is_empty() {
+            // TODO What to do about short write? Can it happen?
+            try_ready!(self.iface.poll_send(&self.write_buf));
+            self.write_buf.clear();
+        }
+        Ok(FAsync::Ready(()))
+    }
+}
+
+impl<C: Decoder, D: Device> Stream for Framed<C, D> {
+    type Item = C::Item;
+    type Error = C::Error;
+    fn poll(&mut self) -> Poll<Option<C::Item>, C::Error> {
+        loop {
+            let bufsize = self.iface.recv_bufsize();
+            self.read_buf.clear();
+            if self.read_buf.capacity() < bufsize {
+                // Reclaims the old buffer if all the packets from it are gone.
+                self.read_buf.reserve(bufsize * self.iface.recv_pool_size());
+            }
+            self.read_buf.resize(bufsize, 0);
+            let size = match self.iface.poll_recv(&mut self.read_buf) {
+                Err(ref e) if Shutdown::is_shutdown(e) => return Ok(FAsync::Ready(None)),
+                result => try_ready!(result),
+            };
+            self.iface.update_bufsize(size);
+            self.read_buf.truncate(size);
+            // A decoder may decide to skip a packet by returning None.
+            if let Some(item) = self.codec.decode(&mut self.read_buf)? {
+                return Ok(FAsync::Ready(Some(item)));
+            }
+        }
+    }
+}
+
+impl<C: Encoder, D: Device> Sink for Framed<C, D> {
+    type SinkItem = C::Item;
+    type SinkError = C::Error;
+    fn start_send(&mut self, item: C::Item) -> StartSend<C::Item, C::Error> {
+        if self.poll_flush()?.is_not_ready() {
+            return Ok(AsyncSink::NotReady(item));
+        }
+        self.codec.encode(item, &mut self.write_buf)?;
+        // Try sending it right away, but if it doesn't work, it'll be finished in poll_complete.
+        self.poll_flush()?;
+        Ok(AsyncSink::Ready)
+    }
+    fn poll_complete(&mut self) -> Poll<(), C::Error> {
+        Ok(self.poll_flush()?)
+    }
+}
@@ -0,0 +1,86 @@
+//! Description of an interface to create.
+//!
+//! See the [`IfaceConfig`](struct.IfaceConfig.html) structure.
+
+use crate::{Cleanup, Error, Iface, Mode, PendingIface, TunFlags};
+
+/// A description of an interface, for creating it later.
+///
+/// With the `serde` feature, this can be deserialized (eg. from a configuration file of a
+/// daemon). The `mode` is written as `"tun"` or `"tap"` and the `packet_info` can be left out
+/// (it defaults to `true`, like with [`Iface::new`](struct.Iface.html#method.new)), as can be
+/// the `one_queue` (defaulting to `false`) and the `cleanup` (defaulting to `"nothing"`).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// let config = IfaceConfig::new("mytun%d", Mode::Tun);
+/// let iface = config.create().expect("Failed to create a TUN device");
+/// ```
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct IfaceConfig {
+    /// The requested name (see [`Iface::new`](struct.Iface.html#method.new)).
+    pub name: String,
+    /// The mode of the interface.
+    pub mode: Mode,
+    /// If the packets are prefixed by the packet info header.
+    #[cfg_attr(feature = "serde", serde(default = "default_packet_info"))]
+    pub packet_info: bool,
+    /// Asks for the legacy [`ONE_QUEUE`](struct.TunFlags.html#associatedconstant.ONE_QUEUE)
+    /// flag.
+    ///
+    /// Old kernels queue the packets both in the device and in the qdisc above it, so a slow
+    /// reader sees a lot of buffering. The flag turns the inner queue off. It is set only if the
+    /// kernel accepts it (current kernels accept and ignore it), otherwise the device is created
+    /// without it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub one_queue: bool,
+    /// What happens to the device when the interface is dropped (see
+    /// [`Iface::set_cleanup`](struct.Iface.html#method.set_cleanup)).
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub cleanup: Cleanup,
+}
+
+#[cfg(feature = "serde")]
+fn default_packet_info() -> bool {
+    true
+}
+
+impl IfaceConfig {
+    /// Creates the description, with the packet info.
+    pub fn new(name: &str, mode: Mode) -> Self {
+        IfaceConfig {
+            name: name.to_owned(),
+            mode,
+            packet_info: true,
+            one_queue: false,
+            cleanup: Cleanup::Nothing,
+        }
+    }
+    /// Creates the described interface.
+    ///
+    /// # Errors
+    ///
+    /// The same as with [`Iface::new`](struct.Iface.html#method.new).
+    pub fn create(&self) -> Result<Iface, Error> {
+        let pending = PendingIface::open()?;
+        let mut flags = TunFlags::from(self.mode);
+        if !self.packet_info {
+            flags |= TunFlags::NO_PI;
+        }
+        if self.one_queue {
+            match pending.supported_flags() {
+                Ok(supported) if supported.contains(TunFlags::ONE_QUEUE) => {
+                    flags |= TunFlags::ONE_QUEUE
+                },
+                Ok(_) => debug!("ONE_QUEUE not supported, creating {} without it", self.name),
+                Err(e) => debug!("Can't check ONE_QUEUE for {}: {}", self.name, e),
+            }
+        }
+        let mut iface = pending.finish_with_flags(&self.name, flags)?;
+        iface.set_cleanup(self.cleanup)?;
+        Ok(iface)
+    }
+}
@@ -0,0 +1,133 @@
+//! Configuration undone when no longer needed.
+//!
+//! See the [`ConfiguredIface`](struct.ConfiguredIface.html).
+
+use std::io::Result;
+use std::net::Ipv4Addr;
+
+use crate::{IffFlags, Iface};
+
+/// A change done to the device, with what is needed to undo it.
+#[derive(Debug)]
+enum Change {
+    /// The link state before.
+    Up(bool),
+    /// The MTU before.
+    Mtu(usize),
+    /// The address before.
+    Ipv4(Option<(Ipv4Addr, u8)>),
+    /// An added route.
+    Route(Ipv4Addr, u8),
+}
+
+/// An interface reverting its configuration when dropped.
+///
+/// The configuration methods are the same as on the [`Iface`](struct.Iface.html), but each change
+/// is recorded (with the previous state) and undone, in the reverse order, once this is dropped.
+/// That happens even when the thread panics midway, so examples and tests can run repeatedly on
+/// persistent devices without leaving a half-configured device behind.
+///
+/// Reverting is best effort ‒ failures are only logged. Changes done through
+/// [`get_ref`](#method.get_ref) or by someone else are not tracked.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::net::Ipv4Addr;
+/// # use tun_tap::*;
+/// let iface = Iface::new("vpn0", Mode::Tun).expect("Failed to create the device");
+/// let mut configured = ConfiguredIface::new(iface);
+/// configured.set_ipv4(Ipv4Addr::new(10, 0, 0, 1), 24).unwrap();
+/// configured.set_up(true).unwrap();
+/// configured.add_route(Ipv4Addr::new(10, 1, 0, 0), 16).unwrap();
+/// // Use it, the device is brought down and the address and route removed at the end
+/// ```
+#[derive(Debug)]
+pub struct ConfiguredIface {
+    iface: Option<Iface>,
+    changes: Vec<Change>,
+}
+
+impl ConfiguredIface {
+    /// Starts tracking the configuration of the device.
+    pub fn new(iface: Iface) -> Self {
+        ConfiguredIface {
+            iface: Some(iface),
+            changes: Vec::new(),
+        }
+    }
+    /// Brings the interface up or down.
+    ///
+    /// See [`Iface::set_up`](struct.Iface.html#method.set_up).
+    pub fn set_up(&mut self, up: bool) -> Result<()> {
+        let was_up = self.get_ref().iff_flags()?.contains(IffFlags::UP);
+        self.changes.push(Change::Up(was_up));
+        self.get_ref().set_up(up)
+    }
+    /// Sets the MTU.
+    ///
+    /// See [`Iface::set_mtu`](struct.Iface.html#method.set_mtu).
+    pub fn set_mtu(&mut self, mtu: usize) -> Result<()> {
+        let previous = self.get_ref().mtu()?;
+        self.changes.push(Change::Mtu(previous));
+        self.get_ref().set_mtu(mtu)
+    }
+    /// Sets the IPv4 address.
+    ///
+    /// The previous address (if any) is put back in the end. See
+    /// [`Iface::set_ipv4`](struct.Iface.html#method.set_ipv4).
+    pub fn set_ipv4(&mut self, address: Ipv4Addr, prefix: u8) -> Result<()> {
+        let previous = self.get_ref().ipv4()?;
+        // Recorded even on failure, the address may be set without the netmask
+        self.changes.push(Change::Ipv4(previous));
+        self.get_ref().set_ipv4(address, prefix)
+    }
+    /// Routes the network through the interface.
+    ///
+    /// See [`Iface::add_route`](struct.Iface.html#method.add_route).
+    pub fn add_route(&mut self, destination: Ipv4Addr, prefix: u8) -> Result<()> {
+        self.get_ref().add_route(destination, prefix)?;
+        self.changes.push(Change::Route(destination, prefix));
+        Ok(())
+    }
+    /// Returns the device.
+    pub fn get_ref(&self) -> &Iface {
+        self.iface.as_ref().expect("Interface taken only by into_inner")
+    }
+    /// Returns the device.
+    pub fn get_mut(&mut self) -> &mut Iface {
+        self.iface.as_mut().expect("Interface taken only by into_inner")
+    }
+    /// Returns the device, keeping the configuration.
+    pub fn into_inner(mut self) -> Iface {
+        self.changes.clear();
+        self.iface.take().expect("Interface taken only by into_inner")
+    }
+}
+
+impl Drop for ConfiguredIface {
+    fn drop(&mut self) {
+        let iface = match &self.iface {
+            Some(iface) => iface,
+            None => return,
+        };
+        while let Some(change) = self.changes.pop() {
+            let result = match change {
+                Change::Up(up) => iface.set_up(up),
+                Change::Mtu(mtu) => iface.set_mtu(mtu),
+                Change::Ipv4(Some((address, prefix))) => iface.set_ipv4(address, prefix),
+                Change::Ipv4(None) => iface.set_ipv4(Ipv4Addr::UNSPECIFIED, 0),
+                // Bringing the device down removes it too
+                Change::Route(destination, prefix) => {
+                    match iface.remove_route(destination, prefix) {
+                        Err(ref e) if e.raw_os_error() == Some(libc::ESRCH) => Ok(()),
+                        result => result,
+                    }
+                },
+            };
+            if let Err(e) = result {
+                debug!("Failed to revert {:?} on {}: {}", change, iface.name(), e);
+            }
+        }
+    }
+}
@@ -0,0 +1,158 @@
+//! Dispatching packets by protocol.
+//!
+//! See the [`Demux`](struct.Demux.html) structure.
+extern crate futures;
+
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+
+use self::futures::sync::mpsc::{self, UnboundedReceiver};
+use self::futures::{Future, Stream};
+
+use crate::codec::TunPacket;
+use crate::EtherType;
+
+/// What a handler is registered for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Key {
+    /// All packets of the given protocol.
+    EtherType(EtherType),
+    /// IPv4 and IPv6 packets carrying the given IP protocol (eg. 1 for ICMP, 6 for TCP, 17 for
+    /// UDP).
+    ///
+    /// For IPv6, only the next header field of the fixed header is considered, extension headers
+    /// are not followed.
+    IpProtocol(u8),
+}
+
+type Handler = Box<dyn FnMut(TunPacket) + Send>;
+
+fn ip_protocol(packet: &TunPacket) -> Option<u8> {
+    match packet.proto {
+        EtherType::Ipv4 => packet.payload.get(9).cloned(),
+        EtherType::Ipv6 => packet.payload.get(6).cloned(),
+        _ => None,
+    }
+}
+
+/// A dispatcher of packets to handlers registered by protocol.
+///
+/// A single reader (eg. [`run`](#method.run) on the stream of a
+/// [`Framed`](../codec/struct.Framed.html)) feeds the packets in and each is passed to the most
+/// specific matching handler ‒ the one registered for the [`IpProtocol`](enum.Key.html), then the
+/// one for the [`EtherType`](enum.Key.html) and then the fallback one. Packets without any
+/// matching handler are dropped.
+///
+/// Instead of a closure, a stream of the packets can be obtained with
+/// [`stream`](#method.stream).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # extern crate futures;
+/// # extern crate tokio_core;
+/// # extern crate tun_tap;
+/// # use futures::Stream;
+/// # use tun_tap::*;
+/// # use tun_tap::codec::*;
+/// # use tun_tap::demux::*;
+/// # use tun_tap::r#async::Async;
+/// # use tokio_core::reactor::Core;
+/// # fn main() {
+/// let iface = Iface::new("mytun%d", Mode::Tun).unwrap();
+/// let mut core = Core::new().unwrap();
+/// let iface = Async::new(iface, &core.handle()).unwrap();
+/// let (_sink, stream) = Framed::new(iface, TunPacketCodec::new(true, Mode::Tun)).split();
+/// let mut demux = Demux::new();
+/// demux.register(Key::IpProtocol(1), |packet| println!("ICMP: {:?}", packet));
+/// let ipv6 = demux.stream(Key::EtherType(EtherType::Ipv6));
+/// core.handle().spawn(ipv6.for_each(|packet| {
+///     println!("IPv6: {:?}", packet);
+///     Ok(())
+/// }));
+/// core.run(demux.run(stream)).unwrap();
+/// # }
+/// ```
+#[derive(Default)]
+pub struct Demux {
+    handlers: HashMap<Key, Handler>,
+    fallback: Option<Handler>,
+}
+
+impl Demux {
+    /// Creates a demultiplexer with no handlers.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Registers a handler for the given key.
+    ///
+    /// A previous handler for the same key is replaced.
+    pub fn register<F>(&mut self, key: Key, handler: F)
+    where
+        F: FnMut(TunPacket) + Send + 'static,
+    {
+        self.handlers.insert(key, Box::new(handler));
+    }
+    /// Registers a handler for packets not matched by any other handler.
+    pub fn fallback<F>(&mut self, handler: F)
+    where
+        F: FnMut(TunPacket) + Send + 'static,
+    {
+        self.fallback = Some(Box::new(handler));
+    }
+    /// Registers a channel for the given key and returns its receiving end.
+    ///
+    /// The channel is unbounded, so the consumer needs to keep up with the traffic. Once the
+    /// receiver is dropped, the matching packets are dropped too.
+    pub fn stream(&mut self, key: Key) -> UnboundedReceiver<TunPacket> {
+        let (sender, receiver) = mpsc::unbounded();
+        self.register(key, move |packet| {
+            // The receiver might be gone, but that's not our problem.
+            let _ = sender.unbounded_send(packet);
+        });
+        receiver
+    }
+    /// Passes a single packet to the right handler.
+    ///
+    /// Returns if there was a handler for it.
+    pub fn dispatch(&mut self, packet: TunPacket) -> bool {
+        let handlers = &mut self.handlers;
+        let by_ether_type = Key::EtherType(packet.proto);
+        let key = ip_protocol(&packet)
+            .map(Key::IpProtocol)
+            .filter(|key| handlers.contains_key(key))
+            .or_else(|| Some(by_ether_type).filter(|key| handlers.contains_key(key)));
+        let handler = match key {
+            Some(key) => handlers.get_mut(&key),
+            None => self.fallback.as_mut(),
+        };
+        match handler {
+            Some(handler) => {
+                handler(packet);
+                true
+            },
+            None => false,
+        }
+    }
+    /// Dispatches all the packets from the stream.
+    ///
+    /// The returned future resolves once the stream ends or fails.
+    pub fn run<S>(mut self, stream: S) -> impl Future<Item = (), Error = S::Error>
+    where
+        S: Stream<Item = TunPacket>,
+    {
+        stream.for_each(move |packet| {
+            self.dispatch(packet);
+            Ok(())
+        })
+    }
+}
+
+impl Debug for Demux {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.debug_struct("Demux")
+            .field("keys", &self.handlers.keys().collect::<Vec<_>>())
+            .field("fallback", &self.fallback.is_some())
+            .finish()
+    }
+}
@@ -0,0 +1,247 @@
+//! Abstraction over the packet devices.
+//!
+//! See the [`Device`](trait.Device.html) trait.
+
+use std::io::{Error, ErrorKind, Result};
+use std::os::raw::c_int;
+use std::os::unix::io::{AsRawFd, BorrowedFd};
+use std::time::Duration;
+
+use crate::packet::{EthernetHeader, VlanTag};
+use crate::shutdown::ShutdownPipe;
+use crate::{Iface, Mode, PacketInfo, Shutdown};
+
+/// The receive buffer size if the MTU can't be read, enough for the default one with all the
+/// headers.
+pub(crate) const BUFSIZE: usize = 1542;
+
+/// A device sending and receiving whole packets, like the [`Iface`](struct.Iface.html).
+///
+/// The code generic over it (eg. the [`Async`](async/struct.Async.html)) can run with something
+/// else in place of the real interface, like a mock in tests that can't create a TUN device.
+///
+/// The file descriptor must be pollable and each read and write on it must transfer exactly one
+/// packet (eg. one end of a `UnixDatagram` pair).
+///
+/// Application code written against this trait instead of the concrete `Iface` can be unit
+/// tested without the privileges needed to create a real device.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::io::Result;
+/// # use std::os::unix::io::{AsRawFd, RawFd};
+/// # use std::os::unix::net::UnixDatagram;
+/// # use tun_tap::*;
+/// struct MockDevice(UnixDatagram);
+///
+/// impl AsRawFd for MockDevice {
+///     fn as_raw_fd(&self) -> RawFd {
+///         self.0.as_raw_fd()
+///     }
+/// }
+///
+/// impl Device for MockDevice {
+///     fn name(&self) -> &str {
+///         "mock0"
+///     }
+///     fn mode(&self) -> Mode {
+///         Mode::Tun
+///     }
+///     fn packet_info(&self) -> bool {
+///         false
+///     }
+///     fn mtu(&self) -> Result<usize> {
+///         Ok(1500)
+///     }
+///     fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+///         self.0.recv(buf)
+///     }
+///     fn send(&self, buf: &[u8]) -> Result<usize> {
+///         self.0.send(buf)
+///     }
+///     fn set_non_blocking(&self) -> Result<()> {
+///         self.0.set_nonblocking(true)
+///     }
+/// }
+///
+/// // The code under test
+/// fn echo<D: Device>(device: &D) -> Result<()> {
+///     let mut buffer = vec![0; device.mtu()?];
+///     let size = device.recv(&mut buffer)?;
+///     device.send(&buffer[..size])?;
+///     Ok(())
+/// }
+///
+/// let (device, peer) = UnixDatagram::pair().unwrap();
+/// peer.send(b"\x45hello").unwrap();
+/// echo(&MockDevice(device)).unwrap();
+/// let mut buffer = [0; 10];
+/// let size = peer.recv(&mut buffer).unwrap();
+/// assert_eq!(b"\x45hello", &buffer[..size]);
+/// ```
+pub trait Device: AsRawFd {
+    /// Returns the name of the interface.
+    fn name(&self) -> &str;
+    /// Returns the mode of the interface.
+    fn mode(&self) -> Mode;
+    /// Returns if the packets carry the packet info header.
+    fn packet_info(&self) -> bool;
+    /// Returns the MTU of the interface.
+    ///
+    /// See [`Iface::mtu`](struct.Iface.html#method.mtu).
+    fn mtu(&self) -> Result<usize>;
+    /// Returns the size of the largest packet the device can produce.
+    ///
+    /// See [`Iface::max_frame_size`](struct.Iface.html#method.max_frame_size).
+    fn max_frame_size(&self) -> Result<usize> {
+        Ok(max_frame_size(self.mode(), self.packet_info(), self.mtu()?))
+    }
+    /// Receives a single packet.
+    ///
+    /// See [`Iface::recv`](struct.Iface.html#method.recv).
+    fn recv(&self, buf: &mut [u8]) -> Result<usize>;
+    /// Sends a single packet.
+    ///
+    /// See [`Iface::send`](struct.Iface.html#method.send).
+    fn send(&self, buf: &[u8]) -> Result<usize>;
+    /// Switches the device to the non-blocking mode.
+    fn set_non_blocking(&self) -> Result<()>;
+    /// A file descriptor becoming readable once the device is shut down.
+    ///
+    /// This lets the asynchronous wrappers wake up the waiting tasks on a shutdown. The default
+    /// is a device that can't be shut down.
+    fn shutdown_fd(&self) -> Option<BorrowedFd<'_>> {
+        None
+    }
+}
+
+/// The MTU plus the headers in front of the packet.
+pub(crate) fn max_frame_size(mode: Mode, packet_info: bool, mtu: usize) -> usize {
+    let link = match mode {
+        Mode::Tun => 0,
+        Mode::Tap => EthernetHeader::SIZE + VlanTag::SIZE,
+    };
+    let info = if packet_info { PacketInfo::SIZE } else { 0 };
+    mtu + link + info
+}
+
+/// The size of the receive buffer to use after a packet of `received` bytes came into one of
+/// `bufsize`.
+///
+/// A packet filling the whole buffer may have been truncated because the MTU was raised (eg. to
+/// jumbo frames), so the MTU is read again then.
+pub(crate) fn refresh_bufsize<D>(device: &D, bufsize: usize, received: usize) -> usize
+where
+    D: Device + ?Sized,
+{
+    if received < bufsize {
+        return bufsize;
+    }
+    match device.max_frame_size() {
+        Ok(size) if size != bufsize => {
+            debug!("Receive buffer of {} resized to {}", device.name(), size);
+            size
+        },
+        // Logged inside, if it's a real iface
+        _ => bufsize,
+    }
+}
+
+/// Receives packets from the device in a loop and passes each to the `handle` callback.
+///
+/// Waits through the `shutdown` pipe if there's one, otherwise the device itself needs to return
+/// the [`Shutdown`](struct.Shutdown.html) error. The callback may change the packet (or take it
+/// away) and stops the loop by returning false. The buffer follows the MTU of the device.
+///
+/// Returns `Ok` once stopped by the callback or the shutdown, the error of the device otherwise.
+pub(crate) fn pump<D, F>(device: &D, shutdown: Option<&ShutdownPipe>, mut handle: F) -> Result<()>
+where
+    D: Device + ?Sized,
+    F: FnMut(&mut Vec<u8>) -> bool,
+{
+    let mut bufsize = device.max_frame_size().unwrap_or(BUFSIZE);
+    let mut buffer = vec![0; bufsize];
+    loop {
+        let received = match shutdown {
+            Some(pipe) => pipe.wait_readable(device.as_raw_fd()),
+            None => Ok(()),
+        };
+        match received.and_then(|()| device.recv(&mut buffer)) {
+            Ok(size) => {
+                buffer.truncate(size);
+                if !handle(&mut buffer) {
+                    return Ok(());
+                }
+                bufsize = refresh_bufsize(device, bufsize, size);
+                buffer.resize(bufsize, 0);
+            },
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(ref e) if Shutdown::is_shutdown(e) => return Ok(()),
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/// Waits until the device is readable (or shut down), up to the timeout.
+///
+/// Returns false on timeout.
+pub(crate) fn poll_readable<D>(device: &D, timeout: Option<Duration>) -> Result<bool>
+where
+    D: Device + ?Sized,
+{
+    let poll_fd = |fd| libc::pollfd {
+        fd,
+        events: libc::POLLIN,
+        revents: 0,
+    };
+    // A negative fd is ignored by poll
+    let wake = device.shutdown_fd().map_or(-1, |fd| fd.as_raw_fd());
+    let mut fds = [poll_fd(device.as_raw_fd()), poll_fd(wake)];
+    let timeout = match timeout {
+        // Rounded up, not to spin on sub-millisecond timeouts
+        Some(timeout) => {
+            let millis = timeout.as_nanos().div_ceil(1_000_000);
+            millis.min(c_int::MAX as u128) as c_int
+        },
+        None => -1,
+    };
+    match unsafe { libc::poll(fds.as_mut_ptr(), 2, timeout) } {
+        -1 => {
+            let error = Error::last_os_error();
+            if error.kind() == ErrorKind::Interrupted {
+                Ok(true)
+            } else {
+                Err(error)
+            }
+        },
+        ready => Ok(ready > 0),
+    }
+}
+
+impl Device for Iface {
+    fn name(&self) -> &str {
+        Iface::name(self)
+    }
+    fn mode(&self) -> Mode {
+        Iface::mode(self)
+    }
+    fn packet_info(&self) -> bool {
+        Iface::packet_info(self)
+    }
+    fn mtu(&self) -> Result<usize> {
+        Iface::mtu(self)
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        Iface::recv(self, buf)
+    }
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        Iface::send(self, buf)
+    }
+    fn set_non_blocking(&self) -> Result<()> {
+        Iface::set_non_blocking(self)
+    }
+    fn shutdown_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.shutdown.get().map(|pipe| pipe.wake_fd())
+    }
+}
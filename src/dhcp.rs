@@ -0,0 +1,445 @@
+//! A tiny DHCP server for TAP test networks.
+//!
+//! Virtual machines and containers plugged into a TAP device usually want to get their address
+//! by DHCP. The [`DhcpServer`](struct.DhcpServer.html) hands out the addresses from a single
+//! range, so a test environment doesn't have to run a full DHCP server (like `dnsmasq`).
+//!
+//! It is not meant for anything else than tests ‒ the leases are kept in memory only and there's
+//! no support for relays, static assignments or most of the options.
+//!
+//! This is available with the `dhcp` feature.
+
+use std::collections::HashMap;
+use std::io::{ErrorKind, Result};
+use std::net::Ipv4Addr;
+use std::ops::RangeInclusive;
+use std::time::{Duration, Instant};
+
+use crate::arp::ArpResponder;
+use crate::checksum::{checksum, fold, sum};
+use crate::packet::{EthernetHeader, MacAddr};
+use crate::{Device, EtherType, Mode, PacketInfo, Shutdown};
+
+const UDP: u8 = 17;
+const SERVER_PORT: u16 = 67;
+const CLIENT_PORT: u16 = 68;
+const IP_HEADER: usize = 20;
+const UDP_HEADER: usize = 8;
+/// The fixed part of the message, up to and including the magic cookie.
+const BOOTP_SIZE: usize = 240;
+/// Some clients don't accept shorter messages (the original BOOTP size).
+const MIN_BOOTP_SIZE: usize = 300;
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+const BOOT_REQUEST: u8 = 1;
+const BOOT_REPLY: u8 = 2;
+const BROADCAST_FLAG: u16 = 0x8000;
+
+const OPT_PAD: u8 = 0;
+const OPT_NETMASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_ID: u8 = 54;
+const OPT_END: u8 = 255;
+
+const DISCOVER: u8 = 1;
+const OFFER: u8 = 2;
+const REQUEST: u8 = 3;
+const DECLINE: u8 = 4;
+const ACK: u8 = 5;
+const NAK: u8 = 6;
+const RELEASE: u8 = 7;
+
+#[derive(Copy, Clone, Debug)]
+struct Lease {
+    address: Ipv4Addr,
+    expires: Instant,
+}
+
+/// The interesting parts of a client message.
+struct Message<'a> {
+    /// The fixed part, up to the magic cookie.
+    fixed: &'a [u8],
+    message_type: u8,
+    requested: Option<Ipv4Addr>,
+    server_id: Option<Ipv4Addr>,
+}
+
+impl<'a> Message<'a> {
+    fn parse(bootp: &'a [u8]) -> Option<Self> {
+        if bootp.len() < BOOTP_SIZE
+            || bootp[0] != BOOT_REQUEST
+            || bootp[1] != 1
+            || bootp[2] != 6
+            || bootp[236..240] != MAGIC_COOKIE
+        {
+            return None;
+        }
+        let mut message = Message {
+            fixed: &bootp[..BOOTP_SIZE],
+            message_type: 0,
+            requested: None,
+            server_id: None,
+        };
+        let mut options = &bootp[BOOTP_SIZE..];
+        while let Some((&code, rest)) = options.split_first() {
+            match code {
+                OPT_PAD => {
+                    options = rest;
+                    continue;
+                },
+                OPT_END => break,
+                _ => (),
+            }
+            let (&len, rest) = rest.split_first()?;
+            let value = rest.get(..usize::from(len))?;
+            match (code, value) {
+                (OPT_MESSAGE_TYPE, &[message_type]) => message.message_type = message_type,
+                (OPT_REQUESTED_IP, &[a, b, c, d]) => {
+                    message.requested = Some(Ipv4Addr::new(a, b, c, d));
+                },
+                (OPT_SERVER_ID, &[a, b, c, d]) => {
+                    message.server_id = Some(Ipv4Addr::new(a, b, c, d));
+                },
+                _ => (),
+            }
+            options = &rest[usize::from(len)..];
+        }
+        Some(message)
+    }
+    fn mac(&self) -> MacAddr {
+        let mut mac = [0; 6];
+        mac.copy_from_slice(&self.fixed[28..34]);
+        MacAddr(mac)
+    }
+    fn ciaddr(&self) -> Ipv4Addr {
+        Ipv4Addr::new(self.fixed[12], self.fixed[13], self.fixed[14], self.fixed[15])
+    }
+    fn broadcast(&self) -> bool {
+        u16::from_be_bytes([self.fixed[10], self.fixed[11]]) & BROADCAST_FLAG != 0
+    }
+}
+
+/// Hands out addresses from a range to the DHCP clients on a TAP device.
+///
+/// Feed it the frames received from a TAP device by [`respond`](#method.respond), or let it
+/// [`run`](#method.run) on its own. It pretends to be a host with the given address and MAC
+/// address on the device's network (the kernel side of the device should have a different
+/// address, or none at all).
+///
+/// Each client (by its MAC address) gets one address from the range and keeps it across renewals
+/// while the lease lasts.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::net::Ipv4Addr;
+/// # use tun_tap::*;
+/// # use tun_tap::dhcp::DhcpServer;
+/// # use tun_tap::packet::MacAddr;
+/// let iface = Iface::without_packet_info("mytap", Mode::Tap).expect("Failed to create a device");
+/// // Bridge the device with the VM's network interface
+/// let range = Ipv4Addr::new(10, 0, 0, 100)..=Ipv4Addr::new(10, 0, 0, 200);
+/// let mut dhcp = DhcpServer::new(Ipv4Addr::new(10, 0, 0, 1), MacAddr([2, 0, 0, 0, 0, 1]), range)
+///     .router(Ipv4Addr::new(10, 0, 0, 1));
+/// dhcp.run(&iface).expect("DHCP failed");
+/// ```
+#[derive(Clone, Debug)]
+pub struct DhcpServer {
+    address: Ipv4Addr,
+    mac: MacAddr,
+    range: RangeInclusive<u32>,
+    netmask: Ipv4Addr,
+    router: Option<Ipv4Addr>,
+    dns: Vec<Ipv4Addr>,
+    lease_time: Duration,
+    leases: HashMap<MacAddr, Lease>,
+}
+
+impl DhcpServer {
+    /// Creates a server at the `address` and `mac`, handing out addresses from the `range`.
+    ///
+    /// The netmask is `255.255.255.0` and the leases last for an hour by default. No router nor
+    /// DNS servers are announced.
+    pub fn new(address: Ipv4Addr, mac: MacAddr, range: RangeInclusive<Ipv4Addr>) -> Self {
+        DhcpServer {
+            address,
+            mac,
+            range: u32::from(*range.start())..=u32::from(*range.end()),
+            netmask: Ipv4Addr::new(255, 255, 255, 0),
+            router: None,
+            dns: Vec::new(),
+            lease_time: Duration::from_secs(3600),
+            leases: HashMap::new(),
+        }
+    }
+    /// Sets the netmask of the network.
+    pub fn netmask(self, netmask: Ipv4Addr) -> Self {
+        DhcpServer { netmask, ..self }
+    }
+    /// Announces a default router.
+    pub fn router(self, router: Ipv4Addr) -> Self {
+        DhcpServer {
+            router: Some(router),
+            ..self
+        }
+    }
+    /// Announces the DNS servers.
+    pub fn dns<I: IntoIterator<Item = Ipv4Addr>>(self, dns: I) -> Self {
+        DhcpServer {
+            dns: dns.into_iter().collect(),
+            ..self
+        }
+    }
+    /// Sets how long the leases last.
+    ///
+    /// The duration is rounded down to whole seconds.
+    pub fn lease_time(self, lease_time: Duration) -> Self {
+        DhcpServer { lease_time, ..self }
+    }
+    /// Returns the address currently leased (or offered) to the client.
+    pub fn lease(&self, mac: MacAddr) -> Option<Ipv4Addr> {
+        self.leases
+            .get(&mac)
+            .filter(|lease| lease.expires > Instant::now())
+            .map(|lease| lease.address)
+    }
+    fn is_free(&self, address: Ipv4Addr, mac: MacAddr, now: Instant) -> bool {
+        let taken = |(owner, lease): (&MacAddr, &Lease)| {
+            *owner != mac && lease.address == address && lease.expires > now
+        };
+        self.range.contains(&u32::from(address))
+            && address != self.address
+            && !self.leases.iter().any(taken)
+    }
+    /// Leases the address to the client, taking it from whoever had it before.
+    fn lease_to(&mut self, mac: MacAddr, address: Ipv4Addr, now: Instant) {
+        self.leases.retain(|owner, lease| *owner == mac || lease.address != address);
+        let expires = now + self.lease_time;
+        self.leases.insert(mac, Lease { address, expires });
+    }
+    /// Picks an address to offer to the client.
+    fn allocate(&mut self, mac: MacAddr, requested: Option<Ipv4Addr>) -> Option<Ipv4Addr> {
+        let now = Instant::now();
+        let current = self.leases.get(&mac).map(|lease| lease.address);
+        let address = current
+            .into_iter()
+            .chain(requested)
+            .find(|address| self.is_free(*address, mac, now))
+            .or_else(|| {
+                self.range
+                    .clone()
+                    .map(Ipv4Addr::from)
+                    .find(|address| self.is_free(*address, mac, now))
+            })?;
+        self.lease_to(mac, address, now);
+        Some(address)
+    }
+    /// Builds the reply to the frame (without the packet info).
+    ///
+    /// Returns `None` if the frame is not a DHCP message to be answered (or if the range is
+    /// exhausted). Releases are processed, but there's nothing to answer for them.
+    pub fn reply(&mut self, frame: &[u8]) -> Option<Vec<u8>> {
+        let (header, ip) = EthernetHeader::parse(frame)?;
+        if header.ether_type != EtherType::Ipv4 || ip.len() < IP_HEADER || ip[0] >> 4 != 4 {
+            return None;
+        }
+        let header_len = usize::from(ip[0] & 0x0f) * 4;
+        let total = usize::from(u16::from_be_bytes([ip[2], ip[3]]));
+        // No fragments, UDP to the server port
+        if header_len < IP_HEADER
+            || total > ip.len()
+            || total < header_len + UDP_HEADER
+            || u16::from_be_bytes([ip[6], ip[7]]) & 0x3fff != 0
+            || ip[9] != UDP
+            || u16::from_be_bytes([ip[header_len + 2], ip[header_len + 3]]) != SERVER_PORT
+        {
+            return None;
+        }
+        let message = Message::parse(&ip[header_len + UDP_HEADER..total])?;
+        if message.server_id.is_some_and(|id| id != self.address) {
+            // The client talks to another server
+            return None;
+        }
+        let mac = message.mac();
+        let (message_type, address) = match message.message_type {
+            DISCOVER => (OFFER, self.allocate(mac, message.requested)?),
+            REQUEST => {
+                let ciaddr = message.ciaddr();
+                let requested = message
+                    .requested
+                    .or_else(|| Some(ciaddr).filter(|ciaddr| !ciaddr.is_unspecified()))?;
+                let now = Instant::now();
+                if self.is_free(requested, mac, now) {
+                    self.lease_to(mac, requested, now);
+                    (ACK, requested)
+                } else {
+                    self.leases.remove(&mac);
+                    (NAK, Ipv4Addr::UNSPECIFIED)
+                }
+            },
+            DECLINE | RELEASE => {
+                debug!("DHCP client {} gave up its address", mac);
+                self.leases.remove(&mac);
+                return None;
+            },
+            _ => return None,
+        };
+        debug!("DHCP {} for {}: {}", message_type, mac, address);
+        Some(self.build(&message, message_type, address))
+    }
+    fn build(&self, message: &Message, message_type: u8, address: Ipv4Addr) -> Vec<u8> {
+        let mut bootp = Vec::with_capacity(MIN_BOOTP_SIZE);
+        bootp.extend_from_slice(&[BOOT_REPLY, 1, 6, 0]);
+        // The transaction ID, secs (zeroed) and flags
+        bootp.extend_from_slice(&message.fixed[4..8]);
+        bootp.extend_from_slice(&[0, 0]);
+        bootp.extend_from_slice(&message.fixed[10..12]);
+        if message_type == NAK {
+            bootp.extend_from_slice(&[0; 12]);
+        } else {
+            bootp.extend_from_slice(&message.fixed[12..16]);
+            bootp.extend_from_slice(&address.octets());
+            bootp.extend_from_slice(&self.address.octets());
+        }
+        // The relay agent and the client hardware address
+        bootp.extend_from_slice(&message.fixed[24..44]);
+        // The server name and the boot file
+        bootp.extend_from_slice(&[0; 192]);
+        bootp.extend_from_slice(&MAGIC_COOKIE);
+        bootp.extend_from_slice(&[OPT_MESSAGE_TYPE, 1, message_type]);
+        bootp.extend_from_slice(&[OPT_SERVER_ID, 4]);
+        bootp.extend_from_slice(&self.address.octets());
+        if message_type != NAK {
+            let lease_time = self.lease_time.as_secs().min(u64::from(u32::MAX)) as u32;
+            bootp.extend_from_slice(&[OPT_LEASE_TIME, 4]);
+            bootp.extend_from_slice(&lease_time.to_be_bytes());
+            bootp.extend_from_slice(&[OPT_NETMASK, 4]);
+            bootp.extend_from_slice(&self.netmask.octets());
+            if let Some(router) = self.router {
+                bootp.extend_from_slice(&[OPT_ROUTER, 4]);
+                bootp.extend_from_slice(&router.octets());
+            }
+            if !self.dns.is_empty() {
+                // Only as many as fit into the option
+                let dns = &self.dns[..self.dns.len().min(63)];
+                bootp.extend_from_slice(&[OPT_DNS, dns.len() as u8 * 4]);
+                for server in dns {
+                    bootp.extend_from_slice(&server.octets());
+                }
+            }
+        }
+        bootp.push(OPT_END);
+        if bootp.len() < MIN_BOOTP_SIZE {
+            bootp.resize(MIN_BOOTP_SIZE, OPT_PAD);
+        }
+
+        // Where to send it. Clients without an address yet may not accept unicast.
+        let ciaddr = message.ciaddr();
+        let (destination, destination_mac) = if message_type == NAK || message.broadcast() {
+            (Ipv4Addr::BROADCAST, MacAddr::BROADCAST)
+        } else if !ciaddr.is_unspecified() {
+            (ciaddr, message.mac())
+        } else {
+            (address, message.mac())
+        };
+
+        let header = EthernetHeader {
+            destination: destination_mac,
+            source: self.mac,
+            ether_type: EtherType::Ipv4,
+        };
+        let udp_len = UDP_HEADER + bootp.len();
+        let total = IP_HEADER + udp_len;
+        let mut frame = Vec::with_capacity(EthernetHeader::SIZE + total);
+        frame.extend_from_slice(&header.to_bytes());
+        let ip_start = frame.len();
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&(total as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 64, UDP, 0, 0]);
+        frame.extend_from_slice(&self.address.octets());
+        frame.extend_from_slice(&destination.octets());
+        let ip_checksum = checksum(&frame[ip_start..]);
+        frame[ip_start + 10..ip_start + 12].copy_from_slice(&ip_checksum.to_be_bytes());
+        let udp_start = frame.len();
+        frame.extend_from_slice(&SERVER_PORT.to_be_bytes());
+        frame.extend_from_slice(&CLIENT_PORT.to_be_bytes());
+        frame.extend_from_slice(&(udp_len as u16).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&bootp);
+        // The pseudo-header: the addresses, the protocol and the length
+        let pseudo = sum(&frame[ip_start + 12..udp_start], u32::from(UDP) + udp_len as u32);
+        let udp_checksum = match !fold(sum(&frame[udp_start..], pseudo)) {
+            // Zero means no checksum in UDP
+            0 => 0xffff,
+            udp_checksum => udp_checksum,
+        };
+        frame[udp_start + 6..udp_start + 8].copy_from_slice(&udp_checksum.to_be_bytes());
+        frame
+    }
+    /// Answers the packet received from the device, if it is a DHCP message.
+    ///
+    /// The `packet` is as received, including the packet info if the device has it. Returns
+    /// whether the packet was answered (and the caller may ignore it). Packets from devices in
+    /// the TUN mode are never answered.
+    ///
+    /// # Errors
+    ///
+    /// If sending the reply fails.
+    pub fn respond<D: Device>(&mut self, device: &D, packet: &[u8]) -> Result<bool> {
+        if device.mode() != Mode::Tap {
+            return Ok(false);
+        }
+        let info = device.packet_info();
+        let frame = if info {
+            match packet.get(PacketInfo::SIZE..) {
+                Some(frame) => frame,
+                None => return Ok(false),
+            }
+        } else {
+            packet
+        };
+        let reply = match self.reply(frame) {
+            Some(reply) => reply,
+            None => return Ok(false),
+        };
+        trace!("Answering a DHCP message on {}", device.name());
+        if info {
+            let mut with_info = PacketInfo::new(EtherType::Ipv4).to_bytes().to_vec();
+            with_info.extend_from_slice(&reply);
+            device.send(&with_info)?;
+        } else {
+            device.send(&reply)?;
+        }
+        Ok(true)
+    }
+    /// Serves the clients on the device until it is shut down.
+    ///
+    /// It also answers the ARP requests for the server's address (the clients renew their leases
+    /// by unicast). All the other packets are dropped.
+    ///
+    /// # Errors
+    ///
+    /// If receiving or sending fails (other than by the
+    /// [`ShutdownHandle`](../struct.ShutdownHandle.html)).
+    pub fn run<D: Device>(&mut self, device: &D) -> Result<()> {
+        let mut arp = ArpResponder::new();
+        arp.insert(self.address, self.mac);
+        let mut buffer = vec![0; device.max_frame_size().unwrap_or(1518)];
+        loop {
+            match device.recv(&mut buffer) {
+                Ok(size) => {
+                    let packet = &buffer[..size];
+                    if !arp.respond(device, packet)? {
+                        self.respond(device, packet)?;
+                    }
+                },
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(ref e) if Shutdown::is_shutdown(e) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
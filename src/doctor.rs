@@ -0,0 +1,271 @@
+//! Diagnosing the environment.
+//!
+//! When creating a device fails, the cause is usually not in the application, but in the system
+//! it runs on ‒ a container without the device node, a missing capability, an old kernel. The
+//! [`diagnose`](fn.diagnose.html) function checks the usual suspects and returns a
+//! [`Report`](struct.Report.html), which can be examined or printed (eg. into a bug report).
+//!
+//! The same report is printed by `tuntap doctor` (with the `cli` feature).
+
+use std::ffi::CStr;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::{self, OpenOptions};
+use std::io::ErrorKind;
+use std::mem;
+use std::os::raw::c_int;
+use std::os::unix::fs::{FileTypeExt, MetadataExt, PermissionsExt};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+use crate::TunFlags;
+
+extern "C" {
+    fn tuntap_get_features(fd: c_int) -> c_int;
+    fn tuntap_has_net_admin() -> c_int;
+}
+
+const DEVICE: &str = "/dev/net/tun";
+/// The major and minor numbers of the device node.
+const DEVICE_NUMBER: (u64, u64) = (10, 200);
+
+/// The outcome of a single check.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum Status {
+    /// Everything is fine.
+    Ok,
+    /// Something is likely to limit what the application can do.
+    Warning,
+    /// The devices can't be used.
+    Failed,
+}
+
+impl Display for Status {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let status = match self {
+            Status::Ok => "ok",
+            Status::Warning => "warning",
+            Status::Failed => "FAILED",
+        };
+        fmt.write_str(status)
+    }
+}
+
+/// A single check of the report.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub struct Check {
+    /// What was checked.
+    pub name: &'static str,
+    /// The outcome.
+    pub status: Status,
+    /// Human readable details, including the hint how to fix a problem.
+    pub detail: String,
+}
+
+impl Check {
+    fn new<D: Into<String>>(name: &'static str, status: Status, detail: D) -> Self {
+        Check {
+            name,
+            status,
+            detail: detail.into(),
+        }
+    }
+}
+
+/// The findings of [`diagnose`](fn.diagnose.html).
+///
+/// Printing it with `{}` produces a human readable summary.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub struct Report {
+    /// The kernel release (as from `uname -r`).
+    pub kernel: Option<String>,
+    /// The flags supported by the TUN driver, if it could be asked.
+    pub features: Option<TunFlags>,
+    /// If the process has the `CAP_NET_ADMIN` capability, if it can be found out.
+    pub net_admin: Option<bool>,
+    /// The individual checks, in the order they were done.
+    pub checks: Vec<Check>,
+}
+
+impl Report {
+    /// The worst status of all the checks.
+    pub fn status(&self) -> Status {
+        self.checks
+            .iter()
+            .map(|check| check.status)
+            .max()
+            .unwrap_or(Status::Ok)
+    }
+    /// Checks the devices can be used (there may be warnings).
+    pub fn is_ok(&self) -> bool {
+        self.status() != Status::Failed
+    }
+}
+
+impl Display for Report {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let kernel = self.kernel.as_deref().unwrap_or("unknown");
+        writeln!(fmt, "Kernel: {}", kernel)?;
+        for check in &self.checks {
+            writeln!(fmt, "[{}] {}: {}", check.status, check.name, check.detail)?;
+        }
+        write!(fmt, "Overall: {}", self.status())
+    }
+}
+
+fn kernel() -> Option<String> {
+    let mut name: libc::utsname = unsafe { mem::zeroed() };
+    if unsafe { libc::uname(&mut name) } < 0 {
+        return None;
+    }
+    let release = unsafe { CStr::from_ptr(name.release.as_ptr()) };
+    Some(release.to_string_lossy().into_owned())
+}
+
+fn device_node() -> Check {
+    const NAME: &str = "device node";
+    let metadata = match fs::metadata(DEVICE) {
+        Ok(metadata) => metadata,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => {
+            let hint = "missing, create it by `mknod /dev/net/tun c 10 200` (in a container, pass \
+                        it in from the host)";
+            return Check::new(NAME, Status::Failed, hint);
+        },
+        Err(e) => return Check::new(NAME, Status::Failed, format!("can't be examined: {}", e)),
+    };
+    let rdev = metadata.rdev();
+    let number = (libc::major(rdev) as u64, libc::minor(rdev) as u64);
+    if !metadata.file_type().is_char_device() || number != DEVICE_NUMBER {
+        let detail = format!("{} is not the TUN device (10:200)", DEVICE);
+        return Check::new(NAME, Status::Failed, detail);
+    }
+    let mode = metadata.permissions().mode() & 0o777;
+    let detail = format!(
+        "{} present, mode {:o}, owner {}:{}",
+        DEVICE,
+        mode,
+        metadata.uid(),
+        metadata.gid()
+    );
+    Check::new(NAME, Status::Ok, detail)
+}
+
+/// Checks the TUN driver, `opened` tells if the device node could be opened.
+fn driver(opened: bool) -> Check {
+    const NAME: &str = "driver";
+    if Path::new("/sys/module/tun").exists() {
+        return Check::new(NAME, Status::Ok, "the tun module is loaded");
+    }
+    let builtin = kernel()
+        .map(|kernel| format!("/lib/modules/{}/modules.builtin", kernel))
+        .and_then(|path| fs::read_to_string(path).ok())
+        .is_some_and(|builtin| builtin.lines().any(|line| line.ends_with("/tun.ko")));
+    if builtin {
+        Check::new(NAME, Status::Ok, "built into the kernel")
+    } else if opened {
+        // Not listed anywhere, but evidently there
+        Check::new(NAME, Status::Ok, "present")
+    } else {
+        // It may still get loaded on the first use of the device node
+        let hint = "the tun module is not loaded, load it by `modprobe tun` if opening the device \
+                    fails";
+        Check::new(NAME, Status::Warning, hint)
+    }
+}
+
+/// Opens the device node and asks for the features.
+fn access(report: &mut Report) -> Check {
+    const NAME: &str = "access";
+    let file = match OpenOptions::new().read(true).write(true).open(DEVICE) {
+        Ok(file) => file,
+        Err(e) => {
+            let hint = match e.raw_os_error() {
+                Some(libc::EACCES) | Some(libc::EPERM) => format!(
+                    "can't open {}: {} (allow the user to access it, eg. by mode 666)",
+                    DEVICE, e
+                ),
+                Some(libc::ENODEV) | Some(libc::ENXIO) => format!(
+                    "can't open {}: {} (the kernel lacks the TUN driver, CONFIG_TUN)",
+                    DEVICE, e
+                ),
+                _ => format!("can't open {}: {}", DEVICE, e),
+            };
+            return Check::new(NAME, Status::Failed, hint);
+        },
+    };
+    let features = unsafe { tuntap_get_features(file.as_raw_fd()) };
+    if features < 0 {
+        return Check::new(NAME, Status::Ok, "can be opened, but doesn't list its features");
+    }
+    let features = TunFlags::from_bits_retain(features as u16);
+    report.features = Some(features);
+    Check::new(NAME, Status::Ok, format!("can be opened, features {:?}", features))
+}
+
+fn features(features: TunFlags) -> Check {
+    const NAME: &str = "features";
+    let wanted = [
+        (TunFlags::MULTI_QUEUE, "multi-queue devices"),
+        (TunFlags::VNET_HDR, "offloads"),
+        (TunFlags::NAPI, "NAPI"),
+    ];
+    let missing = wanted
+        .iter()
+        .filter(|(flag, _)| !features.contains(*flag))
+        .map(|(_, name)| *name)
+        .collect::<Vec<_>>();
+    if missing.is_empty() {
+        Check::new(NAME, Status::Ok, "all the optional features are supported")
+    } else {
+        let detail = format!("not supported by the kernel: {}", missing.join(", "));
+        Check::new(NAME, Status::Warning, detail)
+    }
+}
+
+fn capability(report: &mut Report) -> Check {
+    const NAME: &str = "capability";
+    match unsafe { tuntap_has_net_admin() } {
+        1 => {
+            report.net_admin = Some(true);
+            Check::new(NAME, Status::Ok, "the process has CAP_NET_ADMIN")
+        },
+        0 => {
+            report.net_admin = Some(false);
+            let hint = "no CAP_NET_ADMIN, only persistent devices owned by the user can be \
+                        attached to (run as root or grant the capability to create them)";
+            Check::new(NAME, Status::Warning, hint)
+        },
+        _ => Check::new(NAME, Status::Warning, "can't find out if the process has CAP_NET_ADMIN"),
+    }
+}
+
+/// Checks if the environment allows using the TUN/TAP devices.
+///
+/// This doesn't create any device and needs no privileges.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::doctor;
+/// let report = doctor::diagnose();
+/// if !report.is_ok() {
+///     eprintln!("Can't use the TUN/TAP devices here:\n{}", report);
+/// }
+/// ```
+pub fn diagnose() -> Report {
+    let mut report = Report {
+        kernel: kernel(),
+        ..Report::default()
+    };
+    report.checks.push(device_node());
+    let access = access(&mut report);
+    report.checks.push(driver(access.status == Status::Ok));
+    report.checks.push(access);
+    if let Some(supported) = report.features {
+        report.checks.push(features(supported));
+    }
+    let capability = capability(&mut report);
+    report.checks.push(capability);
+    report
+}
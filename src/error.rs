@@ -0,0 +1,230 @@
+//! The error types of creating the interface and of parsing its settings.
+
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs;
+use std::io::{Error as IoError, ErrorKind};
+
+use crate::{Mode, TunFlags, IFNAMSIZ};
+
+use libc::{EBADFD, EBUSY, EINVAL, ENODEV, ENOENT, ENOTTY, ENXIO, EPERM};
+
+extern "C" {
+    fn tuntap_has_net_admin() -> std::os::raw::c_int;
+}
+
+/// An error when creating the interface.
+///
+/// The errors most commonly returned by the OS are turned into specific variants, so it's
+/// possible to act on them (or at least show the user something more telling than „Invalid
+/// argument“). Everything else is passed through as [`Io`](#variant.Io).
+///
+/// It can be converted into `std::io::Error` (therefore the `?` operator works in functions
+/// returning `std::io::Result`).
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// The requested name is longer than the OS allows.
+    NameTooLong,
+    /// The requested name contains a NUL byte.
+    NameContainsNul,
+    /// The requested name contains a character not allowed in interface names (a slash, a colon
+    /// or whitespace).
+    NameContainsInvalidChar(char),
+    /// The requested name is not valid, either because it is reserved (`.` and `..`) or uses the
+    /// `%` other than as a single `%d`.
+    InvalidName,
+    /// The name is already taken by a device of different kind or mode.
+    NameTaken,
+    /// The process doesn't have the needed privileges.
+    ///
+    /// Unlike [`MissingCapability`](#variant.MissingCapability), the reason is not known (eg.
+    /// the `/dev/net/tun` is not accessible or the device belongs to another user).
+    PermissionDenied,
+    /// The process doesn't have the `CAP_NET_ADMIN` capability needed to create the device (or
+    /// attach to one that doesn't belong to its user).
+    ///
+    /// Run it as root, give it the capability (`setcap cap_net_admin+ep`) or create the device
+    /// beforehand as persistent and owned by the user.
+    MissingCapability,
+    /// The device exists and is already in use by someone else.
+    DeviceBusy,
+    /// The `/dev/net/tun` is missing (eg. the kernel module isn't loaded) or isn't a TUN/TAP
+    /// device.
+    NotATunDevice,
+    /// Some other error reported by the OS.
+    Io(IoError),
+}
+
+impl Error {
+    /// Checks the name is acceptable for the kernel.
+    ///
+    /// The rules are the same as of the Linux `dev_valid_name`, with the `%d` pattern allowed.
+    /// The empty name is fine too, the kernel picks one.
+    pub(crate) fn check_name(name: &str) -> Result<(), Self> {
+        if name.len() >= IFNAMSIZ {
+            return Err(Error::NameTooLong);
+        }
+        if name.contains('\0') {
+            return Err(Error::NameContainsNul);
+        }
+        if let Some(c) = name.chars().find(|&c| c == '/' || c == ':' || c.is_whitespace()) {
+            return Err(Error::NameContainsInvalidChar(c));
+        }
+        let pattern_ok = match name.find('%') {
+            Some(pos) => name[pos + 1..].starts_with('d') && !name[pos + 2..].contains('%'),
+            None => true,
+        };
+        if name == "." || name == ".." || !pattern_ok {
+            return Err(Error::InvalidName);
+        }
+        Ok(())
+    }
+    /// Interprets a failure of opening the `/dev/net/tun`.
+    pub(crate) fn from_open(error: IoError) -> Self {
+        match error.raw_os_error() {
+            Some(ENOENT) | Some(ENODEV) | Some(ENXIO) => Error::NotATunDevice,
+            Some(EPERM) => Error::PermissionDenied,
+            _ => Error::Io(error),
+        }
+    }
+    /// Interprets a failure of the `TUNSETIFF` ioctl creating the device `ifname` in `mode`.
+    pub(crate) fn from_setup(error: IoError, ifname: &str, mode: Mode) -> Self {
+        match error.raw_os_error() {
+            Some(EPERM) if unsafe { tuntap_has_net_admin() } == 0 => Error::MissingCapability,
+            Some(EPERM) => Error::PermissionDenied,
+            Some(EBUSY) => Error::DeviceBusy,
+            Some(EINVAL) if Error::is_taken(ifname, mode) => Error::NameTaken,
+            Some(ENOTTY) | Some(EBADFD) => Error::NotATunDevice,
+            _ => Error::Io(error),
+        }
+    }
+    /// Checks if the name belongs to a device of a different kind or mode.
+    ///
+    /// The kernel refuses both that and flags it doesn't like (eg. unsupported ones or a
+    /// multi-queue mismatch) with the same EINVAL.
+    fn is_taken(ifname: &str, mode: Mode) -> bool {
+        // The kernel picks a free name for these
+        if ifname.is_empty() || ifname.contains('%') {
+            return false;
+        }
+        let path = format!("/sys/class/net/{}", ifname);
+        if fs::metadata(&path).is_err() {
+            return false;
+        }
+        match fs::read_to_string(format!("{}/tun_flags", path)) {
+            Ok(flags) => {
+                let flags = u16::from_str_radix(flags.trim().trim_start_matches("0x"), 16);
+                match flags {
+                    Ok(flags) => TunFlags::from_bits_retain(flags).mode() != Some(mode),
+                    Err(_) => false,
+                }
+            },
+            // Not a TUN/TAP device at all
+            Err(_) => true,
+        }
+    }
+    /// Interprets a failure of the `TUNGETIFF` ioctl on a file descriptor from the outside.
+    pub(crate) fn from_adopt(error: IoError) -> Self {
+        match error.raw_os_error() {
+            Some(ENOTTY) | Some(EBADFD) | Some(EINVAL) => Error::NotATunDevice,
+            _ => Error::Io(error),
+        }
+    }
+    fn io_kind(&self) -> ErrorKind {
+        match self {
+            Error::NameTooLong
+            | Error::NameContainsNul
+            | Error::NameContainsInvalidChar(_)
+            | Error::InvalidName => ErrorKind::InvalidInput,
+            Error::NameTaken => ErrorKind::AlreadyExists,
+            Error::PermissionDenied | Error::MissingCapability => ErrorKind::PermissionDenied,
+            Error::DeviceBusy => ErrorKind::ResourceBusy,
+            Error::NotATunDevice => ErrorKind::NotFound,
+            Error::Io(e) => e.kind(),
+        }
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            Error::NameTooLong => write!(fmt, "The interface name is too long"),
+            Error::NameContainsNul => write!(fmt, "The interface name contains a NUL byte"),
+            Error::NameContainsInvalidChar(c) => {
+                write!(fmt, "The interface name contains an invalid character {:?}", c)
+            },
+            Error::InvalidName => write!(
+                fmt,
+                "The interface name is not valid (it is reserved or uses % other than as one %d)"
+            ),
+            Error::NameTaken => {
+                write!(fmt, "The interface name is taken by a device of different kind")
+            },
+            Error::PermissionDenied => write!(fmt, "Not permitted to create the interface"),
+            Error::MissingCapability => write!(
+                fmt,
+                "Not permitted to create the interface, the CAP_NET_ADMIN capability is missing \
+                 (run as root, use setcap or create a persistent device owned by the user)"
+            ),
+            Error::DeviceBusy => write!(fmt, "The interface is already in use"),
+            Error::NotATunDevice => write!(
+                fmt,
+                "The /dev/net/tun is missing or is not a TUN/TAP device (is the tun module loaded?)"
+            ),
+            Error::Io(e) => write!(fmt, "{}", e),
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<IoError> for Error {
+    fn from(error: IoError) -> Self {
+        Error::Io(error)
+    }
+}
+
+impl From<Error> for IoError {
+    fn from(error: Error) -> Self {
+        match error {
+            Error::Io(e) => e,
+            other => IoError::new(other.io_kind(), other),
+        }
+    }
+}
+
+/// An error parsing a [`Mode`](enum.Mode.html), flags or other setting from a string.
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct ParseError {
+    what: &'static str,
+    input: String,
+}
+
+impl ParseError {
+    pub(crate) fn new(what: &'static str, input: &str) -> Self {
+        ParseError {
+            what,
+            input: input.to_owned(),
+        }
+    }
+    /// The part of the input that was not understood.
+    pub fn input(&self) -> &str {
+        &self.input
+    }
+}
+
+impl Display for ParseError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "Invalid {}: {:?}", self.what, self.input)
+    }
+}
+
+impl StdError for ParseError {}
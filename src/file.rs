@@ -0,0 +1,91 @@
+//! The interface turned into a plain file.
+//!
+//! See the [`Iface::into_file`](../struct.Iface.html#method.into_file) method.
+
+use std::fs::File;
+use std::io::{IoSlice, IoSliceMut, Read, Result, Write};
+use std::ops::{Deref, DerefMut};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+use std::sync::Arc;
+
+use crate::cleanup::CleanupGuard;
+
+/// The file of an interface, keeping its [cleanup](struct.Iface.html#method.set_cleanup).
+///
+/// Created by [`Iface::into_file`](struct.Iface.html#method.into_file). It dereferences to the
+/// `File`, so it can be handed to code expecting one, and it can be read and written directly.
+/// Giving it back to [`Iface::from_file`](struct.Iface.html#method.from_file) takes the cleanup
+/// over again.
+///
+/// Dropping it (or taking the bare file out by [`into_inner`](#method.into_inner)) performs the
+/// cleanup, the same as dropping the interface would.
+#[derive(Debug)]
+pub struct IfaceFile {
+    pub(crate) file: File,
+    pub(crate) cleanup: Option<Arc<CleanupGuard>>,
+}
+
+impl IfaceFile {
+    /// Returns the bare file.
+    ///
+    /// The cleanup (if not shared with a clone of the interface) happens now.
+    pub fn into_inner(self) -> File {
+        self.file
+    }
+}
+
+/// Wraps a file without any cleanup.
+impl From<File> for IfaceFile {
+    fn from(file: File) -> Self {
+        IfaceFile {
+            file,
+            cleanup: None,
+        }
+    }
+}
+
+impl Deref for IfaceFile {
+    type Target = File;
+    fn deref(&self) -> &File {
+        &self.file
+    }
+}
+
+impl DerefMut for IfaceFile {
+    fn deref_mut(&mut self) -> &mut File {
+        &mut self.file
+    }
+}
+
+impl Read for IfaceFile {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.file.read(buf)
+    }
+    fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> Result<usize> {
+        self.file.read_vectored(bufs)
+    }
+}
+
+impl Write for IfaceFile {
+    fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.file.write(buf)
+    }
+    fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        self.file.write_vectored(bufs)
+    }
+    fn flush(&mut self) -> Result<()> {
+        self.file.flush()
+    }
+}
+
+impl AsRawFd for IfaceFile {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for IfaceFile {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
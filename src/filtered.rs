@@ -0,0 +1,189 @@
+//! Hooking into the packets passing through a device.
+//!
+//! See the [`FilteredIface`](struct.FilteredIface.html) structure.
+
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::Result;
+use std::os::unix::io::{AsRawFd, BorrowedFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+use crate::{Device, Iface, Mode};
+
+/// Which way a packet goes through a [`FilteredIface`](struct.FilteredIface.html).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Direction {
+    /// Received from the device.
+    Received,
+    /// Being sent into the device.
+    Sent,
+}
+
+type Hook = Box<dyn Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync>;
+
+/// A device running hooks on every packet passing through.
+///
+/// A hook is a function taking the [`Direction`](enum.Direction.html) and the packet (as
+/// received from the device or as given to [`send`](#method.send), including the packet info if
+/// the device has one). It may modify the packet in place. If it returns `false`, the packet is
+/// dropped and the later hooks don't see it.
+///
+/// The hooks are stacked like middleware ‒ the sent packets go through them in the order they
+/// were added by [`hook`](#method.hook), the received ones in the reverse order. The first added
+/// hook is therefore the closest to the application and the last one the closest to the device.
+/// This allows for address rewriting (NAT), logging or injecting faults without touching the code
+/// using the device.
+///
+/// The wrapper is a [`Device`](../trait.Device.html) itself, so it can be used by the other
+/// wrappers of this crate.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// # use tun_tap::filtered::{Direction, FilteredIface};
+/// let iface = Iface::without_packet_info("mytun", Mode::Tun).expect("Failed to create a device");
+/// let iface = FilteredIface::new(iface)
+///     .hook(|direction: Direction, packet: &mut Vec<u8>| {
+///         println!("{:?} a packet of {} bytes", direction, packet.len());
+///         true
+///     })
+///     // Drop everything that isn't IPv4
+///     .hook(|_: Direction, packet: &mut Vec<u8>| packet.first().map(|b| b >> 4) == Some(4));
+/// let mut buffer = vec![0; 1500];
+/// loop {
+///     let size = iface.recv(&mut buffer).unwrap();
+///     iface.send(&buffer[..size]).unwrap();
+/// }
+/// ```
+pub struct FilteredIface<D = Iface> {
+    device: D,
+    hooks: Vec<Hook>,
+    dropped: AtomicU64,
+}
+
+impl<D: Device> FilteredIface<D> {
+    /// Wraps the device, with no hooks yet.
+    pub fn new(device: D) -> Self {
+        FilteredIface {
+            device,
+            hooks: Vec::new(),
+            dropped: AtomicU64::new(0),
+        }
+    }
+    /// Adds another hook, closer to the device than the ones already present.
+    pub fn hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync + 'static,
+    {
+        self.hooks.push(Box::new(hook));
+        self
+    }
+    fn run(&self, direction: Direction, packet: &mut Vec<u8>) -> bool {
+        let pass = match direction {
+            Direction::Received => self.hooks.iter().rev().all(|hook| hook(direction, packet)),
+            Direction::Sent => self.hooks.iter().all(|hook| hook(direction, packet)),
+        };
+        if !pass {
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        pass
+    }
+    /// Receives a packet that passed all the hooks.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv). The dropped packets are skipped. If
+    /// a hook makes the packet larger than the buffer, it is truncated.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let size = self.device.recv(buf)?;
+            if self.hooks.is_empty() {
+                return Ok(size);
+            }
+            let mut packet = buf[..size].to_vec();
+            if self.run(Direction::Received, &mut packet) {
+                let size = packet.len().min(buf.len());
+                buf[..size].copy_from_slice(&packet[..size]);
+                return Ok(size);
+            }
+        }
+    }
+    /// Sends a packet into the device, once it passes all the hooks.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send). Returns the size of the given
+    /// packet, even if a hook dropped or changed it.
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        if self.hooks.is_empty() {
+            return self.device.send(buf);
+        }
+        let mut packet = buf.to_vec();
+        if self.run(Direction::Sent, &mut packet) {
+            self.device.send(&packet)?;
+        }
+        Ok(buf.len())
+    }
+    /// Returns the number of packets dropped by the hooks so far, in both directions.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+    /// Returns the device.
+    ///
+    /// Note that the packets passing directly through the device skip the hooks.
+    pub fn get_ref(&self) -> &D {
+        &self.device
+    }
+    /// Removes the hooks and returns the device.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+}
+
+impl<D: Device> From<D> for FilteredIface<D> {
+    fn from(device: D) -> Self {
+        Self::new(device)
+    }
+}
+
+impl<D: Debug> Debug for FilteredIface<D> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.debug_struct("FilteredIface")
+            .field("device", &self.device)
+            .field("hooks", &self.hooks.len())
+            .field("dropped", &self.dropped)
+            .finish()
+    }
+}
+
+impl<D: AsRawFd> AsRawFd for FilteredIface<D> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.device.as_raw_fd()
+    }
+}
+
+impl<D: Device> Device for FilteredIface<D> {
+    fn name(&self) -> &str {
+        self.device.name()
+    }
+    fn mode(&self) -> Mode {
+        self.device.mode()
+    }
+    fn packet_info(&self) -> bool {
+        self.device.packet_info()
+    }
+    fn mtu(&self) -> Result<usize> {
+        self.device.mtu()
+    }
+    fn max_frame_size(&self) -> Result<usize> {
+        self.device.max_frame_size()
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        FilteredIface::recv(self, buf)
+    }
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        FilteredIface::send(self, buf)
+    }
+    fn set_non_blocking(&self) -> Result<()> {
+        self.device.set_non_blocking()
+    }
+    fn shutdown_fd(&self) -> Option<BorrowedFd<'_>> {
+        self.device.shutdown_fd()
+    }
+}
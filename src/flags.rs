@@ -0,0 +1,177 @@
+//! Flags of the interfaces.
+
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::str::FromStr;
+
+use bitflags::bitflags;
+
+use crate::{Mode, ParseError};
+
+/// Text forms of the flags, for configuration files and command lines.
+///
+/// They are formatted as the names separated by ` | `, with the unknown bits in hex (eg.
+/// `TUN | NO_PI | 0x400`). Parsing accepts the same, but ignores the case, takes `-` in place of
+/// `_` and allows any whitespace around the `|`. An empty string means no flags.
+macro_rules! text_flags {
+    ($flags: ident, $bits: ty, $what: expr) => {
+        impl Display for $flags {
+            fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+                bitflags::parser::to_writer(self, fmt)
+            }
+        }
+
+        impl FromStr for $flags {
+            type Err = ParseError;
+            fn from_str(s: &str) -> Result<Self, ParseError> {
+                s.split('|')
+                    .map(str::trim)
+                    .filter(|flag| !flag.is_empty())
+                    .try_fold(Self::empty(), |flags, flag| {
+                        let parsed = match flag.strip_prefix("0x") {
+                            Some(hex) => <$bits>::from_str_radix(hex, 16)
+                                .ok()
+                                .map(Self::from_bits_retain),
+                            None => Self::from_name(&flag.to_ascii_uppercase().replace('-', "_")),
+                        };
+                        parsed
+                            .map(|parsed| flags | parsed)
+                            .ok_or_else(|| ParseError::new($what, flag))
+                    })
+            }
+        }
+    };
+}
+
+bitflags! {
+    /// Flags of the TUN/TAP device itself.
+    ///
+    /// These are passed to the kernel on creation (see
+    /// [`Iface::with_flags`](struct.Iface.html#method.with_flags)) and can be read back by
+    /// [`Iface::tun_flags`](struct.Iface.html#method.tun_flags). Exactly one of `TUN` and `TAP`
+    /// is set on every device.
+    ///
+    /// Like the other flags, they can be parsed from and formatted into text.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// # use tun_tap::TunFlags;
+    /// let flags: TunFlags = "tap | no-pi".parse().unwrap();
+    /// assert_eq!(TunFlags::TAP | TunFlags::NO_PI, flags);
+    /// assert_eq!("TAP | NO_PI", flags.to_string());
+    /// ```
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct TunFlags: u16 {
+        /// The TUN mode.
+        const TUN = 0x0001;
+        /// The TAP mode.
+        const TAP = 0x0002;
+        /// Use the NAPI in the kernel.
+        const NAPI = 0x0010;
+        /// Use the NAPI with fragment passing (TAP only).
+        const NAPI_FRAGS = 0x0020;
+        /// Start with the carrier off.
+        const NO_CARRIER = 0x0040;
+        /// The device has multiple queues.
+        const MULTI_QUEUE = 0x0100;
+        /// The device survives closing the file descriptor.
+        ///
+        /// This is only reported, it can't be set on creation.
+        const PERSIST = 0x0800;
+        /// Don't prepend the packet info header.
+        const NO_PI = 0x1000;
+        /// The legacy single queue flag, ignored by current kernels.
+        const ONE_QUEUE = 0x2000;
+        /// Prepend the virtio net header to the packets.
+        ///
+        /// Note that the helpers in this crate don't understand this header.
+        const VNET_HDR = 0x4000;
+        /// Fail if the device already exists.
+        const TUN_EXCL = 0x8000;
+    }
+}
+
+impl TunFlags {
+    /// The mode, if exactly one of `TUN` and `TAP` is set.
+    pub fn mode(self) -> Option<Mode> {
+        if self.contains(TunFlags::TUN) && !self.contains(TunFlags::TAP) {
+            Some(Mode::Tun)
+        } else if self.contains(TunFlags::TAP) && !self.contains(TunFlags::TUN) {
+            Some(Mode::Tap)
+        } else {
+            None
+        }
+    }
+}
+
+text_flags!(TunFlags, u16, "TUN/TAP flag");
+
+impl From<Mode> for TunFlags {
+    fn from(mode: Mode) -> Self {
+        match mode {
+            Mode::Tun => TunFlags::TUN,
+            Mode::Tap => TunFlags::TAP,
+        }
+    }
+}
+
+bitflags! {
+    /// Flags of a network interface, as shown by `ip link`.
+    ///
+    /// Read by [`Iface::iff_flags`](struct.Iface.html#method.iff_flags).
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct IffFlags: u16 {
+        /// The interface is administratively up.
+        const UP = libc::IFF_UP as u16;
+        /// The broadcast address is valid.
+        const BROADCAST = libc::IFF_BROADCAST as u16;
+        /// Debugging is turned on.
+        const DEBUG = libc::IFF_DEBUG as u16;
+        /// A loopback interface.
+        const LOOPBACK = libc::IFF_LOOPBACK as u16;
+        /// A point-to-point link.
+        const POINTOPOINT = libc::IFF_POINTOPOINT as u16;
+        /// The resources are allocated (the link is operational).
+        const RUNNING = libc::IFF_RUNNING as u16;
+        /// No ARP protocol.
+        const NOARP = libc::IFF_NOARP as u16;
+        /// Receives all the packets.
+        const PROMISC = libc::IFF_PROMISC as u16;
+        /// Receives all the multicast packets.
+        const ALLMULTI = libc::IFF_ALLMULTI as u16;
+        /// Supports multicast.
+        const MULTICAST = libc::IFF_MULTICAST as u16;
+        // Keep whatever other flags the OS sets.
+        const _ = !0;
+    }
+}
+
+bitflags! {
+    /// Offloads the userspace is able to handle.
+    ///
+    /// Set by [`Iface::set_offload`](struct.Iface.html#method.set_offload). These need the
+    /// [`VNET_HDR`](struct.TunFlags.html#associatedconstant.VNET_HDR) to be useful.
+    #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+    #[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+    pub struct OffloadFlags: u32 {
+        /// Partial checksums are fine.
+        const CSUM = 0x01;
+        /// TCP segmentation offload over IPv4.
+        const TSO4 = 0x02;
+        /// TCP segmentation offload over IPv6.
+        const TSO6 = 0x04;
+        /// TCP segmentation offload with the ECN bits.
+        const TSO_ECN = 0x08;
+        /// UDP fragmentation offload.
+        const UFO = 0x10;
+        /// UDP segmentation offload over IPv4.
+        const USO4 = 0x20;
+        /// UDP segmentation offload over IPv6.
+        const USO6 = 0x40;
+    }
+}
+
+text_flags!(IffFlags, u16, "interface flag");
+text_flags!(OffloadFlags, u32, "offload flag");
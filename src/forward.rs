@@ -0,0 +1,36 @@
+//! Tunneling the packets of a device through sockets.
+//!
+//! This is the plumbing of a simple VPN ‒ packets read from the device are sent to the other
+//! endpoint through a socket and whatever arrives from there is put into the device. The
+//! [`udp`](udp/index.html) module carries each packet in its own UDP datagram, the
+//! [`tcp`](tcp/index.html) one frames them into a stream. The [`unix`](unix/index.html) one
+//! serves a container or a sandbox on the same machine.
+//!
+//! Each packet goes through a transform on the way (eg. to encrypt or authenticate it). It is a
+//! function taking the [`Direction`](enum.Direction.html) and the packet, which it may modify in
+//! place. If it returns `false`, the packet is dropped. Use [`unchanged`](fn.unchanged.html) for
+//! no transformation at all.
+//!
+//! The packets are forwarded verbatim, including the packet info header if the device has one.
+//! Both endpoints therefore need to use the same setting, usually without the packet info.
+
+mod datagram;
+pub mod tcp;
+pub mod udp;
+pub mod unix;
+
+pub use self::datagram::DatagramTunnel;
+
+/// Which way a packet goes through the tunnel.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Direction {
+    /// Received from the device, to be sent to the other endpoint.
+    Outbound,
+    /// Received from the other endpoint, to be sent into the device.
+    Inbound,
+}
+
+/// A transform passing all the packets unchanged.
+pub fn unchanged(_: Direction, _: &mut Vec<u8>) -> bool {
+    true
+}
@@ -0,0 +1,224 @@
+//! The threads forwarding between a device and a socket carrying a packet in each message.
+
+use std::io::{Error, ErrorKind, Result};
+use std::mem;
+use std::net::UdpSocket;
+use std::os::raw::c_int;
+use std::os::unix::io::AsRawFd;
+use std::os::unix::net::UnixDatagram;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use super::Direction;
+use crate::shutdown::ShutdownPipe;
+use crate::{device, Device, Shutdown, ShutdownHandle};
+
+/// The largest possible UDP payload (and a reasonable limit for the other sockets too).
+pub(crate) const MAX_DATAGRAM: usize = 65535;
+
+/// A connected socket preserving the message boundaries.
+pub(crate) trait Datagram: AsRawFd {
+    fn send(&self, buf: &[u8]) -> Result<usize>;
+    fn recv(&self, buf: &mut [u8]) -> Result<usize>;
+    /// Checks if an empty message means the other end closed the connection.
+    fn is_seqpacket(&self) -> bool {
+        false
+    }
+}
+
+impl Datagram for UdpSocket {
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        UdpSocket::send(self, buf)
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        UdpSocket::recv(self, buf)
+    }
+}
+
+impl Datagram for UnixDatagram {
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        UnixDatagram::send(self, buf)
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        UnixDatagram::recv(self, buf)
+    }
+    fn is_seqpacket(&self) -> bool {
+        let mut kind: c_int = 0;
+        let mut len = mem::size_of::<c_int>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                self.as_raw_fd(),
+                libc::SOL_SOCKET,
+                libc::SO_TYPE,
+                &mut kind as *mut c_int as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        result == 0 && kind == libc::SOCK_SEQPACKET
+    }
+}
+
+/// Checks the error of a receive on a connected socket is worth giving up.
+///
+/// The ICMP errors caused by our previous datagrams (eg. nobody listening on the other side yet)
+/// are reported by the following operations.
+pub(crate) fn is_transient(error: &Error) -> bool {
+    matches!(
+        error.kind(),
+        ErrorKind::ConnectionRefused | ErrorKind::Interrupted | ErrorKind::WouldBlock
+    )
+}
+
+pub(crate) fn spawn<D, S, F>(iface: D, socket: S, transform: F) -> Result<DatagramTunnel<D, S>>
+where
+    D: Device + Send + Sync + 'static,
+    S: Datagram + Send + Sync + 'static,
+    F: Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync + 'static,
+{
+    let mut tunnel = DatagramTunnel {
+        iface: Arc::new(iface),
+        socket: Arc::new(socket),
+        shutdown: ShutdownHandle {
+            pipe: Arc::new(ShutdownPipe::new()?),
+        },
+        threads: Vec::with_capacity(2),
+    };
+    let transform = Arc::new(transform);
+    start(&mut tunnel, Direction::Outbound, &transform)?;
+    start(&mut tunnel, Direction::Inbound, &transform)?;
+    Ok(tunnel)
+}
+
+fn start<D, S, F>(
+    tunnel: &mut DatagramTunnel<D, S>,
+    direction: Direction,
+    transform: &Arc<F>,
+) -> Result<()>
+where
+    D: Device + Send + Sync + 'static,
+    S: Datagram + Send + Sync + 'static,
+    F: Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync + 'static,
+{
+    let iface = Arc::clone(&tunnel.iface);
+    let socket = Arc::clone(&tunnel.socket);
+    let shutdown = Arc::clone(&tunnel.shutdown.pipe);
+    let transform = Arc::clone(transform);
+    let thread = thread::Builder::new()
+        .name(format!("{}-tunnel", iface.name()))
+        .spawn(move || match direction {
+            Direction::Outbound => outbound(&*iface, &*socket, &shutdown, &*transform),
+            Direction::Inbound => inbound(&*iface, &*socket, &shutdown, &*transform),
+        })?;
+    tunnel.threads.push(thread);
+    Ok(())
+}
+
+fn outbound<D, S, F>(iface: &D, socket: &S, shutdown: &ShutdownPipe, transform: &F)
+where
+    D: Device,
+    S: Datagram,
+    F: Fn(Direction, &mut Vec<u8>) -> bool,
+{
+    let forwarded = device::pump(iface, Some(shutdown), |packet| {
+        if transform(Direction::Outbound, packet) {
+            if let Err(e) = socket.send(packet) {
+                debug!("Dropped a packet tunneled from {}: {}", iface.name(), e);
+            }
+        }
+        true
+    });
+    if let Err(e) = forwarded {
+        error!("Tunneling from {} failed: {}", iface.name(), e);
+    }
+}
+
+fn inbound<D, S, F>(iface: &D, socket: &S, shutdown: &ShutdownPipe, transform: &F)
+where
+    D: Device,
+    S: Datagram,
+    F: Fn(Direction, &mut Vec<u8>) -> bool,
+{
+    let mut buffer = vec![0; MAX_DATAGRAM];
+    let seqpacket = socket.is_seqpacket();
+    loop {
+        let received = shutdown
+            .wait_readable(socket.as_raw_fd())
+            .and_then(|()| socket.recv(&mut buffer));
+        match received {
+            // An empty datagram is just an invalid packet, but a closed SEQPACKET socket would
+            // return nothing else.
+            Ok(0) if seqpacket => {
+                debug!("The other end of the tunnel of {} closed the socket", iface.name());
+                return;
+            },
+            Ok(size) => {
+                buffer.truncate(size);
+                if transform(Direction::Inbound, &mut buffer) {
+                    if let Err(e) = iface.send(&buffer) {
+                        debug!("Dropped a packet tunneled into {}: {}", iface.name(), e);
+                    }
+                }
+                buffer.resize(MAX_DATAGRAM, 0);
+            },
+            Err(ref e) if is_transient(e) => (),
+            Err(ref e) if Shutdown::is_shutdown(e) => return,
+            Err(e) => {
+                error!("Tunneling into {} failed: {}", iface.name(), e);
+                return;
+            },
+        }
+    }
+}
+
+/// A device tunneled through a socket carrying a packet in each message.
+///
+/// Created by [`udp::spawn`](udp/fn.spawn.html) or [`unix::spawn`](unix/fn.spawn.html).
+/// Dropping it tells the threads to terminate, but doesn't wait for them,
+/// [`shutdown`](#method.shutdown) does (and returns the device and the socket).
+///
+/// A direction stops forwarding on an error of its receiving side (it is logged), the other one
+/// keeps running.
+#[derive(Debug)]
+pub struct DatagramTunnel<D, S> {
+    iface: Arc<D>,
+    socket: Arc<S>,
+    shutdown: ShutdownHandle,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl<D, S> DatagramTunnel<D, S> {
+    /// Returns the device.
+    pub fn iface(&self) -> &D {
+        &self.iface
+    }
+    /// Returns the socket.
+    pub fn socket(&self) -> &S {
+        &self.socket
+    }
+    /// Stops the forwarding and returns the device and the socket.
+    ///
+    /// A packet being forwarded is finished first.
+    ///
+    /// # Panics
+    ///
+    /// If the transform panicked.
+    pub fn shutdown(mut self) -> (D, S) {
+        let _ = self.shutdown.shutdown();
+        for thread in self.threads.drain(..) {
+            thread.join().expect("Tunnel transform panicked");
+        }
+        let iface = Arc::clone(&self.iface);
+        let socket = Arc::clone(&self.socket);
+        drop(self);
+        match (Arc::try_unwrap(iface), Arc::try_unwrap(socket)) {
+            (Ok(iface), Ok(socket)) => (iface, socket),
+            _ => unreachable!("Tunnel threads terminated"),
+        }
+    }
+}
+
+impl<D, S> Drop for DatagramTunnel<D, S> {
+    fn drop(&mut self) {
+        let _ = self.shutdown.shutdown();
+    }
+}
@@ -0,0 +1,439 @@
+//! Tunneling the packets through a TCP stream.
+//!
+//! For networks letting only TCP through. The packets (after passing through the transform) are
+//! sent over the stream one after another, each prefixed by its length as a 16-bit big endian
+//! number. The other endpoint does the same in the opposite direction.
+//!
+//! The [`TcpTunnel`](struct.TcpTunnel.html) forwards in its own threads, the
+//! [`AsyncTcpTunnel`](struct.AsyncTcpTunnel.html) (with the `tokio` feature) is a future running
+//! in the event loop and works over any asynchronous stream (eg. a TLS one).
+//!
+//! Unlike with [`udp`](../udp/index.html), the stream doesn't lose packets on its own. If the
+//! other endpoint doesn't keep up, the packets are held back and the ones read from the device
+//! meanwhile wait in its queue (from where the kernel drops them once it is full). Note that
+//! tunneling TCP connections through TCP performs poorly on lossy networks, as both layers
+//! retransmit.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::net::TcpStream;
+//! # use tun_tap::*;
+//! # use tun_tap::forward::{self, tcp};
+//! let iface = Iface::without_packet_info("vpn%d", Mode::Tun).expect("Failed to create a device");
+//! let stream = TcpStream::connect("192.0.2.2:4242").unwrap();
+//! let tunnel = tcp::spawn(iface, stream, forward::unchanged).expect("Failed to start the tunnel");
+//! // Configure the device here
+//! // And once done
+//! let (iface, stream) = tunnel.shutdown();
+//! ```
+
+use std::io::{ErrorKind, Read, Result, Write};
+use std::net::TcpStream;
+use std::os::unix::io::AsRawFd;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle};
+
+use super::Direction;
+use crate::shutdown::ShutdownPipe;
+use crate::{device, Device, Shutdown, ShutdownHandle};
+
+/// The size of the length prefix.
+const PREFIX: usize = 2;
+/// The longest packet that can be framed.
+const MAX_PACKET: usize = u16::MAX as usize;
+
+/// Returns the length of the packet at the start of the buffer, if it is there whole.
+fn complete_frame(buf: &[u8]) -> Option<usize> {
+    if buf.len() < PREFIX {
+        return None;
+    }
+    let len = usize::from(u16::from_be_bytes([buf[0], buf[1]]));
+    if buf.len() < PREFIX + len {
+        return None;
+    }
+    Some(len)
+}
+
+/// Checks the packet fits into a frame, logs it if not.
+fn fits(packet: &[u8]) -> bool {
+    if packet.len() > MAX_PACKET {
+        debug!("Dropped a packet of {} bytes, too long to be tunneled", packet.len());
+        return false;
+    }
+    true
+}
+
+/// Starts forwarding packets between the device and the stream.
+///
+/// The `transform` is called on each packet (see the [`forward`](../index.html) module). Each
+/// direction is served by its own thread.
+///
+/// # Errors
+///
+/// If the stream can't be cloned for the other thread or the threads can't be started.
+pub fn spawn<D, F>(iface: D, stream: TcpStream, transform: F) -> Result<TcpTunnel<D>>
+where
+    D: Device + Send + Sync + 'static,
+    F: Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync + 'static,
+{
+    let writer = stream.try_clone()?;
+    let reader = stream.try_clone()?;
+    let mut tunnel = TcpTunnel {
+        iface: Arc::new(iface),
+        stream: Some(stream),
+        shutdown: ShutdownHandle {
+            pipe: Arc::new(ShutdownPipe::new()?),
+        },
+        threads: Vec::with_capacity(2),
+    };
+    let transform = Arc::new(transform);
+    tunnel.start(Direction::Outbound, writer, &transform)?;
+    tunnel.start(Direction::Inbound, reader, &transform)?;
+    Ok(tunnel)
+}
+
+fn outbound<D, F>(iface: &D, mut stream: TcpStream, shutdown: &ShutdownPipe, transform: &F)
+where
+    D: Device,
+    F: Fn(Direction, &mut Vec<u8>) -> bool,
+{
+    let mut frame = Vec::new();
+    let forwarded = device::pump(iface, Some(shutdown), |packet| {
+        if !transform(Direction::Outbound, packet) || !fits(packet) {
+            return true;
+        }
+        frame.clear();
+        frame.extend_from_slice(&(packet.len() as u16).to_be_bytes());
+        frame.extend_from_slice(packet);
+        // Together, to save a write (and a tiny TCP segment)
+        match stream.write_all(&frame) {
+            Ok(()) => true,
+            Err(e) => {
+                error!("Tunneling from {} failed: {}", iface.name(), e);
+                false
+            },
+        }
+    });
+    if let Err(e) = forwarded {
+        error!("Tunneling from {} failed: {}", iface.name(), e);
+    }
+}
+
+fn inbound<D, F>(iface: &D, mut stream: TcpStream, shutdown: &ShutdownPipe, transform: &F)
+where
+    D: Device,
+    F: Fn(Direction, &mut Vec<u8>) -> bool,
+{
+    let mut chunk = vec![0; PREFIX + MAX_PACKET];
+    // The data read from the stream, possibly ending with an incomplete frame
+    let mut pending = Vec::new();
+    loop {
+        let received = shutdown
+            .wait_readable(stream.as_raw_fd())
+            .and_then(|()| stream.read(&mut chunk));
+        match received {
+            Ok(0) => {
+                debug!("The other end of the tunnel of {} closed the stream", iface.name());
+                return;
+            },
+            Ok(size) => {
+                pending.extend_from_slice(&chunk[..size]);
+                let mut start = 0;
+                while let Some(len) = complete_frame(&pending[start..]) {
+                    let mut packet = pending[start + PREFIX..start + PREFIX + len].to_vec();
+                    start += PREFIX + len;
+                    if transform(Direction::Inbound, &mut packet) {
+                        if let Err(e) = iface.send(&packet) {
+                            debug!("Dropped a packet tunneled into {}: {}", iface.name(), e);
+                        }
+                    }
+                }
+                pending.drain(..start);
+            },
+            Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+            Err(ref e) if Shutdown::is_shutdown(e) => return,
+            Err(e) => {
+                error!("Tunneling into {} failed: {}", iface.name(), e);
+                return;
+            },
+        }
+    }
+}
+
+/// A device tunneled through a TCP stream.
+///
+/// Created by [`spawn`](fn.spawn.html). Dropping it tells the threads to terminate, but doesn't
+/// wait for them, [`shutdown`](#method.shutdown) does (and returns the device and the stream).
+///
+/// A direction stops forwarding on an error of its receiving side, a failed write to the stream
+/// or once the other endpoint closes the stream (it is logged). The other one keeps running.
+#[derive(Debug)]
+pub struct TcpTunnel<D> {
+    iface: Arc<D>,
+    stream: Option<TcpStream>,
+    shutdown: ShutdownHandle,
+    threads: Vec<JoinHandle<()>>,
+}
+
+impl<D> TcpTunnel<D>
+where
+    D: Device + Send + Sync + 'static,
+{
+    fn start<F>(
+        &mut self,
+        direction: Direction,
+        stream: TcpStream,
+        transform: &Arc<F>,
+    ) -> Result<()>
+    where
+        F: Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync + 'static,
+    {
+        let iface = Arc::clone(&self.iface);
+        let shutdown = Arc::clone(&self.shutdown.pipe);
+        let transform = Arc::clone(transform);
+        let thread = thread::Builder::new()
+            .name(format!("{}-tcp", iface.name()))
+            .spawn(move || match direction {
+                Direction::Outbound => outbound(&*iface, stream, &shutdown, &*transform),
+                Direction::Inbound => inbound(&*iface, stream, &shutdown, &*transform),
+            })?;
+        self.threads.push(thread);
+        Ok(())
+    }
+    /// Returns the device.
+    pub fn iface(&self) -> &D {
+        &self.iface
+    }
+    /// Returns the stream.
+    pub fn stream(&self) -> &TcpStream {
+        self.stream.as_ref().expect("Stream taken only on shutdown")
+    }
+    /// Stops the forwarding and returns the device and the stream.
+    ///
+    /// A packet being forwarded is finished first. That includes waiting for the other endpoint
+    /// to accept it, if the stream is full. The data already read from the stream, but not yet
+    /// forwarded (eg. a partial frame), is lost.
+    ///
+    /// # Panics
+    ///
+    /// If the transform panicked.
+    pub fn shutdown(mut self) -> (D, TcpStream) {
+        let _ = self.shutdown.shutdown();
+        for thread in self.threads.drain(..) {
+            thread.join().expect("Tunnel transform panicked");
+        }
+        let iface = Arc::clone(&self.iface);
+        let stream = self.stream.take().expect("Stream taken only on shutdown");
+        drop(self);
+        match Arc::try_unwrap(iface) {
+            Ok(iface) => (iface, stream),
+            Err(_) => unreachable!("Tunnel threads terminated"),
+        }
+    }
+}
+
+impl<D> Drop for TcpTunnel<D> {
+    fn drop(&mut self) {
+        let _ = self.shutdown.shutdown();
+    }
+}
+
+#[cfg(feature = "tokio")]
+pub use self::event_loop::AsyncTcpTunnel;
+
+#[cfg(feature = "tokio")]
+mod event_loop {
+    extern crate bytes;
+    extern crate futures;
+    extern crate tokio_codec;
+    extern crate tokio_io;
+
+    use std::io::Error;
+
+    use self::bytes::{BufMut, BytesMut};
+    use self::futures::{try_ready, Async as FAsync, AsyncSink, Future, Poll, Sink, Stream};
+    use self::tokio_codec::{Decoder, Encoder, Framed};
+    use self::tokio_io::{AsyncRead, AsyncWrite};
+
+    use super::{complete_frame, fits, Direction, PREFIX};
+    use crate::r#async::Async;
+    use crate::{Device, Iface, Shutdown};
+
+    /// The length prefixed framing.
+    #[derive(Debug, Default)]
+    struct Frames;
+
+    impl Decoder for Frames {
+        type Item = Vec<u8>;
+        type Error = Error;
+        fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Vec<u8>>, Error> {
+            match complete_frame(src) {
+                Some(len) => {
+                    let frame = src.split_to(PREFIX + len);
+                    Ok(Some(frame[PREFIX..].to_vec()))
+                },
+                None => Ok(None),
+            }
+        }
+    }
+
+    impl Encoder for Frames {
+        type Item = Vec<u8>;
+        type Error = Error;
+        fn encode(&mut self, item: Vec<u8>, dst: &mut BytesMut) -> Result<(), Error> {
+            dst.reserve(PREFIX + item.len());
+            dst.put_u16_be(item.len() as u16);
+            dst.put_slice(&item);
+            Ok(())
+        }
+    }
+
+    /// A device tunneled through a stream, driven by the tokio event loop.
+    ///
+    /// This is the asynchronous counterpart of the [`TcpTunnel`](struct.TcpTunnel.html), working
+    /// over any stream (a `TcpStream` of `tokio_core`, a TLS stream, ...). It is a future
+    /// forwarding the packets both ways as long as it is polled. It resolves once the other
+    /// endpoint closes the stream or the device is [shut down](../../struct.ShutdownHandle.html)
+    /// and fails on an error of either of them. Dropping it stops the forwarding.
+    ///
+    /// The stream may accept or return the frames in arbitrary pieces. While the stream isn't
+    /// able to take more data, no packets are read from the device, and while the device isn't
+    /// able to take more packets, nothing is read from the stream.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate futures;
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use futures::Future;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::Async;
+    /// # use tun_tap::forward::{self, tcp::AsyncTcpTunnel};
+    /// # use tokio_core::net::TcpStream;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::without_packet_info("vpn%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let handle = core.handle();
+    /// let iface = Async::new(iface, &handle).unwrap();
+    /// let tunnel = TcpStream::connect(&"192.0.2.2:4242".parse().unwrap(), &handle)
+    ///     .and_then(|stream| AsyncTcpTunnel::new(iface, stream, forward::unchanged));
+    /// core.run(tunnel).unwrap();
+    /// # }
+    /// ```
+    pub struct AsyncTcpTunnel<S, F, D = Iface> {
+        iface: Async<D>,
+        stream: Framed<S, Frames>,
+        transform: F,
+        outbound: Option<Vec<u8>>,
+        inbound: Option<Vec<u8>>,
+    }
+
+    impl<S, F, D> AsyncTcpTunnel<S, F, D>
+    where
+        S: AsyncRead + AsyncWrite,
+        D: Device,
+        F: FnMut(Direction, &mut Vec<u8>) -> bool,
+    {
+        /// Creates the tunnel.
+        ///
+        /// The `transform` is called on each packet (see the [`forward`](../index.html) module).
+        pub fn new(iface: Async<D>, stream: S, transform: F) -> Self {
+            AsyncTcpTunnel {
+                iface,
+                stream: Framed::new(stream, Frames),
+                transform,
+                outbound: None,
+                inbound: None,
+            }
+        }
+        /// Returns the device.
+        pub fn get_ref(&self) -> &Async<D> {
+            &self.iface
+        }
+        /// Returns the stream.
+        pub fn stream(&self) -> &S {
+            self.stream.get_ref()
+        }
+        /// Stops the forwarding and returns the device and the stream.
+        ///
+        /// The packets received, but not yet sent, are lost. That includes the buffered data of
+        /// the stream, so it is not usable for continuing the tunnel.
+        pub fn into_inner(self) -> (Async<D>, S) {
+            (self.iface, self.stream.into_inner())
+        }
+        fn poll_outbound(&mut self) -> Poll<(), Error> {
+            loop {
+                if let Some(packet) = self.outbound.take() {
+                    if let AsyncSink::NotReady(packet) = self.stream.start_send(packet)? {
+                        self.outbound = Some(packet);
+                        break;
+                    }
+                }
+                let mut packet = vec![0; self.iface.recv_bufsize()];
+                match self.iface.poll_recv(&mut packet)? {
+                    FAsync::Ready(size) => {
+                        packet.truncate(size);
+                        if (self.transform)(Direction::Outbound, &mut packet) && fits(&packet) {
+                            self.outbound = Some(packet);
+                        }
+                    },
+                    FAsync::NotReady => break,
+                }
+            }
+            self.stream.poll_complete()?;
+            Ok(FAsync::NotReady)
+        }
+        fn poll_inbound(&mut self) -> Poll<(), Error> {
+            loop {
+                if let Some(packet) = self.inbound.take() {
+                    match self.iface.poll_send(&packet) {
+                        Ok(FAsync::Ready(_)) => (),
+                        Ok(FAsync::NotReady) => {
+                            self.inbound = Some(packet);
+                            return Ok(FAsync::NotReady);
+                        },
+                        Err(ref e) if !Shutdown::is_shutdown(e) => {
+                            debug!("Dropped a packet tunneled into {}: {}", self.name(), e)
+                        },
+                        Err(e) => return Err(e),
+                    }
+                }
+                match try_ready!(self.stream.poll()) {
+                    Some(mut packet) => {
+                        if (self.transform)(Direction::Inbound, &mut packet) {
+                            self.inbound = Some(packet);
+                        }
+                    },
+                    None => {
+                        debug!("The other end of the tunnel of {} closed the stream", self.name());
+                        return Ok(FAsync::Ready(()));
+                    },
+                }
+            }
+        }
+        fn name(&self) -> &str {
+            self.iface.iface().name()
+        }
+    }
+
+    impl<S, F, D> Future for AsyncTcpTunnel<S, F, D>
+    where
+        S: AsyncRead + AsyncWrite,
+        D: Device,
+        F: FnMut(Direction, &mut Vec<u8>) -> bool,
+    {
+        type Item = ();
+        type Error = Error;
+        fn poll(&mut self) -> Poll<(), Error> {
+            // Both directions need to be polled, to register for their wake ups. Only the inbound
+            // one ends (when the stream does).
+            match self.poll_outbound().and_then(|_| self.poll_inbound()) {
+                Err(ref e) if Shutdown::is_shutdown(e) => Ok(FAsync::Ready(())),
+                result => result,
+            }
+        }
+    }
+}
@@ -0,0 +1,230 @@
+//! Tunneling the packets through UDP.
+//!
+//! Each packet of the device becomes a single datagram (after passing through the transform) and
+//! each received datagram is put into the device as a single packet. The socket needs to be
+//! connected to the other endpoint, datagrams from anywhere else are ignored by the kernel.
+//!
+//! The [`UdpTunnel`](type.UdpTunnel.html) forwards in its own threads, the
+//! [`AsyncUdpTunnel`](struct.AsyncUdpTunnel.html) (with the `tokio` feature) is a future running
+//! in the event loop.
+//!
+//! Packets that fail to be delivered either way are dropped (and logged), like on any other
+//! network. That includes the time the other endpoint is not up yet.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::net::UdpSocket;
+//! # use tun_tap::*;
+//! # use tun_tap::forward::{udp, Direction};
+//! let iface = Iface::without_packet_info("vpn%d", Mode::Tun).expect("Failed to create a device");
+//! let socket = UdpSocket::bind("192.0.2.1:4242").unwrap();
+//! socket.connect("192.0.2.2:4242").unwrap();
+//! // Not a real encryption, don't do this at home
+//! let tunnel = udp::spawn(iface, socket, |_: Direction, packet: &mut Vec<u8>| {
+//!     packet.iter_mut().for_each(|b| *b ^= 0x42);
+//!     true
+//! })
+//! .expect("Failed to start the tunnel");
+//! // Configure the device here
+//! // And once done
+//! let (iface, socket) = tunnel.shutdown();
+//! ```
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::UdpSocket;
+
+use super::datagram::{self, DatagramTunnel};
+use super::Direction;
+use crate::Device;
+
+/// A device tunneled through a UDP socket.
+///
+/// Created by [`spawn`](fn.spawn.html), see the [`DatagramTunnel`](../struct.DatagramTunnel.html)
+/// for the details.
+pub type UdpTunnel<D> = DatagramTunnel<D, UdpSocket>;
+
+/// Starts forwarding packets between the device and the socket.
+///
+/// The `socket` needs to be connected. The `transform` is called on each packet (see the
+/// [`forward`](../index.html) module). Each direction is served by its own thread.
+///
+/// # Errors
+///
+/// If the socket is not connected or the threads can't be started.
+pub fn spawn<D, F>(iface: D, socket: UdpSocket, transform: F) -> Result<UdpTunnel<D>>
+where
+    D: Device + Send + Sync + 'static,
+    F: Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync + 'static,
+{
+    if socket.peer_addr().is_err() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The tunnel socket needs to be connected",
+        ));
+    }
+    datagram::spawn(iface, socket, transform)
+}
+
+#[cfg(feature = "tokio")]
+pub use self::event_loop::AsyncUdpTunnel;
+
+#[cfg(feature = "tokio")]
+mod event_loop {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use std::io::{Error, ErrorKind};
+
+    use self::futures::{Async as FAsync, Future, Poll};
+    use self::tokio_core::net::UdpSocket;
+
+    use super::super::datagram::{is_transient, MAX_DATAGRAM};
+    use super::Direction;
+    use crate::r#async::Async;
+    use crate::{Device, Iface, Shutdown};
+
+    /// A packet on its way, waiting for the other side to become writable.
+    #[derive(Default)]
+    struct Pending {
+        buffer: Vec<u8>,
+        full: bool,
+    }
+
+    /// A device tunneled through a UDP socket, driven by the tokio event loop.
+    ///
+    /// This is the asynchronous counterpart of the [`UdpTunnel`](type.UdpTunnel.html). It is a
+    /// future forwarding the packets both ways as long as it is polled. It resolves once the
+    /// device is [shut down](../../struct.ShutdownHandle.html) and fails on an error receiving
+    /// from the device or the socket. Dropping it stops the forwarding.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # extern crate tokio_core;
+    /// # extern crate tun_tap;
+    /// # use tun_tap::*;
+    /// # use tun_tap::r#async::Async;
+    /// # use tun_tap::forward::{self, udp::AsyncUdpTunnel};
+    /// # use tokio_core::net::UdpSocket;
+    /// # use tokio_core::reactor::Core;
+    /// # fn main() {
+    /// let iface = Iface::without_packet_info("vpn%d", Mode::Tun).unwrap();
+    /// let mut core = Core::new().unwrap();
+    /// let iface = Async::new(iface, &core.handle()).unwrap();
+    /// let socket = UdpSocket::bind(&"192.0.2.1:4242".parse().unwrap(), &core.handle()).unwrap();
+    /// socket.connect(&"192.0.2.2:4242".parse().unwrap()).unwrap();
+    /// core.run(AsyncUdpTunnel::new(iface, socket, forward::unchanged)).unwrap();
+    /// # }
+    /// ```
+    pub struct AsyncUdpTunnel<F, D = Iface> {
+        iface: Async<D>,
+        socket: UdpSocket,
+        transform: F,
+        outbound: Pending,
+        inbound: Pending,
+    }
+
+    impl<F, D> AsyncUdpTunnel<F, D>
+    where
+        D: Device,
+        F: FnMut(Direction, &mut Vec<u8>) -> bool,
+    {
+        /// Creates the tunnel.
+        ///
+        /// The `socket` needs to be connected to the other endpoint. The `transform` is called on
+        /// each packet (see the [`forward`](../index.html) module).
+        pub fn new(iface: Async<D>, socket: UdpSocket, transform: F) -> Self {
+            AsyncUdpTunnel {
+                iface,
+                socket,
+                transform,
+                outbound: Pending::default(),
+                inbound: Pending::default(),
+            }
+        }
+        /// Returns the device.
+        pub fn get_ref(&self) -> &Async<D> {
+            &self.iface
+        }
+        /// Returns the socket.
+        pub fn socket(&self) -> &UdpSocket {
+            &self.socket
+        }
+        /// Stops the forwarding and returns the device and the socket.
+        ///
+        /// A packet received, but not yet sent, is lost.
+        pub fn into_inner(self) -> (Async<D>, UdpSocket) {
+            (self.iface, self.socket)
+        }
+        fn poll_outbound(&mut self) -> Poll<(), Error> {
+            loop {
+                if self.outbound.full {
+                    match self.socket.send(&self.outbound.buffer) {
+                        Ok(_) => (),
+                        Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                            return Ok(FAsync::NotReady)
+                        },
+                        Err(e) => {
+                            debug!("Dropped a packet tunneled from {}: {}", self.name(), e)
+                        },
+                    }
+                    self.outbound.full = false;
+                }
+                self.outbound.buffer.resize(self.iface.recv_bufsize(), 0);
+                let size = match self.iface.poll_recv(&mut self.outbound.buffer)? {
+                    FAsync::Ready(size) => size,
+                    FAsync::NotReady => return Ok(FAsync::NotReady),
+                };
+                let buffer = &mut self.outbound.buffer;
+                buffer.truncate(size);
+                self.outbound.full = (self.transform)(Direction::Outbound, buffer);
+            }
+        }
+        fn poll_inbound(&mut self) -> Poll<(), Error> {
+            loop {
+                if self.inbound.full {
+                    match self.iface.poll_send(&self.inbound.buffer) {
+                        Ok(FAsync::Ready(_)) => (),
+                        Ok(FAsync::NotReady) => return Ok(FAsync::NotReady),
+                        Err(ref e) if !Shutdown::is_shutdown(e) => {
+                            debug!("Dropped a packet tunneled into {}: {}", self.name(), e)
+                        },
+                        Err(e) => return Err(e),
+                    }
+                    self.inbound.full = false;
+                }
+                self.inbound.buffer.resize(MAX_DATAGRAM, 0);
+                let size = match self.socket.recv(&mut self.inbound.buffer) {
+                    Ok(size) => size,
+                    Err(ref e) if e.kind() == ErrorKind::WouldBlock => return Ok(FAsync::NotReady),
+                    Err(ref e) if is_transient(e) => continue,
+                    Err(e) => return Err(e),
+                };
+                let buffer = &mut self.inbound.buffer;
+                buffer.truncate(size);
+                self.inbound.full = (self.transform)(Direction::Inbound, buffer);
+            }
+        }
+        fn name(&self) -> &str {
+            self.iface.iface().name()
+        }
+    }
+
+    impl<F, D> Future for AsyncUdpTunnel<F, D>
+    where
+        D: Device,
+        F: FnMut(Direction, &mut Vec<u8>) -> bool,
+    {
+        type Item = ();
+        type Error = Error;
+        fn poll(&mut self) -> Poll<(), Error> {
+            // Both directions need to be polled, to register for their wake ups. Neither of them
+            // ends with success.
+            match self.poll_outbound().and_then(|_| self.poll_inbound()) {
+                Err(ref e) if Shutdown::is_shutdown(e) => Ok(FAsync::Ready(())),
+                result => result,
+            }
+        }
+    }
+}
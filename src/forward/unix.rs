@@ -0,0 +1,95 @@
+//! Forwarding the packets through a Unix domain socket.
+//!
+//! This serves the networking of a container or a sandboxed process (which has no device of its
+//! own) from a process on the host side, over a socket they already share (eg. inherited or
+//! passed along with other file descriptors). Each packet is sent as a single message of a
+//! `SOCK_DGRAM` or `SOCK_SEQPACKET` socket, the other side just reads and writes whole packets
+//! (and may run a userspace network stack on them).
+//!
+//! The standard library has no type for `SOCK_SEQPACKET` sockets, but their connected ends work
+//! as a `UnixDatagram` (eg. `UnixDatagram::from(OwnedFd)`). The
+//! [`seqpacket_pair`](fn.seqpacket_pair.html) creates such a pair. Unlike with a `SOCK_DGRAM`
+//! socket, the forwarding stops once the other side closes it.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::io::Error;
+//! # use std::os::unix::io::AsRawFd;
+//! # use std::os::unix::process::CommandExt;
+//! # use std::process::Command;
+//! # use tun_tap::*;
+//! # use tun_tap::forward::{self, unix};
+//! let iface = Iface::without_packet_info("sandbox%d", Mode::Tun).expect("Failed to create");
+//! let (ours, theirs) = unix::seqpacket_pair().unwrap();
+//! // Hand the other end to the sandboxed process somehow, eg. by inheritance
+//! let fd = theirs.as_raw_fd();
+//! let mut command = Command::new("sandboxed");
+//! command.arg(fd.to_string());
+//! // The sockets are close-on-exec, this one needs to survive the exec in the child
+//! unsafe {
+//!     command.pre_exec(move || {
+//!         if libc::fcntl(fd, libc::F_SETFD, 0) < 0 {
+//!             return Err(Error::last_os_error());
+//!         }
+//!         Ok(())
+//!     });
+//! }
+//! let mut child = command.spawn().unwrap();
+//! drop(theirs);
+//! let tunnel = unix::spawn(iface, ours, forward::unchanged).expect("Failed to start forwarding");
+//! child.wait().unwrap();
+//! tunnel.shutdown();
+//! ```
+
+use std::io::{Error, ErrorKind, Result};
+use std::os::raw::c_int;
+use std::os::unix::io::FromRawFd;
+use std::os::unix::net::UnixDatagram;
+
+use super::datagram::{self, DatagramTunnel};
+use super::Direction;
+use crate::Device;
+
+/// A device tunneled through a Unix domain socket.
+///
+/// Created by [`spawn`](fn.spawn.html), see the [`DatagramTunnel`](../struct.DatagramTunnel.html)
+/// for the details.
+pub type UnixTunnel<D> = DatagramTunnel<D, UnixDatagram>;
+
+/// Creates a connected pair of `SOCK_SEQPACKET` sockets.
+///
+/// Unlike `UnixDatagram::pair`, the other side learns when one of them is closed. The sockets
+/// are close-on-exec, so they need to be passed to a child process explicitly.
+pub fn seqpacket_pair() -> Result<(UnixDatagram, UnixDatagram)> {
+    let mut fds: [c_int; 2] = [-1; 2];
+    let kind = libc::SOCK_SEQPACKET | libc::SOCK_CLOEXEC;
+    if unsafe { libc::socketpair(libc::AF_UNIX, kind, 0, fds.as_mut_ptr()) } < 0 {
+        return Err(Error::last_os_error());
+    }
+    let pair = unsafe { (UnixDatagram::from_raw_fd(fds[0]), UnixDatagram::from_raw_fd(fds[1])) };
+    Ok(pair)
+}
+
+/// Starts forwarding packets between the device and the socket.
+///
+/// The `socket` needs to be connected (eg. one of a pair). The `transform` is called on each
+/// packet (see the [`forward`](../index.html) module). Each direction is served by its own
+/// thread.
+///
+/// # Errors
+///
+/// If the socket is not connected or the threads can't be started.
+pub fn spawn<D, F>(iface: D, socket: UnixDatagram, transform: F) -> Result<UnixTunnel<D>>
+where
+    D: Device + Send + Sync + 'static,
+    F: Fn(Direction, &mut Vec<u8>) -> bool + Send + Sync + 'static,
+{
+    if socket.peer_addr().is_err() {
+        return Err(Error::new(
+            ErrorKind::InvalidInput,
+            "The tunnel socket needs to be connected",
+        ));
+    }
+    datagram::spawn(iface, socket, transform)
+}
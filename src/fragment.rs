@@ -0,0 +1,383 @@
+//! Fragmenting and reassembling IPv4 packets.
+//!
+//! A tunnel eats some of the MTU of the path it goes through. The packets of the inner network
+//! that no longer fit are normally refused by the tunnel with an ICMP error and the sender lowers
+//! the size of its packets (the path MTU discovery). That doesn't work everywhere ‒ the ICMP
+//! errors get filtered, some applications ignore them and some transports don't report the limit
+//! at all. The alternative is to split the large packets into fragments on the way in and glue
+//! them back together on the other side, the way the routers used to do it.
+//!
+//! The [`fragment`](fn.fragment.html) function splits a single packet, the
+//! [`Reassembler`](struct.Reassembler.html) collects the fragments and puts the packets together
+//! again. Both work on bare IPv4 packets (without the packet info or the ethernet header). IPv6
+//! has no fragmentation on the way, so there's nothing to do about it here.
+//!
+//! This needs the `fragment` feature.
+//!
+//! # Examples
+//!
+//! ```rust
+//! # use tun_tap::fragment::{self, Reassembler};
+//! let mut packet = vec![0x45, 0, 0x05, 0xDC, 0, 42, 0, 0, 64, 17, 0, 0];
+//! packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]); // The addresses
+//! packet.resize(1500, 0); // Some payload
+//! // Going through a tunnel with 1400 bytes of room
+//! let fragments = fragment::fragment(&packet, 1400).unwrap();
+//! assert_eq!(2, fragments.len());
+//! // And on the other side
+//! let mut reassembler = Reassembler::new();
+//! assert_eq!(None, reassembler.push(&fragments[1]));
+//! let reassembled = reassembler.push(&fragments[0]).unwrap();
+//! assert_eq!(&packet[20..], &reassembled[20..]);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::net::Ipv4Addr;
+use std::time::{Duration, Instant};
+
+use crate::checksum::checksum;
+
+/// The more fragments flag.
+const MF: u16 = 0x2000;
+/// The don't fragment flag.
+const DF: u16 = 0x4000;
+/// The fragment offset, in units of 8 bytes.
+const OFFSET: u16 = 0x1FFF;
+/// The largest IP packet.
+const MAX_SIZE: usize = 65_535;
+
+/// Why a packet can't be fragmented.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum FragmentError {
+    /// It is not a valid IPv4 packet.
+    Malformed,
+    /// The packet is too large and has the don't fragment flag.
+    ///
+    /// The sender should be told by an ICMP error (fragmentation needed).
+    DontFragment,
+    /// The MTU doesn't leave room even for the header and 8 bytes of the payload.
+    MtuTooSmall,
+}
+
+impl Display for FragmentError {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            FragmentError::Malformed => write!(fmt, "Not a valid IPv4 packet"),
+            FragmentError::DontFragment => write!(fmt, "The packet must not be fragmented"),
+            FragmentError::MtuTooSmall => write!(fmt, "The MTU is too small to fragment into"),
+        }
+    }
+}
+
+impl std::error::Error for FragmentError {}
+
+fn be16(packet: &[u8], offset: usize) -> u16 {
+    u16::from_be_bytes([packet[offset], packet[offset + 1]])
+}
+
+/// Checks the packet is IPv4 and returns the length of its header and of the whole packet.
+fn parse(packet: &[u8]) -> Option<(usize, usize)> {
+    if packet.len() < 20 || packet[0] >> 4 != 4 {
+        return None;
+    }
+    let header_len = usize::from(packet[0] & 0x0F) * 4;
+    let total = usize::from(be16(packet, 2));
+    if header_len < 20 || total < header_len || total > packet.len() {
+        return None;
+    }
+    Some((header_len, total))
+}
+
+/// Fills in the length, the fragmentation fields and the checksum of the header.
+fn finish_header(packet: &mut [u8], header_len: usize, flags_offset: u16) {
+    let total = packet.len() as u16;
+    packet[2..4].copy_from_slice(&total.to_be_bytes());
+    packet[6..8].copy_from_slice(&flags_offset.to_be_bytes());
+    packet[10..12].copy_from_slice(&[0, 0]);
+    let sum = checksum(&packet[..header_len]);
+    packet[10..12].copy_from_slice(&sum.to_be_bytes());
+}
+
+/// The options that go into every fragment, not only the first one (padded).
+fn copied_options(options: &[u8]) -> Vec<u8> {
+    let mut copied = Vec::new();
+    let mut i = 0;
+    while i < options.len() {
+        match options[i] {
+            // End of the options
+            0 => break,
+            // No operation
+            1 => i += 1,
+            kind => {
+                let len = options.get(i + 1).map_or(0, |len| usize::from(*len));
+                if len < 2 || i + len > options.len() {
+                    break;
+                }
+                if kind & 0x80 != 0 {
+                    copied.extend_from_slice(&options[i..i + len]);
+                }
+                i += len;
+            },
+        }
+    }
+    copied.resize(copied.len().div_ceil(4) * 4, 0);
+    copied
+}
+
+/// Splits an IPv4 packet into fragments no larger than the `mtu`.
+///
+/// A packet that fits is returned as it is (as the only item). The options are kept in the first
+/// fragment, the later ones get only those meant to be copied. Fragmenting an already fragmented
+/// packet works too, the pieces can be reassembled at once.
+///
+/// Anything after the length in the header (eg. the padding of a short ethernet frame) is cut
+/// off.
+///
+/// # Errors
+///
+/// If it isn't an IPv4 packet, if it has the don't fragment flag or the `mtu` is too small to
+/// make any progress.
+pub fn fragment(packet: &[u8], mtu: usize) -> Result<Vec<Vec<u8>>, FragmentError> {
+    let (header_len, total) = parse(packet).ok_or(FragmentError::Malformed)?;
+    let packet = &packet[..total];
+    if total <= mtu {
+        return Ok(vec![packet.to_vec()]);
+    }
+    let flags_offset = be16(packet, 6);
+    if flags_offset & DF != 0 {
+        return Err(FragmentError::DontFragment);
+    }
+    if mtu < header_len + 8 {
+        return Err(FragmentError::MtuTooSmall);
+    }
+    let first_header = &packet[..header_len];
+    let mut later_header = first_header[..20].to_vec();
+    later_header.extend(copied_options(&first_header[20..]));
+    later_header[0] = 0x40 | (later_header.len() / 4) as u8;
+    // The place of this packet in the original one, if it is a fragment already
+    let base = usize::from(flags_offset & OFFSET) * 8;
+    let more = flags_offset & MF != 0;
+    let payload = &packet[header_len..];
+    let mut fragments = Vec::new();
+    let mut start = 0;
+    while start < payload.len() {
+        let header = if start == 0 {
+            first_header
+        } else {
+            &later_header[..]
+        };
+        let end = payload.len().min(start + (mtu - header.len()) / 8 * 8);
+        let mut fragment = Vec::with_capacity(header.len() + end - start);
+        fragment.extend_from_slice(header);
+        fragment.extend_from_slice(&payload[start..end]);
+        let mut flags_offset = (flags_offset & !(MF | OFFSET)) | ((base + start) / 8) as u16;
+        if end < payload.len() || more {
+            flags_offset |= MF;
+        }
+        finish_header(&mut fragment, header.len(), flags_offset);
+        fragments.push(fragment);
+        start = end;
+    }
+    Ok(fragments)
+}
+
+/// Identifies the fragments of a single packet.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+struct Key {
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+    protocol: u8,
+    id: u16,
+}
+
+/// A packet being put together.
+#[derive(Debug)]
+struct Partial {
+    started: Instant,
+    /// The header of the first fragment, once it comes.
+    header: Option<Vec<u8>>,
+    payload: Vec<u8>,
+    /// The parts of the payload received so far, sorted and merged.
+    received: Vec<(usize, usize)>,
+    /// The length of the payload, known from the last fragment.
+    len: Option<usize>,
+}
+
+impl Partial {
+    fn new() -> Self {
+        Partial {
+            started: Instant::now(),
+            header: None,
+            payload: Vec::new(),
+            received: Vec::new(),
+            len: None,
+        }
+    }
+    /// Adds a fragment, returns false if it doesn't fit with the others.
+    fn add(&mut self, start: usize, data: &[u8], last: bool) -> bool {
+        let end = start + data.len();
+        match self.len {
+            Some(len) if end > len || (last && end != len) => return false,
+            // Something already came from beyond the end
+            None if last && self.payload.len() > end => return false,
+            _ => (),
+        }
+        if last {
+            self.len = Some(end);
+        }
+        if self.payload.len() < end {
+            self.payload.resize(end, 0);
+        }
+        self.payload[start..end].copy_from_slice(data);
+        self.received.push((start, end));
+        self.received.sort_unstable();
+        let mut merged: Vec<(usize, usize)> = Vec::with_capacity(self.received.len());
+        for &(start, end) in &self.received {
+            match merged.last_mut() {
+                Some(previous) if previous.1 >= start => previous.1 = previous.1.max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+        self.received = merged;
+        true
+    }
+    fn is_complete(&self) -> bool {
+        match self.len {
+            Some(len) => self.header.is_some() && self.received == [(0, len)],
+            None => false,
+        }
+    }
+}
+
+/// Puts the fragmented IPv4 packets back together.
+///
+/// The fragments are fed to it by [`push`](#method.push), one by one, in any order. Once all the
+/// fragments of a packet are there, the whole packet comes out. Packets that are not fragmented
+/// pass through right away.
+///
+/// The incomplete packets are kept only for a limited time (30 seconds by default, like the
+/// kernel does) and only a limited number of them (64 by default), to not run out of memory when
+/// fragments get lost. The ones over the limit are forgotten, starting with the oldest.
+///
+/// Overlapping fragments are accepted, the later ones win. Fragments not agreeing on the size of
+/// the packet throw the whole packet away.
+#[derive(Debug)]
+pub struct Reassembler {
+    partial: HashMap<Key, Partial>,
+    timeout: Duration,
+    max_pending: usize,
+    expired: u64,
+}
+
+impl Reassembler {
+    /// Creates the reassembler, with the default limits.
+    pub fn new() -> Self {
+        Reassembler {
+            partial: HashMap::new(),
+            timeout: Duration::from_secs(30),
+            max_pending: 64,
+            expired: 0,
+        }
+    }
+    /// Sets how long to wait for the rest of the fragments of a packet.
+    pub fn timeout(self, timeout: Duration) -> Self {
+        Reassembler { timeout, ..self }
+    }
+    /// Sets how many incomplete packets can be kept at once.
+    ///
+    /// # Panics
+    ///
+    /// If it is 0.
+    pub fn max_pending(self, max_pending: usize) -> Self {
+        assert!(max_pending > 0, "Need room for at least one packet");
+        Reassembler {
+            max_pending,
+            ..self
+        }
+    }
+    /// Feeds another packet.
+    ///
+    /// Returns the packet that got completed by it, if any. Anything but IPv4 fragments
+    /// (including IPv6 packets) is returned as it is.
+    pub fn push(&mut self, packet: &[u8]) -> Option<Vec<u8>> {
+        let (header_len, total) = match parse(packet) {
+            Some(lengths) => lengths,
+            None => return Some(packet.to_vec()),
+        };
+        let flags_offset = be16(packet, 6);
+        if flags_offset & (MF | OFFSET) == 0 {
+            return Some(packet.to_vec());
+        }
+        self.expire();
+        let key = Key {
+            source: Ipv4Addr::new(packet[12], packet[13], packet[14], packet[15]),
+            destination: Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]),
+            protocol: packet[9],
+            id: be16(packet, 4),
+        };
+        if !self.partial.contains_key(&key) && self.partial.len() >= self.max_pending {
+            let oldest = self
+                .partial
+                .iter()
+                .min_by_key(|(_, partial)| partial.started)
+                .map(|(key, _)| *key);
+            if let Some(oldest) = oldest {
+                trace!("Forgetting fragments of {:?}, too many pending", oldest);
+                self.partial.remove(&oldest);
+                self.expired += 1;
+            }
+        }
+        let start = usize::from(flags_offset & OFFSET) * 8;
+        let data = &packet[header_len..total];
+        let last = flags_offset & MF == 0;
+        // All but the last fragment carry whole blocks of 8 bytes
+        if (!last && data.len() & 7 != 0) || start + data.len() + header_len > MAX_SIZE {
+            return None;
+        }
+        let partial = self.partial.entry(key).or_insert_with(Partial::new);
+        if !partial.add(start, data, last) {
+            debug!("Inconsistent fragments of {:?}", key);
+            self.partial.remove(&key);
+            return None;
+        }
+        if start == 0 {
+            partial.header = Some(packet[..header_len].to_vec());
+        }
+        if !partial.is_complete() {
+            return None;
+        }
+        let partial = self.partial.remove(&key).expect("Just found it");
+        let header = partial.header.expect("Checked by is_complete");
+        // The fragments were checked against their own headers, the first one may be longer
+        if header.len() + partial.payload.len() > MAX_SIZE {
+            debug!("Reassembled packet {:?} too large", key);
+            return None;
+        }
+        let mut reassembled = header.clone();
+        reassembled.extend_from_slice(&partial.payload);
+        let flags_offset = be16(&header, 6) & !(MF | OFFSET);
+        finish_header(&mut reassembled, header.len(), flags_offset);
+        Some(reassembled)
+    }
+    fn expire(&mut self) {
+        let timeout = self.timeout;
+        let before = self.partial.len();
+        self.partial.retain(|_, partial| partial.started.elapsed() < timeout);
+        self.expired += (before - self.partial.len()) as u64;
+    }
+    /// The number of incomplete packets waiting for more fragments.
+    pub fn pending(&self) -> usize {
+        self.partial.len()
+    }
+    /// The number of incomplete packets given up on so far (timed out or over the limit).
+    pub fn expired(&self) -> u64 {
+        self.expired
+    }
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,384 @@
+//! Sending bursts of packets with fewer writes, by the segmentation offload.
+//!
+//! Each write into a TUN device is a syscall and a trip through the network stack. A VPN
+//! decrypting a burst of packets of a single flow (eg. a TCP upload) spends a lot of time on
+//! these. If the device has the virtio net header enabled (the
+//! [`VNET_HDR`](../struct.TunFlags.html#associatedconstant.VNET_HDR) flag), consecutive packets
+//! of the same TCP or UDP flow can be glued together into a single large packet, written at
+//! once and split back by the kernel (the generic segmentation offload).
+//!
+//! The [`GsoSender`](struct.GsoSender.html) does this. Without the virtio net header, it sends
+//! the packets one by one, so the same code works with both kinds of devices.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use tun_tap::*;
+//! # use tun_tap::gso::GsoSender;
+//! let iface = Iface::with_flags("mytun", TunFlags::TUN | TunFlags::NO_PI | TunFlags::VNET_HDR)
+//!     .expect("Failed to create a TUN device");
+//! let mut sender = GsoSender::new(iface, true);
+//! # let decrypted: Vec<Vec<u8>> = Vec::new();
+//! // A burst of IP packets, eg. decrypted from the tunnel
+//! sender
+//!     .send_batch(decrypted.iter().map(Vec::as_slice))
+//!     .expect("Failed to send");
+//! ```
+
+use std::io::Result;
+use std::os::unix::io::{AsRawFd, RawFd};
+
+use crate::checksum::{checksum, fold, sum};
+use crate::packet::{EthernetHeader, VirtioNetHeader};
+use crate::{Device, EtherType, Iface, Mode, PacketInfo};
+
+/// The largest IP packet.
+const MAX_SIZE: usize = 65_535;
+/// The kernel refuses to split a packet into too many segments.
+const MAX_SEGMENTS: usize = 64;
+
+const TCP: u8 = 6;
+const UDP: u8 = 17;
+const TCP_ACK: u8 = 0x10;
+const TCP_PSH: u8 = 0x08;
+
+/// The headers of a packet that can be part of a batch.
+#[derive(Copy, Clone, Debug)]
+struct Segment {
+    ipv6: bool,
+    proto: u8,
+    // Length of the IP header
+    ip_len: usize,
+    // Length of the IP and transport headers
+    hdr_len: usize,
+    seq: u32,
+    psh: bool,
+}
+
+impl Segment {
+    fn parse(packet: &[u8]) -> Option<Self> {
+        let be16 = |i: usize| usize::from(u16::from_be_bytes([packet[i], packet[i + 1]]));
+        let (ipv6, ip_len, proto) = match packet.first()? >> 4 {
+            4 => {
+                // No options, no fragments
+                if packet.len() < 20 || packet[0] & 0x0f != 5 || be16(6) & 0x3fff != 0 {
+                    return None;
+                }
+                (false, 20, packet[9])
+            },
+            // No extension headers (the next header would not be TCP or UDP)
+            6 if packet.len() >= 40 => (true, 40, packet[6]),
+            _ => return None,
+        };
+        let ip_total = if ipv6 { be16(4) + 40 } else { be16(2) };
+        if ip_total != packet.len() {
+            return None;
+        }
+        let (hdr_len, seq, psh) = match proto {
+            UDP if packet.len() >= ip_len + 8 => {
+                if be16(ip_len + 4) != packet.len() - ip_len {
+                    return None;
+                }
+                (ip_len + 8, 0, false)
+            },
+            TCP if packet.len() >= ip_len + 20 => {
+                let hdr_len = ip_len + usize::from(packet[ip_len + 12] >> 4) * 4;
+                let flags = packet[ip_len + 13];
+                // Plain data segments only, no SYN, FIN, URG…
+                if hdr_len < ip_len + 20 || hdr_len > packet.len() || flags & !TCP_PSH != TCP_ACK
+                {
+                    return None;
+                }
+                let mut seq = [0; 4];
+                seq.copy_from_slice(&packet[ip_len + 4..ip_len + 8]);
+                (hdr_len, u32::from_be_bytes(seq), flags & TCP_PSH != 0)
+            },
+            _ => return None,
+        };
+        if hdr_len == packet.len() {
+            // Nothing to segment
+            return None;
+        }
+        Some(Segment {
+            ipv6,
+            proto,
+            ip_len,
+            hdr_len,
+            seq,
+            psh,
+        })
+    }
+}
+
+/// The packets glued together so far.
+#[derive(Copy, Clone, Debug)]
+struct Batch {
+    // None if the packet can't be glued to anything
+    first: Option<Segment>,
+    gso_size: usize,
+    segments: usize,
+    next_seq: u32,
+    // A shorter (or pushed) segment was added, nothing can follow
+    closed: bool,
+}
+
+/// Compares the headers that need to be the same in all the segments.
+fn same_flow(first: &Segment, a: &[u8], b: &[u8]) -> bool {
+    let ip = if first.ipv6 {
+        // Everything except the payload length
+        a[..4] == b[..4] && a[6..40] == b[6..40]
+    } else {
+        // Everything except the total length, identification and checksum
+        a[..2] == b[..2] && a[6..10] == b[6..10] && a[12..20] == b[12..20]
+    };
+    let (a, b) = (&a[first.ip_len..first.hdr_len], &b[first.ip_len..first.hdr_len]);
+    let transport = if first.proto == UDP {
+        // The ports
+        a[..4] == b[..4]
+    } else {
+        // Everything except the sequence number, the PSH flag and the checksum
+        a[..4] == b[..4]
+            && a[8..13] == b[8..13]
+            && a[13] & !TCP_PSH == b[13] & !TCP_PSH
+            && a[14..16] == b[14..16]
+            && a[18..] == b[18..]
+    };
+    ip && transport
+}
+
+/// A sender gluing bursts of same-flow packets into fewer writes.
+///
+/// Packets are [`push`](#method.push)ed into the sender. As long as they continue the same TCP
+/// or UDP flow (same addresses and ports, same segment size, the TCP sequence numbers following
+/// each other), they are collected into a single large packet. The packet is written once a
+/// packet of another flow comes or on [`flush`](#method.flush), with the virtio net header
+/// telling the kernel how to split it back. The receivers get the same packets as if they were
+/// sent one by one.
+///
+/// Only IPv4 and IPv6 packets without options or extension headers in the TUN mode are glued
+/// together, the rest is sent as it is. Without the virtio net header, all the packets are sent
+/// as they are, right away.
+///
+/// The packets are IP packets (or ethernet frames in the TAP mode), without the packet info or
+/// the virtio net header. The sender adds these as the device needs.
+///
+/// Dropping the sender (or unwrapping it by [`into_inner`](#method.into_inner)) discards the
+/// packets not yet flushed.
+#[derive(Debug)]
+pub struct GsoSender<D = Iface> {
+    device: D,
+    vnet_hdr: bool,
+    prefix_len: usize,
+    buffer: Vec<u8>,
+    batch: Option<Batch>,
+}
+
+impl<D: Device> GsoSender<D> {
+    /// Wraps the device.
+    ///
+    /// The `vnet_hdr` tells if the device was created with the
+    /// [`VNET_HDR`](../struct.TunFlags.html#associatedconstant.VNET_HDR) flag. The packets can be
+    /// glued together only if it was.
+    pub fn new(device: D, vnet_hdr: bool) -> Self {
+        let info = if device.packet_info() {
+            PacketInfo::SIZE
+        } else {
+            0
+        };
+        let vnet = if vnet_hdr { VirtioNetHeader::SIZE } else { 0 };
+        GsoSender {
+            device,
+            vnet_hdr,
+            prefix_len: info + vnet,
+            buffer: Vec::new(),
+            batch: None,
+        }
+    }
+    /// Sends a packet, possibly gluing it to the previous ones.
+    ///
+    /// The packet may stay in the sender until a later call (or [`flush`](#method.flush)).
+    ///
+    /// # Errors
+    ///
+    /// The errors of sending the packets collected before. These are lost, this packet is kept
+    /// for later.
+    pub fn push(&mut self, packet: &[u8]) -> Result<()> {
+        if !self.vnet_hdr {
+            return self.send_single(packet);
+        }
+        if let Some(batch) = self.batch.as_mut() {
+            if let Some(segment) = Self::appendable(batch, &self.buffer[self.prefix_len..], packet)
+            {
+                let payload = &packet[segment.hdr_len..];
+                self.buffer.extend_from_slice(payload);
+                batch.segments += 1;
+                batch.next_seq = batch.next_seq.wrapping_add(payload.len() as u32);
+                if payload.len() < batch.gso_size || segment.psh {
+                    batch.closed = true;
+                }
+                if segment.psh {
+                    let flags = self.prefix_len + segment.ip_len + 13;
+                    self.buffer[flags] |= TCP_PSH;
+                }
+                return Ok(());
+            }
+        }
+        let result = self.flush();
+        self.start(packet);
+        result
+    }
+    /// Sends all the packets, gluing together what can be.
+    ///
+    /// This is a shortcut for [`push`](#method.push)ing them and then calling
+    /// [`flush`](#method.flush).
+    ///
+    /// # Errors
+    ///
+    /// Stops on the first error, the rest of the packets is not sent.
+    pub fn send_batch<'p, I>(&mut self, packets: I) -> Result<()>
+    where
+        I: IntoIterator<Item = &'p [u8]>,
+    {
+        for packet in packets {
+            self.push(packet)?;
+        }
+        self.flush()
+    }
+    /// Writes the packets collected so far.
+    pub fn flush(&mut self) -> Result<()> {
+        let batch = match self.batch.take() {
+            Some(batch) => batch,
+            None => return Ok(()),
+        };
+        let header = match batch.first {
+            Some(first) if batch.segments > 1 => self.glue(&first, batch.gso_size),
+            _ => VirtioNetHeader::default(),
+        };
+        let proto = self.proto(&self.buffer[self.prefix_len..]);
+        let mut prefix = &mut self.buffer[..self.prefix_len];
+        if self.device.packet_info() {
+            prefix[..PacketInfo::SIZE].copy_from_slice(&PacketInfo::new(proto).to_bytes());
+            prefix = &mut prefix[PacketInfo::SIZE..];
+        }
+        prefix.copy_from_slice(&header.to_bytes());
+        trace!(
+            "Sending {} segments of {} bytes into {}",
+            batch.segments,
+            batch.gso_size,
+            self.device.name()
+        );
+        let result = self.device.send(&self.buffer);
+        self.buffer.clear();
+        result.map(|_| ())
+    }
+    /// Returns the number of packets waiting for the [`flush`](#method.flush).
+    pub fn pending(&self) -> usize {
+        self.batch.map(|batch| batch.segments).unwrap_or(0)
+    }
+    /// Accesses the wrapped device.
+    pub fn get_ref(&self) -> &D {
+        &self.device
+    }
+    /// Unwraps the device, discarding the packets not yet flushed.
+    pub fn into_inner(self) -> D {
+        self.device
+    }
+    fn proto(&self, packet: &[u8]) -> EtherType {
+        match self.device.mode() {
+            Mode::Tun => match packet.first().map(|version| version >> 4) {
+                Some(6) => EtherType::Ipv6,
+                _ => EtherType::Ipv4,
+            },
+            Mode::Tap => EthernetHeader::parse(packet)
+                .map(|(header, _)| header.ether_type)
+                .unwrap_or(EtherType::Ipv4),
+        }
+    }
+    fn send_single(&mut self, packet: &[u8]) -> Result<()> {
+        if self.device.packet_info() {
+            self.start(packet);
+            self.flush()
+        } else {
+            self.device.send(packet).map(|_| ())
+        }
+    }
+    /// Checks if the packet can be glued to the batch.
+    fn appendable(batch: &Batch, glued: &[u8], packet: &[u8]) -> Option<Segment> {
+        let first = batch.first.as_ref()?;
+        let segment = Segment::parse(packet)?;
+        let payload = packet.len() - segment.hdr_len;
+        let fits = !batch.closed
+            && batch.segments < MAX_SEGMENTS
+            && glued.len() + payload <= MAX_SIZE
+            && payload <= batch.gso_size;
+        let continues = segment.ipv6 == first.ipv6
+            && segment.proto == first.proto
+            && segment.hdr_len == first.hdr_len
+            && (segment.proto != TCP || segment.seq == batch.next_seq)
+            && same_flow(first, glued, packet);
+        if fits && continues {
+            Some(segment)
+        } else {
+            None
+        }
+    }
+    fn start(&mut self, packet: &[u8]) {
+        self.buffer.clear();
+        self.buffer.resize(self.prefix_len, 0);
+        self.buffer.extend_from_slice(packet);
+        // Only TUN packets are understood
+        let first = match self.device.mode() {
+            Mode::Tun if self.vnet_hdr => Segment::parse(packet),
+            _ => None,
+        };
+        let gso_size = first.map(|first| packet.len() - first.hdr_len).unwrap_or(0);
+        self.batch = Some(Batch {
+            first,
+            gso_size,
+            segments: 1,
+            next_seq: first.map(|first| first.seq.wrapping_add(gso_size as u32)).unwrap_or(0),
+            closed: first.map(|first| first.psh).unwrap_or(true),
+        });
+    }
+    /// Fixes up the headers of the glued packet and describes it by the virtio net header.
+    fn glue(&mut self, first: &Segment, gso_size: usize) -> VirtioNetHeader {
+        let packet = &mut self.buffer[self.prefix_len..];
+        let len = packet.len();
+        let l4_len = len - first.ip_len;
+        let (addrs, gso_type) = if first.ipv6 {
+            packet[4..6].copy_from_slice(&((len - 40) as u16).to_be_bytes());
+            (8..40, VirtioNetHeader::GSO_TCPV6)
+        } else {
+            packet[2..4].copy_from_slice(&(len as u16).to_be_bytes());
+            packet[10..12].copy_from_slice(&[0, 0]);
+            let checksum = checksum(&packet[..20]);
+            packet[10..12].copy_from_slice(&checksum.to_be_bytes());
+            (12..20, VirtioNetHeader::GSO_TCPV4)
+        };
+        let (gso_type, csum_offset) = if first.proto == UDP {
+            let udp_len = first.ip_len + 4;
+            packet[udp_len..udp_len + 2].copy_from_slice(&(l4_len as u16).to_be_bytes());
+            (VirtioNetHeader::GSO_UDP_L4, 6)
+        } else {
+            (gso_type, 16)
+        };
+        // The kernel finishes the checksum from the pseudo-header one, for each segment
+        let pseudo = sum(&packet[addrs], u32::from(first.proto) + l4_len as u32);
+        let csum = first.ip_len + csum_offset;
+        packet[csum..csum + 2].copy_from_slice(&fold(pseudo).to_be_bytes());
+        VirtioNetHeader {
+            flags: VirtioNetHeader::F_NEEDS_CSUM,
+            gso_type,
+            hdr_len: first.hdr_len as u16,
+            gso_size: gso_size as u16,
+            csum_start: first.ip_len as u16,
+            csum_offset: csum_offset as u16,
+        }
+    }
+}
+
+impl<D: AsRawFd> AsRawFd for GsoSender<D> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.device.as_raw_fd()
+    }
+}
@@ -0,0 +1,252 @@
+//! Creating the devices through a privileged helper process.
+//!
+//! Creating a device needs the `CAP_NET_ADMIN` capability, but the application using it doesn't
+//! need any privileges afterwards. Instead of giving them to the whole application, a small
+//! helper binary can be made privileged (either setuid root or with the file capability). The
+//! application runs the [`Helper`](struct.Helper.html), which creates the device and passes the
+//! file descriptor back over a socket.
+//!
+//! The `tun-tap-helper` binary is built with the `helper` feature. Alternatively, the
+//! [`run_helper`](fn.run_helper.html) can be embedded into an application's own helper.
+//!
+//! Note that anyone able to run a privileged helper can create devices. Restrict who can execute
+//! it (eg. by making it executable only by a dedicated group).
+//!
+//! This module is available only with the `helper` feature.
+//!
+//! # Setup
+//!
+//! ```sh
+//! cargo install tun-tap --features helper --bin tun-tap-helper
+//! sudo setcap cap_net_admin+ep ~/.cargo/bin/tun-tap-helper
+//! ```
+
+use std::env;
+use std::fs::File;
+use std::io::{self, ErrorKind, Result};
+use std::os::raw::{c_int, c_uchar};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::os::unix::process::CommandExt;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+use crate::{Error, Iface, TunFlags};
+
+extern "C" {
+    fn tuntap_send_fd(sock: c_int, fd: c_int, data: *const c_uchar, len: usize) -> isize;
+    fn tuntap_recv_fd(sock: c_int, fd: *mut c_int, data: *mut c_uchar, len: usize) -> isize;
+}
+
+/// The file descriptor number the helper gets the socket on.
+pub const HELPER_SOCKET_FD: RawFd = 3;
+
+const OK: u8 = 0;
+const NAME_TOO_LONG: u8 = 1;
+const NAME_TAKEN: u8 = 2;
+const PERMISSION_DENIED: u8 = 3;
+const DEVICE_BUSY: u8 = 4;
+const NOT_A_TUN_DEVICE: u8 = 5;
+const IO: u8 = 6;
+const MISSING_CAPABILITY: u8 = 7;
+const NAME_CONTAINS_NUL: u8 = 8;
+const NAME_CONTAINS_INVALID_CHAR: u8 = 9;
+const INVALID_NAME: u8 = 10;
+
+fn encode(error: &Error) -> Vec<u8> {
+    let code = match error {
+        Error::NameTooLong => NAME_TOO_LONG,
+        Error::NameTaken => NAME_TAKEN,
+        Error::PermissionDenied => PERMISSION_DENIED,
+        Error::DeviceBusy => DEVICE_BUSY,
+        Error::NotATunDevice => NOT_A_TUN_DEVICE,
+        Error::MissingCapability => MISSING_CAPABILITY,
+        Error::NameContainsNul => NAME_CONTAINS_NUL,
+        Error::NameContainsInvalidChar(_) => NAME_CONTAINS_INVALID_CHAR,
+        Error::InvalidName => INVALID_NAME,
+        _ => IO,
+    };
+    let mut result = vec![code];
+    if let Error::NameContainsInvalidChar(c) = error {
+        result.extend_from_slice(&u32::from(*c).to_ne_bytes());
+    }
+    if let Error::Io(e) = error {
+        result.extend_from_slice(&e.raw_os_error().unwrap_or(0).to_ne_bytes());
+        result.extend_from_slice(e.to_string().as_bytes());
+    }
+    result
+}
+
+fn decode(msg: &[u8]) -> Error {
+    match msg.first() {
+        Some(&NAME_TOO_LONG) => Error::NameTooLong,
+        Some(&NAME_TAKEN) => Error::NameTaken,
+        Some(&PERMISSION_DENIED) => Error::PermissionDenied,
+        Some(&DEVICE_BUSY) => Error::DeviceBusy,
+        Some(&NOT_A_TUN_DEVICE) => Error::NotATunDevice,
+        Some(&MISSING_CAPABILITY) => Error::MissingCapability,
+        Some(&NAME_CONTAINS_NUL) => Error::NameContainsNul,
+        Some(&NAME_CONTAINS_INVALID_CHAR) if msg.len() >= 5 => {
+            let c = u32::from_ne_bytes([msg[1], msg[2], msg[3], msg[4]]);
+            Error::NameContainsInvalidChar(char::from_u32(c).unwrap_or(char::REPLACEMENT_CHARACTER))
+        },
+        Some(&INVALID_NAME) => Error::InvalidName,
+        Some(&IO) if msg.len() >= 5 => {
+            let errno = i32::from_ne_bytes([msg[1], msg[2], msg[3], msg[4]]);
+            if errno != 0 {
+                Error::Io(io::Error::from_raw_os_error(errno))
+            } else {
+                Error::Io(io::Error::other(String::from_utf8_lossy(&msg[5..]).into_owned()))
+            }
+        },
+        _ => Error::Io(io::Error::new(
+            ErrorKind::InvalidData,
+            "Malformed reply from the helper",
+        )),
+    }
+}
+
+fn send_fd(sock: &UnixDatagram, fd: RawFd, msg: &[u8]) -> Result<()> {
+    if unsafe { tuntap_send_fd(sock.as_raw_fd(), fd, msg.as_ptr(), msg.len()) } < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+fn recv_fd(sock: &UnixDatagram, buf: &mut [u8]) -> Result<(usize, Option<RawFd>)> {
+    let mut fd: c_int = -1;
+    let size = unsafe { tuntap_recv_fd(sock.as_raw_fd(), &mut fd, buf.as_mut_ptr(), buf.len()) };
+    if size < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok((size as usize, if fd >= 0 { Some(fd) } else { None }))
+}
+
+/// A privileged helper binary to create the devices.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// # use tun_tap::helper::Helper;
+/// let helper = Helper::new("/usr/local/bin/tun-tap-helper");
+/// let iface = helper.create("mytun%d", TunFlags::TUN).expect("Failed to create a TUN device");
+/// println!("Created {}", iface.name());
+/// ```
+#[derive(Clone, Debug)]
+pub struct Helper {
+    path: PathBuf,
+}
+
+impl Helper {
+    /// Uses the helper binary at the given path.
+    pub fn new<P: Into<PathBuf>>(path: P) -> Self {
+        Helper { path: path.into() }
+    }
+    /// Returns the path of the helper binary.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+    /// Creates a device through the helper.
+    ///
+    /// The parameters are the same as with
+    /// [`Iface::with_flags`](../struct.Iface.html#method.with_flags). The helper is run once for
+    /// each device.
+    ///
+    /// # Errors
+    ///
+    /// The errors reported by the helper are passed through. Failing to run the helper (or the
+    /// helper failing without a reply) results in the [`Io`](../enum.Error.html#variant.Io)
+    /// error.
+    pub fn create(&self, ifname: &str, flags: TunFlags) -> std::result::Result<Iface, Error> {
+        let (ours, theirs) = UnixDatagram::pair()?;
+        let theirs_fd = theirs.as_raw_fd();
+        let mut command = Command::new(&self.path);
+        command.arg(ifname).arg(format!("{:#x}", flags.bits()));
+        unsafe {
+            command.pre_exec(move || {
+                // dup2 clears the close-on-exec, unless the fd is already in place.
+                let result = if theirs_fd == HELPER_SOCKET_FD {
+                    libc::fcntl(theirs_fd, libc::F_SETFD, 0)
+                } else {
+                    libc::dup2(theirs_fd, HELPER_SOCKET_FD)
+                };
+                if result < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+        let status = command.status().map_err(|e| {
+            error!("Failed to run helper {}: {}", self.path.display(), e);
+            e
+        })?;
+        drop(theirs);
+        ours.set_nonblocking(true)?;
+        let mut buf = [0; 512];
+        let (size, fd) = match recv_fd(&ours, &mut buf) {
+            Ok(reply) => reply,
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => {
+                error!("Helper {} exited with {} without a reply", self.path.display(), status);
+                return Err(Error::Io(io::Error::other(format!(
+                    "The helper exited with {} without a reply",
+                    status
+                ))));
+            },
+            Err(e) => return Err(Error::Io(e)),
+        };
+        match (buf[..size].first(), fd) {
+            (Some(&OK), Some(fd)) => Iface::adopt(unsafe { File::from_raw_fd(fd) }),
+            (_, fd) => {
+                if let Some(fd) = fd {
+                    unsafe { libc::close(fd) };
+                }
+                let error = decode(&buf[..size]);
+                error!("Helper failed to create {:?}: {}", ifname, error);
+                Err(error)
+            },
+        }
+    }
+}
+
+/// The body of the helper binary.
+///
+/// It takes the requested name and the flags (as a hexadecimal number) from the command line
+/// arguments, creates the device and sends it (or the error) over the socket at the
+/// [`HELPER_SOCKET_FD`](constant.HELPER_SOCKET_FD.html). Returns the exit code of the process.
+///
+/// This can be used to build a custom helper, eg. one allowing only some names.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// // The main of the custom helper
+/// std::process::exit(tun_tap::helper::run_helper());
+/// ```
+pub fn run_helper() -> i32 {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+    let flags = match args.as_slice() {
+        [_, flags] => u16::from_str_radix(flags.trim_start_matches("0x"), 16).ok(),
+        _ => None,
+    };
+    let flags = match flags {
+        Some(flags) => TunFlags::from_bits_retain(flags),
+        None => {
+            eprintln!("Usage: tun-tap-helper NAME FLAGS");
+            eprintln!("The device is sent over a unix socket at the file descriptor 3.");
+            return 2;
+        },
+    };
+    let sock = unsafe { UnixDatagram::from_raw_fd(HELPER_SOCKET_FD) };
+    let result = match Iface::with_flags(&args[0], flags) {
+        Ok(iface) => send_fd(&sock, iface.as_raw_fd(), &[OK]),
+        Err(e) => send_fd(&sock, -1, &encode(&e)),
+    };
+    match result {
+        Ok(()) => 0,
+        Err(e) => {
+            eprintln!("Failed to send the reply: {}", e);
+            1
+        },
+    }
+}
@@ -0,0 +1,282 @@
+//! Sharing the incoming packets of a device between several consumers.
+//!
+//! See the [`Hub`](struct.Hub.html).
+
+use std::io::Result;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, SyncSender, TrySendError};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread::{self, JoinHandle};
+use std::time::Duration;
+
+use crate::packet::{self, EthernetHeader, FiveTuple, VlanTag};
+use crate::shutdown::ShutdownPipe;
+use crate::{device, Device, EtherType, Mode, PacketInfo, ShutdownHandle};
+
+/// Which packets a [`Subscription`](struct.Subscription.html) gets.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Filter {
+    /// All of them.
+    All,
+    /// The packets of the protocol (of the innermost one in tagged frames).
+    EtherType(EtherType),
+    /// The TCP or UDP packets of the flow (see [`FiveTuple`](../packet/struct.FiveTuple.html)).
+    ///
+    /// Only the packets going exactly that way match, not the replies.
+    Flow(FiveTuple),
+}
+
+/// Finds the protocol and the network layer of a packet produced by the device.
+fn network_layer(mode: Mode, packet_info: bool, packet: &[u8]) -> Option<(EtherType, &[u8])> {
+    let packet = if packet_info {
+        packet.get(PacketInfo::SIZE..)?
+    } else {
+        packet
+    };
+    match mode {
+        // The packet info is not there in every case, the IP version is
+        Mode::Tun => match packet.first()? >> 4 {
+            4 => Some((EtherType::Ipv4, packet)),
+            6 => Some((EtherType::Ipv6, packet)),
+            _ => None,
+        },
+        Mode::Tap => {
+            let ether_type = packet::inner_ether_type(packet)?;
+            let offset = EthernetHeader::SIZE + packet::vlan_tags(packet).count() * VlanTag::SIZE;
+            Some((ether_type, packet.get(offset..)?))
+        },
+    }
+}
+
+#[derive(Debug)]
+struct Subscriber {
+    filter: Filter,
+    sender: SyncSender<Vec<u8>>,
+    dropped: Arc<AtomicU64>,
+}
+
+#[derive(Debug, Default)]
+struct Subscribers {
+    list: Vec<Subscriber>,
+    /// The read loop terminated, nothing is coming any more.
+    closed: bool,
+}
+
+fn lock(subscribers: &Mutex<Subscribers>) -> MutexGuard<'_, Subscribers> {
+    // Nothing panics while holding it, but just in case
+    subscribers.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+fn dispatch(subscribers: &Mutex<Subscribers>, mode: Mode, packet_info: bool, packet: &[u8]) {
+    let layer = network_layer(mode, packet_info, packet);
+    // Parsed only if someone asks
+    let mut flow = None;
+    lock(subscribers).list.retain(|subscriber| {
+        let matches = match subscriber.filter {
+            Filter::All => true,
+            Filter::EtherType(ether_type) => layer.map(|(proto, _)| proto) == Some(ether_type),
+            Filter::Flow(wanted) => {
+                let flow = flow.get_or_insert_with(|| {
+                    layer.and_then(|(_, network)| FiveTuple::parse(network))
+                });
+                *flow == Some(wanted)
+            },
+        };
+        if !matches {
+            return true;
+        }
+        match subscriber.sender.try_send(packet.to_vec()) {
+            Ok(()) => true,
+            Err(TrySendError::Full(_)) => {
+                subscriber.dropped.fetch_add(1, Ordering::Relaxed);
+                true
+            },
+            Err(TrySendError::Disconnected(_)) => false,
+        }
+    });
+}
+
+fn read_loop<D: Device>(iface: &D, subscribers: &Mutex<Subscribers>, shutdown: &ShutdownPipe) {
+    let (mode, packet_info) = (iface.mode(), iface.packet_info());
+    let dispatched = device::pump(iface, Some(shutdown), |packet| {
+        dispatch(subscribers, mode, packet_info, packet);
+        true
+    });
+    if let Err(e) = dispatched {
+        error!("Reading from {} failed: {}", iface.name(), e);
+    }
+}
+
+/// A device with its incoming packets fanned out to subscribers.
+///
+/// The hub owns the read loop (in its own thread) and hands each received packet to every
+/// [`Subscription`](struct.Subscription.html) whose [`Filter`](enum.Filter.html) it matches.
+/// Each subscription has its own bounded channel, a slow consumer loses its packets (and they
+/// are counted) without holding up the others. The packets are passed whole, as received from
+/// the device (including the packet info or the ethernet header).
+///
+/// Sending is not affected, it is done through the [`iface`](#method.iface) directly.
+///
+/// Dropping the hub tells the thread to terminate, but doesn't wait for it,
+/// [`shutdown`](#method.shutdown) does (and returns the device). The subscriptions end after
+/// that, once they run out of the packets already queued. They also end if reading from the
+/// device fails (the error is logged).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::thread;
+/// # use tun_tap::*;
+/// # use tun_tap::hub::{Filter, Hub};
+/// let iface = Iface::without_packet_info("mytun", Mode::Tun).expect("Failed to create a device");
+/// let hub = Hub::new(iface).expect("Failed to start the hub");
+/// let ipv6 = hub.subscribe(Filter::EtherType(EtherType::Ipv6), 64);
+/// thread::spawn(move || {
+///     for packet in ipv6 {
+///         println!("IPv6 packet of {} bytes", packet.len());
+///     }
+/// });
+/// for packet in hub.subscribe(Filter::All, 64) {
+///     hub.iface().send(&packet).unwrap();
+/// }
+/// ```
+#[derive(Debug)]
+pub struct Hub<D> {
+    iface: Arc<D>,
+    subscribers: Arc<Mutex<Subscribers>>,
+    shutdown: ShutdownHandle,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<D> Hub<D>
+where
+    D: Device + Send + Sync + 'static,
+{
+    /// Starts reading from the device.
+    ///
+    /// Packets received before the first subscription are dropped.
+    ///
+    /// # Errors
+    ///
+    /// If the thread can't be started.
+    pub fn new(iface: D) -> Result<Self> {
+        let iface = Arc::new(iface);
+        let subscribers = Arc::new(Mutex::new(Subscribers::default()));
+        let pipe = Arc::new(ShutdownPipe::new()?);
+        let thread = {
+            let iface = Arc::clone(&iface);
+            let subscribers = Arc::clone(&subscribers);
+            let pipe = Arc::clone(&pipe);
+            thread::Builder::new()
+                .name(format!("{}-hub", iface.name()))
+                .spawn(move || {
+                    read_loop(&*iface, &subscribers, &pipe);
+                    // Disconnect the subscriptions
+                    let mut subscribers = lock(&subscribers);
+                    subscribers.closed = true;
+                    subscribers.list.clear();
+                })?
+        };
+        Ok(Hub {
+            iface,
+            subscribers,
+            shutdown: ShutdownHandle { pipe },
+            thread: Some(thread),
+        })
+    }
+}
+
+impl<D> Hub<D> {
+    /// Subscribes to the packets matching the `filter`.
+    ///
+    /// The `capacity` is the number of packets the subscription can queue up, the ones coming
+    /// when it is full are dropped. The subscription gets the packets received from now on.
+    ///
+    /// # Panics
+    ///
+    /// If the `capacity` is 0.
+    pub fn subscribe(&self, filter: Filter, capacity: usize) -> Subscription {
+        assert!(capacity > 0, "The subscription needs capacity of at least 1");
+        let (sender, receiver) = mpsc::sync_channel(capacity);
+        let dropped = Arc::new(AtomicU64::new(0));
+        let mut subscribers = lock(&self.subscribers);
+        // If closed, the sender is dropped right away and the subscription ends
+        if !subscribers.closed {
+            subscribers.list.push(Subscriber {
+                filter,
+                sender,
+                dropped: Arc::clone(&dropped),
+            });
+        }
+        Subscription { receiver, dropped }
+    }
+    /// Returns the number of live subscriptions.
+    ///
+    /// The dropped ones are noticed (and removed) only when a packet for them comes.
+    pub fn subscriptions(&self) -> usize {
+        lock(&self.subscribers).list.len()
+    }
+    /// Returns the device.
+    pub fn iface(&self) -> &D {
+        &self.iface
+    }
+    /// Stops reading and returns the device.
+    ///
+    /// A packet being dispatched is finished first. The subscriptions end.
+    pub fn shutdown(mut self) -> D {
+        let _ = self.shutdown.shutdown();
+        if let Some(thread) = self.thread.take() {
+            thread.join().expect("Hub thread panicked");
+        }
+        let iface = Arc::clone(&self.iface);
+        drop(self);
+        match Arc::try_unwrap(iface) {
+            Ok(iface) => iface,
+            Err(_) => unreachable!("Hub thread terminated"),
+        }
+    }
+}
+
+impl<D> Drop for Hub<D> {
+    fn drop(&mut self) {
+        let _ = self.shutdown.shutdown();
+    }
+}
+
+/// The packets a [`Hub`](struct.Hub.html) hands to one consumer.
+///
+/// Iterating over it yields the packets until the hub stops and the queue is drained. Dropping
+/// it unsubscribes.
+#[derive(Debug)]
+pub struct Subscription {
+    receiver: Receiver<Vec<u8>>,
+    dropped: Arc<AtomicU64>,
+}
+
+impl Subscription {
+    /// Waits for the next packet.
+    ///
+    /// Returns `None` once the hub stopped and there are no more packets.
+    pub fn recv(&self) -> Option<Vec<u8>> {
+        self.receiver.recv().ok()
+    }
+    /// Waits for the next packet, up to the given timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<Vec<u8>> {
+        self.receiver.recv_timeout(timeout).ok()
+    }
+    /// Returns the next packet if one is already available.
+    pub fn try_recv(&self) -> Option<Vec<u8>> {
+        self.receiver.try_recv().ok()
+    }
+    /// How many packets were dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Iterator for Subscription {
+    type Item = Vec<u8>;
+    fn next(&mut self) -> Option<Vec<u8>> {
+        self.recv()
+    }
+}
@@ -0,0 +1,195 @@
+//! Answering pings on a TUN device.
+//!
+//! The [`EchoResponder`](struct.EchoResponder.html) replies to the ICMP (and ICMPv6) echo
+//! requests, which turns `ping` into a working connectivity check of the device and the code
+//! around it.
+
+use std::collections::HashSet;
+use std::io::{ErrorKind, Result};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use crate::checksum::{checksum, fold, sum};
+use crate::{Device, EtherType, Mode, PacketInfo, Shutdown};
+
+const ICMP: u8 = 1;
+const ICMPV6: u8 = 58;
+const ECHO_REQUEST: u8 = 8;
+const ECHO_REPLY: u8 = 0;
+const ECHO_REQUEST_V6: u8 = 128;
+const ECHO_REPLY_V6: u8 = 129;
+const TTL: u8 = 64;
+
+/// Replies to ICMP echo requests (pings).
+///
+/// Feed it the packets received from a TUN device by [`respond`](#method.respond), or let it
+/// [`run`](#method.run) on its own as a test fixture. Both IPv4 and IPv6 are supported, the
+/// requests need to be in a single unfragmented packet (without IPv6 extension headers).
+///
+/// By default, it answers requests to any (unicast) address, as if all the hosts routed through
+/// the device were there. This can be restricted by [`add_address`](#method.add_address).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::thread;
+/// # use tun_tap::*;
+/// # use tun_tap::icmp::EchoResponder;
+/// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+/// // Configure the device, eg. with an address 10.0.0.1/24
+/// // Now ping 10.0.0.2 answers
+/// thread::spawn(move || EchoResponder::new().run(&iface));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct EchoResponder {
+    addresses: HashSet<IpAddr>,
+}
+
+impl EchoResponder {
+    /// Creates a responder answering for all the addresses.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Answers (only) the requests to the address.
+    ///
+    /// Once an address is added, requests to the addresses not added are ignored.
+    pub fn add_address(&mut self, address: IpAddr) {
+        self.addresses.insert(address);
+    }
+    fn answers(&self, address: IpAddr) -> bool {
+        if self.addresses.is_empty() {
+            !address.is_multicast() && address != IpAddr::V4(Ipv4Addr::BROADCAST)
+        } else {
+            self.addresses.contains(&address)
+        }
+    }
+    /// Builds the reply to the IP packet (without the packet info).
+    ///
+    /// Returns `None` if the packet is not an echo request to be answered.
+    pub fn reply(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        match packet.first()? >> 4 {
+            4 => self.reply_v4(packet),
+            6 => self.reply_v6(packet),
+            _ => None,
+        }
+    }
+    fn reply_v4(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        let header_len = usize::from(packet[0] & 0x0f) * 4;
+        let total = usize::from(u16::from_be_bytes([*packet.get(2)?, *packet.get(3)?]));
+        // The header, no fragments and at least the ICMP header
+        if header_len < 20
+            || total > packet.len()
+            || total < header_len + 8
+            || u16::from_be_bytes([packet[6], packet[7]]) & 0x3fff != 0
+            || packet[9] != ICMP
+            || packet[header_len] != ECHO_REQUEST
+        {
+            return None;
+        }
+        let destination = Ipv4Addr::new(packet[16], packet[17], packet[18], packet[19]);
+        if !self.answers(destination.into()) {
+            return None;
+        }
+        let mut reply = packet[..total].to_vec();
+        reply[8] = TTL;
+        reply.copy_within(12..16, 16);
+        reply[12..16].copy_from_slice(&destination.octets());
+        reply[10..12].copy_from_slice(&[0, 0]);
+        let ip_checksum = checksum(&reply[..header_len]);
+        reply[10..12].copy_from_slice(&ip_checksum.to_be_bytes());
+        let icmp = &mut reply[header_len..];
+        icmp[0] = ECHO_REPLY;
+        icmp[2..4].copy_from_slice(&[0, 0]);
+        let icmp_checksum = checksum(icmp);
+        icmp[2..4].copy_from_slice(&icmp_checksum.to_be_bytes());
+        Some(reply)
+    }
+    fn reply_v6(&self, packet: &[u8]) -> Option<Vec<u8>> {
+        if packet.len() < 48 {
+            return None;
+        }
+        let total = 40 + usize::from(u16::from_be_bytes([packet[4], packet[5]]));
+        let request = packet[6] == ICMPV6 && packet[40] == ECHO_REQUEST_V6;
+        if total > packet.len() || total < 48 || !request {
+            return None;
+        }
+        let mut destination = [0; 16];
+        destination.copy_from_slice(&packet[24..40]);
+        if !self.answers(Ipv6Addr::from(destination).into()) {
+            return None;
+        }
+        let mut reply = packet[..total].to_vec();
+        reply[7] = TTL;
+        reply.copy_within(8..24, 24);
+        reply[8..24].copy_from_slice(&destination);
+        reply[40] = ECHO_REPLY_V6;
+        reply[42..44].copy_from_slice(&[0, 0]);
+        // The pseudo-header: the addresses, the length and the next header
+        let pseudo = sum(&reply[8..40], (total - 40) as u32 + u32::from(ICMPV6));
+        let icmp_checksum = !fold(sum(&reply[40..], pseudo));
+        reply[42..44].copy_from_slice(&icmp_checksum.to_be_bytes());
+        Some(reply)
+    }
+    /// Answers the packet received from the device, if it is an echo request.
+    ///
+    /// The `packet` is as received, including the packet info if the device has it. Returns
+    /// whether the packet was answered (and the caller may ignore it). Packets from devices in
+    /// the TAP mode are never answered.
+    ///
+    /// # Errors
+    ///
+    /// If sending the reply fails.
+    pub fn respond<D: Device>(&self, device: &D, packet: &[u8]) -> Result<bool> {
+        if device.mode() != Mode::Tun {
+            return Ok(false);
+        }
+        let info = device.packet_info();
+        let ip = if info {
+            match packet.get(PacketInfo::SIZE..) {
+                Some(ip) => ip,
+                None => return Ok(false),
+            }
+        } else {
+            packet
+        };
+        let reply = match self.reply(ip) {
+            Some(reply) => reply,
+            None => return Ok(false),
+        };
+        trace!("Answering a ping on {}", device.name());
+        if info {
+            let proto = if ip[0] >> 4 == 6 {
+                EtherType::Ipv6
+            } else {
+                EtherType::Ipv4
+            };
+            let mut with_info = PacketInfo::new(proto).to_bytes().to_vec();
+            with_info.extend_from_slice(&reply);
+            device.send(&with_info)?;
+        } else {
+            device.send(&reply)?;
+        }
+        Ok(true)
+    }
+    /// Answers the pings on the device until it is shut down.
+    ///
+    /// All the other packets are dropped. This is meant to run in its own thread, as a peer on
+    /// the other side of the device in tests.
+    ///
+    /// # Errors
+    ///
+    /// If receiving or sending fails (other than by the
+    /// [`ShutdownHandle`](../struct.ShutdownHandle.html)).
+    pub fn run<D: Device>(&self, device: &D) -> Result<()> {
+        let mut buffer = vec![0; device.max_frame_size().unwrap_or(1504)];
+        loop {
+            match device.recv(&mut buffer) {
+                Ok(size) => {
+                    self.respond(device, &buffer[..size])?;
+                },
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                Err(ref e) if Shutdown::is_shutdown(e) => return Ok(()),
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
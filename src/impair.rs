@@ -0,0 +1,362 @@
+//! Emulating a bad network, by delaying, reordering and losing packets.
+//!
+//! See the [`ImpairedIface`](struct.ImpairedIface.html) structure.
+
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
+use std::io::Result;
+use std::sync::atomic::{AtomicU64, Ordering as AtomicOrdering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::device::{self, Device};
+use crate::Iface;
+
+/// What happens to the packets going one way through an
+/// [`ImpairedIface`](struct.ImpairedIface.html).
+///
+/// The default is a perfect network, without any delays or losses. The impairments are applied
+/// in this order:
+///
+/// * The packet is lost with the given probability.
+/// * The packet waits until the link is free, if there's a rate limit. The link is then busy for
+///   the time it takes to transmit the packet at the given rate.
+/// * The packet is delayed, by a random time from `delay - jitter` to `delay + jitter`. A large
+///   jitter reorders the packets.
+/// * With the given probability, the packet skips the delay, overtaking the packets before it.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use tun_tap::impair::Impairment;
+/// // A slow, lossy link on the other side of the globe
+/// let impairment = Impairment::new()
+///     .rate(1_000_000)
+///     .delay(Duration::from_millis(150))
+///     .jitter(Duration::from_millis(10))
+///     .loss(0.01);
+/// ```
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Impairment {
+    loss: f64,
+    rate: Option<u64>,
+    delay: Duration,
+    jitter: Duration,
+    reorder: f64,
+    seed: Option<u64>,
+}
+
+impl Impairment {
+    /// Creates a perfect network, to be made worse by the other methods.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Sets the probability (from 0 to 1) of losing each packet.
+    pub fn loss(self, loss: f64) -> Self {
+        Impairment { loss, ..self }
+    }
+    /// Limits the rate, in bits per second.
+    pub fn rate(self, bits_per_second: u64) -> Self {
+        Impairment {
+            rate: Some(bits_per_second),
+            ..self
+        }
+    }
+    /// Delays the packets by a fixed time.
+    pub fn delay(self, delay: Duration) -> Self {
+        Impairment { delay, ..self }
+    }
+    /// Varies the delay randomly, up to the given time in either direction.
+    pub fn jitter(self, jitter: Duration) -> Self {
+        Impairment { jitter, ..self }
+    }
+    /// Sets the probability (from 0 to 1) of a packet skipping the delay.
+    pub fn reorder(self, reorder: f64) -> Self {
+        Impairment { reorder, ..self }
+    }
+    /// Seeds the random generator, to make the losses and delays reproducible.
+    ///
+    /// It is seeded from the current time by default.
+    pub fn seed(self, seed: u64) -> Self {
+        Impairment {
+            seed: Some(seed),
+            ..self
+        }
+    }
+    fn is_perfect(&self) -> bool {
+        self.loss <= 0.0 && self.rate.is_none() && self.delay.is_zero() && self.jitter.is_zero()
+    }
+}
+
+/// A xorshift random generator, good enough for emulating a network.
+#[derive(Debug)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: Option<u64>) -> Self {
+        let seed = seed.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or(0)
+        });
+        // Zero is a fixed point of xorshift
+        Rng(seed | 1)
+    }
+    /// Returns a random number from 0 to 1.
+    fn uniform(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
+
+/// The state of one direction.
+#[derive(Debug)]
+struct Link {
+    impairment: Impairment,
+    rng: Rng,
+    // When the rate-limited link is free to transmit another packet
+    free: Instant,
+    // To keep the order of packets due at the same time
+    seq: u64,
+}
+
+impl Link {
+    fn new(impairment: Impairment) -> Self {
+        Link {
+            impairment,
+            rng: Rng::new(impairment.seed),
+            free: Instant::now(),
+            seq: 0,
+        }
+    }
+    /// Decides when the packet of the given size leaves, `None` if it gets lost.
+    fn schedule(&mut self, size: usize) -> Option<(Instant, u64)> {
+        let imp = self.impairment;
+        if imp.loss > 0.0 && self.rng.uniform() < imp.loss {
+            return None;
+        }
+        let now = Instant::now();
+        let mut due = now;
+        if let Some(rate) = imp.rate {
+            let transmit = Duration::from_secs_f64(size as f64 * 8.0 / rate.max(1) as f64);
+            self.free = self.free.max(now) + transmit;
+            due = self.free;
+        }
+        if imp.reorder <= 0.0 || self.rng.uniform() >= imp.reorder {
+            let jitter = imp.jitter.as_secs_f64() * (self.rng.uniform() * 2.0 - 1.0);
+            let delay = (imp.delay.as_secs_f64() + jitter).max(0.0);
+            due += Duration::from_secs_f64(delay);
+        }
+        self.seq += 1;
+        Some((due, self.seq))
+    }
+}
+
+/// A packet waiting for its time.
+#[derive(Debug)]
+struct Delayed {
+    due: Instant,
+    seq: u64,
+    packet: Vec<u8>,
+}
+
+impl PartialEq for Delayed {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Delayed {}
+
+impl PartialOrd for Delayed {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Delayed {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.due, self.seq).cmp(&(other.due, other.seq))
+    }
+}
+
+type Queue = BinaryHeap<Reverse<Delayed>>;
+
+#[derive(Debug)]
+struct Incoming {
+    link: Link,
+    queue: Queue,
+}
+
+#[derive(Debug)]
+struct Outgoing {
+    link: Link,
+    delayed: Sender<Delayed>,
+}
+
+/// Sends the delayed packets when they are due.
+fn deliver<D: Device>(device: &D, delayed: Receiver<Delayed>) {
+    let mut queue = Queue::new();
+    loop {
+        let next = queue.peek().map(|Reverse(packet)| packet.due);
+        let received = match next {
+            Some(due) => delayed.recv_timeout(due.saturating_duration_since(Instant::now())),
+            None => delayed.recv().map_err(|_| RecvTimeoutError::Disconnected),
+        };
+        match received {
+            Ok(packet) => queue.push(Reverse(packet)),
+            Err(RecvTimeoutError::Timeout) => (),
+            // The wrapper is gone, the packets in flight with it
+            Err(RecvTimeoutError::Disconnected) => return,
+        }
+        let now = Instant::now();
+        while queue.peek().map(|Reverse(packet)| packet.due <= now) == Some(true) {
+            let Reverse(packet) = queue.pop().unwrap();
+            // Errors are logged by the send and the packet is lost, as on a real network
+            let _ = device.send(&packet.packet);
+        }
+    }
+}
+
+/// A wrapper around [`Iface`](../struct.Iface.html) (or another
+/// [`Device`](../trait.Device.html)) emulating a bad network.
+///
+/// The packets going through it (both [`send`](#method.send) and [`recv`](#method.recv)) are
+/// delayed, reordered or lost as configured by the [`Impairment`](struct.Impairment.html) of the
+/// direction. This allows building userspace network emulators or testing how a protocol copes
+/// with bad conditions.
+///
+/// A lost packet is reported as successfully sent, like with a real network. Delayed sent
+/// packets are held by a background thread; the ones still in flight are discarded when the
+/// wrapper is dropped. Received packets wait inside the wrapper, so `recv` blocks (even on a
+/// non-blocking interface) until a packet is due.
+///
+/// The packets that got lost can be counted by [`lost`](#method.lost).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::time::Duration;
+/// # use tun_tap::*;
+/// # use tun_tap::impair::{ImpairedIface, Impairment};
+/// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+/// let lossy = Impairment::new().loss(0.1).delay(Duration::from_millis(50));
+/// let iface = ImpairedIface::new(iface, lossy, lossy).expect("Failed to start the wrapper");
+/// let mut buffer = vec![0; 1504];
+/// loop {
+///     let size = iface.recv(&mut buffer).unwrap();
+///     // 10% of packets never get here, the rest 50ms later
+/// }
+/// ```
+#[derive(Debug)]
+pub struct ImpairedIface<D = Iface> {
+    device: Arc<D>,
+    outgoing: Mutex<Outgoing>,
+    incoming: Mutex<Incoming>,
+    lost: AtomicU64,
+}
+
+impl<D: Device + Send + Sync + 'static> ImpairedIface<D> {
+    /// Wraps the device.
+    ///
+    /// The `send` impairment applies to the packets sent into the device, the `recv` one to the
+    /// packets received from it.
+    ///
+    /// # Errors
+    ///
+    /// If the background thread for the sent packets can't be started.
+    pub fn new(device: D, send: Impairment, recv: Impairment) -> Result<Self> {
+        let device = Arc::new(device);
+        let (sender, receiver) = mpsc::channel();
+        let delivering = Arc::clone(&device);
+        thread::Builder::new()
+            .name(format!("{}-impair", device.name()))
+            .spawn(move || deliver(&*delivering, receiver))?;
+        Ok(ImpairedIface {
+            device,
+            outgoing: Mutex::new(Outgoing {
+                link: Link::new(send),
+                delayed: sender,
+            }),
+            incoming: Mutex::new(Incoming {
+                link: Link::new(recv),
+                queue: Queue::new(),
+            }),
+            lost: AtomicU64::new(0),
+        })
+    }
+    /// Sends a packet into the device, after the delay (if it doesn't get lost).
+    ///
+    /// Returns the size of the packet, even if it gets lost or delayed.
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        let mut outgoing = self.outgoing.lock().unwrap();
+        if outgoing.link.impairment.is_perfect() {
+            drop(outgoing);
+            return self.device.send(buf);
+        }
+        match outgoing.link.schedule(buf.len()) {
+            Some((due, seq)) => {
+                let delayed = Delayed {
+                    due,
+                    seq,
+                    packet: buf.to_vec(),
+                };
+                // The thread never terminates before the wrapper
+                let _ = outgoing.delayed.send(delayed);
+            },
+            None => {
+                self.lost.fetch_add(1, AtomicOrdering::Relaxed);
+            },
+        }
+        Ok(buf.len())
+    }
+    /// Receives a packet from the device, once its delay passes.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv). The lost packets are skipped.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        let mut incoming = self.incoming.lock().unwrap();
+        if incoming.link.impairment.is_perfect() {
+            drop(incoming);
+            return self.device.recv(buf);
+        }
+        loop {
+            let next = incoming.queue.peek().map(|Reverse(packet)| packet.due);
+            let now = Instant::now();
+            if next.map(|due| due <= now) == Some(true) {
+                let Reverse(delayed) = incoming.queue.pop().unwrap();
+                let size = delayed.packet.len().min(buf.len());
+                buf[..size].copy_from_slice(&delayed.packet[..size]);
+                return Ok(size);
+            }
+            let timeout = next.map(|due| due - now);
+            if !device::poll_readable(&*self.device, timeout)? {
+                continue;
+            }
+            let mut packet = vec![0; buf.len()];
+            let size = self.device.recv(&mut packet)?;
+            packet.truncate(size);
+            match incoming.link.schedule(size) {
+                Some((due, seq)) => incoming.queue.push(Reverse(Delayed { due, seq, packet })),
+                None => {
+                    self.lost.fetch_add(1, AtomicOrdering::Relaxed);
+                },
+            }
+        }
+    }
+    /// Returns the number of packets lost so far, in both directions.
+    pub fn lost(&self) -> u64 {
+        self.lost.load(AtomicOrdering::Relaxed)
+    }
+    /// Accesses the wrapped device.
+    ///
+    /// Note that traffic passing directly through the device is not impaired.
+    pub fn get_ref(&self) -> &D {
+        &self.device
+    }
+}
@@ -0,0 +1,239 @@
+//! Interface with traffic counters.
+//!
+//! See the [`InstrumentedIface`](struct.InstrumentedIface.html) structure.
+//!
+//! With the `metrics` feature, the counters are also reported through the
+//! [`metrics`](https://docs.rs/metrics) facade, from where an exporter (eg.
+//! `metrics-exporter-prometheus`) can pick them up.
+
+use std::io::{ErrorKind, Result};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicU64, Ordering};
+
+#[cfg(feature = "metrics")]
+use metrics::{counter, describe_counter, Counter, Unit};
+
+use crate::Iface;
+
+/// A snapshot of the counters of an [`InstrumentedIface`](struct.InstrumentedIface.html).
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub struct Counters {
+    /// Number of packets successfully sent into the interface.
+    pub packets_sent: u64,
+    /// Number of bytes successfully sent into the interface.
+    pub bytes_sent: u64,
+    /// Number of packets received from the interface.
+    pub packets_received: u64,
+    /// Number of bytes received from the interface.
+    pub bytes_received: u64,
+    /// Number of failed sends (not counting the would-block ones).
+    pub send_errors: u64,
+    /// Number of failed receives (not counting the would-block ones).
+    pub recv_errors: u64,
+    /// Number of sends and receives that failed with
+    /// [`WouldBlock`](std::io::ErrorKind::WouldBlock).
+    pub would_block: u64,
+}
+
+#[derive(Debug, Default)]
+struct AtomicCounters {
+    packets_sent: AtomicU64,
+    bytes_sent: AtomicU64,
+    packets_received: AtomicU64,
+    bytes_received: AtomicU64,
+    send_errors: AtomicU64,
+    recv_errors: AtomicU64,
+    would_block: AtomicU64,
+}
+
+impl AtomicCounters {
+    fn account(
+        &self,
+        result: &Result<usize>,
+        packets: &AtomicU64,
+        bytes: &AtomicU64,
+        errors: &AtomicU64,
+    ) {
+        match result {
+            Ok(size) => {
+                packets.fetch_add(1, Ordering::Relaxed);
+                bytes.fetch_add(*size as u64, Ordering::Relaxed);
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => {
+                self.would_block.fetch_add(1, Ordering::Relaxed);
+            },
+            Err(_) => {
+                errors.fetch_add(1, Ordering::Relaxed);
+            },
+        }
+    }
+    fn snapshot(&self) -> Counters {
+        Counters {
+            packets_sent: self.packets_sent.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed),
+            packets_received: self.packets_received.load(Ordering::Relaxed),
+            bytes_received: self.bytes_received.load(Ordering::Relaxed),
+            send_errors: self.send_errors.load(Ordering::Relaxed),
+            recv_errors: self.recv_errors.load(Ordering::Relaxed),
+            would_block: self.would_block.load(Ordering::Relaxed),
+        }
+    }
+}
+
+/// The same counters, reported through the `metrics` facade.
+#[cfg(feature = "metrics")]
+#[derive(Debug)]
+struct Metrics {
+    packets_sent: Counter,
+    bytes_sent: Counter,
+    packets_received: Counter,
+    bytes_received: Counter,
+    send_errors: Counter,
+    recv_errors: Counter,
+    would_block: Counter,
+}
+
+#[cfg(feature = "metrics")]
+impl Metrics {
+    fn register(name: &str) -> Self {
+        describe_counter!("tuntap_packets_sent", "Packets sent into the interface");
+        describe_counter!("tuntap_bytes_sent", Unit::Bytes, "Bytes sent into the interface");
+        describe_counter!("tuntap_packets_received", "Packets received from the interface");
+        describe_counter!(
+            "tuntap_bytes_received",
+            Unit::Bytes,
+            "Bytes received from the interface"
+        );
+        describe_counter!("tuntap_send_errors", "Failed sends (dropped packets)");
+        describe_counter!("tuntap_recv_errors", "Failed receives");
+        describe_counter!("tuntap_would_block", "Sends and receives that would block");
+        let interface = name.to_owned();
+        Metrics {
+            packets_sent: counter!("tuntap_packets_sent", "interface" => interface.clone()),
+            bytes_sent: counter!("tuntap_bytes_sent", "interface" => interface.clone()),
+            packets_received: counter!("tuntap_packets_received", "interface" => interface.clone()),
+            bytes_received: counter!("tuntap_bytes_received", "interface" => interface.clone()),
+            send_errors: counter!("tuntap_send_errors", "interface" => interface.clone()),
+            recv_errors: counter!("tuntap_recv_errors", "interface" => interface.clone()),
+            would_block: counter!("tuntap_would_block", "interface" => interface),
+        }
+    }
+    fn account(
+        &self,
+        result: &Result<usize>,
+        packets: &Counter,
+        bytes: &Counter,
+        errors: &Counter,
+    ) {
+        match result {
+            Ok(size) => {
+                packets.increment(1);
+                bytes.increment(*size as u64);
+            },
+            Err(e) if e.kind() == ErrorKind::WouldBlock => self.would_block.increment(1),
+            Err(_) => errors.increment(1),
+        }
+    }
+}
+
+/// A wrapper around [`Iface`](../struct.Iface.html) counting the traffic passing through.
+///
+/// It provides the same [`send`](#method.send) and [`recv`](#method.recv) methods as the
+/// interface itself, but keeps track of the number of packets, bytes and errors. The counters can
+/// be read at any time (even from other threads while the interface is in use) by
+/// [`counters`](#method.counters), for example to feed a monitoring dashboard.
+///
+/// The counters are updated with relaxed atomic operations, so they are cheap, but a snapshot
+/// taken while traffic is flowing needs not be exactly consistent between its fields.
+///
+/// With the `metrics` feature, the counters are registered with the currently installed
+/// [`metrics`](https://docs.rs/metrics) recorder when the wrapper is created. They are named
+/// `tuntap_packets_sent`, `tuntap_bytes_sent`, `tuntap_packets_received`,
+/// `tuntap_bytes_received`, `tuntap_send_errors`, `tuntap_recv_errors` and `tuntap_would_block`,
+/// labeled by the `interface` name.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// # use tun_tap::instrumented::InstrumentedIface;
+/// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+/// let iface = InstrumentedIface::new(iface);
+/// let mut buffer = vec![0; 1504];
+/// iface.recv(&mut buffer).unwrap();
+/// assert_eq!(1, iface.counters().packets_received);
+/// ```
+#[derive(Debug)]
+pub struct InstrumentedIface {
+    iface: Iface,
+    counters: AtomicCounters,
+    #[cfg(feature = "metrics")]
+    metrics: Metrics,
+}
+
+impl InstrumentedIface {
+    /// Wraps the interface, with all the counters set to 0.
+    pub fn new(iface: Iface) -> Self {
+        InstrumentedIface {
+            #[cfg(feature = "metrics")]
+            metrics: Metrics::register(iface.name()),
+            iface,
+            counters: AtomicCounters::default(),
+        }
+    }
+    /// Receives a packet from the interface.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        let result = self.iface.recv(buf);
+        let c = &self.counters;
+        c.account(&result, &c.packets_received, &c.bytes_received, &c.recv_errors);
+        #[cfg(feature = "metrics")]
+        {
+            let m = &self.metrics;
+            m.account(&result, &m.packets_received, &m.bytes_received, &m.recv_errors);
+        }
+        result
+    }
+    /// Sends a packet into the interface.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        let result = self.iface.send(buf);
+        let c = &self.counters;
+        c.account(&result, &c.packets_sent, &c.bytes_sent, &c.send_errors);
+        #[cfg(feature = "metrics")]
+        {
+            let m = &self.metrics;
+            m.account(&result, &m.packets_sent, &m.bytes_sent, &m.send_errors);
+        }
+        result
+    }
+    /// Returns a snapshot of the current values of the counters.
+    pub fn counters(&self) -> Counters {
+        self.counters.snapshot()
+    }
+    /// Accesses the wrapped interface.
+    ///
+    /// Note that traffic passing directly through the interface is not counted.
+    pub fn iface(&self) -> &Iface {
+        &self.iface
+    }
+    /// Unwraps the interface, discarding the counters.
+    pub fn into_inner(self) -> Iface {
+        self.iface
+    }
+}
+
+impl From<Iface> for InstrumentedIface {
+    fn from(iface: Iface) -> Self {
+        InstrumentedIface::new(iface)
+    }
+}
+
+impl AsRawFd for InstrumentedIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.iface.as_raw_fd()
+    }
+}
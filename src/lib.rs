@@ -12,7 +12,75 @@
 //! [`send`](struct.Iface.html#method.send) and [`recv`](struct.Iface.html#method.recv) methods.
 //!
 //! You can also use [`Async`](async/struct.Async.html) if you want to integrate with tokio event
-//! loop. This is configurable by a feature (it is on by default).
+//! loop, possibly together with the [`codec`](codec/index.html) module. This is configurable by a
+//! feature (it is on by default).
+//!
+//! The `tracing` feature (off by default) emits [`tracing`](https://docs.rs/tracing) events on
+//! device creation and failed operations. Successful sends and receives are logged on the `trace`
+//! level only.
+//!
+//! The `metrics` feature (off by default) reports the counters of the
+//! [`InstrumentedIface`](instrumented/struct.InstrumentedIface.html) through the
+//! [`metrics`](https://docs.rs/metrics) facade, to be exported to Prometheus and such.
+//!
+//! The `futures-io` feature (off by default) adds the [`agnostic`](agnostic/index.html) module,
+//! with an asynchronous interface usable with any executor (not only tokio). The `smol` feature
+//! is an alias for it.
+//!
+//! The `capture` feature (off by default) adds the [`capture`](capture/index.html) module, for
+//! recording the traffic into pcapng files readable by Wireshark.
+//!
+//! The `smoltcp` feature (off by default) implements the `smoltcp::phy::Device` trait for a
+//! wrapper of the [`Iface`](struct.Iface.html), to run the [`smoltcp`](https://docs.rs/smoltcp)
+//! userspace network stack on top of it. See the [`phy`](phy/index.html) module.
+//!
+//! The `helper` feature (off by default) adds the [`helper`](helper/index.html) module and the
+//! `tun-tap-helper` binary, to create the devices from a small privileged process.
+//!
+//! The `cli` feature (off by default) builds the `tuntap` binary, managing persistent devices
+//! much like `ip tuntap`.
+//!
+//! If creating the devices fails, the [`doctor`](doctor/index.html) module (or `tuntap doctor`)
+//! checks if the system is set up to allow it.
+//!
+//! The `dhcp` feature (off by default) adds the [`dhcp`](dhcp/index.html) module, a tiny DHCP
+//! server handing out addresses to virtual machines and containers plugged into a TAP device.
+//!
+//! The `vhost` feature (off by default) adds the [`vhost`](vhost/index.html) module, handing TAP
+//! devices to the vhost-net kernel driver to serve virtual machines without the userspace copy.
+//!
+//! The `fragment` feature (off by default) adds the [`fragment`](fragment/index.html) module,
+//! splitting IPv4 packets too large for a tunnel and putting them back together on the other
+//! side.
+//!
+//! The experimental `xdp` feature (off by default) adds the [`xdp`](xdp/index.html) module,
+//! receiving and sending through AF_XDP sockets on real network cards, behind the same
+//! [`Device`](trait.Device.html) trait.
+//!
+//! The `serde` feature (off by default) allows loading [`IfaceConfig`](struct.IfaceConfig.html)
+//! (and the [`Mode`](enum.Mode.html)) from configuration files.
+//!
+//! To avoid allocating a buffer for each received packet, a
+//! [`PacketPool`](pool/struct.PacketPool.html) can recycle them. The
+//! [`workers`](workers/index.html) module spreads the load of a multi-queue device over several
+//! threads. The [`gso`](gso/index.html) module sends bursts of packets with fewer writes. The
+//! [`selftest`](selftest/index.html) measures what a device can do on the given machine and the
+//! [`impair`](impair/index.html) module emulates a bad network. The [`bridge`](bridge/index.html)
+//! forwards packets between two devices. The [`typed`](typed/index.html) module tracks the
+//! presence of the packet info in the type of the interface. The
+//! [`resilient`](resilient/index.html) module creates the device again if someone deletes it.
+//! The [`forward`](forward/index.html) module tunnels the packets through sockets to another
+//! machine or a sandboxed process. The [`hub`](hub/index.html) shares the incoming packets of a
+//! device between several consumers. The [`filtered`](filtered/index.html) module runs hooks on
+//! the packets, to drop or rewrite them. The [`raw`](raw/index.html) module puts a real network
+//! interface in place of a TAP device.
+//!
+//! The [`testing`](testing/index.html) module contains fake interfaces, for testing code using
+//! the devices without any privileges. The `testgen` feature (off by default) adds the
+//! [`testgen`](testgen/index.html) module, crafting packets to feed them. The
+//! [`testnet`](testnet/index.html) module configures real devices for tests. The
+//! [`arp`](arp/index.html) module answers ARP requests on TAP devices and the
+//! [`icmp`](icmp/index.html) one pings on TUN devices.
 //!
 //! Creating the devices requires `CAP_NETADM` privileges (most commonly done by running as root).
 //!
@@ -21,33 +89,146 @@
 //! * It is tested only on Linux and probably doesn't work anywhere else, even though other systems
 //!   have some TUN/TAP support. Reports that it works (or not) and pull request to add other
 //!   system's support are welcome.
-//! * The [`Async`](async/struct.Async.html) interface is very minimal and will require extention
-//!   for further use cases and better performance.
-//! * This doesn't support advanced usage patters, like reusing already created device or creating
-//!   persistent devices. Again, pull requests are welcome.
-//! * There are no automated tests. Any idea how to test this in a reasonable way?
-
-use std::ffi::CStr;
-use std::fs::{File, OpenOptions};
-use std::io::{Error, Read, Result, Write};
-use std::os::raw::{c_char, c_int};
-use std::os::unix::io::{AsRawFd, IntoRawFd, RawFd};
+//! * The [`Async`](async/struct.Async.html) interface runs on the old tokio-core (the tokio 0.1
+//!   era) only. There's no support for tokio 1 yet, the [`agnostic`](agnostic/index.html)
+//!   module (with the `futures-io` feature) works with other runtimes.
+//! * Most of the tests need a prepared `tun10` device and the privileges to create more devices
+//!   (or an unprivileged network namespace). See the README for how to set them up.
+
+use std::convert::TryFrom;
+use std::ffi::{CStr, CString};
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::{self, File};
+use std::io::{self, ErrorKind, IoSlice, Read, Result, Write};
+use std::net::Ipv4Addr;
+use std::os::raw::{c_char, c_int, c_uint};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, IntoRawFd, OwnedFd, RawFd};
+use std::ptr;
+use std::str::FromStr;
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 
+#[macro_use]
+mod log;
+
+#[cfg(feature = "futures-io")]
+pub mod agnostic;
+pub mod arp;
+#[cfg(feature = "tokio")]
+pub mod r#async;
+pub mod bridge;
+#[cfg(feature = "capture")]
+pub mod capture;
+#[cfg(feature = "tokio")]
+pub mod codec;
 #[cfg(feature = "tokio")]
-pub mod async;
+pub mod demux;
+#[cfg(feature = "dhcp")]
+pub mod dhcp;
+pub mod doctor;
+pub mod filtered;
+pub mod forward;
+#[cfg(feature = "fragment")]
+pub mod fragment;
+pub mod gso;
+#[cfg(feature = "helper")]
+pub mod helper;
+pub mod hub;
+pub mod icmp;
+pub mod impair;
+pub mod instrumented;
+pub mod packet;
+#[cfg(feature = "smoltcp")]
+pub mod phy;
+pub mod poll_set;
+pub mod pool;
+pub mod privileges;
+pub mod raw;
+pub mod resilient;
+pub mod selftest;
+pub mod tee;
+#[cfg(feature = "testgen")]
+pub mod testgen;
+pub mod testing;
+pub mod testnet;
+pub mod typed;
+#[cfg(feature = "vhost")]
+pub mod vhost;
+pub mod workers;
+#[cfg(feature = "xdp")]
+pub mod xdp;
+mod channels;
+mod checksum;
+mod cleanup;
+mod config;
+mod configured;
+mod device;
+mod error;
+mod file;
+mod flags;
+mod pending;
+mod sealed;
+mod shutdown;
+mod split;
+mod systemd;
+mod timestamp;
+
+pub use crate::channels::{PacketChannels, PacketReceiver};
+pub use crate::cleanup::Cleanup;
+pub use crate::config::IfaceConfig;
+pub use crate::configured::ConfiguredIface;
+pub use crate::device::Device;
+pub use crate::error::{Error, ParseError};
+pub use crate::file::IfaceFile;
+pub use crate::flags::{IffFlags, OffloadFlags, TunFlags};
+pub use crate::packet::{EtherType, PacketInfo};
+pub use crate::pending::PendingIface;
+pub use crate::sealed::SealedIface;
+pub use crate::shutdown::{Shutdown, ShutdownHandle};
+pub use crate::split::{RecvHalf, SendHalf};
+pub use crate::timestamp::Timestamp;
+use crate::cleanup::CleanupGuard;
+use crate::shutdown::ShutdownPipe;
+
+/// Maximum length of the interface name, including the terminating `\0`.
+const IFNAMSIZ: usize = 16;
 
 extern "C" {
-    fn tuntap_setup(fd: c_int, name: *mut u8, mode: c_int, packet_info: c_int) -> c_int;
+    fn tuntap_get_flags(fd: c_int, name: *mut u8) -> c_int;
+    fn tuntap_set_offload(fd: c_int, offload: c_uint) -> c_int;
+    fn tuntap_set_persist(fd: c_int, persist: c_int) -> c_int;
+    fn tuntap_set_nocsum(fd: c_int, nocsum: c_int) -> c_int;
+    fn tuntap_set_owner(fd: c_int, owner: c_uint) -> c_int;
+    fn tuntap_set_group(fd: c_int, group: c_uint) -> c_int;
+    fn tuntap_set_steering_ebpf(fd: c_int, prog: c_int) -> c_int;
+    fn tuntap_get_mtu(name: *const c_char) -> c_int;
+    fn tuntap_get_iff_flags(name: *const c_char) -> c_int;
+    fn tuntap_set_up(name: *const c_char, up: c_int) -> c_int;
+    fn tuntap_set_mtu(name: *const c_char, mtu: c_int) -> c_int;
+    fn tuntap_set_ipv4(name: *const c_char, address: u32, netmask: u32) -> c_int;
+    fn tuntap_get_ipv4(name: *const c_char, address: *mut u32, netmask: *mut u32) -> c_int;
+    fn tuntap_route_ipv4(name: *const c_char, dest: u32, netmask: u32, add: c_int) -> c_int;
+}
+
+/// Turns the length of a network prefix into the netmask.
+fn netmask(prefix: u8) -> Result<u32> {
+    if prefix > 32 {
+        return Err(io::Error::new(ErrorKind::InvalidInput, "Prefix longer than 32"));
+    }
+    Ok(u32::MAX.checked_shl(32 - u32::from(prefix)).unwrap_or(0))
 }
 
 /// The mode in which open the virtual network adapter.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "lowercase"))]
 pub enum Mode {
     /// TUN mode
     ///
     /// The packets returned are on the IP layer (layer 3), prefixed with 4-byte header (2 bytes
     /// are flags, 2 bytes are the protocol inside, eg one of
-    /// <https://en.wikipedia.org/wiki/EtherType#Examples>.
+    /// <https://en.wikipedia.org/wiki/EtherType#Examples>. See
+    /// [`PacketInfo`](struct.PacketInfo.html) for decoding it.
     Tun = 1,
     /// TAP mode
     ///
@@ -55,6 +236,48 @@ pub enum Mode {
     Tap = 2,
 }
 
+/// Formats the mode as `tun` or `tap`.
+impl Display for Mode {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            Mode::Tun => write!(fmt, "tun"),
+            Mode::Tap => write!(fmt, "tap"),
+        }
+    }
+}
+
+/// Parses `tun` or `tap` (in any case).
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::Mode;
+/// assert_eq!(Ok(Mode::Tap), "tap".parse());
+/// assert_eq!("tun", Mode::Tun.to_string());
+/// assert!("tan".parse::<Mode>().is_err());
+/// ```
+impl FromStr for Mode {
+    type Err = ParseError;
+    fn from_str(s: &str) -> std::result::Result<Self, ParseError> {
+        if s.eq_ignore_ascii_case("tun") {
+            Ok(Mode::Tun)
+        } else if s.eq_ignore_ascii_case("tap") {
+            Ok(Mode::Tap)
+        } else {
+            Err(ParseError::new("mode", s))
+        }
+    }
+}
+
+/// What [`Iface::ensure`](struct.Iface.html#method.ensure) did to get the device.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Ensured {
+    /// The device didn't exist and was created.
+    Created,
+    /// The device already existed (it is persistent) and was attached to.
+    Attached,
+}
+
 /// The virtual interface.
 ///
 /// This is the main structure of the crate, representing the actual virtual interface, either in
@@ -64,6 +287,10 @@ pub struct Iface {
     fd: File,
     mode: Mode,
     name: String,
+    packet_info: bool,
+    shutdown: OnceLock<Arc<ShutdownPipe>>,
+    busy_poll: Duration,
+    cleanup: Option<Arc<CleanupGuard>>,
 }
 
 impl Iface {
@@ -72,18 +299,29 @@ impl Iface {
     /// # Parameters
     ///
     /// * `ifname`: The requested name of the virtual device. If left empty, the kernel will
-    ///   provide some reasonable, currently unused name. It also can contain `%d`, which will be
-    ///   replaced by a number to ensure the name is unused. Even if it isn't empty or doesn't
-    ///   contain `%d`, the actual name may be different (for example truncated to OS-dependent
-    ///   length). Use [`name`](#method.name) to find out the real name.
+    ///   provide some reasonable, currently unused name. It also can contain a single `%d`, which
+    ///   the kernel replaces by the lowest number making the name unused (`tun%d` becomes `tun0`,
+    ///   then `tun1`, …); the name with the number must still fit the limit. Even if it isn't
+    ///   empty or doesn't contain `%d`, the actual name may be different. Use
+    ///   [`name`](#method.name) to find out the real name.
     /// * `mode`: In which mode to create the device.
     ///
     /// # Errors
     ///
-    /// This may fail for various OS-dependent reasons. However, two most common are:
+    /// This may fail for various OS-dependent reasons. The common ones are turned into specific
+    /// variants of [`Error`](enum.Error.html), most notably:
     ///
-    /// * The name is already taken.
-    /// * The process doesn't have the needed privileges (eg. `CAP_NETADM`).
+    /// * The name is not valid, which is checked before asking the kernel. It is longer than the OS
+    ///   limit of 15 bytes ([`NameTooLong`](enum.Error.html#variant.NameTooLong)), contains a NUL
+    ///   byte ([`NameContainsNul`](enum.Error.html#variant.NameContainsNul)), a slash, a colon or
+    ///   whitespace
+    ///   ([`NameContainsInvalidChar`](enum.Error.html#variant.NameContainsInvalidChar)), is `.`
+    ///   or `..` or uses `%` other than as a single `%d`
+    ///   ([`InvalidName`](enum.Error.html#variant.InvalidName)).
+    /// * The name is already taken ([`NameTaken`](enum.Error.html#variant.NameTaken)).
+    /// * The process doesn't have the `CAP_NET_ADMIN` capability
+    ///   ([`MissingCapability`](enum.Error.html#variant.MissingCapability)) or other needed
+    ///   privileges ([`PermissionDenied`](enum.Error.html#variant.PermissionDenied)).
     ///
     /// # Examples
     ///
@@ -95,26 +333,37 @@ impl Iface {
     /// let mut buffer = vec![0; 1504]; // MTU + 4 for the header
     /// iface.recv(&mut buffer).unwrap();
     /// ```
-    pub fn new(ifname: &str, mode: Mode) -> Result<Self> {
-        Iface::with_options(ifname, mode, true)
+    pub fn new(ifname: &str, mode: Mode) -> std::result::Result<Self, Error> {
+        Iface::with_flags(ifname, mode.into())
     }
     /// Creates a new virtual interface without the prepended packet info.
     ///
     /// # Parameters
     ///
     /// * `ifname`: The requested name of the virtual device. If left empty, the kernel will
-    ///   provide some reasonable, currently unused name. It also can contain `%d`, which will be
-    ///   replaced by a number to ensure the name is unused. Even if it isn't empty or doesn't
-    ///   contain `%d`, the actual name may be different (for example truncated to OS-dependent
-    ///   length). Use [`name`](#method.name) to find out the real name.
+    ///   provide some reasonable, currently unused name. It also can contain a single `%d`, which
+    ///   the kernel replaces by the lowest number making the name unused (`tun%d` becomes `tun0`,
+    ///   then `tun1`, …); the name with the number must still fit the limit. Even if it isn't
+    ///   empty or doesn't contain `%d`, the actual name may be different. Use
+    ///   [`name`](#method.name) to find out the real name.
     /// * `mode`: In which mode to create the device.
     ///
     /// # Errors
     ///
-    /// This may fail for various OS-dependent reasons. However, two most common are:
+    /// This may fail for various OS-dependent reasons. The common ones are turned into specific
+    /// variants of [`Error`](enum.Error.html), most notably:
     ///
-    /// * The name is already taken.
-    /// * The process doesn't have the needed privileges (eg. `CAP_NETADM`).
+    /// * The name is not valid, which is checked before asking the kernel. It is longer than the OS
+    ///   limit of 15 bytes ([`NameTooLong`](enum.Error.html#variant.NameTooLong)), contains a NUL
+    ///   byte ([`NameContainsNul`](enum.Error.html#variant.NameContainsNul)), a slash, a colon or
+    ///   whitespace
+    ///   ([`NameContainsInvalidChar`](enum.Error.html#variant.NameContainsInvalidChar)), is `.`
+    ///   or `..` or uses `%` other than as a single `%d`
+    ///   ([`InvalidName`](enum.Error.html#variant.InvalidName)).
+    /// * The name is already taken ([`NameTaken`](enum.Error.html#variant.NameTaken)).
+    /// * The process doesn't have the `CAP_NET_ADMIN` capability
+    ///   ([`MissingCapability`](enum.Error.html#variant.MissingCapability)) or other needed
+    ///   privileges ([`PermissionDenied`](enum.Error.html#variant.PermissionDenied)).
     ///
     /// # Examples
     ///
@@ -126,42 +375,332 @@ impl Iface {
     /// let mut buffer = vec![0; 1500]; // MTU
     /// iface.recv(&mut buffer).unwrap();
     /// ```
-    pub fn without_packet_info(ifname: &str, mode: Mode) -> Result<Self> {
-        Iface::with_options(ifname, mode, false)
+    pub fn without_packet_info(ifname: &str, mode: Mode) -> std::result::Result<Self, Error> {
+        Iface::with_flags(ifname, TunFlags::from(mode) | TunFlags::NO_PI)
+    }
+    /// Creates a new virtual interface with the given flags.
+    ///
+    /// This allows creating the device with flags not covered by the other constructors. The
+    /// mode is taken from the flags. Whether the packet info is prepended is decided by the
+    /// [`NO_PI`](struct.TunFlags.html#associatedconstant.NO_PI) flag.
+    ///
+    /// # Parameters
+    ///
+    /// * `ifname`: The requested name of the virtual device, see [`new`](#method.new).
+    /// * `flags`: The flags to pass to the kernel.
+    ///
+    /// # Errors
+    ///
+    /// Apart from the errors of [`new`](#method.new), this fails with an
+    /// [`Io`](enum.Error.html#variant.Io) error of the `InvalidInput` kind if not exactly one of
+    /// the [`TUN`](struct.TunFlags.html#associatedconstant.TUN) and
+    /// [`TAP`](struct.TunFlags.html#associatedconstant.TAP) flags is set. Flags the kernel
+    /// doesn't support result in an error too.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let flags = TunFlags::TAP | TunFlags::NO_PI | TunFlags::TUN_EXCL;
+    /// let iface = Iface::with_flags("mytap", flags).expect("Failed to create a TAP device");
+    /// ```
+    pub fn with_flags(ifname: &str, flags: TunFlags) -> std::result::Result<Self, Error> {
+        PendingIface::open()?.finish_with_flags(ifname, flags)
+    }
+    /// Attaches to the device if it exists, creates it otherwise.
+    ///
+    /// This is for daemons reclaiming their (persistent) device after a restart. The `options`
+    /// are the flags besides the mode, like in [`with_flags`](#method.with_flags). The returned
+    /// [`Ensured`](enum.Ensured.html) tells which of the two happened.
+    ///
+    /// The kernel checks the existing device matches the mode and the
+    /// [`MULTI_QUEUE`](struct.TunFlags.html#associatedconstant.MULTI_QUEUE) flag. The others
+    /// (eg. [`NO_PI`](struct.TunFlags.html#associatedconstant.NO_PI)) are applied to it.
+    ///
+    /// A name with `%d` always creates a new device.
+    ///
+    /// # Errors
+    ///
+    /// As with [`with_flags`](#method.with_flags). Notably, if a device of a different mode (or
+    /// a different kind of network interface) has the name, it is
+    /// [`NameTaken`](enum.Error.html#variant.NameTaken). If the existing device is not
+    /// multi-queue and is in use by someone else, it is
+    /// [`DeviceBusy`](enum.Error.html#variant.DeviceBusy).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let (iface, ensured) =
+    ///     Iface::ensure("vpn0", Mode::Tun, TunFlags::NO_PI).expect("Failed to get the device");
+    /// if ensured == Ensured::Created {
+    ///     iface.set_persistent(true).unwrap();
+    ///     // Configure it only the first time
+    /// }
+    /// ```
+    pub fn ensure(
+        ifname: &str,
+        mode: Mode,
+        options: TunFlags,
+    ) -> std::result::Result<(Self, Ensured), Error> {
+        let flags = (TunFlags::from(mode) | options) - TunFlags::TUN_EXCL;
+        // The exclusive flag makes the kernel refuse existing devices with EBUSY
+        match Iface::with_flags(ifname, flags | TunFlags::TUN_EXCL) {
+            Ok(iface) => Ok((iface, Ensured::Created)),
+            Err(Error::DeviceBusy) => {
+                debug!("Device {} exists, attaching", ifname);
+                let iface = Iface::with_flags(ifname, flags)?;
+                Ok((iface, Ensured::Attached))
+            },
+            Err(e) => Err(e),
+        }
+    }
+    /// Creates a batch of interfaces from a name pattern.
+    ///
+    /// This is for testbeds and emulators needing many devices at once. The `pattern` contains
+    /// `%d` (or is empty), so each device gets its own name, like with [`new`](#method.new). The
+    /// devices are returned in the order of creation, [`name`](#method.name) tells the names
+    /// picked by the kernel.
+    ///
+    /// It's all or nothing ‒ if any of the devices can't be created, the ones created so far are
+    /// closed (and therefore removed) before returning the error.
+    ///
+    /// # Errors
+    ///
+    /// As with [`new`](#method.new). If more than one device is asked for and the `pattern`
+    /// doesn't contain `%d`, it is [`InvalidName`](enum.Error.html#variant.InvalidName).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let ifaces = Iface::new_many("node%d", Mode::Tap, 32).expect("Failed to create devices");
+    /// for iface in &ifaces {
+    ///     println!("Created {}", iface.name());
+    /// }
+    /// ```
+    pub fn new_many(
+        pattern: &str,
+        mode: Mode,
+        count: usize,
+    ) -> std::result::Result<Vec<Self>, Error> {
+        if count > 1 && !pattern.is_empty() && !pattern.contains("%d") {
+            error!("Can't create {} devices named {:?}", count, pattern);
+            return Err(Error::InvalidName);
+        }
+        let mut ifaces = Vec::with_capacity(count);
+        for _ in 0..count {
+            match Iface::new(pattern, mode) {
+                Ok(iface) => ifaces.push(iface),
+                Err(e) => {
+                    error!(
+                        "Creating device {} of {} failed, removing the others",
+                        ifaces.len() + 1,
+                        count
+                    );
+                    return Err(e);
+                },
+            }
+        }
+        Ok(ifaces)
     }
 
-    fn with_options(ifname: &str, mode: Mode, packet_info: bool) -> Result<Self> {
-        let fd = OpenOptions::new()
-            .read(true)
-            .write(true)
-            .open("/dev/net/tun")?;
-        // The buffer is larger than needed, but who cares… it is large enough.
-        let mut name_buffer = Vec::new();
-        name_buffer.extend_from_slice(ifname.as_bytes());
-        name_buffer.extend_from_slice(&[0; 33]);
-        let name_ptr: *mut u8 = name_buffer.as_mut_ptr();
-        let result = unsafe {
-            tuntap_setup(
-                fd.as_raw_fd(),
-                name_ptr,
-                mode as c_int,
-                if packet_info { 1 } else { 0 },
-            )
+    /// Picks up a device passed by systemd.
+    ///
+    /// This allows a service to run without any privileges ‒ systemd (or another service manager
+    /// implementing the same protocol) creates the device and passes its file descriptor. The
+    /// `name` is the name of the file descriptor (the `FileDescriptorName=` of the socket unit or
+    /// `FDNAME=` of the file descriptor store), it doesn't have to match the name of the
+    /// interface.
+    ///
+    /// The file descriptor is checked to be a TUN/TAP device and the name, mode and presence of
+    /// the packet info are read from the kernel. It is switched to close-on-exec. Each file
+    /// descriptor is handed out only once ‒ it is owned by the returned interface, or closed if
+    /// it turns out to be unusable. Picking it up again fails.
+    ///
+    /// The device is looked up by its name in the network namespace of the process, a device
+    /// living in another one (eg. a file descriptor passed from a container) is rejected. A device
+    /// of the same name and kind in this namespace can't be told apart, its packet info setting is
+    /// used then.
+    ///
+    /// # Errors
+    ///
+    /// * There's no such file descriptor or it was already picked up
+    ///   ([`Io`](enum.Error.html#variant.Io) of the `NotFound` kind).
+    /// * The file descriptor is not an attached TUN/TAP device
+    ///   ([`NotATunDevice`](enum.Error.html#variant.NotATunDevice)).
+    /// * The device is not in the network namespace of the process
+    ///   ([`Io`](enum.Error.html#variant.Io) of the `NotFound` kind).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let iface = Iface::from_systemd("vpn").expect("Didn't get the device from systemd");
+    /// println!("Running on {}", iface.name());
+    /// ```
+    pub fn from_systemd(name: &str) -> std::result::Result<Self, Error> {
+        let fd = match systemd::take_fd(name) {
+            Some(fd) => fd,
+            None => {
+                error!("No file descriptor {} passed by systemd", name);
+                return Err(Error::Io(io::Error::new(
+                    ErrorKind::NotFound,
+                    format!("No file descriptor {} passed by systemd", name),
+                )));
+            },
         };
-        if result < 0 {
-            return Err(Error::last_os_error());
+        if unsafe { libc::fcntl(fd, libc::F_SETFD, libc::FD_CLOEXEC) } < 0 {
+            let error = io::Error::last_os_error();
+            error!("Passed file descriptor {} is not usable: {}", name, error);
+            return Err(Error::Io(error));
+        }
+        Iface::adopt(unsafe { File::from_raw_fd(fd) })
+    }
+    /// Wraps a file descriptor attached to a device by someone else.
+    ///
+    /// This is for file descriptors received from another process (eg. over a unix socket) or
+    /// from a library that doesn't use this crate. Like with
+    /// [`from_systemd`](#method.from_systemd), the name, mode and presence of the packet info are
+    /// read from the kernel, so [`name`](#method.name), [`mode`](#method.mode) and
+    /// [`packet_info`](#method.packet_info) reflect how the device was really created.
+    ///
+    /// The device is looked up by its name in the network namespace of the process, a device
+    /// living in another one (eg. a file descriptor passed from a container) is rejected. A device
+    /// of the same name and kind in this namespace can't be told apart, its packet info setting is
+    /// used then.
+    ///
+    /// # Errors
+    ///
+    /// * The file descriptor is not an attached TUN/TAP device
+    ///   ([`NotATunDevice`](enum.Error.html#variant.NotATunDevice)).
+    /// * The device is not in the network namespace of the process
+    ///   ([`Io`](enum.Error.html#variant.Io) of the `NotFound` kind).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::os::unix::io::{FromRawFd, OwnedFd};
+    /// # use tun_tap::*;
+    /// # let raw_fd = 3;
+    /// let fd = unsafe { OwnedFd::from_raw_fd(raw_fd) };
+    /// let iface = Iface::from_fd(fd).expect("Not a TUN/TAP device");
+    /// let header = if iface.packet_info() { 4 } else { 0 };
+    /// println!("{:?} device {}, {} bytes of header", iface.mode(), iface.name(), header);
+    /// ```
+    pub fn from_fd(fd: OwnedFd) -> std::result::Result<Self, Error> {
+        Iface::adopt(File::from(fd))
+    }
+    /// Wraps a file attached to a device.
+    ///
+    /// This is the way back from [`into_file`](#method.into_file) (taking over the cleanup of the
+    /// [`IfaceFile`](struct.IfaceFile.html)), but works with any `File` attached to a device
+    /// (like [`from_fd`](#method.from_fd) does). The name, mode and presence of the packet info
+    /// are read from the kernel. The same limitation to the network namespace of the process
+    /// applies.
+    ///
+    /// # Errors
+    ///
+    /// * The file is not an attached TUN/TAP device
+    ///   ([`NotATunDevice`](enum.Error.html#variant.NotATunDevice)).
+    /// * The device is not in the network namespace of the process
+    ///   ([`Io`](enum.Error.html#variant.Io) of the `NotFound` kind).
+    pub fn from_file<F: Into<IfaceFile>>(file: F) -> std::result::Result<Self, Error> {
+        let IfaceFile { file, cleanup } = file.into();
+        let mut iface = Iface::adopt(file)?;
+        iface.cleanup = cleanup;
+        Ok(iface)
+    }
+    /// Turns the interface into the underlying file.
+    ///
+    /// This is for temporarily handing the device to code expecting a `File` (eg. generic copying
+    /// utilities). Reading and writing the file receives and sends the packets the same way
+    /// [`recv`](#method.recv) and [`send`](#method.send) do. Once done, the interface can be
+    /// reclaimed by [`from_file`](#method.from_file).
+    ///
+    /// The [cleanup](#method.set_cleanup) travels with the file and happens only once it is
+    /// dropped (or the bare `File` is taken out by
+    /// [`IfaceFile::into_inner`](struct.IfaceFile.html#method.into_inner)). The
+    /// [shutdown handles](#method.shutdown_handle) no longer reach the file.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::fs::File;
+    /// # use std::io::{self, Read};
+    /// # use tun_tap::*;
+    /// fn dump(file: &mut File) -> io::Result<()> {
+    ///     let mut packet = vec![0; 1504];
+    ///     let size = file.read(&mut packet)?;
+    ///     println!("{:?}", &packet[..size]);
+    ///     Ok(())
+    /// }
+    ///
+    /// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a device");
+    /// let mut file = iface.into_file();
+    /// dump(&mut file).unwrap();
+    /// let iface = Iface::from_file(file).unwrap();
+    /// ```
+    pub fn into_file(self) -> IfaceFile {
+        IfaceFile {
+            file: self.fd,
+            cleanup: self.cleanup,
         }
-        let name = unsafe {
-            CStr::from_ptr(name_ptr as *const c_char)
-                .to_string_lossy()
-                .into_owned()
+    }
+    /// Wraps a file descriptor attached to a device somewhere else.
+    pub(crate) fn adopt(fd: File) -> std::result::Result<Self, Error> {
+        let mut name_buffer = [0u8; 33];
+        let flags = unsafe { tuntap_get_flags(fd.as_raw_fd(), name_buffer.as_mut_ptr()) };
+        if flags < 0 {
+            let error = io::Error::last_os_error();
+            error!("TUNGETIFF failed: {}", error);
+            return Err(Error::from_adopt(error));
+        }
+        let flags = TunFlags::from_bits_retain(flags as u16);
+        let mode = flags.mode().ok_or(Error::NotATunDevice)?;
+        let name = CStr::from_bytes_until_nul(&name_buffer)
+            .map_err(|_| Error::NotATunDevice)?
+            .to_string_lossy()
+            .into_owned();
+        let foreign = || {
+            error!("Device {} is not in the network namespace of the process", name);
+            Error::Io(io::Error::new(
+                ErrorKind::NotFound,
+                format!("Device {} is not in the network namespace of the process", name),
+            ))
+        };
+        // The NO_PI bit from TUNGETIFF is overloaded (see tun_flags), the sysfs one is not. But
+        // the sysfs is of our network namespace, the device may live elsewhere.
+        let sys_flags = match fs::read_to_string(format!("/sys/class/net/{}/tun_flags", name)) {
+            Ok(sys_flags) => sys_flags,
+            Err(ref e) if e.kind() == ErrorKind::NotFound => return Err(foreign()),
+            Err(e) => return Err(Error::Io(e)),
         };
-        Ok(Iface { fd, mode, name })
+        let sys_flags = u16::from_str_radix(sys_flags.trim().trim_start_matches("0x"), 16)
+            .map_err(|e| Error::Io(io::Error::new(ErrorKind::InvalidData, e)))?;
+        let sys_flags = TunFlags::from_bits_retain(sys_flags);
+        // Another device of the same name here, at least if it is of a different kind
+        let kind = TunFlags::TUN | TunFlags::TAP | TunFlags::MULTI_QUEUE | TunFlags::VNET_HDR;
+        if (flags ^ sys_flags).intersects(kind) {
+            return Err(foreign());
+        }
+        let packet_info = !sys_flags.contains(TunFlags::NO_PI);
+        debug!("Adopted {:?} interface {}", mode, name);
+        Ok(Iface {
+            fd,
+            mode,
+            name,
+            packet_info,
+            shutdown: OnceLock::new(),
+            busy_poll: Duration::ZERO,
+            cleanup: None,
+        })
     }
 
     /// Returns the mode of the adapter.
     ///
-    /// It is always the same as the one passed to [`new`](#method.new).
+    /// It is always the same as the one passed to [`new`](#method.new). For adopted file
+    /// descriptors (eg. [`from_fd`](#method.from_fd)), it is the mode the device was created with.
     pub fn mode(&self) -> Mode {
         self.mode
     }
@@ -174,6 +713,412 @@ impl Iface {
     pub fn name(&self) -> &str {
         &self.name
     }
+    /// Returns if the packets are prepended with the packet info.
+    ///
+    /// If true, each packet starts with the 4 bytes of [`PacketInfo`](struct.PacketInfo.html)
+    /// (as created by [`new`](#method.new)), otherwise it is the bare packet (as created by
+    /// [`without_packet_info`](#method.without_packet_info)). For adopted file descriptors, it is
+    /// found out from the kernel.
+    pub fn packet_info(&self) -> bool {
+        self.packet_info
+    }
+    /// Returns the flags of the device, as the kernel reports them.
+    ///
+    /// These include the mode, [`PERSIST`](struct.TunFlags.html#associatedconstant.PERSIST)
+    /// if the device is persistent, and the flags it was created with.
+    ///
+    /// The kernel reports its `IFF_NOFILTER` in the same bit as
+    /// [`NO_PI`](struct.TunFlags.html#associatedconstant.NO_PI), therefore that one is filled in
+    /// from what this handle was created with instead.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen)
+    /// or on systems without the `TUNGETIFF` ioctl.
+    pub fn tun_flags(&self) -> Result<TunFlags> {
+        let result = unsafe { tuntap_get_flags(self.fd.as_raw_fd(), ptr::null_mut()) };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            error!("TUNGETIFF on {} failed: {}", self.name, error);
+            Err(error)
+        } else {
+            let mut flags = TunFlags::from_bits_retain(result as u16);
+            flags.set(TunFlags::NO_PI, !self.packet_info);
+            Ok(flags)
+        }
+    }
+    /// Returns the flags of the network interface (eg. if it is up).
+    ///
+    /// Unlike [`tun_flags`](#method.tun_flags), these are the generic flags every network
+    /// interface has, as shown by `ip link`.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn iff_flags(&self) -> Result<IffFlags> {
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let result = unsafe { tuntap_get_iff_flags(name.as_ptr()) };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            error!("SIOCGIFFLAGS on {} failed: {}", self.name, error);
+            Err(error)
+        } else {
+            Ok(IffFlags::from_bits_retain(result as u16))
+        }
+    }
+    /// Brings the interface up or down, like `ip link set up`.
+    ///
+    /// # Errors
+    ///
+    /// If the process lacks the `CAP_NET_ADMIN` capability.
+    pub fn set_up(&self, up: bool) -> Result<()> {
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let result = unsafe { tuntap_set_up(name.as_ptr(), c_int::from(up)) };
+        self.check_set(result, "SIOCSIFFLAGS")
+    }
+    /// Sets the IPv4 address of the kernel side of the interface, like `ip addr add`.
+    ///
+    /// The `prefix` is the length of the network part of the address (eg. 24 for
+    /// `10.0.0.1/24`). This replaces the primary IPv4 address of the interface, if it has one.
+    /// The unspecified address (`0.0.0.0`) removes it.
+    ///
+    /// # Errors
+    ///
+    /// If the `prefix` is longer than 32 or if the process lacks the `CAP_NET_ADMIN` capability.
+    pub fn set_ipv4(&self, address: Ipv4Addr, prefix: u8) -> Result<()> {
+        let netmask = netmask(prefix)?;
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let result = unsafe {
+            tuntap_set_ipv4(
+                name.as_ptr(),
+                u32::from(address).to_be(),
+                netmask.to_be(),
+            )
+        };
+        self.check_set(result, "SIOCSIFADDR")
+    }
+    /// Returns the primary IPv4 address of the kernel side of the interface and its prefix length.
+    ///
+    /// # Errors
+    ///
+    /// If the kernel can't be asked (eg. the device is gone).
+    pub fn ipv4(&self) -> Result<Option<(Ipv4Addr, u8)>> {
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let mut address = 0;
+        let mut netmask = 0;
+        match unsafe { tuntap_get_ipv4(name.as_ptr(), &mut address, &mut netmask) } {
+            0 => Ok(None),
+            1 => {
+                let address = Ipv4Addr::from(u32::from_be(address));
+                Ok(Some((address, netmask.count_ones() as u8)))
+            },
+            _ => {
+                let error = io::Error::last_os_error();
+                error!("SIOCGIFADDR on {} failed: {}", self.name, error);
+                Err(error)
+            },
+        }
+    }
+    /// Routes the IPv4 network through the interface, like `ip route add NETWORK dev NAME`.
+    ///
+    /// The route goes away with the device or when the device is brought down.
+    ///
+    /// # Errors
+    ///
+    /// If the `prefix` is longer than 32, the `destination` has bits set outside of the prefix,
+    /// the route already exists or if the process lacks the `CAP_NET_ADMIN` capability. The
+    /// device needs to be up.
+    pub fn add_route(&self, destination: Ipv4Addr, prefix: u8) -> Result<()> {
+        self.route(destination, prefix, true)
+    }
+    /// Removes a route added by [`add_route`](#method.add_route).
+    ///
+    /// # Errors
+    ///
+    /// If there's no such route or if the process lacks the `CAP_NET_ADMIN` capability.
+    pub fn remove_route(&self, destination: Ipv4Addr, prefix: u8) -> Result<()> {
+        self.route(destination, prefix, false)
+    }
+    fn route(&self, destination: Ipv4Addr, prefix: u8, add: bool) -> Result<()> {
+        let netmask = netmask(prefix)?;
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let destination = u32::from(destination).to_be();
+        let result = unsafe {
+            tuntap_route_ipv4(name.as_ptr(), destination, netmask.to_be(), c_int::from(add))
+        };
+        self.check_set(result, if add { "SIOCADDRT" } else { "SIOCDELRT" })
+    }
+    /// Tells the kernel which offloads the application handles.
+    ///
+    /// With offloads enabled, the kernel may pass packets that are larger than the MTU or have
+    /// the checksum not filled in, described by the virtio net header. Therefore this makes
+    /// sense only on devices created with the
+    /// [`VNET_HDR`](struct.TunFlags.html#associatedconstant.VNET_HDR) flag (see
+    /// [`with_flags`](#method.with_flags)) and the application needs to parse that header
+    /// itself.
+    ///
+    /// # Errors
+    ///
+    /// If the kernel doesn't support some of the offloads or on systems without the
+    /// `TUNSETOFFLOAD` ioctl.
+    pub fn set_offload(&self, offload: OffloadFlags) -> Result<()> {
+        let result = unsafe { tuntap_set_offload(self.fd.as_raw_fd(), offload.bits()) };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            error!("TUNSETOFFLOAD on {} failed: {}", self.name, error);
+            Err(error)
+        } else {
+            Ok(())
+        }
+    }
+    /// Makes the device persistent (or not).
+    ///
+    /// A persistent device stays around after the last file descriptor is closed and can be
+    /// attached to again later (eg. by [`new`](#method.new) with the same name). This is how
+    /// `ip tuntap add` creates the devices. Making a device non-persistent removes it once it is
+    /// closed.
+    ///
+    /// # Errors
+    ///
+    /// If the process lacks the `CAP_NET_ADMIN` capability and isn't the owner of the device.
+    pub fn set_persistent(&self, persistent: bool) -> Result<()> {
+        let result = unsafe { tuntap_set_persist(self.fd.as_raw_fd(), c_int::from(persistent)) };
+        self.check_set(result, "TUNSETPERSIST")
+    }
+    /// Checks if the device is persistent.
+    ///
+    /// A persistent device outlives the application (see
+    /// [`set_persistent`](#method.set_persistent)), so someone has to remove it eventually ‒ if
+    /// it isn't, closing the last file descriptor does. The flag is asked from the kernel each
+    /// time, as it can be changed through any file descriptor of the device.
+    ///
+    /// # Errors
+    ///
+    /// As with [`tun_flags`](#method.tun_flags).
+    pub fn is_persistent(&self) -> Result<bool> {
+        Ok(self.tun_flags()?.contains(TunFlags::PERSIST))
+    }
+    /// Sets what happens to the device when the interface is dropped.
+    ///
+    /// This is for short-lived tools not wanting to leave half-configured devices behind (see
+    /// [`Cleanup`](enum.Cleanup.html) for the options). The cleanup is shared with the
+    /// [clones](#method.try_clone) made afterwards and happens once the last of them is dropped
+    /// (or turned into a raw file descriptor). Failures at that point are only logged.
+    ///
+    /// # Errors
+    ///
+    /// The cleanup holds a copy of the file descriptor (to be able to remove the persistence).
+    /// This fails if it can't be duplicated.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::net::Ipv4Addr;
+    /// # use tun_tap::*;
+    /// let mut iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// iface.set_persistent(true).unwrap();
+    /// iface.set_cleanup(Cleanup::RemovePersistence).unwrap();
+    /// iface.set_ipv4(Ipv4Addr::new(10, 0, 0, 1), 24).unwrap();
+    /// // Even if this panics, the device goes away
+    /// ```
+    pub fn set_cleanup(&mut self, cleanup: Cleanup) -> Result<()> {
+        self.cleanup = match cleanup {
+            Cleanup::Nothing => None,
+            cleanup => Some(Arc::new(CleanupGuard::new(&self.fd, &self.name, cleanup)?)),
+        };
+        Ok(())
+    }
+    /// Returns what happens to the device when the interface is dropped.
+    pub fn cleanup(&self) -> Cleanup {
+        self.cleanup.as_ref().map_or(Cleanup::Nothing, |guard| guard.cleanup())
+    }
+    /// Sets the user allowed to attach to the device without privileges.
+    ///
+    /// This makes sense mostly for [persistent](#method.set_persistent) devices, created in
+    /// advance for an unprivileged application. The kernel doesn't allow removing the owner once
+    /// set, only changing it.
+    ///
+    /// # Errors
+    ///
+    /// If the process lacks the `CAP_NET_ADMIN` capability and isn't the owner of the device.
+    pub fn set_owner(&self, uid: u32) -> Result<()> {
+        let result = unsafe { tuntap_set_owner(self.fd.as_raw_fd(), uid) };
+        self.check_set(result, "TUNSETOWNER")
+    }
+    /// Sets the group allowed to attach to the device without privileges.
+    ///
+    /// See [`set_owner`](#method.set_owner).
+    ///
+    /// # Errors
+    ///
+    /// If the process lacks the `CAP_NET_ADMIN` capability and isn't the owner of the device, or
+    /// on systems without the `TUNSETGROUP` ioctl.
+    pub fn set_group(&self, gid: u32) -> Result<()> {
+        let result = unsafe { tuntap_set_group(self.fd.as_raw_fd(), gid) };
+        self.check_set(result, "TUNSETGROUP")
+    }
+    /// Installs an eBPF program choosing the queue for each packet of a multi-queue device.
+    ///
+    /// The `prog` is a file descriptor of an already loaded program of the
+    /// `BPF_PROG_TYPE_SOCKET_FILTER` type, returning the index of the queue. The program applies
+    /// to the whole device, so it is enough to install it through one of the queues. `None`
+    /// detaches it, returning to the default steering by the flow hash.
+    ///
+    /// Loading the program is out of the scope of this crate (see eg. the `aya` or `libbpf-rs`
+    /// crates).
+    ///
+    /// # Errors
+    ///
+    /// If the program is not a valid eBPF program or on systems without the
+    /// `TUNSETSTEERINGEBPF` ioctl.
+    pub fn set_steering_ebpf(&self, prog: Option<RawFd>) -> Result<()> {
+        let result = unsafe { tuntap_set_steering_ebpf(self.fd.as_raw_fd(), prog.unwrap_or(-1)) };
+        self.check_set(result, "TUNSETSTEERINGEBPF")
+    }
+    /// Asks the kernel not to validate the checksums of the packets sent into the device.
+    ///
+    /// This is the legacy `TUNSETNOCSUM` ioctl, for applications guaranteeing correct checksums
+    /// themselves. Packets with broken checksums may then get accepted by the kernel, so use it
+    /// only for experiments and compatibility with old code. Current kernels accept the ioctl,
+    /// but ignore it (the checksums are handled through the
+    /// [offloads](#method.set_offload) instead).
+    ///
+    /// # Errors
+    ///
+    /// On systems without the `TUNSETNOCSUM` ioctl.
+    pub fn set_no_checksum(&self, no_checksum: bool) -> Result<()> {
+        let result = unsafe { tuntap_set_nocsum(self.fd.as_raw_fd(), c_int::from(no_checksum)) };
+        self.check_set(result, "TUNSETNOCSUM")
+    }
+    fn check_set(&self, result: c_int, what: &str) -> Result<()> {
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            error!("{} on {} failed: {}", what, self.name, error);
+            Err(error)
+        } else {
+            debug!("{} on {} succeeded", what, self.name);
+            Ok(())
+        }
+    }
+    /// Returns the current MTU of the interface.
+    ///
+    /// The MTU can be changed from the outside at any time (eg. by `ip link set mtu`), so this
+    /// asks the kernel each time it is called. It doesn't include the ethernet header in the TAP
+    /// mode nor the packet info header.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn mtu(&self) -> Result<usize> {
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let result = unsafe { tuntap_get_mtu(name.as_ptr()) };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            error!("SIOCGIFMTU on {} failed: {}", self.name, error);
+            Err(error)
+        } else {
+            Ok(result as usize)
+        }
+    }
+    /// Changes the MTU of the interface, like `ip link set mtu`.
+    ///
+    /// Note that the kernel may lose a packet in flight around the change.
+    ///
+    /// # Errors
+    ///
+    /// If the MTU is out of the range the interface supports or if the process lacks the
+    /// `CAP_NET_ADMIN` capability.
+    pub fn set_mtu(&self, mtu: usize) -> Result<()> {
+        let mtu = c_int::try_from(mtu)
+            .map_err(|_| io::Error::new(ErrorKind::InvalidInput, "MTU too large"))?;
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let result = unsafe { tuntap_set_mtu(name.as_ptr(), mtu) };
+        self.check_set(result, "SIOCSIFMTU")
+    }
+    /// Returns the size of the largest packet [`recv`](#method.recv) can return.
+    ///
+    /// This is the [MTU](#method.mtu) plus the headers in front of the packet ‒ the ethernet
+    /// header (with room for a single VLAN tag) in the TAP mode and the packet info, if present.
+    /// Buffers of this size are enough to receive any packet without truncating it.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn max_frame_size(&self) -> Result<usize> {
+        Ok(device::max_frame_size(self.mode, self.packet_info, self.mtu()?))
+    }
+    /// Returns the index of the interface.
+    ///
+    /// The index identifies the interface in the routing and netlink APIs (and in the scope of
+    /// IPv6 link-local addresses).
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn index(&self) -> Result<u32> {
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let result = unsafe { libc::if_nametoindex(name.as_ptr()) };
+        if result == 0 {
+            let error = io::Error::last_os_error();
+            error!("Getting index of {} failed: {}", self.name, error);
+            Err(error)
+        } else {
+            Ok(result)
+        }
+    }
+    /// Checks if the device is a multi-queue one.
+    ///
+    /// This is the [`MULTI_QUEUE`](struct.TunFlags.html#associatedconstant.MULTI_QUEUE) flag of
+    /// [`tun_flags`](#method.tun_flags).
+    ///
+    /// # Errors
+    ///
+    /// As with [`tun_flags`](#method.tun_flags).
+    pub fn is_multiqueue(&self) -> Result<bool> {
+        Ok(self.tun_flags()?.contains(TunFlags::MULTI_QUEUE))
+    }
+    /// Returns the number of queues currently attached to the device.
+    ///
+    /// Each file descriptor attached to a multi-queue device (by any process) is a queue, a
+    /// device without the [`MULTI_QUEUE`](struct.TunFlags.html#associatedconstant.MULTI_QUEUE)
+    /// flag has just one. The number is read from the sysfs each time, as it changes when the
+    /// queues come and go.
+    ///
+    /// # Errors
+    ///
+    /// If the sysfs is not mounted.
+    pub fn queue_count(&self) -> Result<usize> {
+        let path = format!("/sys/class/net/{}/queues", self.name);
+        let mut count = 0;
+        for entry in fs::read_dir(path)? {
+            if entry?.file_name().to_string_lossy().starts_with("rx-") {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+    /// Fetches all the metadata and restricts the interface to sending and receiving.
+    ///
+    /// This is meant for entering a strict sandbox (eg. seccomp) after the setup. See
+    /// [`SealedIface`](struct.SealedIface.html).
+    ///
+    /// # Errors
+    ///
+    /// If reading any of the metadata fails.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// let iface = iface.seal().expect("Failed to seal the device");
+    /// // Enter the sandbox here
+    /// let mut buffer = vec![0; iface.mtu() + 4];
+    /// let size = iface.recv(&mut buffer).unwrap();
+    /// ```
+    pub fn seal(self) -> Result<SealedIface> {
+        sealed::seal(self)
+    }
     /// Receives a packet from the interface.
     ///
     /// By default, blocks until a packet is sent into the virtual interface. At that point,
@@ -184,14 +1129,93 @@ impl Iface {
     /// if no packet is queued up.
     ///
     /// Make sure the buffer is large enough. It is MTU of the interface (usually 1500, unless
-    /// reconfigured) + 4 for the header in case that packet info is prepended, MTU + size of ethernet frame (38 bytes,
-    /// unless VLan tags are enabled). If the buffer isn't large enough, the packet gets truncated.
+    /// reconfigured) + 4 for the header in case that packet info is prepended, MTU + size of
+    /// ethernet frame (38 bytes) in the TAP mode, + 4 more for each VLAN tag (see
+    /// [`VlanTag`](packet/struct.VlanTag.html)) if the frames are tagged. If the buffer isn't
+    /// large enough, the packet gets truncated. The [`max_frame_size`](#method.max_frame_size)
+    /// computes the size from the current MTU, which covers jumbo frames too.
+    ///
+    /// If a [`ShutdownHandle`](struct.ShutdownHandle.html) was triggered, this returns the
+    /// [`Shutdown`](struct.Shutdown.html) error (even if it is already blocked).
     ///
     /// # Result
     ///
     /// On successful receive, the number of bytes copied into the buffer is returned.
     pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
-        (&self.fd).read(buf)
+        if !self.busy_poll.is_zero() {
+            self.spin();
+        }
+        if let Some(pipe) = self.shutdown.get() {
+            // Unlike the read, poll is not restarted after a signal handler
+            loop {
+                match pipe.wait_readable(self.fd.as_raw_fd()) {
+                    Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                    result => break result?,
+                }
+            }
+        }
+        let result = (&self.fd).read(buf);
+        self.log_result("recv", &result);
+        result
+    }
+    /// Receives a packet and tells when it was received.
+    ///
+    /// This is like [`recv`](#method.recv), but the [`Timestamp`](struct.Timestamp.html) is
+    /// taken right after the packet is read, before anything else is done with it. Note that it
+    /// is the time the application got the packet, not the time the kernel queued it into the
+    /// device.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// let mut buffer = vec![0; 1504];
+    /// let (size, first) = iface.recv_timestamped(&mut buffer).unwrap();
+    /// let (size, second) = iface.recv_timestamped(&mut buffer).unwrap();
+    /// println!("{:?} between the packets", second.duration_since(&first));
+    /// ```
+    pub fn recv_timestamped(&self, buf: &mut [u8]) -> Result<(usize, Timestamp)> {
+        let size = self.recv(buf)?;
+        Ok((size, Timestamp::now()))
+    }
+    /// Receives a packet and splits off the packet info header.
+    ///
+    /// This is like [`recv`](#method.recv), but if the interface was created with the packet info,
+    /// the header is parsed and returned separately. In either case, the returned slice is the
+    /// packet itself (without the header), borrowed from the buffer.
+    ///
+    /// # Errors
+    ///
+    /// Apart from the errors of `recv`, this fails with
+    /// [`InvalidData`](std::io::ErrorKind::InvalidData) if the received data is too short to
+    /// contain the header.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// let mut buffer = vec![0; 1504];
+    /// let (info, packet) = iface.recv_parsed(&mut buffer).unwrap();
+    /// if info.map(|info| info.proto) == Some(EtherType::Ipv4) {
+    ///     println!("IPv4 packet: {:?}", packet);
+    /// }
+    /// ```
+    pub fn recv_parsed<'b>(&self, buf: &'b mut [u8]) -> Result<(Option<PacketInfo>, &'b [u8])> {
+        let size = self.recv(buf)?;
+        let packet = &buf[..size];
+        if !self.packet_info {
+            return Ok((None, packet));
+        }
+        if size < PacketInfo::SIZE {
+            return Err(io::Error::new(
+                ErrorKind::InvalidData,
+                "Packet too short to contain the packet info",
+            ));
+        }
+        let info = PacketInfo::parse(&[packet[0], packet[1], packet[2], packet[3]]);
+        Ok((Some(info), &packet[PacketInfo::SIZE..]))
     }
     /// Sends a packet into the interface.
     ///
@@ -217,7 +1241,61 @@ impl Iface {
     /// are likely to get dropped too. If you send a packet for address that is not assigned to any
     /// interface and not routed anywhere… you get the idea.
     pub fn send(&self, buf: &[u8]) -> Result<usize> {
-        (&self.fd).write(buf)
+        let result = (&self.fd).write(buf);
+        self.log_result("send", &result);
+        result
+    }
+    /// Sends a packet of the given protocol into the interface.
+    ///
+    /// If the interface was created with the packet info, the appropriate
+    /// [`PacketInfo`](struct.PacketInfo.html) header is prepended to the payload (without copying
+    /// it). Otherwise the `proto` is ignored and this is a plain [`send`](#method.send). This
+    /// allows the same code to work with both kinds of devices.
+    ///
+    /// # Result
+    ///
+    /// On successful send, the number of bytes of the payload sent is returned (the header is not
+    /// counted).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// let ip_packet = [0x45, 0, 0, 20]; // And the rest of the IPv4 packet
+    /// iface.send_with_proto(EtherType::Ipv4, &ip_packet).unwrap();
+    /// ```
+    pub fn send_with_proto(&self, proto: EtherType, payload: &[u8]) -> Result<usize> {
+        if !self.packet_info {
+            return self.send(payload);
+        }
+        self.send_with_header(&PacketInfo::new(proto).to_bytes(), payload)
+    }
+    /// Sends a packet composed of a header and a payload into the interface.
+    ///
+    /// The two parts are sent as a single packet (by the `writev` syscall), without copying them
+    /// into a common buffer first. The header can be anything that precedes the payload ‒ the
+    /// packet info, the virtio net header or even the IP header of the packet.
+    ///
+    /// # Result
+    ///
+    /// On successful send, the number of bytes of the payload sent is returned (the header is not
+    /// counted).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// let header = PacketInfo::new(EtherType::Ipv4).to_bytes();
+    /// let decrypted = [0x45, 0, 0, 20]; // And the rest of the IPv4 packet
+    /// iface.send_with_header(&header, &decrypted).unwrap();
+    /// ```
+    pub fn send_with_header(&self, header: &[u8], payload: &[u8]) -> Result<usize> {
+        let bufs = [IoSlice::new(header), IoSlice::new(payload)];
+        let result = (&self.fd).write_vectored(&bufs);
+        self.log_result("send", &result);
+        result.map(|size| size.saturating_sub(header.len()))
     }
     /// Sets the interface to be non-blocking
     ///
@@ -226,20 +1304,186 @@ impl Iface {
     /// # Errors
     ///
     /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
-    ///
-    /// # Notes
-    /// If default features are excluded, include feature "libc" for this function to be available
-    #[cfg(feature = "libc")]
     pub fn set_non_blocking(&self) -> Result<()> {
         let fd = self.as_raw_fd();
         let mut nonblock: c_int = 1;
         let result = unsafe { libc::ioctl(fd, libc::FIONBIO, &mut nonblock) };
         if result == -1 {
-            Err(Error::last_os_error())
+            let error = io::Error::last_os_error();
+            error!("FIONBIO on {} failed: {}", self.name, error);
+            Err(error)
         } else {
             Ok(())
         }
     }
+    /// Makes [`recv`](#method.recv) busy-poll for a packet before going to sleep.
+    ///
+    /// If no packet is available right away, `recv` keeps checking for the given time before it
+    /// blocks (or, in the non-blocking mode, fails with `WouldBlock`, leaving the waiting to an
+    /// event loop). This saves the latency of putting the thread to sleep and waking it up when
+    /// packets come in quick succession, at the cost of burning the CPU while spinning. A few
+    /// tens of microseconds are usually enough; the default of zero turns it off.
+    ///
+    /// Spinning makes sense only with a CPU to spare for each spinning thread.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::time::Duration;
+    /// # use tun_tap::*;
+    /// let mut iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// iface.set_busy_poll(Duration::from_micros(50));
+    /// ```
+    pub fn set_busy_poll(&mut self, spin: Duration) {
+        self.busy_poll = spin;
+    }
+    /// Returns the configured busy-polling time.
+    pub fn busy_poll(&self) -> Duration {
+        self.busy_poll
+    }
+    /// Spins until the interface (or the shutdown pipe) is readable or the busy-poll time runs
+    /// out.
+    fn spin(&self) {
+        let deadline = Instant::now() + self.busy_poll;
+        while Instant::now() < deadline {
+            // Readable or an error, which the read itself reports.
+            if device::poll_readable(self, Some(Duration::ZERO)).unwrap_or(true) {
+                return;
+            }
+            std::hint::spin_loop();
+        }
+    }
+    /// Returns a handle able to interrupt blocked [`recv`](#method.recv) calls.
+    ///
+    /// This is useful for clean termination of a daemon ‒ the reading thread can be woken up and
+    /// told to terminate without closing the file descriptor under its hands. All the handles
+    /// obtained from the same interface share the same state.
+    ///
+    /// Note that once a handle is created, each `recv` performs an additional `poll` syscall.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (eg. running out of file
+    /// descriptors).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::thread;
+    /// # use tun_tap::*;
+    /// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// let handle = iface.shutdown_handle().unwrap();
+    /// let reader = thread::spawn(move || {
+    ///     let mut buffer = vec![0; 1504];
+    ///     loop {
+    ///         match iface.recv(&mut buffer) {
+    ///             Ok(size) => println!("Packet: {:?}", &buffer[..size]),
+    ///             Err(ref e) if Shutdown::is_shutdown(e) => break,
+    ///             Err(e) => panic!("{}", e),
+    ///         }
+    ///     }
+    /// });
+    /// // Eg. on SIGTERM
+    /// handle.shutdown().unwrap();
+    /// reader.join().unwrap();
+    /// ```
+    pub fn shutdown_handle(&self) -> Result<ShutdownHandle> {
+        if self.shutdown.get().is_none() {
+            // If someone else won the race, our pipe is simply dropped.
+            let _ = self.shutdown.set(Arc::new(ShutdownPipe::new()?));
+        }
+        let pipe = Arc::clone(self.shutdown.get().expect("Shutdown pipe just set"));
+        Ok(ShutdownHandle { pipe })
+    }
+
+    /// Splits the interface into owned receiving and sending halves.
+    ///
+    /// This is handy when the reading and writing is done in separate threads, each can own its
+    /// half. The halves can be put back together with
+    /// [`RecvHalf::reunite`](struct.RecvHalf.html#method.reunite).
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use std::thread;
+    /// # use tun_tap::*;
+    /// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// let (recv, send) = iface.split();
+    /// let reader = thread::spawn(move || {
+    ///     let mut buffer = vec![0; 1504];
+    ///     recv.recv(&mut buffer).unwrap();
+    /// });
+    /// send.send(&[0, 0, 8, 0]).unwrap();
+    /// reader.join().unwrap();
+    /// ```
+    pub fn split(self) -> (RecvHalf, SendHalf) {
+        split::split(self)
+    }
+    /// Turns the interface into a pair of channels.
+    ///
+    /// Two threads are spawned. One receives packets from the interface and passes them into the
+    /// returned receiver, the other one sends whatever is put into the returned sender. Both
+    /// channels hold up to `buffer` packets. If the application doesn't keep up with reading
+    /// the packets, the receiving thread stops and the kernel starts dropping them (as it would
+    /// with a full queue of the interface).
+    ///
+    /// The sending thread terminates once all the senders are dropped. The receiving one
+    /// terminates once the [receiver](struct.PacketReceiver.html) is dropped, or on an
+    /// error. The interface is closed once both are done. Failed sends are dropped (and logged),
+    /// like on any other network.
+    ///
+    /// # Errors
+    ///
+    /// If the threads can't be spawned.
+    ///
+    /// # Examples
+    ///
+    /// ```rust,no_run
+    /// # use tun_tap::*;
+    /// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+    /// let (sender, receiver) = iface.into_channels(64).unwrap();
+    /// for packet in receiver {
+    ///     // Echo everything back
+    ///     sender.send(packet).unwrap();
+    /// }
+    /// ```
+    pub fn into_channels(self, buffer: usize) -> Result<PacketChannels> {
+        channels::channels(self, buffer)
+    }
+
+    /// Creates another handle to the same interface.
+    ///
+    /// The file descriptor is duplicated, so both handles send into and receive from the same
+    /// queue (each packet is received by only one of them). Unlike [`split`](#method.split), each
+    /// handle is a full `Iface` and can be further cloned or wrapped.
+    ///
+    /// The non-blocking mode is shared between the handles. A
+    /// [`shutdown_handle`](#method.shutdown_handle) obtained before cloning interrupts both of
+    /// them, one obtained afterwards only the handle it was obtained from.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (eg. running out of file
+    /// descriptors).
+    pub fn try_clone(&self) -> Result<Self> {
+        Ok(Iface {
+            fd: self.fd.try_clone()?,
+            mode: self.mode,
+            name: self.name.clone(),
+            packet_info: self.packet_info,
+            shutdown: self.shutdown.clone(),
+            busy_poll: self.busy_poll,
+            cleanup: self.cleanup.clone(),
+        })
+    }
+
+    fn log_result(&self, op: &str, result: &Result<usize>) {
+        match result {
+            Ok(size) => trace!("{} of {} bytes on {}", op, size, self.name),
+            Err(e) if e.kind() == ErrorKind::WouldBlock => (),
+            Err(e) => debug!("{} on {} failed: {}", op, self.name, e),
+        }
+    }
 }
 
 impl AsRawFd for Iface {
@@ -248,6 +1492,12 @@ impl AsRawFd for Iface {
     }
 }
 
+impl AsFd for Iface {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
 impl IntoRawFd for Iface {
     fn into_raw_fd(self) -> RawFd {
         self.fd.into_raw_fd()
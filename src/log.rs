@@ -0,0 +1,32 @@
+//! Internal logging macros.
+//!
+//! They forward to `tracing` if the feature is enabled and compile to nothing otherwise (while
+//! still type-checking the arguments, so there are no unused variable warnings).
+
+#[cfg(feature = "tracing")]
+macro_rules! log_event {
+    ($level: ident, $($arg: tt)*) => {
+        tracing::$level!($($arg)*)
+    };
+}
+
+#[cfg(not(feature = "tracing"))]
+macro_rules! log_event {
+    ($level: ident, $($arg: tt)*) => {
+        if false {
+            let _ = format_args!($($arg)*);
+        }
+    };
+}
+
+macro_rules! error {
+    ($($arg: tt)*) => { log_event!(error, $($arg)*) };
+}
+
+macro_rules! debug {
+    ($($arg: tt)*) => { log_event!(debug, $($arg)*) };
+}
+
+macro_rules! trace {
+    ($($arg: tt)*) => { log_event!(trace, $($arg)*) };
+}
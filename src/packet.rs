@@ -0,0 +1,616 @@
+//! Helpers to interpret the packets.
+//!
+//! This contains the [`PacketInfo`](struct.PacketInfo.html) header prepended to each packet
+//! (unless the interface is created with
+//! [`without_packet_info`](../struct.Iface.html#method.without_packet_info)) and the
+//! [`EthernetHeader`](struct.EthernetHeader.html) starting each frame in the TAP mode, possibly
+//! followed by [`VlanTag`](struct.VlanTag.html)s. Devices with offloads also prepend the
+//! [`VirtioNetHeader`](struct.VirtioNetHeader.html).
+//!
+//! The [`FiveTuple`](struct.FiveTuple.html) identifies the TCP or UDP flow of an IP packet.
+//!
+//! For debugging, the [`PacketDisplay`](struct.PacketDisplay.html) prints a packet as a hex dump.
+
+use std::convert::TryInto;
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::net::{IpAddr, SocketAddr};
+
+use crate::Device;
+
+/// The raw ether type of IPv4 (the kernel's `ETH_P_IP`).
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+/// The raw ether type of ARP (the kernel's `ETH_P_ARP`).
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+/// The raw ether type of IPv6 (the kernel's `ETH_P_IPV6`).
+pub const ETHERTYPE_IPV6: u16 = 0x86DD;
+
+/// The protocol of the packet, as carried in the packet info header (or ethernet frame).
+///
+/// See <https://en.wikipedia.org/wiki/EtherType#Examples> for the full list. The raw values of
+/// the known ones are the `ETHERTYPE_*` constants, the VLAN tags use the `TPID_*` ones.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub enum EtherType {
+    /// Internet Protocol version 4 (`0x0800`).
+    Ipv4,
+    /// Address Resolution Protocol (`0x0806`).
+    Arp,
+    /// Internet Protocol Version 6 (`0x86DD`).
+    Ipv6,
+    /// Any other protocol, with the raw value.
+    Other(u16),
+}
+
+impl From<u16> for EtherType {
+    fn from(value: u16) -> Self {
+        match value {
+            ETHERTYPE_IPV4 => EtherType::Ipv4,
+            ETHERTYPE_ARP => EtherType::Arp,
+            ETHERTYPE_IPV6 => EtherType::Ipv6,
+            other => EtherType::Other(other),
+        }
+    }
+}
+
+impl From<EtherType> for u16 {
+    fn from(ether_type: EtherType) -> u16 {
+        match ether_type {
+            EtherType::Ipv4 => ETHERTYPE_IPV4,
+            EtherType::Arp => ETHERTYPE_ARP,
+            EtherType::Ipv6 => ETHERTYPE_IPV6,
+            EtherType::Other(other) => other,
+        }
+    }
+}
+
+/// The 4-byte header prepended to packets in packet-info mode.
+///
+/// It corresponds to the kernel's `struct tun_pi`. The flags are in the native byte order, the
+/// protocol is in network byte order.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::*;
+/// let info = PacketInfo::parse(&[0, 0, 8, 0]);
+/// assert_eq!(EtherType::Ipv4, info.proto);
+/// assert_eq!([0, 0, 8, 0], info.to_bytes());
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PacketInfo {
+    /// The flags (the kernel uses [`TUN_PKT_STRIP`](#associatedconstant.TUN_PKT_STRIP) to
+    /// signal a truncated packet).
+    pub flags: u16,
+    /// The protocol of the packet.
+    pub proto: EtherType,
+}
+
+impl PacketInfo {
+    /// Size of the header, in bytes.
+    pub const SIZE: usize = 4;
+    /// The flag set by the kernel when the packet didn't fit into the buffer and was truncated.
+    pub const TUN_PKT_STRIP: u16 = 0x0001;
+
+    /// Creates a header for the given protocol, with no flags.
+    pub fn new(proto: EtherType) -> Self {
+        PacketInfo { flags: 0, proto }
+    }
+    /// Checks the [`TUN_PKT_STRIP`](#associatedconstant.TUN_PKT_STRIP) flag.
+    pub fn is_truncated(&self) -> bool {
+        self.flags & Self::TUN_PKT_STRIP != 0
+    }
+    /// Decodes the header.
+    pub fn parse(header: &[u8; 4]) -> Self {
+        PacketInfo {
+            flags: u16::from_ne_bytes([header[0], header[1]]),
+            proto: u16::from_be_bytes([header[2], header[3]]).into(),
+        }
+    }
+    /// Encodes the header.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let flags = self.flags.to_ne_bytes();
+        let proto = u16::from(self.proto).to_be_bytes();
+        [flags[0], flags[1], proto[0], proto[1]]
+    }
+}
+
+/// The virtio net header, prepended to the packets of devices with the
+/// [`VNET_HDR`](../struct.TunFlags.html#associatedconstant.VNET_HDR) flag.
+///
+/// It describes the offloads (see [`Iface::set_offload`](../struct.Iface.html#method.set_offload))
+/// applied to the packet: a checksum left for the other side to fill in and a large packet to be
+/// split into segments. With the packet info, this header comes after it.
+///
+/// Only the basic 10-byte variant (the default header size) is supported. The fields are in the
+/// native byte order.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::packet::VirtioNetHeader;
+/// let header = VirtioNetHeader {
+///     flags: VirtioNetHeader::F_NEEDS_CSUM,
+///     gso_type: VirtioNetHeader::GSO_UDP_L4,
+///     hdr_len: 28,
+///     gso_size: 1000,
+///     csum_start: 20,
+///     csum_offset: 6,
+/// };
+/// assert_eq!(header, VirtioNetHeader::parse(&header.to_bytes()));
+/// ```
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct VirtioNetHeader {
+    /// The `F_*` flags.
+    pub flags: u8,
+    /// The kind of segmentation, one of the `GSO_*` constants (possibly with `GSO_ECN`).
+    pub gso_type: u8,
+    /// Length of the headers (up to and including the transport one) of the packet.
+    pub hdr_len: u16,
+    /// Size of the payload of each segment.
+    pub gso_size: u16,
+    /// Where the checksummed part starts (the transport header), counted from the packet start.
+    pub csum_start: u16,
+    /// Where the checksum is stored, counted from the `csum_start`.
+    pub csum_offset: u16,
+}
+
+impl VirtioNetHeader {
+    /// Size of the header, in bytes.
+    pub const SIZE: usize = 10;
+    /// The checksum needs to be computed, as described by `csum_start` and `csum_offset`.
+    pub const F_NEEDS_CSUM: u8 = 1;
+    /// The checksum was already validated.
+    pub const F_DATA_VALID: u8 = 2;
+    /// A single packet, not to be segmented.
+    pub const GSO_NONE: u8 = 0;
+    /// A TCP over IPv4 packet to be segmented.
+    pub const GSO_TCPV4: u8 = 1;
+    /// An UDP packet to be fragmented (the deprecated UFO).
+    pub const GSO_UDP: u8 = 3;
+    /// A TCP over IPv6 packet to be segmented.
+    pub const GSO_TCPV6: u8 = 4;
+    /// An UDP packet to be split into datagrams of `gso_size`.
+    pub const GSO_UDP_L4: u8 = 5;
+    /// The ECN bit is to be set on the segments.
+    pub const GSO_ECN: u8 = 0x80;
+
+    /// Decodes the header.
+    pub fn parse(header: &[u8; 10]) -> Self {
+        let field = |i: usize| u16::from_ne_bytes([header[i], header[i + 1]]);
+        VirtioNetHeader {
+            flags: header[0],
+            gso_type: header[1],
+            hdr_len: field(2),
+            gso_size: field(4),
+            csum_start: field(6),
+            csum_offset: field(8),
+        }
+    }
+    /// Encodes the header.
+    pub fn to_bytes(&self) -> [u8; 10] {
+        let mut result = [self.flags, self.gso_type, 0, 0, 0, 0, 0, 0, 0, 0];
+        result[2..4].copy_from_slice(&self.hdr_len.to_ne_bytes());
+        result[4..6].copy_from_slice(&self.gso_size.to_ne_bytes());
+        result[6..8].copy_from_slice(&self.csum_start.to_ne_bytes());
+        result[8..].copy_from_slice(&self.csum_offset.to_ne_bytes());
+        result
+    }
+}
+
+/// A MAC (hardware) address.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash, Ord, PartialOrd)]
+pub struct MacAddr(pub [u8; 6]);
+
+impl MacAddr {
+    /// The broadcast address (`ff:ff:ff:ff:ff:ff`).
+    pub const BROADCAST: MacAddr = MacAddr([0xff; 6]);
+
+    /// Checks if this is the broadcast address.
+    pub fn is_broadcast(&self) -> bool {
+        *self == Self::BROADCAST
+    }
+    /// Checks if this is a multicast (group) address, including broadcast.
+    pub fn is_multicast(&self) -> bool {
+        self.0[0] & 0x01 != 0
+    }
+}
+
+impl Display for MacAddr {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let a = &self.0;
+        write!(
+            fmt,
+            "{:02x}:{:02x}:{:02x}:{:02x}:{:02x}:{:02x}",
+            a[0], a[1], a[2], a[3], a[4], a[5]
+        )
+    }
+}
+
+/// The header of an ethernet frame, as seen in the TAP mode.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::packet::*;
+/// # use tun_tap::EtherType;
+/// let frame = [
+///     0xff, 0xff, 0xff, 0xff, 0xff, 0xff, // Destination
+///     0x02, 0, 0, 0, 0, 1, // Source
+///     0x08, 0x06, // ARP
+///     1, 2, 3, // Payload
+/// ];
+/// let (header, payload) = EthernetHeader::parse(&frame).unwrap();
+/// assert!(header.destination.is_broadcast());
+/// assert_eq!(EtherType::Arp, header.ether_type);
+/// assert_eq!(&[1, 2, 3], payload);
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct EthernetHeader {
+    /// Where the frame goes.
+    pub destination: MacAddr,
+    /// Who sent the frame.
+    pub source: MacAddr,
+    /// The protocol of the payload.
+    pub ether_type: EtherType,
+}
+
+impl EthernetHeader {
+    /// Size of the header, in bytes.
+    pub const SIZE: usize = 14;
+
+    /// Parses the header at the start of the frame.
+    ///
+    /// Returns the header and the rest of the frame, or `None` if the frame is too short.
+    pub fn parse(frame: &[u8]) -> Option<(Self, &[u8])> {
+        if frame.len() < Self::SIZE {
+            return None;
+        }
+        let mut destination = [0; 6];
+        destination.copy_from_slice(&frame[..6]);
+        let mut source = [0; 6];
+        source.copy_from_slice(&frame[6..12]);
+        let header = EthernetHeader {
+            destination: MacAddr(destination),
+            source: MacAddr(source),
+            ether_type: u16::from_be_bytes([frame[12], frame[13]]).into(),
+        };
+        Some((header, &frame[Self::SIZE..]))
+    }
+    /// Encodes the header.
+    pub fn to_bytes(&self) -> [u8; 14] {
+        let mut result = [0; 14];
+        result[..6].copy_from_slice(&self.destination.0);
+        result[6..12].copy_from_slice(&self.source.0);
+        result[12..].copy_from_slice(&u16::from(self.ether_type).to_be_bytes());
+        result
+    }
+}
+
+/// The tag protocol identifier of an 802.1Q VLAN tag (also the inner tag of QinQ).
+pub const TPID_8021Q: u16 = 0x8100;
+/// The tag protocol identifier of an 802.1ad (QinQ) outer tag.
+pub const TPID_8021AD: u16 = 0x88A8;
+/// The pre-standard tag protocol identifier sometimes used for QinQ outer tags.
+pub const TPID_QINQ_LEGACY: u16 = 0x9100;
+
+fn is_vlan_tpid(tpid: u16) -> bool {
+    tpid == TPID_8021Q || tpid == TPID_8021AD || tpid == TPID_QINQ_LEGACY
+}
+
+/// An 802.1Q VLAN tag.
+///
+/// In a tagged frame, the tag sits between the source MAC address and the ether type. Each tag
+/// makes the frame 4 bytes longer, which needs to be accounted for when sizing receive buffers.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct VlanTag {
+    /// The tag protocol identifier (one of the `TPID_*` constants).
+    pub tpid: u16,
+    /// Priority code point (3 bits).
+    pub pcp: u8,
+    /// Drop eligible indicator.
+    pub dei: bool,
+    /// The VLAN identifier (12 bits).
+    pub vid: u16,
+}
+
+impl VlanTag {
+    /// Size of the tag, in bytes.
+    pub const SIZE: usize = 4;
+
+    /// Creates an 802.1Q tag with the given VLAN ID and no priority.
+    pub fn new(vid: u16) -> Self {
+        VlanTag {
+            tpid: TPID_8021Q,
+            pcp: 0,
+            dei: false,
+            vid: vid & 0x0FFF,
+        }
+    }
+    /// Decodes the tag.
+    pub fn parse(tag: &[u8; 4]) -> Self {
+        let tci = u16::from_be_bytes([tag[2], tag[3]]);
+        VlanTag {
+            tpid: u16::from_be_bytes([tag[0], tag[1]]),
+            pcp: (tci >> 13) as u8,
+            dei: tci & 0x1000 != 0,
+            vid: tci & 0x0FFF,
+        }
+    }
+    /// Encodes the tag.
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let tci = (u16::from(self.pcp & 0x07) << 13)
+            | if self.dei { 0x1000 } else { 0 }
+            | (self.vid & 0x0FFF);
+        let tpid = self.tpid.to_be_bytes();
+        let tci = tci.to_be_bytes();
+        [tpid[0], tpid[1], tci[0], tci[1]]
+    }
+}
+
+/// An iterator over the VLAN tags of a frame, outermost first.
+///
+/// Created by [`vlan_tags`](fn.vlan_tags.html).
+#[derive(Clone, Debug)]
+pub struct VlanTags<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for VlanTags<'a> {
+    type Item = VlanTag;
+    fn next(&mut self) -> Option<VlanTag> {
+        if self.rest.len() < VlanTag::SIZE + 2 {
+            return None;
+        }
+        let tag = VlanTag::parse(&[self.rest[0], self.rest[1], self.rest[2], self.rest[3]]);
+        if is_vlan_tpid(tag.tpid) {
+            self.rest = &self.rest[VlanTag::SIZE..];
+            Some(tag)
+        } else {
+            None
+        }
+    }
+}
+
+/// Iterates over the VLAN tags of an ethernet frame (without the packet info).
+///
+/// Both single 802.1Q tags and stacked (QinQ) tags are supported. An untagged frame yields
+/// nothing.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::packet::*;
+/// let mut frame = vec![0; 12]; // The MAC addresses
+/// frame.extend_from_slice(&[0x08, 0x00]); // IPv4
+/// insert_vlan_tag(&mut frame, VlanTag::new(42)).unwrap();
+/// assert_eq!(vec![VlanTag::new(42)], vlan_tags(&frame).collect::<Vec<_>>());
+/// assert_eq!(Some(VlanTag::new(42)), strip_vlan_tag(&mut frame));
+/// assert_eq!(14, frame.len());
+/// ```
+pub fn vlan_tags(frame: &[u8]) -> VlanTags<'_> {
+    VlanTags {
+        rest: frame.get(12..).unwrap_or(&[]),
+    }
+}
+
+/// Returns the ether type of the payload, after skipping all the VLAN tags.
+///
+/// Returns `None` if the frame is too short (including a truncated VLAN tag).
+pub fn inner_ether_type(frame: &[u8]) -> Option<EtherType> {
+    let offset = 12 + vlan_tags(frame).count() * VlanTag::SIZE;
+    let raw = frame.get(offset..offset + 2)?;
+    let ether_type = u16::from_be_bytes([raw[0], raw[1]]);
+    if is_vlan_tpid(ether_type) {
+        None
+    } else {
+        Some(ether_type.into())
+    }
+}
+
+/// Removes the outermost VLAN tag of the frame, if it has one.
+pub fn strip_vlan_tag(frame: &mut Vec<u8>) -> Option<VlanTag> {
+    let tag = vlan_tags(frame).next()?;
+    frame.drain(12..12 + VlanTag::SIZE);
+    Some(tag)
+}
+
+/// Inserts a VLAN tag as the outermost one into the frame.
+///
+/// # Errors
+///
+/// Fails (and leaves the frame untouched) if the frame is too short to contain the ethernet
+/// header.
+pub fn insert_vlan_tag(frame: &mut Vec<u8>, tag: VlanTag) -> Result<(), TooShort> {
+    if frame.len() < EthernetHeader::SIZE {
+        return Err(TooShort);
+    }
+    frame.splice(12..12, tag.to_bytes().iter().cloned());
+    Ok(())
+}
+
+/// The frame is too short to be manipulated.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct TooShort;
+
+impl Display for TooShort {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "The frame is too short")
+    }
+}
+
+impl std::error::Error for TooShort {}
+
+/// The protocol number of TCP.
+pub const IPPROTO_TCP: u8 = 6;
+/// The protocol number of UDP.
+pub const IPPROTO_UDP: u8 = 17;
+
+/// The addresses and ports identifying a TCP or UDP flow.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::packet::*;
+/// let mut packet = vec![0x45, 0, 0, 28, 0, 0, 0, 0, 64, IPPROTO_UDP, 0, 0];
+/// packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]); // The addresses
+/// packet.extend_from_slice(&[0x10, 0x92, 0, 53, 0, 8, 0, 0]); // The UDP header
+/// let flow = FiveTuple::parse(&packet).unwrap();
+/// assert_eq!("10.0.0.1:4242".parse(), Ok(flow.source));
+/// assert_eq!("10.0.0.2:53".parse(), Ok(flow.destination));
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct FiveTuple {
+    /// The IP protocol ([`IPPROTO_TCP`](constant.IPPROTO_TCP.html) or
+    /// [`IPPROTO_UDP`](constant.IPPROTO_UDP.html)).
+    pub protocol: u8,
+    /// The sender.
+    pub source: SocketAddr,
+    /// The recipient.
+    pub destination: SocketAddr,
+}
+
+impl FiveTuple {
+    /// Reads the flow of an IP packet (without the packet info or the ethernet header).
+    ///
+    /// Returns `None` for anything but TCP and UDP over IPv4 or IPv6, for non-first fragments
+    /// and for truncated packets. The IPv6 extension headers are not followed.
+    pub fn parse(packet: &[u8]) -> Option<Self> {
+        let (protocol, source, destination, rest): (_, IpAddr, IpAddr, _) =
+            match packet.first()? >> 4 {
+                4 => {
+                    let header_len = usize::from(packet[0] & 0x0F) * 4;
+                    let fragment = u16::from_be_bytes([*packet.get(6)?, *packet.get(7)?]);
+                    if header_len < 20 || fragment & 0x1FFF != 0 {
+                        return None;
+                    }
+                    let source: [u8; 4] = packet.get(12..16)?.try_into().ok()?;
+                    let destination: [u8; 4] = packet.get(16..20)?.try_into().ok()?;
+                    (packet[9], source.into(), destination.into(), packet.get(header_len..)?)
+                },
+                6 => {
+                    let source: [u8; 16] = packet.get(8..24)?.try_into().ok()?;
+                    let destination: [u8; 16] = packet.get(24..40)?.try_into().ok()?;
+                    (packet[6], source.into(), destination.into(), packet.get(40..)?)
+                },
+                _ => return None,
+            };
+        if protocol != IPPROTO_TCP && protocol != IPPROTO_UDP {
+            return None;
+        }
+        let ports = rest.get(..4)?;
+        Some(FiveTuple {
+            protocol,
+            source: SocketAddr::new(source, u16::from_be_bytes([ports[0], ports[1]])),
+            destination: SocketAddr::new(destination, u16::from_be_bytes([ports[2], ports[3]])),
+        })
+    }
+    /// The flow of the replies.
+    pub fn reversed(&self) -> Self {
+        FiveTuple {
+            protocol: self.protocol,
+            source: self.destination,
+            destination: self.source,
+        }
+    }
+}
+
+/// Renders a packet as a classic hex dump, for debugging.
+///
+/// Each line holds 16 bytes: the offset, the bytes in hex and the printable ones as ASCII. If the
+/// packet starts with the [`PacketInfo`](struct.PacketInfo.html), it is decoded on a line of its
+/// own (and the offsets start after it). The `Debug` output is the same.
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::packet::PacketDisplay;
+/// let packet = [0, 0, 8, 0, 0x45, 0, 0, 20];
+/// let dump = PacketDisplay::with_packet_info(&packet).to_string();
+/// let mut lines = dump.lines();
+/// assert_eq!(Some("Packet info: Ipv4, flags 0x0000"), lines.next());
+/// let line = lines.next().unwrap();
+/// assert!(line.starts_with("0000  45 00 00 14 "));
+/// assert!(line.ends_with(" |E...|"));
+/// ```
+#[derive(Copy, Clone)]
+pub struct PacketDisplay<'a> {
+    packet: &'a [u8],
+    packet_info: bool,
+}
+
+impl<'a> PacketDisplay<'a> {
+    const LINE: usize = 16;
+
+    /// Displays the packet without the packet info.
+    pub fn new(packet: &'a [u8]) -> Self {
+        PacketDisplay {
+            packet,
+            packet_info: false,
+        }
+    }
+    /// Displays the packet, decoding the packet info at its start.
+    pub fn with_packet_info(packet: &'a [u8]) -> Self {
+        PacketDisplay {
+            packet,
+            packet_info: true,
+        }
+    }
+    /// Displays a packet as received from the device.
+    ///
+    /// The packet info is decoded if the device has it.
+    pub fn for_device<D: Device + ?Sized>(device: &D, packet: &'a [u8]) -> Self {
+        PacketDisplay {
+            packet,
+            packet_info: device.packet_info(),
+        }
+    }
+}
+
+impl Display for PacketDisplay<'_> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        let mut data = self.packet;
+        if self.packet_info && data.len() >= PacketInfo::SIZE {
+            let mut header = [0; PacketInfo::SIZE];
+            header.copy_from_slice(&data[..PacketInfo::SIZE]);
+            let info = PacketInfo::parse(&header);
+            write!(fmt, "Packet info: {:?}, flags {:#06x}", info.proto, info.flags)?;
+            data = &data[PacketInfo::SIZE..];
+            if !data.is_empty() {
+                writeln!(fmt)?;
+            }
+        }
+        for (i, line) in data.chunks(Self::LINE).enumerate() {
+            if i > 0 {
+                writeln!(fmt)?;
+            }
+            write!(fmt, "{:04x} ", i * Self::LINE)?;
+            for (j, byte) in line.iter().enumerate() {
+                // An extra space in the middle, for orientation
+                if j == Self::LINE / 2 {
+                    write!(fmt, " ")?;
+                }
+                write!(fmt, " {:02x}", byte)?;
+            }
+            // Align the ASCII column of a short last line
+            let missing = Self::LINE - line.len();
+            let gap = if line.len() <= Self::LINE / 2 { 1 } else { 0 };
+            write!(fmt, "{:width$}", "", width = missing * 3 + gap)?;
+            write!(fmt, "  |")?;
+            for byte in line {
+                let c = if byte.is_ascii_graphic() || *byte == b' ' {
+                    char::from(*byte)
+                } else {
+                    '.'
+                };
+                write!(fmt, "{}", c)?;
+            }
+            write!(fmt, "|")?;
+        }
+        Ok(())
+    }
+}
+
+impl Debug for PacketDisplay<'_> {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        Display::fmt(self, fmt)
+    }
+}
@@ -0,0 +1,145 @@
+//! Creating the interface in two steps.
+//!
+//! See the [`PendingIface`](struct.PendingIface.html).
+
+use std::ffi::CStr;
+use std::fs::{File, OpenOptions};
+use std::io::{self, ErrorKind};
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::OnceLock;
+use std::time::Duration;
+
+use crate::{Error, Iface, Mode, TunFlags};
+
+extern "C" {
+    fn tuntap_setup(fd: c_int, name: *mut u8, flags: c_int) -> c_int;
+    fn tuntap_get_features(fd: c_int) -> c_int;
+}
+
+/// An opened `/dev/net/tun`, not yet turned into an interface.
+///
+/// The creation of an [`Iface`](struct.Iface.html) consists of opening the `/dev/net/tun` and
+/// telling the kernel which device to create (or attach to). This allows doing the two steps at
+/// different times, eg. opening the file while still having access to it and finishing after
+/// changing the root directory, entering another network namespace (the device is created in the
+/// namespace current at the time of finishing) or dropping some privileges (creating the device
+/// still needs the `CAP_NET_ADMIN` capability, unless attaching to a device owned by the user).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// let pending = PendingIface::open().expect("Failed to open /dev/net/tun");
+/// // Enter the network namespace (or chroot) here
+/// let iface = pending.finish("mytun", Mode::Tun).expect("Failed to create a TUN device");
+/// ```
+#[derive(Debug)]
+pub struct PendingIface {
+    fd: File,
+}
+
+impl PendingIface {
+    /// Opens the `/dev/net/tun`.
+    ///
+    /// # Errors
+    ///
+    /// * The `/dev/net/tun` is missing or isn't a TUN/TAP device
+    ///   ([`NotATunDevice`](enum.Error.html#variant.NotATunDevice)).
+    /// * The process isn't allowed to open it
+    ///   ([`PermissionDenied`](enum.Error.html#variant.PermissionDenied)).
+    pub fn open() -> Result<Self, Error> {
+        let fd = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/net/tun")
+            .map_err(|e| {
+                error!("Failed to open /dev/net/tun: {}", e);
+                Error::from_open(e)
+            })?;
+        Ok(PendingIface { fd })
+    }
+    /// Returns the flags the kernel accepts on creation (from `TUNGETFEATURES`).
+    ///
+    /// This allows finding out at runtime if an optional flag (eg.
+    /// [`ONE_QUEUE`](struct.TunFlags.html#associatedconstant.ONE_QUEUE) or
+    /// [`NAPI`](struct.TunFlags.html#associatedconstant.NAPI)) can be used, before the creation
+    /// fails on it. The [`TUN`](struct.TunFlags.html#associatedconstant.TUN) and
+    /// [`TAP`](struct.TunFlags.html#associatedconstant.TAP) are not included.
+    ///
+    /// # Errors
+    ///
+    /// If the kernel is too old to tell.
+    pub fn supported_flags(&self) -> io::Result<TunFlags> {
+        let features = unsafe { tuntap_get_features(self.fd.as_raw_fd()) };
+        if features < 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(TunFlags::from_bits_retain(features as u16))
+        }
+    }
+    /// Creates the interface.
+    ///
+    /// The parameters and errors are the same as with
+    /// [`Iface::new`](struct.Iface.html#method.new).
+    pub fn finish(self, ifname: &str, mode: Mode) -> Result<Iface, Error> {
+        self.finish_with_flags(ifname, mode.into())
+    }
+    /// Creates the interface with the given flags.
+    ///
+    /// The parameters and errors are the same as with
+    /// [`Iface::with_flags`](struct.Iface.html#method.with_flags).
+    pub fn finish_with_flags(self, ifname: &str, flags: TunFlags) -> Result<Iface, Error> {
+        #[cfg(feature = "tracing")]
+        let _span = tracing::debug_span!("create", ifname, ?flags).entered();
+        let fd = self.fd;
+        let mode = match flags.mode() {
+            Some(mode) => mode,
+            None => {
+                error!("Invalid mode in flags {:?}", flags);
+                return Err(Error::Io(io::Error::new(
+                    ErrorKind::InvalidInput,
+                    "Exactly one of the TUN and TAP flags must be set",
+                )));
+            },
+        };
+        let packet_info = !flags.contains(TunFlags::NO_PI);
+        // Catch what the kernel would refuse with a plain EINVAL, to tell what is wrong
+        if let Err(e) = Error::check_name(ifname) {
+            error!("Interface name {:?} is not valid: {}", ifname, e);
+            return Err(e);
+        }
+        // The buffer is larger than needed, but who cares… it is large enough.
+        let mut name_buffer = Vec::new();
+        name_buffer.extend_from_slice(ifname.as_bytes());
+        name_buffer.extend_from_slice(&[0; 33]);
+        let name_ptr: *mut u8 = name_buffer.as_mut_ptr();
+        let result = unsafe { tuntap_setup(fd.as_raw_fd(), name_ptr, c_int::from(flags.bits())) };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            error!("TUNSETIFF for {:?} failed: {}", ifname, error);
+            return Err(Error::from_setup(error, ifname, mode));
+        }
+        let name = unsafe {
+            CStr::from_ptr(name_ptr as *const c_char)
+                .to_string_lossy()
+                .into_owned()
+        };
+        debug!("Created {:?} interface {}", mode, name);
+        Ok(Iface {
+            fd,
+            mode,
+            name,
+            packet_info,
+            shutdown: OnceLock::new(),
+            busy_poll: Duration::ZERO,
+            cleanup: None,
+        })
+    }
+}
+
+impl AsRawFd for PendingIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
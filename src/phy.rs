@@ -0,0 +1,194 @@
+//! Integration with the [`smoltcp`](https://docs.rs/smoltcp) userspace network stack.
+//!
+//! The [`SmolIface`](struct.SmolIface.html) wraps an [`Iface`](../struct.Iface.html) and
+//! implements the `smoltcp::phy::Device` trait, so it can be passed to
+//! `smoltcp::iface::Interface`. A TUN device uses the `Ip` medium, a TAP device the `Ethernet`
+//! one. The packet info header, if present, is stripped from the received packets and filled in
+//! on the sent ones.
+//!
+//! The stack polls the device for packets, so the interface is switched to the
+//! [non-blocking mode](../struct.Iface.html#method.set_non_blocking). A
+//! [`PollSet`](../poll_set/struct.PollSet.html) (or the `smoltcp::phy::wait` function) can be
+//! used to sleep until there's something to do.
+//!
+//! The maximum transmission unit follows the [MTU](../struct.Iface.html#method.mtu) of the
+//! device, so jumbo frames work. It is read when wrapping the interface and then only when a
+//! packet larger than it is received (which happens after the MTU was raised). The packets are
+//! received into a single buffer large enough for any MTU, so none is lost to truncation.
+//! Lowering the MTU shows in the capabilities only once it is raised again. If the MTU can't be
+//! read, 1500 bytes (the usual default of the devices) is assumed. Errors can't be propagated
+//! through the `smoltcp` interface, so failed sends and receives are only logged and the packet
+//! is dropped.
+//!
+//! This module is available only with the `smoltcp` feature.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use smoltcp::phy::{Device as _, RxToken as _};
+//! # use smoltcp::time::Instant;
+//! # use tun_tap::*;
+//! # use tun_tap::phy::SmolIface;
+//! let iface = Iface::new("mytap", Mode::Tap).expect("Failed to create a TAP device");
+//! let mut device = SmolIface::new(iface).unwrap();
+//! let caps = device.capabilities();
+//! println!("Medium: {:?}, MTU: {}", caps.medium, caps.max_transmission_unit);
+//! // Normally, the smoltcp::iface::Interface does this.
+//! if let Some((rx, _tx)) = device.receive(Instant::now()) {
+//!     rx.consume(|packet| println!("Packet: {:?}", packet));
+//! }
+//! ```
+
+use std::io::{ErrorKind, Result};
+
+use smoltcp::phy::{self, DeviceCapabilities, Medium};
+use smoltcp::time::Instant;
+
+use crate::device;
+use crate::packet::EthernetHeader;
+use crate::{EtherType, Iface, Mode, PacketInfo};
+
+// If the MTU can't be read.
+const DEFAULT_MTU: usize = 1500;
+
+// The largest MTU a device can have, the receive buffer is sized for it.
+const MAX_MTU: usize = 65_535;
+
+/// Reads the MTU of the interface.
+fn mtu(iface: &Iface) -> usize {
+    // The error is already logged inside
+    iface.mtu().unwrap_or(DEFAULT_MTU)
+}
+
+/// An [`Iface`](../struct.Iface.html) usable by the `smoltcp` stack.
+///
+/// It implements the `smoltcp::phy::Device` trait. See the [module](index.html) documentation.
+#[derive(Debug)]
+pub struct SmolIface {
+    iface: Iface,
+    /// The cached MTU.
+    mtu: usize,
+    /// The receive buffer, reused for all the packets.
+    buffer: Vec<u8>,
+}
+
+impl SmolIface {
+    /// Wraps the interface.
+    ///
+    /// The interface is switched to the non-blocking mode.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn new(iface: Iface) -> Result<Self> {
+        iface.set_non_blocking()?;
+        let mtu = mtu(&iface);
+        let bufsize = device::max_frame_size(iface.mode(), iface.packet_info(), MAX_MTU);
+        Ok(SmolIface {
+            iface,
+            mtu,
+            buffer: vec![0; bufsize],
+        })
+    }
+    /// Accesses the wrapped interface.
+    pub fn iface(&self) -> &Iface {
+        &self.iface
+    }
+    /// Unwraps the interface.
+    ///
+    /// The interface stays in the non-blocking mode.
+    pub fn into_inner(self) -> Iface {
+        self.iface
+    }
+}
+
+/// Guesses the protocol of an outgoing packet, for the packet info header.
+fn proto(mode: Mode, packet: &[u8]) -> EtherType {
+    match mode {
+        Mode::Tun => match packet.first().map(|b| b >> 4) {
+            Some(6) => EtherType::Ipv6,
+            _ => EtherType::Ipv4,
+        },
+        Mode::Tap if packet.len() >= EthernetHeader::SIZE => {
+            EtherType::from(u16::from_be_bytes([packet[12], packet[13]]))
+        },
+        Mode::Tap => EtherType::Other(0),
+    }
+}
+
+/// A received packet, handed to the `smoltcp` stack.
+#[derive(Debug)]
+pub struct RxToken<'a> {
+    packet: &'a [u8],
+}
+
+impl phy::RxToken for RxToken<'_> {
+    fn consume<R, F>(self, f: F) -> R
+    where
+        F: FnOnce(&[u8]) -> R,
+    {
+        f(self.packet)
+    }
+}
+
+/// A permission to send a single packet from the `smoltcp` stack.
+#[derive(Debug)]
+pub struct TxToken<'a> {
+    iface: &'a Iface,
+}
+
+impl phy::TxToken for TxToken<'_> {
+    fn consume<R, F>(self, len: usize, f: F) -> R
+    where
+        F: FnOnce(&mut [u8]) -> R,
+    {
+        let mut buffer = vec![0; len];
+        let result = f(&mut buffer);
+        let proto = proto(self.iface.mode(), &buffer);
+        // The error is already logged inside and there's no way to report it.
+        let _ = self.iface.send_with_proto(proto, &buffer);
+        result
+    }
+}
+
+impl phy::Device for SmolIface {
+    type RxToken<'a> = RxToken<'a>;
+    type TxToken<'a> = TxToken<'a>;
+
+    fn receive(&mut self, _timestamp: Instant) -> Option<(RxToken<'_>, TxToken<'_>)> {
+        let (mode, packet_info) = (self.iface.mode(), self.iface.packet_info());
+        let header = if packet_info { PacketInfo::SIZE } else { 0 };
+        loop {
+            match self.iface.recv(&mut self.buffer) {
+                Ok(size) if size < header => continue,
+                Ok(size) => {
+                    if size > device::max_frame_size(mode, packet_info, self.mtu) {
+                        self.mtu = mtu(&self.iface);
+                        debug!("MTU of {} re-read as {}", self.iface.name(), self.mtu);
+                    }
+                    let packet = &self.buffer[header..size];
+                    return Some((RxToken { packet }, TxToken { iface: &self.iface }));
+                },
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => continue,
+                // WouldBlock (the usual case), shutdown or some real error, which is already
+                // logged.
+                Err(_) => return None,
+            }
+        }
+    }
+
+    fn transmit(&mut self, _timestamp: Instant) -> Option<TxToken<'_>> {
+        Some(TxToken { iface: &self.iface })
+    }
+
+    fn capabilities(&self) -> DeviceCapabilities {
+        let mut caps = DeviceCapabilities::default();
+        let (medium, link) = match self.iface.mode() {
+            Mode::Tun => (Medium::Ip, 0),
+            Mode::Tap => (Medium::Ethernet, EthernetHeader::SIZE),
+        };
+        caps.medium = medium;
+        caps.max_transmission_unit = self.mtu + link;
+        caps
+    }
+}
@@ -0,0 +1,166 @@
+//! Waiting on multiple interfaces at once.
+//!
+//! See the [`PollSet`](struct.PollSet.html) structure.
+
+use std::fs::File;
+use std::io::{Error, Result};
+use std::os::unix::io::{AsRawFd, FromRawFd, RawFd};
+use std::time::Duration;
+
+/// What readiness to wait for.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Interest {
+    /// Wait for the interface to have a packet to receive.
+    Readable,
+    /// Wait for the interface to accept a packet.
+    Writable,
+    /// Wait for either.
+    Both,
+}
+
+impl Interest {
+    fn events(self) -> u32 {
+        let events = match self {
+            Interest::Readable => libc::EPOLLIN,
+            Interest::Writable => libc::EPOLLOUT,
+            Interest::Both => libc::EPOLLIN | libc::EPOLLOUT,
+        };
+        events as u32
+    }
+}
+
+/// A readiness event returned by [`PollSet::wait`](struct.PollSet.html#method.wait).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Event {
+    /// The token the interface was registered with.
+    pub token: usize,
+    /// There's a packet to receive (or an error to pick up).
+    pub readable: bool,
+    /// A packet can be sent.
+    pub writable: bool,
+}
+
+/// A set of interfaces to wait on together.
+///
+/// This allows a single thread to serve many interfaces (and other file descriptors), without
+/// an async runtime. Each one is registered with a token and [`wait`](#method.wait) returns the
+/// tokens of the ones that are ready. The readiness is level-triggered ‒ an interface is
+/// reported as long as it has packets waiting.
+///
+/// Anything with a file descriptor can be registered, including the
+/// [`RecvHalf`](../struct.RecvHalf.html) and [`SendHalf`](../struct.SendHalf.html) or sockets.
+/// The interfaces are best switched to the
+/// [non-blocking mode](../struct.Iface.html#method.set_non_blocking), so draining them doesn't
+/// block.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// # use tun_tap::poll_set::{Interest, PollSet};
+/// let ifaces = (0..10)
+///     .map(|_| Iface::new("mytun%d", Mode::Tun).unwrap())
+///     .collect::<Vec<_>>();
+/// let poll_set = PollSet::new().unwrap();
+/// for (token, iface) in ifaces.iter().enumerate() {
+///     iface.set_non_blocking().unwrap();
+///     poll_set.add(iface, token, Interest::Readable).unwrap();
+/// }
+/// let mut events = Vec::new();
+/// let mut buffer = vec![0; 1504];
+/// loop {
+///     poll_set.wait(&mut events, None).unwrap();
+///     for event in &events {
+///         let iface = &ifaces[event.token];
+///         while let Ok(size) = iface.recv(&mut buffer) {
+///             println!("{}: {:?}", iface.name(), &buffer[..size]);
+///         }
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct PollSet {
+    epoll: File,
+}
+
+impl PollSet {
+    /// Creates an empty set.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn new() -> Result<Self> {
+        let fd = unsafe { libc::epoll_create1(libc::EPOLL_CLOEXEC) };
+        if fd < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(PollSet {
+            epoll: unsafe { File::from_raw_fd(fd) },
+        })
+    }
+    fn ctl(&self, op: i32, fd: RawFd, token: usize, interest: Interest) -> Result<()> {
+        let mut event = libc::epoll_event {
+            events: interest.events(),
+            u64: token as u64,
+        };
+        if unsafe { libc::epoll_ctl(self.epoll.as_raw_fd(), op, fd, &mut event) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(())
+    }
+    /// Registers an interface (or anything else with a file descriptor).
+    ///
+    /// # Errors
+    ///
+    /// If the file descriptor is already registered (`AlreadyExists`) or in case of low-level
+    /// OS errors.
+    pub fn add<F: AsRawFd>(&self, iface: &F, token: usize, interest: Interest) -> Result<()> {
+        self.ctl(libc::EPOLL_CTL_ADD, iface.as_raw_fd(), token, interest)
+    }
+    /// Changes the token or interest of an already registered interface.
+    pub fn modify<F: AsRawFd>(&self, iface: &F, token: usize, interest: Interest) -> Result<()> {
+        self.ctl(libc::EPOLL_CTL_MOD, iface.as_raw_fd(), token, interest)
+    }
+    /// Unregisters an interface.
+    ///
+    /// Closing the file descriptor (eg. by dropping the interface) unregisters it too.
+    pub fn remove<F: AsRawFd>(&self, iface: &F) -> Result<()> {
+        self.ctl(libc::EPOLL_CTL_DEL, iface.as_raw_fd(), 0, Interest::Both)
+    }
+    /// Waits for some of the registered interfaces to become ready.
+    ///
+    /// The `events` is cleared and filled with the ready ones (up to its capacity, or 64 if it
+    /// has less). Returns their number, which is 0 if the `timeout` elapsed. `None` waits
+    /// indefinitely.
+    ///
+    /// Interruption by a signal is reported as an error of the `Interrupted` kind.
+    pub fn wait(&self, events: &mut Vec<Event>, timeout: Option<Duration>) -> Result<usize> {
+        let timeout = match timeout {
+            // Round up, so we don't spin with sub-millisecond timeouts.
+            Some(timeout) => timeout.as_nanos().div_ceil(1_000_000).min(i32::MAX as u128) as i32,
+            None => -1,
+        };
+        let capacity = events.capacity().max(64);
+        let mut raw = vec![libc::epoll_event { events: 0, u64: 0 }; capacity];
+        let cnt = unsafe {
+            libc::epoll_wait(self.epoll.as_raw_fd(), raw.as_mut_ptr(), capacity as i32, timeout)
+        };
+        events.clear();
+        if cnt < 0 {
+            return Err(Error::last_os_error());
+        }
+        let err_flags = (libc::EPOLLERR | libc::EPOLLHUP) as u32;
+        events.extend(raw[..cnt as usize].iter().map(|raw| Event {
+            token: raw.u64 as usize,
+            readable: raw.events & (libc::EPOLLIN as u32 | err_flags) != 0,
+            writable: raw.events & libc::EPOLLOUT as u32 != 0,
+        }));
+        Ok(events.len())
+    }
+}
+
+impl AsRawFd for PollSet {
+    fn as_raw_fd(&self) -> RawFd {
+        self.epoll.as_raw_fd()
+    }
+}
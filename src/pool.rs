@@ -0,0 +1,185 @@
+//! Recycling of the packet buffers.
+//!
+//! Receiving each packet into a freshly allocated buffer is convenient, but at high packet rates
+//! the allocations show. The [`PacketPool`](struct.PacketPool.html) hands out buffers that
+//! return to it once dropped, so in the steady state no allocation happens.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use tun_tap::*;
+//! # use tun_tap::pool::PacketPool;
+//! let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+//! let pool = PacketPool::for_device(&iface).unwrap();
+//! loop {
+//!     let packet = pool.recv(&iface).unwrap();
+//!     println!("Packet: {:?}", &packet[..]);
+//!     // The buffer goes back to the pool here
+//! }
+//! ```
+
+use std::fmt::{Debug, Formatter, Result as FmtResult};
+use std::io::Result;
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, Weak};
+
+use crate::Device;
+
+#[derive(Debug)]
+struct Inner {
+    buffer_size: usize,
+    max_idle: usize,
+    idle: Mutex<Vec<Vec<u8>>>,
+}
+
+impl Inner {
+    fn put(&self, buffer: Vec<u8>) {
+        let mut idle = self.idle.lock().unwrap_or_else(|e| e.into_inner());
+        if idle.len() < self.max_idle {
+            idle.push(buffer);
+        }
+    }
+}
+
+/// A pool of equally sized packet buffers.
+///
+/// Cloning the pool is cheap and the clones share the buffers. The pool can be used from multiple
+/// threads.
+#[derive(Clone, Debug)]
+pub struct PacketPool {
+    inner: Arc<Inner>,
+}
+
+impl PacketPool {
+    /// Creates a pool of buffers of the given size.
+    ///
+    /// There's no limit on how many idle buffers are kept.
+    pub fn new(buffer_size: usize) -> Self {
+        PacketPool::with_max_idle(buffer_size, usize::MAX)
+    }
+    /// Creates a pool keeping at most `max_idle` unused buffers.
+    ///
+    /// Buffers returned to a full pool are freed. This limits the memory kept after a burst of
+    /// packets.
+    pub fn with_max_idle(buffer_size: usize, max_idle: usize) -> Self {
+        PacketPool {
+            inner: Arc::new(Inner {
+                buffer_size,
+                max_idle,
+                idle: Mutex::new(Vec::new()),
+            }),
+        }
+    }
+    /// Creates a pool with buffers large enough for any packet of the device.
+    ///
+    /// The size is taken from
+    /// [`Device::max_frame_size`](../trait.Device.html#method.max_frame_size) at the time of the
+    /// call.
+    ///
+    /// # Errors
+    ///
+    /// If reading the MTU of the device fails.
+    pub fn for_device<D: Device>(device: &D) -> Result<Self> {
+        Ok(PacketPool::new(device.max_frame_size()?))
+    }
+    /// Returns the size of the buffers.
+    pub fn buffer_size(&self) -> usize {
+        self.inner.buffer_size
+    }
+    /// Returns the number of unused buffers in the pool.
+    pub fn idle(&self) -> usize {
+        self.inner.idle.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+    /// Takes a buffer from the pool, allocating a new one if there's none.
+    ///
+    /// The buffer has the full size. It can be shortened by
+    /// [`truncate`](struct.PooledPacket.html#method.truncate), but the whole buffer returns to the
+    /// pool.
+    pub fn get(&self) -> PooledPacket {
+        let buffer = self
+            .inner
+            .idle
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop()
+            .unwrap_or_else(|| vec![0; self.inner.buffer_size]);
+        PooledPacket {
+            len: buffer.len(),
+            buffer,
+            pool: Arc::downgrade(&self.inner),
+        }
+    }
+    /// Receives a packet from the device into a buffer from the pool.
+    ///
+    /// In case of an error, the buffer returns to the pool right away.
+    pub fn recv<D: Device>(&self, device: &D) -> Result<PooledPacket> {
+        let mut packet = self.get();
+        let size = device.recv(&mut packet)?;
+        packet.truncate(size);
+        Ok(packet)
+    }
+}
+
+/// A packet in a buffer borrowed from a [`PacketPool`](struct.PacketPool.html).
+///
+/// It dereferences to the content of the packet. The buffer returns to the pool on drop (if the
+/// pool still exists).
+pub struct PooledPacket {
+    buffer: Vec<u8>,
+    len: usize,
+    pool: Weak<Inner>,
+}
+
+impl PooledPacket {
+    /// Shortens the packet to `len` bytes.
+    ///
+    /// It has no effect if the packet is already shorter.
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+    /// Takes the packet out of the pool, as an ordinary vector.
+    ///
+    /// The buffer doesn't return to the pool.
+    pub fn into_vec(mut self) -> Vec<u8> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.truncate(self.len);
+        buffer
+    }
+}
+
+impl Deref for PooledPacket {
+    type Target = [u8];
+    fn deref(&self) -> &[u8] {
+        &self.buffer[..self.len]
+    }
+}
+
+impl DerefMut for PooledPacket {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buffer[..self.len]
+    }
+}
+
+impl AsRef<[u8]> for PooledPacket {
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Debug for PooledPacket {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.debug_tuple("PooledPacket").field(&&self[..]).finish()
+    }
+}
+
+impl Drop for PooledPacket {
+    fn drop(&mut self) {
+        // Taken by into_vec
+        if self.buffer.is_empty() {
+            return;
+        }
+        if let Some(pool) = self.pool.upgrade() {
+            pool.put(std::mem::take(&mut self.buffer));
+        }
+    }
+}
@@ -0,0 +1,89 @@
+//! Dropping the privileges after the setup.
+//!
+//! The `CAP_NET_ADMIN` capability (or root) is needed only to create and configure the device.
+//! Afterwards, the file descriptor keeps working without it, so the recommended practice is to
+//! drop the privileges as soon as the setup is done. See the
+//! [`DropPrivileges`](struct.DropPrivileges.html).
+
+use std::io::{Error, Result};
+
+extern "C" {
+    fn tuntap_drop_net_admin() -> std::os::raw::c_int;
+}
+
+fn check(result: libc::c_int, what: &str) -> Result<()> {
+    if result < 0 {
+        let error = Error::last_os_error();
+        error!("{} failed: {}", what, error);
+        return Err(error);
+    }
+    Ok(())
+}
+
+/// Drops the privileges of the process.
+///
+/// Applying it removes the `CAP_NET_ADMIN` capability (from all the capability sets, so it can't
+/// be regained) and optionally switches to another user and group. Switching from root to
+/// another user also removes all the other capabilities.
+///
+/// The already created interfaces keep working. Creating new ones (or changing their
+/// configuration) won't be possible.
+///
+/// Note that the capabilities are a property of a thread. The dropping affects only the thread
+/// calling [`apply`](#method.apply) and the threads it spawns afterwards, so do it before
+/// spawning any other threads. The switch of the user affects the whole process.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// # use tun_tap::privileges::DropPrivileges;
+/// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+/// // Configure the device here
+/// DropPrivileges::new()
+///     .user(65534, 65534)
+///     .apply()
+///     .expect("Failed to drop privileges");
+/// // Use the iface without privileges
+/// ```
+#[derive(Clone, Debug, Default, Eq, PartialEq)]
+pub struct DropPrivileges {
+    user: Option<(u32, u32)>,
+}
+
+impl DropPrivileges {
+    /// Drops only the `CAP_NET_ADMIN` capability, keeping the user.
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Also switches to the given user and group.
+    ///
+    /// The supplementary groups are reset to just the `gid`.
+    pub fn user(self, uid: u32, gid: u32) -> Self {
+        DropPrivileges {
+            user: Some((uid, gid)),
+        }
+    }
+    /// Drops the privileges.
+    ///
+    /// # Errors
+    ///
+    /// If the OS refuses any of the steps (eg. switching the user without being root). The
+    /// process may be left with the privileges partially dropped and should terminate.
+    ///
+    /// Removing the capability from the bounding set needs `CAP_SETPCAP` (which root has). If
+    /// it's missing and `CAP_NET_ADMIN` is still in the bounding set, this fails with `EPERM`
+    /// instead of leaving a way to regain it.
+    pub fn apply(&self) -> Result<()> {
+        // Before switching the user, which would take CAP_SETPCAP away
+        check(unsafe { tuntap_drop_net_admin() }, "Dropping CAP_NET_ADMIN")?;
+        if let Some((uid, gid)) = self.user {
+            let groups = [gid as libc::gid_t];
+            check(unsafe { libc::setgroups(1, groups.as_ptr()) }, "setgroups")?;
+            check(unsafe { libc::setgid(gid as libc::gid_t) }, "setgid")?;
+            check(unsafe { libc::setuid(uid as libc::uid_t) }, "setuid")?;
+        }
+        debug!("Dropped privileges");
+        Ok(())
+    }
+}
@@ -0,0 +1,253 @@
+//! Using a real network interface in place of a TAP device.
+//!
+//! See the [`RawIface`](struct.RawIface.html) structure.
+
+use std::ffi::CString;
+use std::io::{self, Error, ErrorKind, Result};
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+
+use crate::{Device, Mode};
+
+extern "C" {
+    fn tuntap_get_mtu(name: *const c_char) -> c_int;
+}
+
+/// All the protocols, in the network byte order.
+const ETH_P_ALL: u16 = (libc::ETH_P_ALL as u16).to_be();
+
+/// An `AF_PACKET` raw socket bound to an existing network interface.
+///
+/// It behaves like a TAP device without the packet info ‒ each [`recv`](#method.recv) returns a
+/// whole ethernet frame seen on the interface and each [`send`](#method.send) puts one on the
+/// wire. It implements the [`Device`](../trait.Device.html) trait, so the code written for TAP
+/// devices (eg. the [`bridge`](../bridge/index.html) or the [`hub`](../hub/index.html)) can work
+/// with a physical NIC (or a veth, a bond…) as well, without changes.
+///
+/// Unlike with a TAP device, the interface keeps its place in the network stack of the kernel.
+/// The received frames are copies, the kernel still processes the originals (and the frames
+/// sent by the kernel itself are not received here). Opening it needs `CAP_NET_RAW`.
+///
+/// The offloads of the NIC show through ‒ with GRO, the received frames can be much larger than
+/// the MTU and the VLAN tags are usually stripped by the hardware. Turn them off (by `ethtool`)
+/// if that matters.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::raw::RawIface;
+/// let nic = RawIface::new("eth0").expect("Failed to open the interface");
+/// nic.set_promiscuous(true).unwrap();
+/// let mut buffer = vec![0; 65_536];
+/// loop {
+///     let size = nic.recv(&mut buffer).unwrap();
+///     println!("A frame of {} bytes", size);
+/// }
+/// ```
+#[derive(Debug)]
+pub struct RawIface {
+    fd: OwnedFd,
+    name: String,
+    index: c_int,
+}
+
+impl RawIface {
+    /// Opens the socket on the interface of the given name.
+    ///
+    /// # Errors
+    ///
+    /// If the interface doesn't exist or the socket can't be created (most commonly for the lack
+    /// of privileges).
+    pub fn new(name: &str) -> Result<Self> {
+        let c_name = CString::new(name).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+        let index = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+        if index == 0 {
+            let error = Error::last_os_error();
+            debug!("No interface {}: {}", name, error);
+            return Err(error);
+        }
+        // No protocol yet, not to queue the packets of all the interfaces until the bind
+        let fd = unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+        if fd < 0 {
+            let error = Error::last_os_error();
+            error!("Opening a raw socket for {} failed: {}", name, error);
+            return Err(error);
+        }
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let mut address: libc::sockaddr_ll = unsafe { mem::zeroed() };
+        address.sll_family = libc::AF_PACKET as u16;
+        address.sll_protocol = ETH_P_ALL;
+        address.sll_ifindex = index as c_int;
+        let result = unsafe {
+            libc::bind(
+                fd.as_raw_fd(),
+                &address as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            let error = Error::last_os_error();
+            error!("Binding the raw socket to {} failed: {}", name, error);
+            return Err(error);
+        }
+        debug!("Opened a raw socket on {}", name);
+        Ok(RawIface {
+            fd,
+            name: name.to_owned(),
+            index: index as c_int,
+        })
+    }
+    /// Returns the name of the interface.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Returns the index of the interface.
+    pub fn index(&self) -> u32 {
+        self.index as u32
+    }
+    /// Returns the MTU of the interface.
+    ///
+    /// See [`Iface::mtu`](../struct.Iface.html#method.mtu).
+    pub fn mtu(&self) -> Result<usize> {
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let result = unsafe { tuntap_get_mtu(name.as_ptr()) };
+        if result < 0 {
+            let error = Error::last_os_error();
+            error!("SIOCGIFMTU on {} failed: {}", self.name, error);
+            Err(error)
+        } else {
+            Ok(result as usize)
+        }
+    }
+    /// Receives the frames for all the addresses, not only for this interface.
+    ///
+    /// The promiscuous mode is counted by the kernel, it ends once all the sockets asking for it
+    /// turn it off (or are closed).
+    pub fn set_promiscuous(&self, promiscuous: bool) -> Result<()> {
+        let mut request: libc::packet_mreq = unsafe { mem::zeroed() };
+        request.mr_ifindex = self.index;
+        request.mr_type = libc::PACKET_MR_PROMISC as u16;
+        let option = if promiscuous {
+            libc::PACKET_ADD_MEMBERSHIP
+        } else {
+            libc::PACKET_DROP_MEMBERSHIP
+        };
+        let result = unsafe {
+            libc::setsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_PACKET,
+                option,
+                &request as *const _ as *const libc::c_void,
+                mem::size_of::<libc::packet_mreq>() as libc::socklen_t,
+            )
+        };
+        if result < 0 {
+            let error = Error::last_os_error();
+            error!("Setting promiscuous mode on {} failed: {}", self.name, error);
+            Err(error)
+        } else {
+            Ok(())
+        }
+    }
+    /// Receives a single frame.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv). The frames leaving through the
+    /// interface are skipped, only the incoming ones are returned.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let mut address: libc::sockaddr_ll = unsafe { mem::zeroed() };
+            let mut len = mem::size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+            let result = unsafe {
+                libc::recvfrom(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                    0,
+                    &mut address as *mut _ as *mut libc::sockaddr,
+                    &mut len,
+                )
+            };
+            if result < 0 {
+                let error = Error::last_os_error();
+                if error.kind() != ErrorKind::WouldBlock {
+                    debug!("recv on {} failed: {}", self.name, error);
+                }
+                return Err(error);
+            }
+            if address.sll_pkttype != libc::PACKET_OUTGOING {
+                return Ok(result as usize);
+            }
+        }
+    }
+    /// Sends a single frame.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        let result = unsafe {
+            libc::send(
+                self.fd.as_raw_fd(),
+                buf.as_ptr() as *const libc::c_void,
+                buf.len(),
+                0,
+            )
+        };
+        if result < 0 {
+            let error = Error::last_os_error();
+            if error.kind() != ErrorKind::WouldBlock {
+                debug!("send on {} failed: {}", self.name, error);
+            }
+            Err(error)
+        } else {
+            Ok(result as usize)
+        }
+    }
+    /// Switches the socket to the non-blocking mode.
+    pub fn set_non_blocking(&self) -> Result<()> {
+        let mut nonblock: c_int = 1;
+        let result = unsafe { libc::ioctl(self.fd.as_raw_fd(), libc::FIONBIO, &mut nonblock) };
+        if result == -1 {
+            let error = Error::last_os_error();
+            error!("FIONBIO on {} failed: {}", self.name, error);
+            Err(error)
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl AsRawFd for RawIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for RawIface {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Device for RawIface {
+    fn name(&self) -> &str {
+        RawIface::name(self)
+    }
+    fn mode(&self) -> Mode {
+        Mode::Tap
+    }
+    fn packet_info(&self) -> bool {
+        false
+    }
+    fn mtu(&self) -> Result<usize> {
+        RawIface::mtu(self)
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        RawIface::recv(self, buf)
+    }
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        RawIface::send(self, buf)
+    }
+    fn set_non_blocking(&self) -> Result<()> {
+        RawIface::set_non_blocking(self)
+    }
+}
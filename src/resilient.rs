@@ -0,0 +1,351 @@
+//! An interface surviving the deletion of the device.
+//!
+//! When someone deletes the device (`ip link del`) or the driver gives up on it, the file
+//! descriptor becomes useless and every operation on it fails. Long-running daemons then need to
+//! create the device again, configure it and continue. The
+//! [`ResilientIface`](struct.ResilientIface.html) does that transparently.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::net::Ipv4Addr;
+//! # use std::time::Duration;
+//! # use tun_tap::*;
+//! # use tun_tap::resilient::{Event, ResilientBuilder};
+//! let iface = ResilientBuilder::new(IfaceConfig::new("vpn0", Mode::Tun))
+//!     .backoff(Duration::from_millis(100), Duration::from_secs(5))
+//!     .on_event(|event: &Event| eprintln!("{}", event))
+//!     .create(|iface: &Iface| {
+//!         // The configuration is lost with the device, so it is done on each creation
+//!         iface.set_ipv4(Ipv4Addr::new(10, 0, 0, 1), 24)?;
+//!         iface.set_up(true)
+//!     })
+//!     .expect("Failed to create the device");
+//! let mut buffer = vec![0; 1504];
+//! loop {
+//!     let size = iface.recv(&mut buffer).unwrap();
+//!     // Process the packet
+//! }
+//! ```
+
+use std::fmt::{Debug, Display, Formatter, Result as FmtResult};
+use std::io::{self, Result};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::thread;
+use std::time::Duration;
+
+use libc::{EBADFD, EFAULT, ENODEV, ENXIO};
+
+use crate::{Error, Iface, IfaceConfig};
+
+type Setup = dyn Fn(&Iface) -> Result<()> + Send + Sync;
+type Observer = dyn Fn(&Event) + Send + Sync;
+
+/// Something that happened to the device.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Event {
+    /// The device stopped working, with the error the operation failed with.
+    Lost(io::Error),
+    /// An attempt to bring the device back failed.
+    Failed {
+        /// The number of the attempt, starting at 1.
+        attempt: u32,
+        /// Why it failed.
+        error: Error,
+    },
+    /// The device is back (and configured).
+    Restored {
+        /// The number of attempts it took.
+        attempts: u32,
+    },
+}
+
+impl Display for Event {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        match self {
+            Event::Lost(e) => write!(fmt, "The device was lost: {}", e),
+            Event::Failed { attempt, error } => {
+                write!(fmt, "Attempt {} to restore the device failed: {}", attempt, error)
+            },
+            Event::Restored { attempts } => {
+                write!(fmt, "The device was restored after {} attempts", attempts)
+            },
+        }
+    }
+}
+
+/// Checks if the error means the device is gone.
+///
+/// A blocked read ends with `EFAULT` when the device is deleted, anything after that with
+/// `EBADFD`. Some of these errors have other causes too, so the loss is confirmed on the interface
+/// itself.
+///
+/// Note that `EIO` is not one of them, writing into a device that is down fails with it.
+pub(crate) fn is_lost(iface: &Iface, error: &io::Error) -> bool {
+    let suspicious = matches!(
+        error.raw_os_error(),
+        Some(EBADFD) | Some(EFAULT) | Some(ENXIO) | Some(ENODEV)
+    );
+    // Once the device is gone, the file descriptor is detached from it and can't tell its flags.
+    suspicious && matches!(iface.tun_flags(), Err(ref e) if e.raw_os_error() == Some(EBADFD))
+}
+
+/// Configuration of a [`ResilientIface`](struct.ResilientIface.html).
+pub struct ResilientBuilder {
+    config: IfaceConfig,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_attempts: Option<u32>,
+    observer: Option<Box<Observer>>,
+}
+
+impl ResilientBuilder {
+    /// Starts configuring the interface.
+    ///
+    /// By default, the device is restored with delays from 100 milliseconds, doubling up to 10
+    /// seconds, and it is tried forever.
+    pub fn new(config: IfaceConfig) -> Self {
+        ResilientBuilder {
+            config,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(10),
+            max_attempts: None,
+            observer: None,
+        }
+    }
+    /// Sets the delay before the first attempt and the limit of the doubling delays.
+    pub fn backoff(self, initial: Duration, max: Duration) -> Self {
+        ResilientBuilder {
+            initial_backoff: initial,
+            max_backoff: max,
+            ..self
+        }
+    }
+    /// Gives up after the number of failed attempts to restore the device.
+    ///
+    /// The operation that found the device lost then fails with the error of the last attempt.
+    pub fn max_attempts(self, attempts: u32) -> Self {
+        ResilientBuilder {
+            max_attempts: Some(attempts),
+            ..self
+        }
+    }
+    /// Calls the `observer` on each [`Event`](enum.Event.html).
+    ///
+    /// It is called from the thread that found out about the problem, in the middle of the
+    /// operation.
+    pub fn on_event<F>(self, observer: F) -> Self
+    where
+        F: Fn(&Event) + Send + Sync + 'static,
+    {
+        ResilientBuilder {
+            observer: Some(Box::new(observer)),
+            ..self
+        }
+    }
+    /// Creates the device.
+    ///
+    /// The `setup` is called on each new device, before it is used (both now and when restoring
+    /// it). This is the place to configure the addresses or to switch it to the non-blocking
+    /// mode. Its failure counts as a failed attempt.
+    ///
+    /// # Errors
+    ///
+    /// If the device can't be created (see [`Iface::new`](../struct.Iface.html#method.new)) or
+    /// the `setup` fails (as the [`Io`](../enum.Error.html#variant.Io) error). There's no
+    /// retrying at this point.
+    pub fn create<F>(self, setup: F) -> std::result::Result<ResilientIface, Error>
+    where
+        F: Fn(&Iface) -> Result<()> + Send + Sync + 'static,
+    {
+        let iface = self.config.create()?;
+        setup(&iface)?;
+        debug!("Created resilient interface {}", iface.name());
+        Ok(ResilientIface {
+            current: Mutex::new(Current {
+                name: iface.name().to_owned(),
+                iface: Some(Arc::new(iface)),
+                generation: 0,
+            }),
+            restoring: Mutex::new(()),
+            config: self.config,
+            initial_backoff: self.initial_backoff,
+            max_backoff: self.max_backoff,
+            max_attempts: self.max_attempts,
+            setup: Box::new(setup),
+            observer: self.observer,
+        })
+    }
+}
+
+impl Debug for ResilientBuilder {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.debug_struct("ResilientBuilder")
+            .field("config", &self.config)
+            .field("initial_backoff", &self.initial_backoff)
+            .field("max_backoff", &self.max_backoff)
+            .field("max_attempts", &self.max_attempts)
+            .finish_non_exhaustive()
+    }
+}
+
+#[derive(Debug)]
+struct Current {
+    /// The device, `None` while it is lost.
+    iface: Option<Arc<Iface>>,
+    /// The name of the device (or the last one while it is lost).
+    name: String,
+    /// How many times the device was restored.
+    generation: u64,
+}
+
+/// An interface creating the device again when it is lost.
+///
+/// When an operation fails in a way meaning the device is gone (eg. it was deleted), it is
+/// created again, configured by the setup callback and the operation is retried on the new
+/// device. Other errors are returned as usual. The packets in flight at the time are lost.
+///
+/// It can be shared between threads. While the device is being restored, the other operations
+/// on it wait for it (the getters don't).
+///
+/// Created through the [`ResilientBuilder`](struct.ResilientBuilder.html).
+pub struct ResilientIface {
+    current: Mutex<Current>,
+    /// Held through the whole restoration, so only one thread restores the device.
+    restoring: Mutex<()>,
+    config: IfaceConfig,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    max_attempts: Option<u32>,
+    setup: Box<Setup>,
+    observer: Option<Box<Observer>>,
+}
+
+impl ResilientIface {
+    fn lock(&self) -> MutexGuard<'_, Current> {
+        // The state is consistent even after a panic in the setup or observer
+        self.current.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    fn emit(&self, event: &Event) {
+        debug!("{} on {}", event, self.config.name);
+        if let Some(observer) = &self.observer {
+            observer(event);
+        }
+    }
+    /// Restores the device, unless someone already did since the `generation`.
+    fn restore(&self, generation: u64, error: io::Error) -> Result<()> {
+        let _restoring = self.restoring.lock().unwrap_or_else(|e| e.into_inner());
+        let name = {
+            let mut current = self.lock();
+            if current.generation != generation {
+                return Ok(());
+            }
+            // Don't hold the old one while creating the new device
+            current.iface = None;
+            current.name.clone()
+        };
+        error!("Interface {} lost: {}", name, error);
+        self.emit(&Event::Lost(error));
+        let mut delay = self.initial_backoff;
+        let mut attempt = 0;
+        loop {
+            attempt += 1;
+            thread::sleep(delay);
+            delay = (delay * 2).min(self.max_backoff);
+            let result = self.config.create().and_then(|iface| {
+                (self.setup)(&iface)?;
+                Ok(iface)
+            });
+            match result {
+                Ok(iface) => {
+                    let mut current = self.lock();
+                    current.name = iface.name().to_owned();
+                    current.iface = Some(Arc::new(iface));
+                    current.generation += 1;
+                    drop(current);
+                    self.emit(&Event::Restored { attempts: attempt });
+                    return Ok(());
+                },
+                Err(error) => {
+                    let event = Event::Failed { attempt, error };
+                    self.emit(&event);
+                    match event {
+                        Event::Failed { error, .. }
+                            if self.max_attempts.is_some_and(|max| attempt >= max) =>
+                        {
+                            return Err(error.into());
+                        },
+                        _ => (),
+                    }
+                },
+            }
+        }
+    }
+    /// Runs the operation, restoring the device and retrying if it is lost.
+    fn run<R, F>(&self, mut op: F) -> Result<R>
+    where
+        F: FnMut(&Iface) -> Result<R>,
+    {
+        loop {
+            let (iface, generation) = {
+                let current = self.lock();
+                (current.iface.clone(), current.generation)
+            };
+            let iface = match iface {
+                Some(iface) => iface,
+                // An earlier restoration gave up, try again
+                None => {
+                    self.restore(generation, io::Error::from_raw_os_error(ENODEV))?;
+                    continue;
+                },
+            };
+            match op(&iface) {
+                Err(e) if is_lost(&iface, &e) => {
+                    drop(iface);
+                    self.restore(generation, e)?;
+                },
+                result => return result,
+            }
+        }
+    }
+    /// Receives a packet from the interface.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv). If the device is lost while
+    /// waiting, this waits for the new one.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.run(|iface| iface.recv(buf))
+    }
+    /// Sends a packet into the interface.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.run(|iface| iface.send(buf))
+    }
+    /// Returns the name of the current device.
+    ///
+    /// It may change when the device is restored (if the configured name contains `%d`).
+    pub fn name(&self) -> String {
+        self.lock().name.clone()
+    }
+    /// Returns the current device.
+    ///
+    /// Operations on it don't restore it, they fail if it is lost. This is `None` while the device
+    /// is being restored (or if restoring it failed).
+    pub fn current(&self) -> Option<Arc<Iface>> {
+        self.lock().iface.clone()
+    }
+    /// Returns how many times the device was restored.
+    pub fn restorations(&self) -> u64 {
+        self.lock().generation
+    }
+}
+
+impl Debug for ResilientIface {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        fmt.debug_struct("ResilientIface")
+            .field("current", &self.current)
+            .field("config", &self.config)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,129 @@
+//! An interface restricted to sending and receiving.
+//!
+//! See the [`Iface::seal`](../struct.Iface.html#method.seal) method.
+
+use std::io::Result;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+
+use crate::{EtherType, IffFlags, Iface, Mode, PacketInfo, TunFlags};
+
+/// An interface with all the metadata fetched up front.
+///
+/// Created by [`Iface::seal`](struct.Iface.html#method.seal). The getters return the values
+/// cached at the time of sealing, without asking the kernel. The only syscalls performed by
+/// sending and receiving are `read`, `write` and `writev`, so the process can enter a strict
+/// seccomp sandbox right after the sealing. Receiving also uses `poll` if a
+/// [`ShutdownHandle`](struct.ShutdownHandle.html) was created or
+/// [busy polling](struct.Iface.html#method.set_busy_poll) was turned on before sealing (the
+/// latter also reads the monotonic clock, which may be a `clock_gettime` syscall).
+///
+/// The interface can't be switched to the non-blocking mode once sealed, do it before.
+///
+/// Note that the cached values may become stale if someone changes the interface from the
+/// outside (eg. by `ip link set mtu`).
+#[derive(Debug)]
+pub struct SealedIface {
+    iface: Iface,
+    mtu: usize,
+    index: u32,
+    tun_flags: TunFlags,
+    iff_flags: IffFlags,
+}
+
+pub(crate) fn seal(iface: Iface) -> Result<SealedIface> {
+    let mtu = iface.mtu()?;
+    let index = iface.index()?;
+    let tun_flags = iface.tun_flags()?;
+    let iff_flags = iface.iff_flags()?;
+    debug!("Sealed interface {}", iface.name());
+    Ok(SealedIface {
+        iface,
+        mtu,
+        index,
+        tun_flags,
+        iff_flags,
+    })
+}
+
+impl SealedIface {
+    /// Returns the mode of the adapter.
+    pub fn mode(&self) -> Mode {
+        self.iface.mode()
+    }
+    /// Returns the real name of the adapter.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+    /// Returns if the packets carry the packet info header.
+    pub fn packet_info(&self) -> bool {
+        self.iface.packet_info
+    }
+    /// Returns the MTU at the time of sealing.
+    ///
+    /// See [`Iface::mtu`](struct.Iface.html#method.mtu).
+    pub fn mtu(&self) -> usize {
+        self.mtu
+    }
+    /// Returns the index of the interface.
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+    /// Returns the flags of the device at the time of sealing.
+    ///
+    /// See [`Iface::tun_flags`](struct.Iface.html#method.tun_flags).
+    pub fn tun_flags(&self) -> TunFlags {
+        self.tun_flags
+    }
+    /// Returns the flags of the network interface at the time of sealing.
+    ///
+    /// See [`Iface::iff_flags`](struct.Iface.html#method.iff_flags).
+    pub fn iff_flags(&self) -> IffFlags {
+        self.iff_flags
+    }
+    /// Receives a packet from the interface.
+    ///
+    /// See [`Iface::recv`](struct.Iface.html#method.recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.iface.recv(buf)
+    }
+    /// Receives a packet and splits off the packet info header.
+    ///
+    /// See [`Iface::recv_parsed`](struct.Iface.html#method.recv_parsed).
+    pub fn recv_parsed<'b>(&self, buf: &'b mut [u8]) -> Result<(Option<PacketInfo>, &'b [u8])> {
+        self.iface.recv_parsed(buf)
+    }
+    /// Sends a packet into the interface.
+    ///
+    /// See [`Iface::send`](struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.iface.send(buf)
+    }
+    /// Sends a packet of the given protocol into the interface.
+    ///
+    /// See [`Iface::send_with_proto`](struct.Iface.html#method.send_with_proto).
+    pub fn send_with_proto(&self, proto: EtherType, payload: &[u8]) -> Result<usize> {
+        self.iface.send_with_proto(proto, payload)
+    }
+    /// Sends a packet composed of a header and a payload into the interface.
+    ///
+    /// See [`Iface::send_with_header`](struct.Iface.html#method.send_with_header).
+    pub fn send_with_header(&self, header: &[u8], payload: &[u8]) -> Result<usize> {
+        self.iface.send_with_header(header, payload)
+    }
+    /// Returns the full interface back.
+    pub fn into_inner(self) -> Iface {
+        self.iface
+    }
+}
+
+impl AsRawFd for SealedIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.iface.as_raw_fd()
+    }
+}
+
+impl AsFd for SealedIface {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.iface.as_fd()
+    }
+}
@@ -0,0 +1,301 @@
+//! Measuring the throughput and latency of a device on the given machine.
+//!
+//! The [`SelfTest`](struct.SelfTest.html) pumps UDP packets through a configured TUN device and
+//! the kernel network stack and reports how many of them made it and how fast. This allows
+//! checking the effect of the configuration (offloads, queues, busy polling, CPU pinning) on the
+//! given machine before deploying.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use std::net::Ipv4Addr;
+//! # use tun_tap::*;
+//! # use tun_tap::selftest::SelfTest;
+//! # use tun_tap::tee::Direction;
+//! // Configured by `ip address add 10.0.0.1/24 dev mytun && ip link set mytun up`
+//! let iface = Iface::without_packet_info("mytun", Mode::Tun).expect("Failed to create a device");
+//! let report = SelfTest::new(Ipv4Addr::new(10, 0, 0, 1), Ipv4Addr::new(10, 0, 0, 2))
+//!     .direction(Direction::Sent)
+//!     .packets(100_000)
+//!     .run(&iface)
+//!     .expect("Failed to run the test");
+//! println!(
+//!     "{} of {} packets, {:.0} pps, {:.2} Gbps, median latency {:?}",
+//!     report.packets_received,
+//!     report.packets_sent,
+//!     report.pps(),
+//!     report.gbps(),
+//!     report.latency(0.5),
+//! );
+//! ```
+
+use std::io::{Error, ErrorKind, Result};
+use std::net::{Ipv4Addr, SocketAddrV4, UdpSocket};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::checksum::checksum;
+use crate::device;
+use crate::tee::Direction;
+use crate::{EtherType, Iface, Mode, PacketInfo};
+
+/// The port of the made-up peer (the discard service).
+const PEER_PORT: u16 = 9;
+/// The sequence number and the time stamp.
+const MIN_PAYLOAD: usize = 16;
+const HEADERS: usize = 28;
+
+/// The results of a [`SelfTest`](struct.SelfTest.html).
+#[derive(Clone, Debug, Default, PartialEq)]
+#[non_exhaustive]
+pub struct Report {
+    /// Number of packets sent.
+    pub packets_sent: u64,
+    /// Number of packets that got through.
+    pub packets_received: u64,
+    /// Number of bytes (of whole IP packets) that got through.
+    pub bytes_received: u64,
+    /// Time from sending the first packet to receiving the last one.
+    pub elapsed: Duration,
+    /// The latencies of the received packets, from the lowest.
+    pub latencies: Vec<Duration>,
+}
+
+impl Report {
+    /// Number of packets lost on the way.
+    pub fn lost(&self) -> u64 {
+        self.packets_sent - self.packets_received
+    }
+    /// Received packets per second.
+    pub fn pps(&self) -> f64 {
+        self.per_second(self.packets_received)
+    }
+    /// Received gigabits per second.
+    pub fn gbps(&self) -> f64 {
+        self.per_second(self.bytes_received) * 8.0 / 1e9
+    }
+    fn per_second(&self, amount: u64) -> f64 {
+        if self.elapsed.is_zero() {
+            0.0
+        } else {
+            amount as f64 / self.elapsed.as_secs_f64()
+        }
+    }
+    /// Returns the latency percentile.
+    ///
+    /// The `percentile` is between 0 and 1 (0.5 is the median, 0.99 the 99th percentile).
+    /// Returns `None` if no packet got through.
+    pub fn latency(&self, percentile: f64) -> Option<Duration> {
+        let last = self.latencies.len().checked_sub(1)?;
+        let index = (last as f64 * percentile.clamp(0.0, 1.0)).round() as usize;
+        Some(self.latencies[index])
+    }
+}
+
+/// A throughput and latency test of a TUN device.
+///
+/// The device needs to be up, with the `local` address assigned and the `peer` address routed
+/// through it (usually by being in the same subnet). The `peer` needs not exist, the test
+/// pretends to be it.
+///
+/// The packets are sent as fast as possible, each carrying the time it was sent. In the
+/// [`Sent`](../tee/enum.Direction.html#variant.Sent) direction (the default), they are sent into
+/// the device and received on a local UDP socket, like packets coming out of a VPN tunnel. In the
+/// [`Received`](../tee/enum.Direction.html#variant.Received) direction, they are sent from a
+/// local socket and received from the device. Packets the kernel drops (because they come too
+/// fast) are reported as lost.
+///
+/// While the test runs, other traffic received from the device is discarded.
+#[derive(Clone, Debug)]
+pub struct SelfTest {
+    local: Ipv4Addr,
+    peer: Ipv4Addr,
+    direction: Direction,
+    packets: u64,
+    payload: usize,
+    timeout: Duration,
+}
+
+impl SelfTest {
+    /// Configures a test with the default of 10 000 packets of 1000 bytes.
+    pub fn new(local: Ipv4Addr, peer: Ipv4Addr) -> Self {
+        SelfTest {
+            local,
+            peer,
+            direction: Direction::Sent,
+            packets: 10_000,
+            payload: 1000,
+            timeout: Duration::from_secs(1),
+        }
+    }
+    /// Sets which way the packets go through the device.
+    pub fn direction(self, direction: Direction) -> Self {
+        SelfTest { direction, ..self }
+    }
+    /// Sets the number of packets to send.
+    pub fn packets(self, packets: u64) -> Self {
+        SelfTest { packets, ..self }
+    }
+    /// Sets the size of the UDP payload of the packets.
+    ///
+    /// It is raised to 16 bytes if smaller, to fit the sequence number and the time stamp.
+    pub fn payload_size(self, payload: usize) -> Self {
+        SelfTest {
+            payload: payload.max(MIN_PAYLOAD),
+            ..self
+        }
+    }
+    /// Sets how long to wait for packets after the last one is sent (1 second by default).
+    pub fn timeout(self, timeout: Duration) -> Self {
+        SelfTest { timeout, ..self }
+    }
+    /// Runs the test.
+    ///
+    /// # Errors
+    ///
+    /// If the device is not in the TUN mode, the packets don't fit its MTU or sending fails (eg.
+    /// because the `local` address is not assigned).
+    pub fn run(&self, iface: &Iface) -> Result<Report> {
+        if iface.mode() != Mode::Tun {
+            return Err(Error::new(ErrorKind::InvalidInput, "Self test needs a TUN device"));
+        }
+        if HEADERS + self.payload > iface.mtu()? {
+            return Err(Error::new(ErrorKind::InvalidInput, "Packets don't fit the MTU"));
+        }
+        let socket = UdpSocket::bind(SocketAddrV4::new(self.local, 0))?;
+        socket.set_read_timeout(Some(self.timeout))?;
+        let port = socket.local_addr()?.port();
+        debug!(
+            "Self test of {} with {} packets {:?}",
+            iface.name(),
+            self.packets,
+            self.direction
+        );
+        let start = Instant::now();
+        let mut report = thread::scope(|scope| {
+            let receiver = scope.spawn(|| match self.direction {
+                Direction::Sent => self.recv_socket(&socket, start),
+                Direction::Received => self.recv_iface(iface, port, start),
+            });
+            let sent = match self.direction {
+                Direction::Sent => self.send_iface(iface, port, start),
+                Direction::Received => self.send_socket(&socket, start),
+            };
+            let report = receiver.join().expect("Self test receiver panicked");
+            sent.and_then(|sent| {
+                report.map(|report| Report {
+                    packets_sent: sent,
+                    ..report
+                })
+            })
+        })?;
+        report.latencies.sort();
+        Ok(report)
+    }
+    /// Stamps the payload with the sequence number and the current time.
+    fn stamp(payload: &mut [u8], seq: u64, start: Instant) {
+        payload[..8].copy_from_slice(&seq.to_be_bytes());
+        let stamp = start.elapsed().as_nanos() as u64;
+        payload[8..16].copy_from_slice(&stamp.to_be_bytes());
+    }
+    fn send_iface(&self, iface: &Iface, port: u16, start: Instant) -> Result<u64> {
+        let total = HEADERS + self.payload;
+        let mut buffer = Vec::with_capacity(PacketInfo::SIZE + total);
+        if iface.packet_info {
+            buffer.extend_from_slice(&PacketInfo::new(EtherType::Ipv4).to_bytes());
+        }
+        let info = buffer.len();
+        buffer.resize(info + total, 0);
+        let packet = &mut buffer[info..];
+        // IPv4 with the DF flag, no options
+        packet[0] = 0x45;
+        packet[2..4].copy_from_slice(&(total as u16).to_be_bytes());
+        packet[6..10].copy_from_slice(&[0x40, 0, 64, 17]);
+        packet[12..16].copy_from_slice(&self.peer.octets());
+        packet[16..20].copy_from_slice(&self.local.octets());
+        // UDP, without the checksum (allowed with IPv4)
+        packet[20..22].copy_from_slice(&PEER_PORT.to_be_bytes());
+        packet[22..24].copy_from_slice(&port.to_be_bytes());
+        packet[24..26].copy_from_slice(&((total - 20) as u16).to_be_bytes());
+        for seq in 0..self.packets {
+            let packet = &mut buffer[info..];
+            packet[4..6].copy_from_slice(&(seq as u16).to_be_bytes());
+            packet[10..12].copy_from_slice(&[0, 0]);
+            let sum = checksum(&packet[..20]);
+            packet[10..12].copy_from_slice(&sum.to_be_bytes());
+            Self::stamp(&mut packet[HEADERS..], seq, start);
+            iface.send(&buffer)?;
+        }
+        Ok(self.packets)
+    }
+    fn send_socket(&self, socket: &UdpSocket, start: Instant) -> Result<u64> {
+        let peer = SocketAddrV4::new(self.peer, PEER_PORT);
+        let mut payload = vec![0; self.payload];
+        for seq in 0..self.packets {
+            Self::stamp(&mut payload, seq, start);
+            socket.send_to(&payload, peer)?;
+        }
+        Ok(self.packets)
+    }
+    fn measure(&self, report: &mut Report, payload: &[u8], start: Instant) {
+        let mut stamp = [0; 8];
+        stamp.copy_from_slice(&payload[8..16]);
+        let now = start.elapsed();
+        let sent = Duration::from_nanos(u64::from_be_bytes(stamp));
+        report.packets_received += 1;
+        report.bytes_received += (HEADERS + payload.len()) as u64;
+        report.elapsed = now;
+        report.latencies.push(now.saturating_sub(sent));
+    }
+    fn recv_socket(&self, socket: &UdpSocket, start: Instant) -> Result<Report> {
+        let mut report = Report::default();
+        let mut buffer = vec![0; self.payload];
+        while report.packets_received < self.packets {
+            match socket.recv(&mut buffer) {
+                Ok(size) if size == self.payload => self.measure(&mut report, &buffer, start),
+                Ok(_) => (),
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                // The timeout, the rest of the packets got lost
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(report)
+    }
+    fn recv_iface(&self, iface: &Iface, port: u16, start: Instant) -> Result<Report> {
+        let mut report = Report::default();
+        let (info, ipv4) = if iface.packet_info {
+            (PacketInfo::SIZE, PacketInfo::new(EtherType::Ipv4).to_bytes())
+        } else {
+            (0, [0; 4])
+        };
+        let mut buffer = vec![0; info + HEADERS + self.payload];
+        let ours = |packet: &[u8]| {
+            packet.len() == HEADERS + self.payload
+                && packet[0] == 0x45
+                && packet[9] == 17
+                && packet[12..16] == self.local.octets()
+                && packet[16..20] == self.peer.octets()
+                && packet[20..22] == port.to_be_bytes()
+                && packet[22..24] == PEER_PORT.to_be_bytes()
+        };
+        while report.packets_received < self.packets {
+            if !device::poll_readable(iface, Some(self.timeout))? {
+                break;
+            }
+            match iface.recv(&mut buffer) {
+                Ok(size) => {
+                    let packet = &buffer[info..size];
+                    if buffer[..info] == ipv4[..info] && ours(packet) {
+                        self.measure(&mut report, &packet[HEADERS..], start);
+                    }
+                },
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => (),
+                // Someone else might have set the device to the non-blocking mode
+                Err(ref e) if e.kind() == ErrorKind::WouldBlock => (),
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(report)
+    }
+}
@@ -0,0 +1,112 @@
+//! Waking up readers blocked on the interface.
+//!
+//! See the [`ShutdownHandle`](struct.ShutdownHandle.html) structure.
+
+use std::error::Error as StdError;
+use std::fmt::{Display, Formatter, Result as FmtResult};
+use std::fs::File;
+use std::io::{Error, ErrorKind, Result, Write};
+use std::os::raw::c_int;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, RawFd};
+use std::sync::Arc;
+
+extern "C" {
+    fn tuntap_shutdown_pipe(pipe_fds: *mut c_int) -> c_int;
+    fn tuntap_wait_readable(fd: c_int, wake_fd: c_int) -> c_int;
+}
+
+/// The error returned by [`recv`](../struct.Iface.html#method.recv) after a shutdown.
+///
+/// It is wrapped inside an `io::Error` of kind `Other`. Use
+/// [`is_shutdown`](#method.is_shutdown) to recognize it.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct Shutdown;
+
+impl Shutdown {
+    /// Checks if the given error was caused by a shutdown of the interface.
+    pub fn is_shutdown(error: &Error) -> bool {
+        error
+            .get_ref()
+            .map(|inner| inner.is::<Shutdown>())
+            .unwrap_or(false)
+    }
+}
+
+impl Display for Shutdown {
+    fn fmt(&self, fmt: &mut Formatter) -> FmtResult {
+        write!(fmt, "The interface has been shut down")
+    }
+}
+
+impl StdError for Shutdown {}
+
+#[derive(Debug)]
+pub(crate) struct ShutdownPipe {
+    read: File,
+    write: File,
+}
+
+impl ShutdownPipe {
+    pub(crate) fn new() -> Result<Self> {
+        let mut fds: [c_int; 2] = [-1; 2];
+        if unsafe { tuntap_shutdown_pipe(fds.as_mut_ptr()) } < 0 {
+            return Err(Error::last_os_error());
+        }
+        Ok(ShutdownPipe {
+            read: unsafe { File::from_raw_fd(fds[0]) },
+            write: unsafe { File::from_raw_fd(fds[1]) },
+        })
+    }
+
+    /// The reading end, to be watched by some other means (eg. an event loop).
+    ///
+    /// It becomes readable once the shutdown is triggered.
+    pub(crate) fn wake_fd(&self) -> BorrowedFd<'_> {
+        self.read.as_fd()
+    }
+
+    /// Waits until the fd is readable or the shutdown is triggered.
+    ///
+    /// Returns the [`Shutdown`](struct.Shutdown.html) error in the latter case.
+    pub(crate) fn wait_readable(&self, fd: RawFd) -> Result<()> {
+        match unsafe { tuntap_wait_readable(fd, self.read.as_raw_fd()) } {
+            0 => Ok(()),
+            1 => Err(Error::other(Shutdown)),
+            _ => Err(Error::last_os_error()),
+        }
+    }
+}
+
+/// A handle to wake up threads blocked in [`recv`](../struct.Iface.html#method.recv).
+///
+/// Obtained through [`Iface::shutdown_handle`](../struct.Iface.html#method.shutdown_handle). It
+/// can be cloned and sent to other threads (for example to a signal handling thread).
+///
+/// Once [`shutdown`](#method.shutdown) is called, any currently blocked and all future calls to
+/// `recv` on the interface return an error recognized by
+/// [`Shutdown::is_shutdown`](struct.Shutdown.html#method.is_shutdown). The interface itself stays
+/// open until it is dropped, so there's no danger of a reader using a closed (or even reused)
+/// file descriptor.
+#[derive(Clone, Debug)]
+pub struct ShutdownHandle {
+    pub(crate) pipe: Arc<ShutdownPipe>,
+}
+
+impl ShutdownHandle {
+    /// Triggers the shutdown.
+    ///
+    /// Calling it multiple times is fine.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn shutdown(&self) -> Result<()> {
+        debug!("Shutdown of the interface requested");
+        match (&self.pipe.write).write(&[1]) {
+            Ok(_) => Ok(()),
+            // The pipe is already full of wake ups, one more is not needed.
+            Err(ref e) if e.kind() == ErrorKind::WouldBlock => Ok(()),
+            Err(e) => Err(e),
+        }
+    }
+}
@@ -0,0 +1,99 @@
+//! Owned receiving and sending halves of an interface.
+//!
+//! See the [`Iface::split`](../struct.Iface.html#method.split) method.
+
+use std::io::Result;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::Arc;
+
+use crate::{Iface, Mode};
+
+/// The receiving half of an [`Iface`](struct.Iface.html).
+///
+/// Created by [`Iface::split`](struct.Iface.html#method.split).
+#[derive(Debug)]
+pub struct RecvHalf {
+    iface: Arc<Iface>,
+}
+
+/// The sending half of an [`Iface`](struct.Iface.html).
+///
+/// Created by [`Iface::split`](struct.Iface.html#method.split).
+#[derive(Debug)]
+pub struct SendHalf {
+    iface: Arc<Iface>,
+}
+
+pub(crate) fn split(iface: Iface) -> (RecvHalf, SendHalf) {
+    let iface = Arc::new(iface);
+    let recv = RecvHalf {
+        iface: Arc::clone(&iface),
+    };
+    (recv, SendHalf { iface })
+}
+
+impl RecvHalf {
+    /// Receives a packet from the interface.
+    ///
+    /// See [`Iface::recv`](struct.Iface.html#method.recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.iface.recv(buf)
+    }
+    /// Returns the mode of the adapter.
+    pub fn mode(&self) -> Mode {
+        self.iface.mode()
+    }
+    /// Returns the real name of the adapter.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+    /// Returns if the packets are prepended with the packet info.
+    pub fn packet_info(&self) -> bool {
+        self.iface.packet_info()
+    }
+    /// Puts the halves back together.
+    ///
+    /// # Errors
+    ///
+    /// If the halves don't come from the same interface, they are returned back.
+    pub fn reunite(self, send: SendHalf) -> std::result::Result<Iface, (RecvHalf, SendHalf)> {
+        if !Arc::ptr_eq(&self.iface, &send.iface) {
+            return Err((self, send));
+        }
+        drop(send);
+        Ok(Arc::try_unwrap(self.iface).expect("Other half of the interface still alive"))
+    }
+}
+
+impl SendHalf {
+    /// Sends a packet into the interface.
+    ///
+    /// See [`Iface::send`](struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.iface.send(buf)
+    }
+    /// Returns the mode of the adapter.
+    pub fn mode(&self) -> Mode {
+        self.iface.mode()
+    }
+    /// Returns the real name of the adapter.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+    /// Returns if the packets are prepended with the packet info.
+    pub fn packet_info(&self) -> bool {
+        self.iface.packet_info()
+    }
+}
+
+impl AsRawFd for RecvHalf {
+    fn as_raw_fd(&self) -> RawFd {
+        self.iface.as_raw_fd()
+    }
+}
+
+impl AsRawFd for SendHalf {
+    fn as_raw_fd(&self) -> RawFd {
+        self.iface.as_raw_fd()
+    }
+}
@@ -0,0 +1,40 @@
+//! Picking up file descriptors passed by systemd.
+//!
+//! See <https://www.freedesktop.org/software/systemd/man/sd_listen_fds.html> for the protocol.
+
+use std::collections::HashSet;
+use std::env;
+use std::os::unix::io::RawFd;
+use std::sync::Mutex;
+
+const LISTEN_FDS_START: RawFd = 3;
+
+/// The file descriptors already picked up, each may have only one owner.
+static TAKEN: Mutex<Option<HashSet<RawFd>>> = Mutex::new(None);
+
+/// Takes the file descriptor passed under the given name.
+///
+/// Returns `None` if systemd didn't pass any file descriptors to this process, none of them is
+/// named like this or it was already taken before. Once returned, the caller owns the file
+/// descriptor.
+pub(crate) fn take_fd(name: &str) -> Option<RawFd> {
+    let pid = env::var("LISTEN_PID").ok()?;
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        debug!("LISTEN_PID {} is not for us", pid);
+        return None;
+    }
+    let count = env::var("LISTEN_FDS").ok()?.parse::<RawFd>().ok()?;
+    let names = env::var("LISTEN_FDNAMES").unwrap_or_default();
+    let fd = names
+        .split(':')
+        .take(count.max(0) as usize)
+        .position(|n| n == name)
+        .map(|idx| LISTEN_FDS_START + idx as RawFd)?;
+    let mut taken = TAKEN.lock().unwrap_or_else(|e| e.into_inner());
+    if taken.get_or_insert_with(HashSet::new).insert(fd) {
+        Some(fd)
+    } else {
+        debug!("File descriptor {} ({}) already taken", name, fd);
+        None
+    }
+}
@@ -0,0 +1,234 @@
+//! Mirroring the traffic for inspection.
+//!
+//! See the [`TeeIface`](struct.TeeIface.html) structure.
+
+use std::collections::VecDeque;
+use std::io::Result;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::Iface;
+
+/// Which way a packet went through the interface.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Direction {
+    /// The packet was received from the interface (sent by the kernel).
+    Received,
+    /// The packet was sent into the interface (to the kernel).
+    Sent,
+}
+
+/// A copy of a packet that passed through a [`TeeIface`](struct.TeeIface.html).
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MirroredPacket {
+    /// Which way the packet went.
+    pub direction: Direction,
+    /// The packet data (including the packet info header, if any).
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Shared {
+    queue: Mutex<VecDeque<MirroredPacket>>,
+    ready: Condvar,
+    capacity: usize,
+    dropped: AtomicU64,
+    receiver_alive: AtomicBool,
+    sender_alive: AtomicBool,
+}
+
+impl Shared {
+    fn push(&self, direction: Direction, data: &[u8]) {
+        if !self.receiver_alive.load(Ordering::Relaxed) {
+            return;
+        }
+        let packet = MirroredPacket {
+            direction,
+            data: data.to_vec(),
+        };
+        let mut queue = self.queue.lock().unwrap_or_else(|e| e.into_inner());
+        if queue.len() >= self.capacity {
+            queue.pop_front();
+            self.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+        queue.push_back(packet);
+        drop(queue);
+        self.ready.notify_one();
+    }
+}
+
+/// A wrapper around [`Iface`](../struct.Iface.html) mirroring all the traffic into a channel.
+///
+/// Every packet successfully sent or received through the wrapper is copied into a bounded
+/// queue, from where it can be picked up through the
+/// [`MirrorReceiver`](struct.MirrorReceiver.html) (eg. by a debugging side-car thread). The main
+/// path is never blocked by a slow consumer ‒ if the queue is full, the oldest packet in it is
+/// dropped.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::thread;
+/// # use tun_tap::*;
+/// # use tun_tap::tee::TeeIface;
+/// let iface = Iface::new("mytun", Mode::Tun).expect("Failed to create a TUN device");
+/// let (iface, mirror) = TeeIface::new(iface, 128);
+/// thread::spawn(move || {
+///     for packet in mirror {
+///         eprintln!("{:?}: {:?}", packet.direction, packet.data);
+///     }
+/// });
+/// let mut buffer = vec![0; 1504];
+/// iface.recv(&mut buffer).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TeeIface {
+    iface: Iface,
+    shared: SenderGuard,
+}
+
+/// Disconnects the receiver once the sending side is gone.
+#[derive(Debug)]
+struct SenderGuard(Arc<Shared>);
+
+impl Drop for SenderGuard {
+    fn drop(&mut self) {
+        // Under the lock, so a receiver can't miss the wake up between checking and waiting.
+        let queue = self.0.queue.lock().unwrap_or_else(|e| e.into_inner());
+        self.0.sender_alive.store(false, Ordering::Relaxed);
+        drop(queue);
+        self.0.ready.notify_all();
+    }
+}
+
+impl TeeIface {
+    /// Wraps the interface.
+    ///
+    /// The `capacity` is the maximum number of packets kept in the queue.
+    ///
+    /// # Panics
+    ///
+    /// If the `capacity` is 0.
+    pub fn new(iface: Iface, capacity: usize) -> (Self, MirrorReceiver) {
+        assert!(capacity > 0, "The mirror needs capacity of at least 1");
+        let shared = Arc::new(Shared {
+            queue: Mutex::new(VecDeque::with_capacity(capacity)),
+            ready: Condvar::new(),
+            capacity,
+            dropped: AtomicU64::new(0),
+            receiver_alive: AtomicBool::new(true),
+            sender_alive: AtomicBool::new(true),
+        });
+        let receiver = MirrorReceiver {
+            shared: Arc::clone(&shared),
+        };
+        let tee = TeeIface {
+            iface,
+            shared: SenderGuard(shared),
+        };
+        (tee, receiver)
+    }
+    /// Receives a packet from the interface and mirrors it.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        let size = self.iface.recv(buf)?;
+        self.shared.0.push(Direction::Received, &buf[..size]);
+        Ok(size)
+    }
+    /// Sends a packet into the interface and mirrors it.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        let size = self.iface.send(buf)?;
+        self.shared.0.push(Direction::Sent, &buf[..size]);
+        Ok(size)
+    }
+    /// Accesses the wrapped interface.
+    ///
+    /// Note that traffic passing directly through the interface is not mirrored.
+    pub fn iface(&self) -> &Iface {
+        &self.iface
+    }
+    /// Unwraps the interface, disconnecting the mirror.
+    pub fn into_inner(self) -> Iface {
+        self.iface
+    }
+}
+
+impl AsRawFd for TeeIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.iface.as_raw_fd()
+    }
+}
+
+/// The receiving end of the mirror of a [`TeeIface`](struct.TeeIface.html).
+///
+/// Dropping it stops the mirroring (the packets are no longer copied). Iterating over it yields
+/// the packets until the `TeeIface` is dropped and the queue drained.
+#[derive(Debug)]
+pub struct MirrorReceiver {
+    shared: Arc<Shared>,
+}
+
+impl MirrorReceiver {
+    /// Waits for the next packet.
+    ///
+    /// Returns `None` once the `TeeIface` is gone and there are no more packets.
+    pub fn recv(&self) -> Option<MirroredPacket> {
+        let mut queue = self.shared.queue.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(packet) = queue.pop_front() {
+                return Some(packet);
+            }
+            if !self.shared.sender_alive.load(Ordering::Relaxed) {
+                return None;
+            }
+            queue = self.shared.ready.wait(queue).unwrap_or_else(|e| e.into_inner());
+        }
+    }
+    /// Waits for the next packet, up to the given timeout.
+    pub fn recv_timeout(&self, timeout: Duration) -> Option<MirroredPacket> {
+        let deadline = Instant::now() + timeout;
+        let mut queue = self.shared.queue.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            if let Some(packet) = queue.pop_front() {
+                return Some(packet);
+            }
+            let now = Instant::now();
+            if !self.shared.sender_alive.load(Ordering::Relaxed) || now >= deadline {
+                return None;
+            }
+            queue = self
+                .shared
+                .ready
+                .wait_timeout(queue, deadline - now)
+                .unwrap_or_else(|e| e.into_inner())
+                .0;
+        }
+    }
+    /// Returns the next packet if one is already available.
+    pub fn try_recv(&self) -> Option<MirroredPacket> {
+        let mut queue = self.shared.queue.lock().unwrap_or_else(|e| e.into_inner());
+        queue.pop_front()
+    }
+    /// How many packets were dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.shared.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl Drop for MirrorReceiver {
+    fn drop(&mut self) {
+        self.shared.receiver_alive.store(false, Ordering::Relaxed);
+    }
+}
+
+impl Iterator for MirrorReceiver {
+    type Item = MirroredPacket;
+    fn next(&mut self) -> Option<MirroredPacket> {
+        self.recv()
+    }
+}
@@ -0,0 +1,176 @@
+//! Crafting packets for tests.
+//!
+//! The [`PacketBuilder`](struct.PacketBuilder.html) builds minimal, but valid IPv4 packets (with
+//! correct lengths and checksums), optionally wrapped in an ethernet frame for TAP devices and
+//! prefixed with the packet info header. It's enough to poke at a device in tests and examples
+//! without pulling in a full packet parsing library.
+//!
+//! This is available with the `testgen` feature.
+
+use std::net::Ipv4Addr;
+
+use crate::checksum::{checksum, fold, sum};
+use crate::packet::{EthernetHeader, MacAddr};
+use crate::{Device, EtherType, Mode, PacketInfo};
+
+const ICMP: u8 = 1;
+const UDP: u8 = 17;
+const ECHO_REQUEST: u8 = 8;
+const ECHO_REPLY: u8 = 0;
+
+/// Builds IPv4 packets between two addresses.
+///
+/// The builder is configured first, then any number of packets can be built by the methods
+/// taking `&self`.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::net::Ipv4Addr;
+/// # use tun_tap::testgen::PacketBuilder;
+/// let builder = PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 1));
+/// let packet = builder.udp(4242, 53, b"hello");
+/// assert_eq!(20 + 8 + 5, packet.len());
+/// ```
+///
+/// To be sent into a TAP device (without the packet info), with the kernel as the destination:
+///
+/// ```rust,no_run
+/// # use std::net::Ipv4Addr;
+/// # use tun_tap::*;
+/// # use tun_tap::packet::MacAddr;
+/// # use tun_tap::testgen::PacketBuilder;
+/// let iface = Iface::without_packet_info("mytap", Mode::Tap).expect("Failed to create a device");
+/// let kernel_mac = MacAddr([0x02, 0, 0, 0, 0, 1]); // Eg. read from /sys/class/net/mytap/address
+/// let builder = PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 1))
+///     .ethernet(MacAddr([0x02, 0, 0, 0, 0, 2]), kernel_mac);
+/// iface.send(&builder.icmp_echo_request(1, 1, b"ping")).unwrap();
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct PacketBuilder {
+    source: Ipv4Addr,
+    destination: Ipv4Addr,
+    ttl: u8,
+    id: u16,
+    ethernet: Option<(MacAddr, MacAddr)>,
+    packet_info: bool,
+}
+
+impl PacketBuilder {
+    /// Creates a builder of packets from the `source` to the `destination`.
+    ///
+    /// The packets are bare IP packets (as for a TUN device without the packet info) with TTL
+    /// of 64 by default.
+    pub fn ipv4(source: Ipv4Addr, destination: Ipv4Addr) -> Self {
+        PacketBuilder {
+            source,
+            destination,
+            ttl: 64,
+            id: 0,
+            ethernet: None,
+            packet_info: false,
+        }
+    }
+    /// Sets the time to live of the packets.
+    pub fn ttl(self, ttl: u8) -> Self {
+        PacketBuilder { ttl, ..self }
+    }
+    /// Sets the identification field of the packets.
+    pub fn id(self, id: u16) -> Self {
+        PacketBuilder { id, ..self }
+    }
+    /// Wraps the packets into ethernet frames between the MAC addresses.
+    pub fn ethernet(self, source: MacAddr, destination: MacAddr) -> Self {
+        PacketBuilder {
+            ethernet: Some((source, destination)),
+            ..self
+        }
+    }
+    /// Prefixes the packets with the packet info header.
+    pub fn packet_info(self, packet_info: bool) -> Self {
+        PacketBuilder {
+            packet_info,
+            ..self
+        }
+    }
+    /// Makes the packets ready to be sent into the device.
+    ///
+    /// This sets the packet info according to the device. TAP devices also need the MAC
+    /// addresses to be set by [`ethernet`](#method.ethernet), the TUN ones don't have any.
+    pub fn for_device<D: Device + ?Sized>(self, device: &D) -> Self {
+        let builder = self.packet_info(device.packet_info());
+        match device.mode() {
+            Mode::Tun => PacketBuilder {
+                ethernet: None,
+                ..builder
+            },
+            Mode::Tap => builder,
+        }
+    }
+    /// Builds a packet of the given IP protocol, with the `payload` as is.
+    pub fn ip(&self, protocol: u8, payload: &[u8]) -> Vec<u8> {
+        let mut packet =
+            Vec::with_capacity(PacketInfo::SIZE + EthernetHeader::SIZE + 20 + payload.len());
+        if self.packet_info {
+            packet.extend_from_slice(&PacketInfo::new(EtherType::Ipv4).to_bytes());
+        }
+        if let Some((source, destination)) = self.ethernet {
+            let header = EthernetHeader {
+                destination,
+                source,
+                ether_type: EtherType::Ipv4,
+            };
+            packet.extend_from_slice(&header.to_bytes());
+        }
+        let start = packet.len();
+        let total = (20 + payload.len()) as u16;
+        packet.extend_from_slice(&[0x45, 0]);
+        packet.extend_from_slice(&total.to_be_bytes());
+        packet.extend_from_slice(&self.id.to_be_bytes());
+        // Don't fragment
+        packet.extend_from_slice(&[0x40, 0, self.ttl, protocol, 0, 0]);
+        packet.extend_from_slice(&self.source.octets());
+        packet.extend_from_slice(&self.destination.octets());
+        let ip_checksum = checksum(&packet[start..]);
+        packet[start + 10..start + 12].copy_from_slice(&ip_checksum.to_be_bytes());
+        packet.extend_from_slice(payload);
+        packet
+    }
+    /// Builds a UDP packet.
+    pub fn udp(&self, source_port: u16, destination_port: u16, payload: &[u8]) -> Vec<u8> {
+        let len = (8 + payload.len()) as u16;
+        let mut udp = Vec::with_capacity(usize::from(len));
+        udp.extend_from_slice(&source_port.to_be_bytes());
+        udp.extend_from_slice(&destination_port.to_be_bytes());
+        udp.extend_from_slice(&len.to_be_bytes());
+        udp.extend_from_slice(&[0, 0]);
+        udp.extend_from_slice(payload);
+        // The pseudo-header: the addresses, the protocol and the length
+        let pseudo = sum(&self.source.octets(), u32::from(UDP) + u32::from(len));
+        let pseudo = sum(&self.destination.octets(), pseudo);
+        let udp_checksum = match !fold(sum(&udp, pseudo)) {
+            // Zero means no checksum in UDP
+            0 => 0xffff,
+            udp_checksum => udp_checksum,
+        };
+        udp[6..8].copy_from_slice(&udp_checksum.to_be_bytes());
+        self.ip(UDP, &udp)
+    }
+    fn icmp_echo(&self, icmp_type: u8, identifier: u16, sequence: u16, payload: &[u8]) -> Vec<u8> {
+        let mut icmp = vec![icmp_type, 0, 0, 0];
+        icmp.extend_from_slice(&identifier.to_be_bytes());
+        icmp.extend_from_slice(&sequence.to_be_bytes());
+        icmp.extend_from_slice(payload);
+        let icmp_checksum = checksum(&icmp);
+        icmp[2..4].copy_from_slice(&icmp_checksum.to_be_bytes());
+        self.ip(ICMP, &icmp)
+    }
+    /// Builds an ICMP echo request (a ping).
+    pub fn icmp_echo_request(&self, identifier: u16, sequence: u16, payload: &[u8]) -> Vec<u8> {
+        self.icmp_echo(ECHO_REQUEST, identifier, sequence, payload)
+    }
+    /// Builds an ICMP echo reply.
+    pub fn icmp_echo_reply(&self, identifier: u16, sequence: u16, payload: &[u8]) -> Vec<u8> {
+        self.icmp_echo(ECHO_REPLY, identifier, sequence, payload)
+    }
+}
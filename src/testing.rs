@@ -0,0 +1,382 @@
+//! Test doubles of the interface.
+//!
+//! These don't need any privileges, so protocol code can be tested as an ordinary user (and in
+//! CI). See the [`pair`](fn.pair.html) function and the [`MockIface`](struct.MockIface.html).
+
+use std::collections::VecDeque;
+use std::io::{Error, ErrorKind, Result};
+use std::mem;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+use std::ptr;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::{Device, Mode};
+
+/// A fake interface, one end of a [`pair`](fn.pair.html).
+///
+/// It offers the same methods as the [`Iface`](../struct.Iface.html) and implements the
+/// [`Device`](../trait.Device.html) trait, so it works with the code generic over it (eg. the
+/// [`Async`](../async/struct.Async.html)). It pretends to be a TUN device without the packet info
+/// and with the usual MTU of 1500.
+#[derive(Debug)]
+pub struct FakeIface {
+    socket: UnixDatagram,
+    name: String,
+}
+
+impl FakeIface {
+    /// Returns the name of the fake interface (`fake0` or `fake1`).
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Returns the mode, which is always [`Tun`](../enum.Mode.html#variant.Tun).
+    pub fn mode(&self) -> Mode {
+        Mode::Tun
+    }
+    /// Returns the MTU, which is always 1500.
+    pub fn mtu(&self) -> Result<usize> {
+        Ok(1500)
+    }
+    /// Receives a packet sent by the other end.
+    ///
+    /// Like with the [`Iface::recv`](../struct.Iface.html#method.recv), a packet too large for
+    /// the buffer gets truncated.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.socket.recv(buf)
+    }
+    /// Receives a packet sent by the other end into a new vector.
+    ///
+    /// A shortcut for tests, the packet is truncated to the MTU.
+    pub fn recv_vec(&self) -> Result<Vec<u8>> {
+        let mut buf = vec![0; 1500];
+        let size = self.socket.recv(&mut buf)?;
+        buf.truncate(size);
+        Ok(buf)
+    }
+    /// Sends a packet to the other end.
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.socket.send(buf)
+    }
+    /// Sets the fake interface to be non-blocking.
+    pub fn set_non_blocking(&self) -> Result<()> {
+        self.socket.set_nonblocking(true)
+    }
+}
+
+impl AsRawFd for FakeIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.socket.as_raw_fd()
+    }
+}
+
+impl Device for FakeIface {
+    fn name(&self) -> &str {
+        FakeIface::name(self)
+    }
+    fn mode(&self) -> Mode {
+        FakeIface::mode(self)
+    }
+    fn packet_info(&self) -> bool {
+        false
+    }
+    fn mtu(&self) -> Result<usize> {
+        FakeIface::mtu(self)
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        FakeIface::recv(self, buf)
+    }
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        FakeIface::send(self, buf)
+    }
+    fn set_non_blocking(&self) -> Result<()> {
+        FakeIface::set_non_blocking(self)
+    }
+}
+
+/// Creates two connected fake interfaces.
+///
+/// Packets sent into one of them are received from the other one, as if two applications were
+/// connected by a wire. The packet boundaries are preserved.
+///
+/// # Errors
+///
+/// This fails with an error in case of low-level OS errors (eg. running out of file descriptors).
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::testing;
+/// let (left, right) = testing::pair().unwrap();
+/// left.send(b"\x45hello").unwrap();
+/// let mut buffer = vec![0; 1500];
+/// let size = right.recv(&mut buffer).unwrap();
+/// assert_eq!(b"\x45hello", &buffer[..size]);
+/// ```
+pub fn pair() -> Result<(FakeIface, FakeIface)> {
+    let (left, right) = UnixDatagram::pair()?;
+    let left = FakeIface {
+        socket: left,
+        name: "fake0".to_owned(),
+    };
+    let right = FakeIface {
+        socket: right,
+        name: "fake1".to_owned(),
+    };
+    Ok((left, right))
+}
+
+#[derive(Debug)]
+struct Script {
+    // When the previous scripted packet was received (or the mock created).
+    last: Instant,
+    recvs: VecDeque<(Duration, Vec<u8>)>,
+    expected: VecDeque<Vec<u8>>,
+    sent: Vec<Vec<u8>>,
+    non_blocking: bool,
+}
+
+/// A scripted interface, for deterministic tests of higher-level protocol logic.
+///
+/// The packets to be received are queued up front, each with a delay after the previous one (or
+/// after the creation of the mock for the first one). A blocking [`recv`](#method.recv) waits
+/// until the next packet is due (if the script ran out, until more is queued by another thread),
+/// a non-blocking one fails with `WouldBlock` until then.
+///
+/// It implements the [`Device`](../trait.Device.html) trait. Its file descriptor (a timer)
+/// becomes readable once the next packet is due, so it works with the code waiting for the
+/// device in an event loop (eg. the [`Async`](../async/struct.Async.html)) too.
+///
+/// The expected sends are queued too. Each [`send`](#method.send) is compared with the next
+/// expected packet and a mismatch panics, failing the test. Sends beyond the expectations are
+/// accepted. All the sent packets are recorded and can be inspected by [`sent`](#method.sent).
+///
+/// The methods take `&self`, so the mock can be shared (eg. in an `Arc`) between the test and
+/// the code under test.
+///
+/// # Examples
+///
+/// ```rust
+/// # use std::time::Duration;
+/// # use tun_tap::Mode;
+/// # use tun_tap::testing::MockIface;
+/// let iface = MockIface::new(Mode::Tun);
+/// iface.push_recv(&b"\x45ping"[..]);
+/// iface.push_recv_after(Duration::from_millis(10), &b"\x45ping again"[..]);
+/// iface.expect_send(&b"\x45pong"[..]);
+///
+/// // The code under test
+/// let mut buffer = vec![0; 1500];
+/// let size = iface.recv(&mut buffer).unwrap();
+/// assert_eq!(b"\x45ping", &buffer[..size]);
+/// iface.send(b"\x45pong").unwrap();
+/// let size = iface.recv(&mut buffer).unwrap();
+/// assert_eq!(b"\x45ping again", &buffer[..size]);
+///
+/// iface.assert_done();
+/// ```
+#[derive(Debug)]
+pub struct MockIface {
+    mode: Mode,
+    script: Mutex<Script>,
+    /// Readable once the next scripted packet is due.
+    timer: OwnedFd,
+}
+
+impl MockIface {
+    /// Creates a mock with an empty script.
+    ///
+    /// # Panics
+    ///
+    /// If the timer behind the file descriptor can't be created (eg. when running out of file
+    /// descriptors).
+    pub fn new(mode: Mode) -> Self {
+        let timer = unsafe {
+            libc::timerfd_create(
+                libc::CLOCK_MONOTONIC,
+                libc::TFD_NONBLOCK | libc::TFD_CLOEXEC,
+            )
+        };
+        if timer < 0 {
+            panic!("Failed to create a timer: {}", Error::last_os_error());
+        }
+        MockIface {
+            mode,
+            script: Mutex::new(Script {
+                last: Instant::now(),
+                recvs: VecDeque::new(),
+                expected: VecDeque::new(),
+                sent: Vec::new(),
+                non_blocking: false,
+            }),
+            timer: unsafe { OwnedFd::from_raw_fd(timer) },
+        }
+    }
+    fn script(&self) -> std::sync::MutexGuard<'_, Script> {
+        self.script.lock().unwrap_or_else(|e| e.into_inner())
+    }
+    /// Sets the timer to fire once the next scripted packet is due (or stops it).
+    ///
+    /// Setting the timer also makes the fd not readable until then.
+    fn arm(&self, script: &Script) {
+        let mut spec: libc::itimerspec = unsafe { mem::zeroed() };
+        if let Some((delay, _)) = script.recvs.front() {
+            let remaining = (script.last + *delay).saturating_duration_since(Instant::now());
+            // Zero would stop the timer, fire right away instead
+            let remaining = remaining.max(Duration::from_nanos(1));
+            spec.it_value.tv_sec = remaining.as_secs() as libc::time_t;
+            spec.it_value.tv_nsec = remaining.subsec_nanos() as libc::c_long;
+        }
+        let result =
+            unsafe { libc::timerfd_settime(self.timer.as_raw_fd(), 0, &spec, ptr::null_mut()) };
+        // Can't fail with a valid timer and time
+        debug_assert_eq!(0, result, "timerfd_settime failed");
+    }
+    /// Queues a packet to be received right after the previous one.
+    pub fn push_recv<P: Into<Vec<u8>>>(&self, packet: P) {
+        self.push_recv_after(Duration::from_secs(0), packet);
+    }
+    /// Queues a packet to be received the given time after the previous one.
+    pub fn push_recv_after<P: Into<Vec<u8>>>(&self, delay: Duration, packet: P) {
+        let mut script = self.script();
+        script.recvs.push_back((delay, packet.into()));
+        self.arm(&script);
+    }
+    /// Queues a packet the code under test is expected to send.
+    pub fn expect_send<P: Into<Vec<u8>>>(&self, packet: P) {
+        self.script().expected.push_back(packet.into());
+    }
+    /// Returns all the packets sent so far.
+    pub fn sent(&self) -> Vec<Vec<u8>> {
+        self.script().sent.clone()
+    }
+    /// Checks the whole script was played.
+    ///
+    /// # Panics
+    ///
+    /// If some of the scripted packets were not received or some of the expected packets were not
+    /// sent.
+    pub fn assert_done(&self) {
+        let script = self.script();
+        assert!(
+            script.recvs.is_empty(),
+            "{} scripted packets not received",
+            script.recvs.len()
+        );
+        assert!(
+            script.expected.is_empty(),
+            "Expected packets not sent: {:?}",
+            script.expected
+        );
+    }
+    /// Returns the name of the mock, `mock0`.
+    pub fn name(&self) -> &str {
+        "mock0"
+    }
+    /// Returns the mode passed to [`new`](#method.new).
+    pub fn mode(&self) -> Mode {
+        self.mode
+    }
+    /// Returns the MTU, which is always 1500.
+    pub fn mtu(&self) -> Result<usize> {
+        Ok(1500)
+    }
+    /// Receives the next scripted packet.
+    ///
+    /// Like with the [`Iface::recv`](../struct.Iface.html#method.recv), a packet too large for
+    /// the buffer gets truncated.
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            let mut script = self.script();
+            let due = script.recvs.front().map(|(delay, _)| script.last + *delay);
+            match due {
+                Some(due) if due <= Instant::now() => {
+                    let (_, packet) = script.recvs.pop_front().expect("Just seen it");
+                    script.last = due;
+                    self.arm(&script);
+                    let size = packet.len().min(buf.len());
+                    buf[..size].copy_from_slice(&packet[..size]);
+                    return Ok(size);
+                },
+                _ if script.non_blocking => return Err(Error::from(ErrorKind::WouldBlock)),
+                _ => (),
+            }
+            // Don't hold the lock while waiting, so the test can still script more. Whatever
+            // changes in the meantime is seen in the next round.
+            drop(script);
+            self.wait()?;
+        }
+    }
+    /// Waits for the timer (a packet being due).
+    fn wait(&self) -> Result<()> {
+        let mut fd = libc::pollfd {
+            fd: self.timer.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        if unsafe { libc::poll(&mut fd, 1, -1) } < 0 {
+            let error = Error::last_os_error();
+            if error.kind() != ErrorKind::Interrupted {
+                return Err(error);
+            }
+        }
+        Ok(())
+    }
+    /// Sends a packet, checking it against the expected one.
+    ///
+    /// # Panics
+    ///
+    /// If the packet differs from the next expected one.
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        let mut script = self.script();
+        if let Some(expected) = script.expected.pop_front() {
+            assert_eq!(expected, buf, "Unexpected packet sent");
+        }
+        script.sent.push(buf.to_vec());
+        Ok(buf.len())
+    }
+    /// Sets the mock to be non-blocking.
+    ///
+    /// Then [`recv`](#method.recv) doesn't wait for the packets that are not yet due.
+    pub fn set_non_blocking(&self) -> Result<()> {
+        self.script().non_blocking = true;
+        Ok(())
+    }
+}
+
+impl AsRawFd for MockIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.timer.as_raw_fd()
+    }
+}
+
+impl AsFd for MockIface {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.timer.as_fd()
+    }
+}
+
+impl Device for MockIface {
+    fn name(&self) -> &str {
+        MockIface::name(self)
+    }
+    fn mode(&self) -> Mode {
+        MockIface::mode(self)
+    }
+    fn packet_info(&self) -> bool {
+        false
+    }
+    fn mtu(&self) -> Result<usize> {
+        MockIface::mtu(self)
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        MockIface::recv(self, buf)
+    }
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        MockIface::send(self, buf)
+    }
+    fn set_non_blocking(&self) -> Result<()> {
+        MockIface::set_non_blocking(self)
+    }
+}
@@ -0,0 +1,94 @@
+//! Real devices configured for tests.
+//!
+//! Unlike the fakes in the [`testing`](../testing/index.html) module, these are real devices the
+//! kernel routes packets through, so they need the `CAP_NET_ADMIN` capability (see the
+//! `tests/netns.sh` script to get it without root). See the [`TestNet`](struct.TestNet.html).
+
+use std::net::Ipv4Addr;
+
+use crate::{Error, Iface, IfaceConfig};
+
+/// A device with an address, up and ready to carry packets.
+///
+/// This does the configuration usually done by the `ip` command ‒ it assigns an IPv4 address to
+/// the kernel side of the device and brings it up. When dropped, it brings the device down and
+/// removes the address again (which matters for persistent devices, the others disappear
+/// completely once closed).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::net::{Ipv4Addr, UdpSocket};
+/// # use tun_tap::*;
+/// # use tun_tap::testnet::TestNet;
+/// let config = IfaceConfig::new("test%d", Mode::Tun);
+/// let net = TestNet::new(&config, Ipv4Addr::new(10, 0, 0, 1), 24).expect("No test network");
+/// let socket = UdpSocket::bind("10.0.0.1:0").unwrap();
+/// socket.send_to(b"hello", "10.0.0.2:4242").unwrap();
+/// let mut buffer = vec![0; net.get_ref().max_frame_size().unwrap()];
+/// let size = net.get_ref().recv(&mut buffer).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TestNet {
+    iface: Iface,
+    address: Ipv4Addr,
+    prefix: u8,
+}
+
+impl TestNet {
+    /// Creates the device and configures it.
+    ///
+    /// The `address` (with the network `prefix` length) belongs to the kernel, the application
+    /// takes the role of another host in the network.
+    ///
+    /// # Errors
+    ///
+    /// If the device can't be created (see [`Iface::new`](../struct.Iface.html#method.new)) or
+    /// configured (eg. the process lacks the `CAP_NET_ADMIN` capability).
+    pub fn new(config: &IfaceConfig, address: Ipv4Addr, prefix: u8) -> Result<Self, Error> {
+        Self::configure(config.create()?, address, prefix)
+    }
+    /// Configures an already created device.
+    ///
+    /// # Errors
+    ///
+    /// As with [`new`](#method.new).
+    pub fn configure(iface: Iface, address: Ipv4Addr, prefix: u8) -> Result<Self, Error> {
+        let net = TestNet {
+            iface,
+            address,
+            prefix,
+        };
+        // From now on, the drop cleans up even a half-done configuration
+        net.iface.set_ipv4(address, prefix)?;
+        net.iface.set_up(true)?;
+        Ok(net)
+    }
+    /// Returns the address of the kernel side.
+    pub fn address(&self) -> Ipv4Addr {
+        self.address
+    }
+    /// Returns the length of the network prefix.
+    pub fn prefix(&self) -> u8 {
+        self.prefix
+    }
+    /// Returns the device.
+    pub fn get_ref(&self) -> &Iface {
+        &self.iface
+    }
+    /// Returns the device.
+    pub fn get_mut(&mut self) -> &mut Iface {
+        &mut self.iface
+    }
+}
+
+impl Drop for TestNet {
+    fn drop(&mut self) {
+        if let Err(e) = self.iface.set_up(false) {
+            debug!("Failed to bring {} down: {}", self.iface.name(), e);
+        }
+        if let Err(e) = self.iface.set_ipv4(Ipv4Addr::UNSPECIFIED, 0) {
+            debug!("Failed to remove the address of {}: {}", self.iface.name(), e);
+        }
+    }
+}
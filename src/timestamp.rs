@@ -0,0 +1,37 @@
+//! Times of receiving the packets.
+//!
+//! See the [`Timestamp`](struct.Timestamp.html).
+
+use std::time::{Duration, Instant, SystemTime};
+
+/// The time a packet was received.
+///
+/// The TUN/TAP devices don't timestamp the packets in the kernel (unlike sockets with
+/// `SO_TIMESTAMP`), so this is taken right after the read returns. Both clocks are read at once,
+/// the monotonic one for measuring latencies and intervals, the realtime one for matching the
+/// packets with the outside world (logs, captures, replays).
+///
+/// Returned by [`Iface::recv_timestamped`](struct.Iface.html#method.recv_timestamped).
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Timestamp {
+    /// The monotonic time.
+    pub monotonic: Instant,
+    /// The wall-clock time.
+    pub realtime: SystemTime,
+}
+
+impl Timestamp {
+    /// Takes the current time.
+    pub fn now() -> Self {
+        Timestamp {
+            monotonic: Instant::now(),
+            realtime: SystemTime::now(),
+        }
+    }
+    /// The time between the two timestamps, by the monotonic clock.
+    ///
+    /// Zero if the `earlier` one is actually later.
+    pub fn duration_since(&self, earlier: &Timestamp) -> Duration {
+        self.monotonic.saturating_duration_since(earlier.monotonic)
+    }
+}
@@ -4,13 +4,27 @@
  */
 
 #include <assert.h>
+#include <errno.h>
+#include <fcntl.h>
+#include <poll.h>
+#include <stddef.h>
 #include <stdint.h>
+#include <stdlib.h>
 #include <string.h>
 
+#include <netinet/in.h>
 #include <sys/socket.h>
+#include <sys/uio.h>
 #ifdef __linux__
+#include <linux/bpf.h>
+#include <linux/capability.h>
 #include <linux/if.h>
 #include <linux/if_tun.h>
+#include <linux/route.h>
+#include <linux/sockios.h>
+#include <linux/vhost.h>
+#include <sys/prctl.h>
+#include <sys/syscall.h>
 #else
 #include <net/if.h>
 #define IFF_TUN 0x0001
@@ -19,32 +33,19 @@
 #define TUNSETIFF _IOW('T', 202, int)
 #endif
 #include <sys/ioctl.h>
+#include <unistd.h>
 
 /**
  * fd ‒ the fd to turn into TUN or TAP.
  * name ‒ the name to use. If empty, kernel will assign something by itself.
  *   Must be buffer with capacity at least 33.
- * mode ‒ 1 = TUN, 2 = TAP.
- * packet_info ‒ if packet info should be provided, if the given value is 0 it will not prepend packet info.
+ * flags ‒ the IFF_* flags, including the mode (IFF_TUN or IFF_TAP).
  */
-int tuntap_setup(int fd, unsigned char *name, int mode, int packet_info) {
+int tuntap_setup(int fd, unsigned char *name, int flags) {
 	struct ifreq ifr;
 	memset(&ifr, 0, sizeof ifr);
-	switch (mode) {
-		case 1:
-			ifr.ifr_flags = IFF_TUN;
-			break;
-		case 2:
-			ifr.ifr_flags = IFF_TAP;
-			break;
-		default:
-			assert(0);
-	}
-
-	// If no packet info needs to be provided add corresponding flag
-	if (!packet_info) {
-		ifr.ifr_flags |= IFF_NO_PI;
-	}
+	assert(flags & (IFF_TUN | IFF_TAP));
+	ifr.ifr_flags = flags;
 
 	// Leave one for terminating '\0'. No idea if it is needed, didn't find
 	// it in the docs, but assuming the worst.
@@ -58,3 +59,987 @@ int tuntap_setup(int fd, unsigned char *name, int mode, int packet_info) {
 	name[32] = '\0';
 	return 0;
 }
+
+/**
+ * fd ‒ the TUN/TAP fd.
+ * name ‒ where to store the name of the device, may be NULL. Must be buffer
+ *   with capacity at least 33.
+ *
+ * Returns the IFF_* flags of the device (from TUNGETIFF) or -1 on error.
+ */
+int tuntap_get_flags(int fd, unsigned char *name) {
+#ifdef TUNGETIFF
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	if (ioctl(fd, TUNGETIFF, &ifr) < 0) {
+		return -1;
+	}
+	if (name) {
+		strncpy((char *)name, ifr.ifr_name, IFNAMSIZ < 32 ? IFNAMSIZ : 32);
+		name[32] = '\0';
+	}
+	return (unsigned short)ifr.ifr_flags;
+#else
+	(void)fd;
+	(void)name;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the TUN/TAP fd.
+ * offload ‒ the TUN_F_* flags.
+ */
+int tuntap_set_offload(int fd, unsigned int offload) {
+#ifdef TUNSETOFFLOAD
+	return ioctl(fd, TUNSETOFFLOAD, (unsigned long)offload);
+#else
+	(void)fd;
+	(void)offload;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the TUN/TAP fd.
+ * persist ‒ non-zero to make the device persistent, 0 to make it go away with
+ *   the last fd.
+ */
+int tuntap_set_persist(int fd, int persist) {
+#ifdef TUNSETPERSIST
+	return ioctl(fd, TUNSETPERSIST, (unsigned long)persist);
+#else
+	(void)fd;
+	(void)persist;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the TUN/TAP fd.
+ * nocsum ‒ non-zero to ask the kernel to skip the checksum validation.
+ */
+int tuntap_set_nocsum(int fd, int nocsum) {
+#ifdef TUNSETNOCSUM
+	return ioctl(fd, TUNSETNOCSUM, (unsigned long)nocsum);
+#else
+	(void)fd;
+	(void)nocsum;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the TUN/TAP fd.
+ * owner ‒ the uid allowed to attach to the device.
+ */
+int tuntap_set_owner(int fd, unsigned int owner) {
+#ifdef TUNSETOWNER
+	return ioctl(fd, TUNSETOWNER, (unsigned long)owner);
+#else
+	(void)fd;
+	(void)owner;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the TUN/TAP fd.
+ * group ‒ the gid allowed to attach to the device.
+ */
+int tuntap_set_group(int fd, unsigned int group) {
+#ifdef TUNSETGROUP
+	return ioctl(fd, TUNSETGROUP, (unsigned long)group);
+#else
+	(void)fd;
+	(void)group;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the TUN/TAP fd.
+ * prog ‒ fd of the loaded eBPF program choosing the queue, -1 to detach.
+ */
+int tuntap_set_steering_ebpf(int fd, int prog) {
+#ifdef TUNSETSTEERINGEBPF
+	return ioctl(fd, TUNSETSTEERINGEBPF, &prog);
+#else
+	(void)fd;
+	(void)prog;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * pipe_fds ‒ where to store the read and write end of a newly created pipe.
+ *   Both ends are close-on-exec, the write end is also non-blocking (so a
+ *   full pipe never blocks the one doing the shutdown).
+ */
+int tuntap_shutdown_pipe(int *pipe_fds) {
+	if (pipe(pipe_fds) < 0) {
+		return -1;
+	}
+	for (int i = 0; i < 2; i ++) {
+		if (fcntl(pipe_fds[i], F_SETFD, FD_CLOEXEC) < 0) {
+			goto FAIL;
+		}
+	}
+	int flags = fcntl(pipe_fds[1], F_GETFL);
+	if (flags < 0 || fcntl(pipe_fds[1], F_SETFL, flags | O_NONBLOCK) < 0) {
+		goto FAIL;
+	}
+	return 0;
+FAIL:
+	close(pipe_fds[0]);
+	close(pipe_fds[1]);
+	return -1;
+}
+
+/**
+ * fd ‒ the TUN/TAP fd to wait on.
+ * wake_fd ‒ read end of the shutdown pipe.
+ *
+ * Waits until either of the fds becomes readable. If the fd is in
+ * non-blocking mode, it doesn't wait at all and only checks the shutdown fd.
+ *
+ * Returns 1 if the shutdown fd is readable, 0 if the fd is (or may be)
+ * readable and -1 on error.
+ */
+int tuntap_wait_readable(int fd, int wake_fd) {
+	int flags = fcntl(fd, F_GETFL);
+	if (flags < 0) {
+		return -1;
+	}
+	struct pollfd fds[2];
+	memset(fds, 0, sizeof fds);
+	fds[0].fd = wake_fd;
+	fds[0].events = POLLIN;
+	fds[1].fd = fd;
+	fds[1].events = POLLIN;
+	int result = poll(fds, 2, (flags & O_NONBLOCK) ? 0 : -1);
+	if (result < 0) {
+		return -1;
+	}
+	return (fds[0].revents & POLLIN) ? 1 : 0;
+}
+
+/**
+ * name ‒ the name of the interface.
+ *
+ * Returns the MTU of the interface or -1 on error.
+ */
+int tuntap_get_mtu(const char *name) {
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+	int result = ioctl(sock, SIOCGIFMTU, &ifr);
+	close(sock);
+	if (result < 0) {
+		return -1;
+	}
+	return ifr.ifr_mtu;
+}
+
+/**
+ * name ‒ the name of the interface.
+ *
+ * Returns the IFF_* interface flags (from SIOCGIFFLAGS) or -1 on error.
+ */
+int tuntap_get_iff_flags(const char *name) {
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+	int result = ioctl(sock, SIOCGIFFLAGS, &ifr);
+	close(sock);
+	if (result < 0) {
+		return -1;
+	}
+	return (unsigned short)ifr.ifr_flags;
+}
+
+/**
+ * name ‒ the name of the interface.
+ * up ‒ non-zero to bring the interface up, zero to bring it down.
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_set_up(const char *name, int up) {
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+	int result = ioctl(sock, SIOCGIFFLAGS, &ifr);
+	if (result == 0) {
+		if (up) {
+			ifr.ifr_flags |= IFF_UP;
+		} else {
+			ifr.ifr_flags &= ~IFF_UP;
+		}
+		result = ioctl(sock, SIOCSIFFLAGS, &ifr);
+	}
+	close(sock);
+	return result;
+}
+
+/**
+ * name ‒ the name of the interface.
+ * mtu ‒ the new MTU.
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_set_mtu(const char *name, int mtu) {
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+	ifr.ifr_mtu = mtu;
+	int result = ioctl(sock, SIOCSIFMTU, &ifr);
+	close(sock);
+	return result;
+}
+
+/**
+ * name ‒ the name of the interface.
+ * address, netmask ‒ the IPv4 address and its netmask, in the network byte
+ *   order. The zero address removes the current one.
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_set_ipv4(const char *name, uint32_t address, uint32_t netmask) {
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+	struct sockaddr_in *addr = (struct sockaddr_in *)&ifr.ifr_addr;
+	addr->sin_family = AF_INET;
+	addr->sin_addr.s_addr = address;
+	int result = ioctl(sock, SIOCSIFADDR, &ifr);
+	if (result == 0 && address != 0) {
+		// The netmask lives in the same place of the request
+		addr->sin_addr.s_addr = netmask;
+		result = ioctl(sock, SIOCSIFNETMASK, &ifr);
+	}
+	close(sock);
+	return result;
+}
+
+/**
+ * name ‒ the name of the interface.
+ * address, netmask ‒ where to store the primary IPv4 address and its netmask,
+ *   in the network byte order.
+ *
+ * Returns 1 if the interface has an IPv4 address, 0 if not and -1 on error.
+ */
+int tuntap_get_ipv4(const char *name, uint32_t *address, uint32_t *netmask) {
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+	struct sockaddr_in *addr = (struct sockaddr_in *)&ifr.ifr_addr;
+	int result = ioctl(sock, SIOCGIFADDR, &ifr);
+	if (result == 0) {
+		*address = addr->sin_addr.s_addr;
+		result = ioctl(sock, SIOCGIFNETMASK, &ifr);
+		*netmask = addr->sin_addr.s_addr;
+		result = result == 0 ? 1 : -1;
+	} else if (errno == EADDRNOTAVAIL) {
+		result = 0;
+	}
+	close(sock);
+	return result;
+}
+
+/**
+ * name ‒ the name of the interface.
+ *
+ * Removes all the IPv4 addresses of the interface (removing the primary one
+ * may promote a secondary one, so it goes until there's none left).
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_flush_ipv4(const char *name) {
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	int result = 0;
+	// Bounded, in case someone keeps adding them
+	for (int i = 0; i < 1024; i++) {
+		memset(&ifr, 0, sizeof ifr);
+		strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+		if (ioctl(sock, SIOCGIFADDR, &ifr) < 0) {
+			result = errno == EADDRNOTAVAIL ? 0 : -1;
+			break;
+		}
+		struct sockaddr_in *addr = (struct sockaddr_in *)&ifr.ifr_addr;
+		addr->sin_family = AF_INET;
+		addr->sin_addr.s_addr = 0;
+		if (ioctl(sock, SIOCSIFADDR, &ifr) < 0) {
+			result = -1;
+			break;
+		}
+	}
+	close(sock);
+	return result;
+}
+
+/**
+ * name ‒ the name of the interface.
+ * destination, netmask ‒ the network routed through the interface, in the
+ *   network byte order.
+ * add ‒ non-zero to add the route, zero to remove it.
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_route_ipv4(const char *name, uint32_t destination, uint32_t netmask, int add) {
+#if defined(SIOCADDRT) && defined(SIOCDELRT)
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	char dev[IFNAMSIZ];
+	memset(dev, 0, sizeof dev);
+	strncpy(dev, name, IFNAMSIZ - 1);
+	struct rtentry route;
+	memset(&route, 0, sizeof route);
+	struct sockaddr_in *dst = (struct sockaddr_in *)&route.rt_dst;
+	dst->sin_family = AF_INET;
+	dst->sin_addr.s_addr = destination;
+	struct sockaddr_in *mask = (struct sockaddr_in *)&route.rt_genmask;
+	mask->sin_family = AF_INET;
+	mask->sin_addr.s_addr = netmask;
+	route.rt_flags = RTF_UP;
+	route.rt_dev = dev;
+	int result = ioctl(sock, add ? SIOCADDRT : SIOCDELRT, &route);
+	close(sock);
+	return result;
+#else
+	(void)name;
+	(void)destination;
+	(void)netmask;
+	(void)add;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * name ‒ the name of the interface.
+ * hwaddr ‒ buffer for the 6 bytes of the MAC address.
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_get_hwaddr(const char *name, unsigned char *hwaddr) {
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+	int result = ioctl(sock, SIOCGIFHWADDR, &ifr);
+	if (result == 0) {
+		memcpy(hwaddr, ifr.ifr_hwaddr.sa_data, 6);
+	}
+	close(sock);
+	return result;
+}
+
+/**
+ * name ‒ the name of the interface.
+ * hwaddr ‒ the 6 bytes of the new MAC address.
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_set_hwaddr(const char *name, const unsigned char *hwaddr) {
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	strncpy(ifr.ifr_name, name, IFNAMSIZ - 1);
+	// ARPHRD_ETHER
+	ifr.ifr_hwaddr.sa_family = 1;
+	memcpy(ifr.ifr_hwaddr.sa_data, hwaddr, 6);
+	int result = ioctl(sock, SIOCSIFHWADDR, &ifr);
+	close(sock);
+	return result;
+}
+
+/**
+ * bridge ‒ the name of the bridge.
+ * index ‒ the index of the interface to add to or remove from the bridge.
+ * add ‒ non-zero to add the interface, zero to remove it.
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_set_bridge(const char *bridge, int index, int add) {
+#if defined(SIOCBRADDIF) && defined(SIOCBRDELIF)
+	int sock = socket(AF_INET, SOCK_DGRAM, 0);
+	if (sock < 0) {
+		return -1;
+	}
+	struct ifreq ifr;
+	memset(&ifr, 0, sizeof ifr);
+	strncpy(ifr.ifr_name, bridge, IFNAMSIZ - 1);
+	ifr.ifr_ifindex = index;
+	int result = ioctl(sock, add ? SIOCBRADDIF : SIOCBRDELIF, &ifr);
+	close(sock);
+	return result;
+#else
+	(void)bridge;
+	(void)index;
+	(void)add;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the TUN/TAP fd.
+ * link ‒ the ARPHRD_* link type of the device.
+ */
+int tuntap_set_link(int fd, unsigned int link) {
+#ifdef TUNSETLINK
+	return ioctl(fd, TUNSETLINK, (unsigned long)link);
+#else
+	(void)fd;
+	(void)link;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * sock ‒ a unix domain socket.
+ * fd ‒ the fd to pass along with the data, -1 to pass none.
+ * data, len ‒ the message to send.
+ *
+ * Returns the number of bytes sent or -1 on error.
+ */
+ssize_t tuntap_send_fd(int sock, int fd, const unsigned char *data, size_t len) {
+	struct iovec iov = { .iov_base = (void *)data, .iov_len = len };
+	union {
+		char buf[CMSG_SPACE(sizeof(int))];
+		struct cmsghdr align;
+	} control;
+	memset(&control, 0, sizeof control);
+	struct msghdr msg;
+	memset(&msg, 0, sizeof msg);
+	msg.msg_iov = &iov;
+	msg.msg_iovlen = 1;
+	if (fd >= 0) {
+		msg.msg_control = control.buf;
+		msg.msg_controllen = sizeof control.buf;
+		struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg);
+		cmsg->cmsg_level = SOL_SOCKET;
+		cmsg->cmsg_type = SCM_RIGHTS;
+		cmsg->cmsg_len = CMSG_LEN(sizeof(int));
+		memcpy(CMSG_DATA(cmsg), &fd, sizeof(int));
+	}
+	return sendmsg(sock, &msg, 0);
+}
+
+/**
+ * sock ‒ a unix domain socket.
+ * fd ‒ where to store the received fd (close-on-exec), -1 if none came.
+ * data, len ‒ the buffer for the message.
+ *
+ * Returns the number of bytes received or -1 on error.
+ */
+ssize_t tuntap_recv_fd(int sock, int *fd, unsigned char *data, size_t len) {
+	*fd = -1;
+	struct iovec iov = { .iov_base = data, .iov_len = len };
+	union {
+		char buf[CMSG_SPACE(sizeof(int))];
+		struct cmsghdr align;
+	} control;
+	memset(&control, 0, sizeof control);
+	struct msghdr msg;
+	memset(&msg, 0, sizeof msg);
+	msg.msg_iov = &iov;
+	msg.msg_iovlen = 1;
+	msg.msg_control = control.buf;
+	msg.msg_controllen = sizeof control.buf;
+	int flags = 0;
+#ifdef MSG_CMSG_CLOEXEC
+	flags |= MSG_CMSG_CLOEXEC;
+#endif
+	ssize_t result = recvmsg(sock, &msg, flags);
+	if (result < 0) {
+		return -1;
+	}
+	for (struct cmsghdr *cmsg = CMSG_FIRSTHDR(&msg); cmsg; cmsg = CMSG_NXTHDR(&msg, cmsg)) {
+		if (cmsg->cmsg_level == SOL_SOCKET && cmsg->cmsg_type == SCM_RIGHTS) {
+			memcpy(fd, CMSG_DATA(cmsg), sizeof(int));
+#ifndef MSG_CMSG_CLOEXEC
+			fcntl(*fd, F_SETFD, FD_CLOEXEC);
+#endif
+		}
+	}
+	return result;
+}
+
+/**
+ * fd ‒ an open /dev/net/tun, not attached to any device yet.
+ *
+ * Returns the IFF_* flags supported by the driver (TUNGETFEATURES) or -1 on
+ * error.
+ */
+int tuntap_get_features(int fd) {
+#ifdef TUNGETFEATURES
+	unsigned int features = 0;
+	if (ioctl(fd, TUNGETFEATURES, &features) < 0) {
+		return -1;
+	}
+	return (int)features;
+#else
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * Returns 1 if the calling thread has the CAP_NET_ADMIN capability in its
+ * effective set, 0 if not and -1 if it can't be found out.
+ */
+int tuntap_has_net_admin(void) {
+#ifdef __linux__
+	struct __user_cap_header_struct header;
+	memset(&header, 0, sizeof header);
+	header.version = _LINUX_CAPABILITY_VERSION_3;
+	struct __user_cap_data_struct data[_LINUX_CAPABILITY_U32S_3];
+	memset(data, 0, sizeof data);
+	if (syscall(SYS_capget, &header, data) < 0) {
+		return -1;
+	}
+	return (data[CAP_TO_INDEX(CAP_NET_ADMIN)].effective & CAP_TO_MASK(CAP_NET_ADMIN)) ? 1 : 0;
+#else
+	return -1;
+#endif
+}
+
+/**
+ * Removes the CAP_NET_ADMIN capability from all the sets of the calling thread
+ * (including the bounding one if permitted, the ambient one is cleared by the
+ * kernel together with the permitted one). The other capabilities are kept.
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_drop_net_admin(void) {
+#ifdef __linux__
+	struct __user_cap_header_struct header;
+	memset(&header, 0, sizeof header);
+	header.version = _LINUX_CAPABILITY_VERSION_3;
+	struct __user_cap_data_struct data[_LINUX_CAPABILITY_U32S_3];
+	memset(data, 0, sizeof data);
+	if (syscall(SYS_capget, &header, data) < 0) {
+		return -1;
+	}
+	// Dropping from the bounding set needs CAP_SETPCAP. Without it, it's fine only if the
+	// capability is not in the bounding set already, otherwise it could be regained by exec.
+	if (prctl(PR_CAPBSET_DROP, CAP_NET_ADMIN, 0, 0, 0) < 0) {
+		if (errno != EPERM || prctl(PR_CAPBSET_READ, CAP_NET_ADMIN, 0, 0, 0) != 0) {
+			errno = EPERM;
+			return -1;
+		}
+	}
+	int idx = CAP_TO_INDEX(CAP_NET_ADMIN);
+	unsigned mask = CAP_TO_MASK(CAP_NET_ADMIN);
+	data[idx].effective &= ~mask;
+	data[idx].permitted &= ~mask;
+	data[idx].inheritable &= ~mask;
+	if (syscall(SYS_capset, &header, data) < 0) {
+		return -1;
+	}
+	return 0;
+#else
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the TUN/TAP fd.
+ * size ‒ the size of the virtio net header in front of each packet.
+ */
+int tuntap_set_vnet_hdr_size(int fd, int size) {
+#ifdef TUNSETVNETHDRSZ
+	return ioctl(fd, TUNSETVNETHDRSZ, &size);
+#else
+	(void)fd;
+	(void)size;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ an open /dev/vhost-net.
+ *
+ * Makes the calling process the owner of the vhost device.
+ */
+int tuntap_vhost_set_owner(int fd) {
+#ifdef VHOST_SET_OWNER
+	return ioctl(fd, VHOST_SET_OWNER, NULL);
+#else
+	(void)fd;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the vhost fd.
+ * features ‒ where to store the VIRTIO_* feature bits supported by the device.
+ */
+int tuntap_vhost_get_features(int fd, uint64_t *features) {
+#ifdef VHOST_GET_FEATURES
+	return ioctl(fd, VHOST_GET_FEATURES, features);
+#else
+	(void)fd;
+	(void)features;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the vhost fd.
+ * features ‒ the feature bits to use (acknowledged by the guest).
+ */
+int tuntap_vhost_set_features(int fd, uint64_t features) {
+#ifdef VHOST_SET_FEATURES
+	return ioctl(fd, VHOST_SET_FEATURES, &features);
+#else
+	(void)fd;
+	(void)features;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the vhost fd.
+ * regions ‒ count triples of the guest physical address, size and the
+ *   userspace address of the memory regions.
+ * count ‒ the number of regions.
+ */
+int tuntap_vhost_set_mem_table(int fd, const uint64_t *regions, unsigned int count) {
+#ifdef VHOST_SET_MEM_TABLE
+	size_t size = sizeof(struct vhost_memory) + count * sizeof(struct vhost_memory_region);
+	struct vhost_memory *memory = calloc(1, size);
+	if (!memory) {
+		return -1;
+	}
+	memory->nregions = count;
+	for (unsigned int i = 0; i < count; i ++) {
+		memory->regions[i].guest_phys_addr = regions[3 * i];
+		memory->regions[i].memory_size = regions[3 * i + 1];
+		memory->regions[i].userspace_addr = regions[3 * i + 2];
+	}
+	int result = ioctl(fd, VHOST_SET_MEM_TABLE, memory);
+	int saved = errno;
+	free(memory);
+	errno = saved;
+	return result;
+#else
+	(void)fd;
+	(void)regions;
+	(void)count;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the vhost fd.
+ * index ‒ the virtqueue.
+ * num ‒ the number of descriptors in the queue.
+ */
+int tuntap_vhost_set_vring_num(int fd, unsigned int index, unsigned int num) {
+#ifdef VHOST_SET_VRING_NUM
+	struct vhost_vring_state state = { .index = index, .num = num };
+	return ioctl(fd, VHOST_SET_VRING_NUM, &state);
+#else
+	(void)fd;
+	(void)index;
+	(void)num;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the vhost fd.
+ * index ‒ the virtqueue.
+ * base ‒ the index of the next available descriptor to process.
+ */
+int tuntap_vhost_set_vring_base(int fd, unsigned int index, unsigned int base) {
+#ifdef VHOST_SET_VRING_BASE
+	struct vhost_vring_state state = { .index = index, .num = base };
+	return ioctl(fd, VHOST_SET_VRING_BASE, &state);
+#else
+	(void)fd;
+	(void)index;
+	(void)base;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the vhost fd.
+ * index ‒ the virtqueue.
+ * base ‒ where to store the index of the next available descriptor.
+ */
+int tuntap_vhost_get_vring_base(int fd, unsigned int index, unsigned int *base) {
+#ifdef VHOST_GET_VRING_BASE
+	struct vhost_vring_state state = { .index = index, .num = 0 };
+	int result = ioctl(fd, VHOST_GET_VRING_BASE, &state);
+	*base = state.num;
+	return result;
+#else
+	(void)fd;
+	(void)index;
+	(void)base;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the vhost fd.
+ * index ‒ the virtqueue.
+ * flags ‒ the VHOST_VRING_F_* flags.
+ * desc, used, avail ‒ the userspace addresses of the parts of the queue.
+ * log ‒ the guest address of the used ring, for logging the writes.
+ */
+int tuntap_vhost_set_vring_addr(int fd, unsigned int index, unsigned int flags, uint64_t desc,
+		uint64_t used, uint64_t avail, uint64_t log) {
+#ifdef VHOST_SET_VRING_ADDR
+	struct vhost_vring_addr addr;
+	memset(&addr, 0, sizeof addr);
+	addr.index = index;
+	addr.flags = flags;
+	addr.desc_user_addr = desc;
+	addr.used_user_addr = used;
+	addr.avail_user_addr = avail;
+	addr.log_guest_addr = log;
+	return ioctl(fd, VHOST_SET_VRING_ADDR, &addr);
+#else
+	(void)fd;
+	(void)index;
+	(void)flags;
+	(void)desc;
+	(void)used;
+	(void)avail;
+	(void)log;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * fd ‒ the vhost fd.
+ * which ‒ 0 for the kick eventfd, 1 for the call one, 2 for the error one and
+ *   3 for the backend (the TUN/TAP fd).
+ * index ‒ the virtqueue.
+ * file ‒ the fd to set, -1 to unset.
+ */
+int tuntap_vhost_set_vring_file(int fd, int which, unsigned int index, int file) {
+#if defined(VHOST_SET_VRING_KICK) && defined(VHOST_NET_SET_BACKEND)
+	struct vhost_vring_file vring_file = { .index = index, .fd = file };
+	unsigned long request;
+	switch (which) {
+		case 0:
+			request = VHOST_SET_VRING_KICK;
+			break;
+		case 1:
+			request = VHOST_SET_VRING_CALL;
+			break;
+		case 2:
+			request = VHOST_SET_VRING_ERR;
+			break;
+		case 3:
+			request = VHOST_NET_SET_BACKEND;
+			break;
+		default:
+			errno = EINVAL;
+			return -1;
+	}
+	return ioctl(fd, request, &vring_file);
+#else
+	(void)fd;
+	(void)which;
+	(void)index;
+	(void)file;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+#if defined(__linux__) && defined(SYS_bpf)
+static int tuntap_bpf(int cmd, union bpf_attr *attr) {
+	return syscall(SYS_bpf, cmd, attr, sizeof *attr);
+}
+#endif
+
+/**
+ * entries ‒ the number of queues the map covers.
+ *
+ * Creates an XSKMAP, the map of the AF_XDP sockets by the queue index.
+ *
+ * Returns the fd of the map, -1 on error.
+ */
+int tuntap_xsk_map_create(unsigned int entries) {
+#if defined(__linux__) && defined(SYS_bpf)
+	union bpf_attr attr;
+	memset(&attr, 0, sizeof attr);
+	attr.map_type = BPF_MAP_TYPE_XSKMAP;
+	attr.key_size = sizeof(uint32_t);
+	attr.value_size = sizeof(uint32_t);
+	attr.max_entries = entries;
+	strncpy(attr.map_name, "tuntap_xsks", BPF_OBJ_NAME_LEN - 1);
+	return tuntap_bpf(BPF_MAP_CREATE, &attr);
+#else
+	(void)entries;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * map ‒ the XSKMAP.
+ * queue ‒ the queue index.
+ * sock ‒ the AF_XDP socket to receive the packets of the queue, -1 to remove it.
+ *
+ * Returns 0 on success, -1 on error.
+ */
+int tuntap_xsk_map_set(int map, unsigned int queue, int sock) {
+#if defined(__linux__) && defined(SYS_bpf)
+	uint32_t key = queue;
+	uint32_t value = sock;
+	union bpf_attr attr;
+	memset(&attr, 0, sizeof attr);
+	attr.map_fd = map;
+	attr.key = (uint64_t)(uintptr_t)&key;
+	if (sock < 0) {
+		return tuntap_bpf(BPF_MAP_DELETE_ELEM, &attr);
+	}
+	attr.value = (uint64_t)(uintptr_t)&value;
+	attr.flags = BPF_ANY;
+	return tuntap_bpf(BPF_MAP_UPDATE_ELEM, &attr);
+#else
+	(void)map;
+	(void)queue;
+	(void)sock;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * map ‒ the XSKMAP.
+ *
+ * Loads an XDP program redirecting the packets of each queue into the socket
+ * of the queue in the map. The packets of queues without a socket are passed
+ * on to the network stack.
+ *
+ * Returns the fd of the program, -1 on error.
+ */
+int tuntap_xdp_redirect_prog(int map) {
+#if defined(__linux__) && defined(SYS_bpf)
+	struct bpf_insn insns[] = {
+		// r2 = ctx->rx_queue_index
+		{
+			.code = BPF_LDX | BPF_W | BPF_MEM,
+			.dst_reg = BPF_REG_2,
+			.src_reg = BPF_REG_1,
+			.off = offsetof(struct xdp_md, rx_queue_index),
+		},
+		// r1 = map (a double-wide instruction)
+		{
+			.code = BPF_LD | BPF_DW | BPF_IMM,
+			.dst_reg = BPF_REG_1,
+			.src_reg = BPF_PSEUDO_MAP_FD,
+			.imm = map,
+		},
+		{ .code = 0 },
+		// r3 = XDP_PASS, the action if the queue has no socket
+		{ .code = BPF_ALU64 | BPF_MOV | BPF_K, .dst_reg = BPF_REG_3, .imm = XDP_PASS },
+		// return bpf_redirect_map(r1, r2, r3)
+		{ .code = BPF_JMP | BPF_CALL, .imm = BPF_FUNC_redirect_map },
+		{ .code = BPF_JMP | BPF_EXIT },
+	};
+	union bpf_attr attr;
+	memset(&attr, 0, sizeof attr);
+	attr.prog_type = BPF_PROG_TYPE_XDP;
+	attr.insns = (uint64_t)(uintptr_t)insns;
+	attr.insn_cnt = sizeof insns / sizeof insns[0];
+	attr.license = (uint64_t)(uintptr_t)"Dual MIT/GPL";
+	strncpy(attr.prog_name, "tuntap_xsk", BPF_OBJ_NAME_LEN - 1);
+	return tuntap_bpf(BPF_PROG_LOAD, &attr);
+#else
+	(void)map;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
+
+/**
+ * prog ‒ the XDP program.
+ * ifindex ‒ the interface to attach it to.
+ * flags ‒ the XDP_FLAGS_* choosing the mode, 0 to let the kernel pick.
+ *
+ * The program stays attached as long as the returned link is open.
+ *
+ * Returns the fd of the link, -1 on error.
+ */
+int tuntap_xdp_attach(int prog, int ifindex, unsigned int flags) {
+#if defined(__linux__) && defined(SYS_bpf)
+	union bpf_attr attr;
+	memset(&attr, 0, sizeof attr);
+	attr.link_create.prog_fd = prog;
+	attr.link_create.target_ifindex = ifindex;
+	attr.link_create.attach_type = BPF_XDP;
+	attr.link_create.flags = flags;
+	return tuntap_bpf(BPF_LINK_CREATE, &attr);
+#else
+	(void)prog;
+	(void)ifindex;
+	(void)flags;
+	errno = ENOTSUP;
+	return -1;
+#endif
+}
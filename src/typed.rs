@@ -0,0 +1,549 @@
+//! Interfaces with the packet info presence known at compile time.
+//!
+//! The plain [`Iface`](../struct.Iface.html) decides at runtime if the packets carry the
+//! [`PacketInfo`](../struct.PacketInfo.html) header. Forgetting about the 4 bytes (or expecting
+//! them when they are not there) is a common mistake and it shows only as mangled packets. The
+//! [`TypedIface`](struct.TypedIface.html) tracks it in the type instead ‒ the methods working
+//! with the header exist only on the `TypedIface<WithPi>`.
+//!
+//! Similarly, the [`TunIface`](struct.TunIface.html) and [`TapIface`](struct.TapIface.html) have
+//! the mode in the type, with the operations that make sense only in that mode.
+//!
+//! These are thin wrappers, the dynamic `Iface` is available through `get_ref` or `into_inner`
+//! for the code that expects it.
+
+use std::convert::TryFrom;
+use std::ffi::CString;
+use std::fmt::Debug;
+use std::io::{self, Result};
+use std::marker::PhantomData;
+use std::os::raw::{c_char, c_int, c_uint};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+
+#[cfg(feature = "tokio")]
+use crate::codec::{EthernetFrameCodec, TunPacketCodec};
+use crate::packet::MacAddr;
+use crate::{Device, EtherType, Error, Iface, Mode, PacketInfo, TunFlags};
+
+extern "C" {
+    fn tuntap_get_hwaddr(name: *const c_char, hwaddr: *mut u8) -> c_int;
+    fn tuntap_set_hwaddr(name: *const c_char, hwaddr: *const u8) -> c_int;
+    fn tuntap_set_bridge(bridge: *const c_char, index: c_int, add: c_int) -> c_int;
+    fn tuntap_set_link(fd: c_int, link: c_uint) -> c_int;
+}
+
+mod private {
+    pub trait Sealed {}
+}
+
+/// Marks the presence of the packet info, implemented by [`WithPi`](enum.WithPi.html) and
+/// [`NoPi`](enum.NoPi.html).
+///
+/// This trait is sealed, it can't be implemented outside of this crate.
+pub trait PacketInfoMarker: private::Sealed + Debug {
+    /// If the packets carry the packet info.
+    const PACKET_INFO: bool;
+}
+
+/// The packets are prepended with the packet info.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum WithPi {}
+
+/// The packets come bare, without the packet info.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum NoPi {}
+
+impl private::Sealed for WithPi {}
+impl private::Sealed for NoPi {}
+
+impl PacketInfoMarker for WithPi {
+    const PACKET_INFO: bool = true;
+}
+
+impl PacketInfoMarker for NoPi {
+    const PACKET_INFO: bool = false;
+}
+
+/// An interface with the packet info presence in its type.
+///
+/// The parameter is either [`WithPi`](enum.WithPi.html) or [`NoPi`](enum.NoPi.html).
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::*;
+/// # use tun_tap::typed::{TypedIface, WithPi};
+/// let iface = TypedIface::<WithPi>::new("mytun", Mode::Tun).expect("Failed to create a device");
+/// let mut buffer = vec![0; 1504];
+/// let (info, packet) = iface.recv_parsed(&mut buffer).unwrap();
+/// iface.send_with_proto(info.proto, packet).unwrap();
+/// ```
+///
+/// Without the packet info, there's no header to work with:
+///
+/// ```rust,compile_fail
+/// # use tun_tap::*;
+/// # use tun_tap::typed::{NoPi, TypedIface};
+/// let iface = TypedIface::<NoPi>::new("mytun", Mode::Tun).expect("Failed to create a device");
+/// iface.send_with_proto(EtherType::Ipv4, &[0x45, 0, 0, 20]).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TypedIface<P: PacketInfoMarker> {
+    iface: Iface,
+    _marker: PhantomData<P>,
+}
+
+impl<P: PacketInfoMarker> TypedIface<P> {
+    /// Creates a new virtual interface.
+    ///
+    /// The packet info is turned on or off according to the type parameter, otherwise this is
+    /// the same as [`Iface::new`](../struct.Iface.html#method.new).
+    ///
+    /// # Errors
+    ///
+    /// As with [`Iface::new`](../struct.Iface.html#method.new).
+    pub fn new(ifname: &str, mode: Mode) -> std::result::Result<Self, Error> {
+        let mut flags = TunFlags::from(mode);
+        flags.set(TunFlags::NO_PI, !P::PACKET_INFO);
+        let iface = Iface::with_flags(ifname, flags)?;
+        Ok(TypedIface {
+            iface,
+            _marker: PhantomData,
+        })
+    }
+    /// Wraps an already created interface.
+    ///
+    /// # Errors
+    ///
+    /// If the interface's packet info doesn't match the type, it is returned back.
+    pub fn from_iface(iface: Iface) -> std::result::Result<Self, Iface> {
+        if iface.packet_info() != P::PACKET_INFO {
+            return Err(iface);
+        }
+        Ok(TypedIface {
+            iface,
+            _marker: PhantomData,
+        })
+    }
+    /// Returns the mode of the adapter.
+    pub fn mode(&self) -> Mode {
+        self.iface.mode()
+    }
+    /// Returns the real name of the adapter.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+    /// Receives a packet from the interface, including the header if any.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.iface.recv(buf)
+    }
+    /// Sends a packet into the interface, including the header if any.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.iface.send(buf)
+    }
+    /// Accesses the wrapped interface.
+    pub fn get_ref(&self) -> &Iface {
+        &self.iface
+    }
+    /// Accesses the wrapped interface.
+    pub fn get_mut(&mut self) -> &mut Iface {
+        &mut self.iface
+    }
+    /// Unwraps the interface.
+    pub fn into_inner(self) -> Iface {
+        self.iface
+    }
+}
+
+impl TypedIface<WithPi> {
+    /// Receives a packet and splits off the packet info header.
+    ///
+    /// Unlike [`Iface::recv_parsed`](../struct.Iface.html#method.recv_parsed), the packet info
+    /// is always there.
+    ///
+    /// # Errors
+    ///
+    /// As with [`Iface::recv_parsed`](../struct.Iface.html#method.recv_parsed).
+    pub fn recv_parsed<'b>(&self, buf: &'b mut [u8]) -> Result<(PacketInfo, &'b [u8])> {
+        let (info, packet) = self.iface.recv_parsed(buf)?;
+        let info = info.expect("Packet info checked when created");
+        Ok((info, packet))
+    }
+    /// Sends a packet of the given protocol, prepending the packet info.
+    ///
+    /// See [`Iface::send_with_proto`](../struct.Iface.html#method.send_with_proto).
+    pub fn send_with_proto(&self, proto: EtherType, payload: &[u8]) -> Result<usize> {
+        self.iface.send_with_proto(proto, payload)
+    }
+}
+
+impl<P: PacketInfoMarker> From<TypedIface<P>> for Iface {
+    fn from(iface: TypedIface<P>) -> Self {
+        iface.into_inner()
+    }
+}
+
+impl<P: PacketInfoMarker> AsRawFd for TypedIface<P> {
+    fn as_raw_fd(&self) -> RawFd {
+        self.iface.as_raw_fd()
+    }
+}
+
+impl<P: PacketInfoMarker> AsFd for TypedIface<P> {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.iface.as_fd()
+    }
+}
+
+impl<P: PacketInfoMarker> Device for TypedIface<P> {
+    fn name(&self) -> &str {
+        self.iface.name()
+    }
+    fn mode(&self) -> Mode {
+        self.iface.mode()
+    }
+    fn packet_info(&self) -> bool {
+        P::PACKET_INFO
+    }
+    fn mtu(&self) -> Result<usize> {
+        self.iface.mtu()
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.iface.recv(buf)
+    }
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.iface.send(buf)
+    }
+    fn set_non_blocking(&self) -> Result<()> {
+        self.iface.set_non_blocking()
+    }
+    fn shutdown_fd(&self) -> Option<BorrowedFd<'_>> {
+        Device::shutdown_fd(&self.iface)
+    }
+}
+
+/// An interface in the TUN mode.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::typed::TunIface;
+/// let iface = TunIface::new("mytun").expect("Failed to create a TUN device");
+/// let mut buffer = vec![0; 1504];
+/// iface.recv(&mut buffer).unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TunIface {
+    iface: Iface,
+}
+
+impl TunIface {
+    /// Creates a new TUN device, with the packet info.
+    ///
+    /// # Errors
+    ///
+    /// As with [`Iface::new`](../struct.Iface.html#method.new).
+    pub fn new(ifname: &str) -> std::result::Result<Self, Error> {
+        Iface::new(ifname, Mode::Tun).map(|iface| TunIface { iface })
+    }
+    /// Creates a new TUN device, without the packet info.
+    ///
+    /// # Errors
+    ///
+    /// As with [`Iface::new`](../struct.Iface.html#method.new).
+    pub fn without_packet_info(ifname: &str) -> std::result::Result<Self, Error> {
+        Iface::without_packet_info(ifname, Mode::Tun).map(|iface| TunIface { iface })
+    }
+    /// Wraps an already created interface.
+    ///
+    /// # Errors
+    ///
+    /// If the interface is not in the TUN mode, it is returned back.
+    pub fn from_iface(iface: Iface) -> std::result::Result<Self, Iface> {
+        match iface.mode() {
+            Mode::Tun => Ok(TunIface { iface }),
+            Mode::Tap => Err(iface),
+        }
+    }
+    /// Returns the real name of the adapter.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+    /// Returns if the packets are prepended with the packet info.
+    pub fn packet_info(&self) -> bool {
+        self.iface.packet_info()
+    }
+    /// Receives a packet from the interface.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.iface.recv(buf)
+    }
+    /// Sends a packet into the interface.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.iface.send(buf)
+    }
+    /// Sets the link type the device reports, like `ARPHRD_NONE` (the default for TUN devices).
+    ///
+    /// This changes how the device looks to the rest of the system (eg. to packet sniffers), not
+    /// what is passed through it. The device must be down.
+    ///
+    /// # Errors
+    ///
+    /// If the process lacks the `CAP_NET_ADMIN` capability or the device is up (`EBUSY`).
+    pub fn set_link_type(&self, link: u16) -> Result<()> {
+        let result = unsafe { tuntap_set_link(self.iface.as_raw_fd(), c_uint::from(link)) };
+        self.iface.check_set(result, "TUNSETLINK")
+    }
+    /// Creates a codec for the packets of this device.
+    ///
+    /// See [`TunPacketCodec`](../codec/struct.TunPacketCodec.html).
+    #[cfg(feature = "tokio")]
+    pub fn codec(&self) -> TunPacketCodec {
+        TunPacketCodec::new(self.packet_info(), Mode::Tun)
+    }
+    /// Accesses the wrapped interface.
+    pub fn get_ref(&self) -> &Iface {
+        &self.iface
+    }
+    /// Accesses the wrapped interface.
+    pub fn get_mut(&mut self) -> &mut Iface {
+        &mut self.iface
+    }
+    /// Unwraps the interface.
+    pub fn into_inner(self) -> Iface {
+        self.iface
+    }
+}
+
+/// An interface in the TAP mode.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use tun_tap::packet::MacAddr;
+/// # use tun_tap::typed::TapIface;
+/// let iface = TapIface::without_packet_info("mytap").expect("Failed to create a TAP device");
+/// iface.set_mac_address(MacAddr([2, 0, 0, 0, 0, 1])).unwrap();
+/// iface.attach_to_bridge("br0").unwrap();
+/// ```
+#[derive(Debug)]
+pub struct TapIface {
+    iface: Iface,
+}
+
+impl TapIface {
+    /// Creates a new TAP device, with the packet info.
+    ///
+    /// # Errors
+    ///
+    /// As with [`Iface::new`](../struct.Iface.html#method.new).
+    pub fn new(ifname: &str) -> std::result::Result<Self, Error> {
+        Iface::new(ifname, Mode::Tap).map(|iface| TapIface { iface })
+    }
+    /// Creates a new TAP device, without the packet info.
+    ///
+    /// # Errors
+    ///
+    /// As with [`Iface::new`](../struct.Iface.html#method.new).
+    pub fn without_packet_info(ifname: &str) -> std::result::Result<Self, Error> {
+        Iface::without_packet_info(ifname, Mode::Tap).map(|iface| TapIface { iface })
+    }
+    /// Wraps an already created interface.
+    ///
+    /// # Errors
+    ///
+    /// If the interface is not in the TAP mode, it is returned back.
+    pub fn from_iface(iface: Iface) -> std::result::Result<Self, Iface> {
+        match iface.mode() {
+            Mode::Tap => Ok(TapIface { iface }),
+            Mode::Tun => Err(iface),
+        }
+    }
+    /// Returns the real name of the adapter.
+    pub fn name(&self) -> &str {
+        self.iface.name()
+    }
+    /// Returns if the frames are prepended with the packet info.
+    pub fn packet_info(&self) -> bool {
+        self.iface.packet_info()
+    }
+    /// Receives a frame from the interface.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.iface.recv(buf)
+    }
+    /// Sends a frame into the interface.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send).
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.iface.send(buf)
+    }
+    /// Returns the MAC address of the kernel side of the device.
+    ///
+    /// # Errors
+    ///
+    /// This fails with an error in case of low-level OS errors (they shouldn't usually happen).
+    pub fn mac_address(&self) -> Result<MacAddr> {
+        let name = CString::new(self.name()).map_err(io::Error::other)?;
+        let mut address = MacAddr::default();
+        let result = unsafe { tuntap_get_hwaddr(name.as_ptr(), address.0.as_mut_ptr()) };
+        if result < 0 {
+            let error = io::Error::last_os_error();
+            error!("SIOCGIFHWADDR on {} failed: {}", self.name(), error);
+            Err(error)
+        } else {
+            Ok(address)
+        }
+    }
+    /// Sets the MAC address of the kernel side of the device, like `ip link set address`.
+    ///
+    /// # Errors
+    ///
+    /// If the process lacks the `CAP_NET_ADMIN` capability or the address is not a valid unicast
+    /// one.
+    pub fn set_mac_address(&self, address: MacAddr) -> Result<()> {
+        let name = CString::new(self.name()).map_err(io::Error::other)?;
+        let result = unsafe { tuntap_set_hwaddr(name.as_ptr(), address.0.as_ptr()) };
+        self.iface.check_set(result, "SIOCSIFHWADDR")
+    }
+    /// Adds the device to a bridge, like `ip link set master`.
+    ///
+    /// # Errors
+    ///
+    /// If the process lacks the `CAP_NET_ADMIN` capability or there's no such bridge.
+    pub fn attach_to_bridge(&self, bridge: &str) -> Result<()> {
+        self.set_bridge(bridge, true)
+    }
+    /// Removes the device from a bridge, like `ip link set nomaster`.
+    ///
+    /// # Errors
+    ///
+    /// If the process lacks the `CAP_NET_ADMIN` capability or the device is not in the bridge.
+    pub fn detach_from_bridge(&self, bridge: &str) -> Result<()> {
+        self.set_bridge(bridge, false)
+    }
+    fn set_bridge(&self, bridge: &str, add: bool) -> Result<()> {
+        let bridge = CString::new(bridge).map_err(io::Error::other)?;
+        let index = c_int::try_from(self.iface.index()?).map_err(io::Error::other)?;
+        let result = unsafe { tuntap_set_bridge(bridge.as_ptr(), index, c_int::from(add)) };
+        let what = if add { "SIOCBRADDIF" } else { "SIOCBRDELIF" };
+        self.iface.check_set(result, what)
+    }
+    /// Creates a codec for the frames of this device.
+    ///
+    /// See [`EthernetFrameCodec`](../codec/struct.EthernetFrameCodec.html).
+    #[cfg(feature = "tokio")]
+    pub fn codec(&self) -> EthernetFrameCodec {
+        EthernetFrameCodec::new(self.packet_info())
+    }
+    /// Accesses the wrapped interface.
+    pub fn get_ref(&self) -> &Iface {
+        &self.iface
+    }
+    /// Accesses the wrapped interface.
+    pub fn get_mut(&mut self) -> &mut Iface {
+        &mut self.iface
+    }
+    /// Unwraps the interface.
+    pub fn into_inner(self) -> Iface {
+        self.iface
+    }
+}
+
+impl From<TunIface> for Iface {
+    fn from(iface: TunIface) -> Self {
+        iface.into_inner()
+    }
+}
+
+impl AsRawFd for TunIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.iface.as_raw_fd()
+    }
+}
+
+impl AsFd for TunIface {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.iface.as_fd()
+    }
+}
+
+impl Device for TunIface {
+    fn name(&self) -> &str {
+        self.iface.name()
+    }
+    fn mode(&self) -> Mode {
+        self.iface.mode()
+    }
+    fn packet_info(&self) -> bool {
+        self.iface.packet_info()
+    }
+    fn mtu(&self) -> Result<usize> {
+        self.iface.mtu()
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.iface.recv(buf)
+    }
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.iface.send(buf)
+    }
+    fn set_non_blocking(&self) -> Result<()> {
+        self.iface.set_non_blocking()
+    }
+    fn shutdown_fd(&self) -> Option<BorrowedFd<'_>> {
+        Device::shutdown_fd(&self.iface)
+    }
+}
+
+impl From<TapIface> for Iface {
+    fn from(iface: TapIface) -> Self {
+        iface.into_inner()
+    }
+}
+
+impl AsRawFd for TapIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.iface.as_raw_fd()
+    }
+}
+
+impl AsFd for TapIface {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.iface.as_fd()
+    }
+}
+
+impl Device for TapIface {
+    fn name(&self) -> &str {
+        self.iface.name()
+    }
+    fn mode(&self) -> Mode {
+        self.iface.mode()
+    }
+    fn packet_info(&self) -> bool {
+        self.iface.packet_info()
+    }
+    fn mtu(&self) -> Result<usize> {
+        self.iface.mtu()
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        self.iface.recv(buf)
+    }
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        self.iface.send(buf)
+    }
+    fn set_non_blocking(&self) -> Result<()> {
+        self.iface.set_non_blocking()
+    }
+    fn shutdown_fd(&self) -> Option<BorrowedFd<'_>> {
+        Device::shutdown_fd(&self.iface)
+    }
+}
@@ -0,0 +1,283 @@
+//! Moving the datapath into the kernel by vhost-net.
+//!
+//! A virtual machine monitor (or a userspace switch talking virtio) would normally copy every
+//! packet between the TAP device and the virtqueues shared with the guest. The vhost-net kernel
+//! driver can do that on its own, without a trip to the userspace. The
+//! [`VhostNet`](struct.VhostNet.html) is a thin wrapper around its `/dev/vhost-net` interface ‒
+//! the application describes the guest memory and the virtqueues, hands it the device and the
+//! kernel takes it from there. The device itself is still created, configured and closed through
+//! this crate.
+//!
+//! The device needs the [`VNET_HDR`](../struct.TunFlags.html#associatedconstant.VNET_HDR) flag
+//! and the size of the header matching the negotiated features (see
+//! [`set_vnet_header_size`](fn.set_vnet_header_size.html)). Setting up the virtqueues is the
+//! business of the application (see the virtio specification), this only passes the addresses
+//! to the kernel.
+//!
+//! This needs the `vhost` feature and access to `/dev/vhost-net`.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use tun_tap::*;
+//! # use tun_tap::vhost::{self, MemoryRegion, VhostNet};
+//! let iface = Iface::with_flags("vm%d", TunFlags::TAP | TunFlags::NO_PI | TunFlags::VNET_HDR)
+//!     .expect("Failed to create the device");
+//! let vhost = VhostNet::new().expect("No vhost-net");
+//! let features = vhost.features().unwrap() & VhostNet::F_MRG_RXBUF;
+//! vhost.set_features(features).unwrap();
+//! vhost::set_vnet_header_size(&iface, 12).unwrap();
+//! // The guest memory, used by nothing else in this process (leaked, so it's never unmapped)
+//! let memory = Box::leak(vec![0u8; 1 << 20].into_boxed_slice());
+//! let region = MemoryRegion {
+//!     guest_address: 0,
+//!     size: memory.len() as u64,
+//!     user_address: memory.as_mut_ptr() as u64,
+//! };
+//! // Safety: the memory stays mapped and the kernel is the only one accessing it
+//! unsafe { vhost.set_mem_table(&[region]).unwrap() };
+//! // Describe the virtqueues (inside the memory) here, by set_vring_*
+//! for queue in &[VhostNet::RX, VhostNet::TX] {
+//!     vhost.set_backend(*queue, &iface).unwrap();
+//! }
+//! ```
+
+use std::fs::{File, OpenOptions};
+use std::io::{Error, Result};
+use std::os::raw::{c_int, c_uint};
+use std::os::unix::fs::OpenOptionsExt;
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, RawFd};
+
+extern "C" {
+    fn tuntap_set_vnet_hdr_size(fd: c_int, size: c_int) -> c_int;
+    fn tuntap_vhost_set_owner(fd: c_int) -> c_int;
+    fn tuntap_vhost_get_features(fd: c_int, features: *mut u64) -> c_int;
+    fn tuntap_vhost_set_features(fd: c_int, features: u64) -> c_int;
+    fn tuntap_vhost_set_mem_table(fd: c_int, regions: *const u64, count: c_uint) -> c_int;
+    fn tuntap_vhost_set_vring_num(fd: c_int, index: c_uint, num: c_uint) -> c_int;
+    fn tuntap_vhost_set_vring_base(fd: c_int, index: c_uint, base: c_uint) -> c_int;
+    fn tuntap_vhost_get_vring_base(fd: c_int, index: c_uint, base: *mut c_uint) -> c_int;
+    fn tuntap_vhost_set_vring_addr(
+        fd: c_int,
+        index: c_uint,
+        flags: c_uint,
+        desc: u64,
+        used: u64,
+        avail: u64,
+        log: u64,
+    ) -> c_int;
+    fn tuntap_vhost_set_vring_file(fd: c_int, which: c_int, index: c_uint, file: c_int) -> c_int;
+}
+
+const DEVICE: &str = "/dev/vhost-net";
+
+// The `which` of tuntap_vhost_set_vring_file
+const KICK: c_int = 0;
+const CALL: c_int = 1;
+const ERR: c_int = 2;
+const BACKEND: c_int = 3;
+
+fn check(result: c_int) -> Result<()> {
+    if result < 0 {
+        Err(Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+/// Sets the size of the virtio net header the device puts in front of each packet.
+///
+/// It is 10 bytes by default, but the header grows to 12 bytes with the
+/// [`F_MRG_RXBUF`](struct.VhostNet.html#associatedconstant.F_MRG_RXBUF) or
+/// [`F_VERSION_1`](struct.VhostNet.html#associatedconstant.F_VERSION_1) features. Only useful
+/// with the [`VNET_HDR`](../struct.TunFlags.html#associatedconstant.VNET_HDR) flag.
+pub fn set_vnet_header_size<D: AsFd>(device: &D, size: usize) -> Result<()> {
+    let fd = device.as_fd().as_raw_fd();
+    check(unsafe { tuntap_set_vnet_hdr_size(fd, size as c_int) })
+}
+
+/// A region of the guest memory, mapped into the process.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct MemoryRegion {
+    /// The address of the region as seen by the guest.
+    pub guest_address: u64,
+    /// The size of the region, in bytes.
+    pub size: u64,
+    /// The address of the region in this process.
+    pub user_address: u64,
+}
+
+/// The location of a virtqueue, in the address space of this process.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct VringAddresses {
+    /// The descriptor table.
+    pub descriptors: u64,
+    /// The used ring.
+    pub used: u64,
+    /// The available ring.
+    pub available: u64,
+    /// The guest address of the used ring, to log the writes into it (for live migration).
+    ///
+    /// The logging is off if not set.
+    pub log: Option<u64>,
+}
+
+/// A handle to the vhost-net kernel driver.
+///
+/// Each one serves a single virtio network device with its two virtqueues,
+/// [`RX`](#associatedconstant.RX) and [`TX`](#associatedconstant.TX). The methods are the
+/// `VHOST_*` ioctls, see the kernel documentation for the details of their use. They fail with
+/// the error of the ioctl.
+///
+/// The driver works on behalf of the process that created the handle and stops once the handle
+/// is closed.
+#[derive(Debug)]
+pub struct VhostNet {
+    file: File,
+}
+
+impl VhostNet {
+    /// The virtqueue of the packets going into the guest.
+    pub const RX: u32 = 0;
+    /// The virtqueue of the packets coming from the guest.
+    pub const TX: u32 = 1;
+    /// The feature of merging the receive buffers (`VIRTIO_NET_F_MRG_RXBUF`).
+    pub const F_MRG_RXBUF: u64 = 1 << 15;
+    /// The feature of notifying when the queue runs empty (`VIRTIO_F_NOTIFY_ON_EMPTY`).
+    pub const F_NOTIFY_ON_EMPTY: u64 = 1 << 24;
+    /// The feature of logging the writes into the guest memory (`VHOST_F_LOG_ALL`).
+    pub const F_LOG_ALL: u64 = 1 << 26;
+    /// The feature of vhost adding the virtio net header itself (`VHOST_NET_F_VIRTIO_NET_HDR`).
+    ///
+    /// Used with devices without the
+    /// [`VNET_HDR`](../struct.TunFlags.html#associatedconstant.VNET_HDR) flag.
+    pub const F_VIRTIO_NET_HDR: u64 = 1 << 27;
+    /// The feature of indirect descriptors (`VIRTIO_RING_F_INDIRECT_DESC`).
+    pub const F_INDIRECT_DESC: u64 = 1 << 28;
+    /// The feature of the event index (`VIRTIO_RING_F_EVENT_IDX`).
+    pub const F_EVENT_IDX: u64 = 1 << 29;
+    /// The feature of virtio 1.0 (`VIRTIO_F_VERSION_1`).
+    pub const F_VERSION_1: u64 = 1 << 32;
+
+    /// Opens the driver and takes the ownership of it.
+    ///
+    /// # Errors
+    ///
+    /// If `/dev/vhost-net` doesn't exist (the `vhost_net` module is not loaded) or it can't be
+    /// opened.
+    pub fn new() -> Result<Self> {
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_CLOEXEC)
+            .open(DEVICE)?;
+        check(unsafe { tuntap_vhost_set_owner(file.as_raw_fd()) })?;
+        debug!("Opened {}", DEVICE);
+        Ok(VhostNet { file })
+    }
+    /// Returns the features supported by the driver.
+    pub fn features(&self) -> Result<u64> {
+        let mut features = 0;
+        check(unsafe { tuntap_vhost_get_features(self.as_raw_fd(), &mut features) })?;
+        Ok(features)
+    }
+    /// Sets the features to use (those negotiated with the guest).
+    pub fn set_features(&self, features: u64) -> Result<()> {
+        check(unsafe { tuntap_vhost_set_features(self.as_raw_fd(), features) })
+    }
+    /// Describes the memory of the guest.
+    ///
+    /// All the addresses in the virtqueues are guest addresses translated through these regions.
+    ///
+    /// # Safety
+    ///
+    /// The kernel reads and writes the regions on its own, behind the back of the compiler. They
+    /// must stay mapped and must not be accessed by this process (other than through the virtio
+    /// protocol) until the backends of all the virtqueues are detached by
+    /// [`detach_backend`](#method.detach_backend) or the handle is dropped.
+    pub unsafe fn set_mem_table(&self, regions: &[MemoryRegion]) -> Result<()> {
+        let raw = regions
+            .iter()
+            .flat_map(|region| [region.guest_address, region.size, region.user_address])
+            .collect::<Vec<_>>();
+        let count = regions.len() as c_uint;
+        check(unsafe { tuntap_vhost_set_mem_table(self.as_raw_fd(), raw.as_ptr(), count) })
+    }
+    /// Sets the number of descriptors in the virtqueue.
+    pub fn set_vring_num(&self, queue: u32, num: u32) -> Result<()> {
+        check(unsafe { tuntap_vhost_set_vring_num(self.as_raw_fd(), queue, num) })
+    }
+    /// Sets the index of the next available descriptor the driver processes.
+    pub fn set_vring_base(&self, queue: u32, base: u32) -> Result<()> {
+        check(unsafe { tuntap_vhost_set_vring_base(self.as_raw_fd(), queue, base) })
+    }
+    /// Stops the virtqueue and returns the index of the next available descriptor.
+    pub fn vring_base(&self, queue: u32) -> Result<u32> {
+        let mut base = 0;
+        check(unsafe { tuntap_vhost_get_vring_base(self.as_raw_fd(), queue, &mut base) })?;
+        Ok(base)
+    }
+    /// Sets the location of the virtqueue.
+    ///
+    /// # Safety
+    ///
+    /// The addresses must point to a valid layout of the virtqueue (of the size set by
+    /// [`set_vring_num`](#method.set_vring_num)), which the kernel reads and writes. The same
+    /// as with [`set_mem_table`](#method.set_mem_table), the memory must stay mapped and
+    /// exclusive to the kernel until the backend of the virtqueue is detached by
+    /// [`detach_backend`](#method.detach_backend) or the handle is dropped.
+    pub unsafe fn set_vring_addr(&self, queue: u32, addresses: &VringAddresses) -> Result<()> {
+        // VHOST_VRING_F_LOG
+        let flags = if addresses.log.is_some() { 1 } else { 0 };
+        let result = unsafe {
+            tuntap_vhost_set_vring_addr(
+                self.as_raw_fd(),
+                queue,
+                flags,
+                addresses.descriptors,
+                addresses.used,
+                addresses.available,
+                addresses.log.unwrap_or(0),
+            )
+        };
+        check(result)
+    }
+    fn set_vring_file(&self, which: c_int, queue: u32, file: RawFd) -> Result<()> {
+        check(unsafe { tuntap_vhost_set_vring_file(self.as_raw_fd(), which, queue, file) })
+    }
+    /// Sets the eventfd signalled by the guest when it adds buffers to the virtqueue.
+    pub fn set_vring_kick<E: AsFd>(&self, queue: u32, eventfd: &E) -> Result<()> {
+        self.set_vring_file(KICK, queue, eventfd.as_fd().as_raw_fd())
+    }
+    /// Sets the eventfd the driver signals when it used buffers of the virtqueue.
+    pub fn set_vring_call<E: AsFd>(&self, queue: u32, eventfd: &E) -> Result<()> {
+        self.set_vring_file(CALL, queue, eventfd.as_fd().as_raw_fd())
+    }
+    /// Sets the eventfd the driver signals on errors of the virtqueue.
+    pub fn set_vring_err<E: AsFd>(&self, queue: u32, eventfd: &E) -> Result<()> {
+        self.set_vring_file(ERR, queue, eventfd.as_fd().as_raw_fd())
+    }
+    /// Hands the device to the driver, to serve the virtqueue.
+    ///
+    /// This starts the forwarding (once the virtqueue is set up). The driver keeps its own
+    /// reference to the device, but the application shouldn't read from it in the meantime.
+    pub fn set_backend<D: AsFd>(&self, queue: u32, device: &D) -> Result<()> {
+        self.set_vring_file(BACKEND, queue, device.as_fd().as_raw_fd())
+    }
+    /// Stops serving the virtqueue and releases the device.
+    pub fn detach_backend(&self, queue: u32) -> Result<()> {
+        self.set_vring_file(BACKEND, queue, -1)
+    }
+}
+
+impl AsRawFd for VhostNet {
+    fn as_raw_fd(&self) -> RawFd {
+        self.file.as_raw_fd()
+    }
+}
+
+impl AsFd for VhostNet {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.file.as_fd()
+    }
+}
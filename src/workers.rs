@@ -0,0 +1,317 @@
+//! Processing the packets of a multi-queue device by a pool of threads.
+//!
+//! A multi-queue device has several file descriptors (queues) and the kernel spreads the packets
+//! between them (by the flow hash, unless a steering program is installed). Having a thread per
+//! queue, each reading and processing its own packets, is the recommended way to scale to
+//! multiple CPUs. The [`WorkerPool`](struct.WorkerPool.html) packages this up.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use tun_tap::*;
+//! # use tun_tap::workers::WorkerPoolBuilder;
+//! let pool = WorkerPoolBuilder::new("mytun", TunFlags::TUN | TunFlags::NO_PI)
+//!     .queues(4)
+//!     .spawn(|queue: &Iface, packet: &[u8]| {
+//!         // Reflect the packet back, through the same queue
+//!         let _ = queue.send(packet);
+//!     })
+//!     .expect("Failed to create the workers");
+//! println!("Running on {} with {} queues", pool.name(), pool.queues());
+//! // Configure the device here
+//! // And once done
+//! pool.shutdown();
+//! ```
+//!
+//! Moving the threads between CPUs costs throughput, so they can be pinned with
+//! [`WorkerPoolBuilder::cpus`](struct.WorkerPoolBuilder.html#method.cpus). Threads reading the
+//! queues by other means can use [`pin_to_cpu`](fn.pin_to_cpu.html).
+
+use std::io;
+use std::mem;
+use std::os::unix::io::RawFd;
+use std::cell::Cell;
+use std::os::unix::thread::JoinHandleExt;
+use std::sync::Arc;
+use std::thread::{self, JoinHandle, ThreadId};
+
+use crate::{device, Error, Iface, ShutdownHandle, TunFlags};
+
+type Callback = dyn Fn(&Iface, &[u8]) + Send + Sync;
+
+thread_local! {
+    static QUEUE: Cell<Option<usize>> = const { Cell::new(None) };
+}
+
+/// Configuration of a [`WorkerPool`](struct.WorkerPool.html).
+#[derive(Clone, Debug)]
+pub struct WorkerPoolBuilder {
+    ifname: String,
+    flags: TunFlags,
+    queues: usize,
+    steering: Option<RawFd>,
+    cpus: Vec<usize>,
+}
+
+impl WorkerPoolBuilder {
+    /// Starts configuring a pool over a new device.
+    ///
+    /// The parameters are the same as with
+    /// [`Iface::with_flags`](../struct.Iface.html#method.with_flags), the
+    /// [`MULTI_QUEUE`](../struct.TunFlags.html#associatedconstant.MULTI_QUEUE) flag is added
+    /// automatically. By default, there's a queue for each available CPU.
+    pub fn new(ifname: &str, flags: TunFlags) -> Self {
+        let queues = thread::available_parallelism()
+            .map(|n| n.get())
+            .unwrap_or(1);
+        WorkerPoolBuilder {
+            ifname: ifname.to_owned(),
+            flags: flags | TunFlags::MULTI_QUEUE,
+            queues,
+            steering: None,
+            cpus: Vec::new(),
+        }
+    }
+    /// Sets the number of queues (and threads).
+    ///
+    /// # Panics
+    ///
+    /// If the `queues` is 0.
+    pub fn queues(self, queues: usize) -> Self {
+        assert!(queues > 0, "The pool needs at least one queue");
+        WorkerPoolBuilder { queues, ..self }
+    }
+    /// Installs an eBPF program choosing the queue for each packet.
+    ///
+    /// See [`Iface::set_steering_ebpf`](../struct.Iface.html#method.set_steering_ebpf).
+    pub fn steering_ebpf(self, prog: RawFd) -> Self {
+        WorkerPoolBuilder {
+            steering: Some(prog),
+            ..self
+        }
+    }
+    /// Pins the threads to the given CPUs.
+    ///
+    /// The thread of the n-th queue runs on the n-th CPU of the list. If there are more queues
+    /// than CPUs, the list is repeated. The threads aren't pinned with an empty list, which is
+    /// the default.
+    ///
+    /// For the best results, the packets should arrive to the queue read on the same CPU, which
+    /// can be arranged by a [steering program](#method.steering_ebpf) or by tuning the interrupt
+    /// affinity of the network card the traffic comes from.
+    pub fn cpus<I: IntoIterator<Item = usize>>(self, cpus: I) -> Self {
+        WorkerPoolBuilder {
+            cpus: cpus.into_iter().collect(),
+            ..self
+        }
+    }
+    /// Creates the device and starts the threads.
+    ///
+    /// The `callback` is called from the thread of the queue for each received packet, with the
+    /// queue it came through (so a reply can be sent through the same one). Packets are received
+    /// into buffers sized by the MTU at the time of the start.
+    ///
+    /// # Errors
+    ///
+    /// The errors of [`Iface::with_flags`](../struct.Iface.html#method.with_flags) (for any of
+    /// the queues) and failures to install the steering program, start the threads or pin them
+    /// (as the [`Io`](../enum.Error.html#variant.Io) error). The already started threads are shut
+    /// down in such case.
+    pub fn spawn<F>(self, callback: F) -> Result<WorkerPool, Error>
+    where
+        F: Fn(&Iface, &[u8]) + Send + Sync + 'static,
+    {
+        let callback: Arc<Callback> = Arc::new(callback);
+        let first = Iface::with_flags(&self.ifname, self.flags)?;
+        if let Some(prog) = self.steering {
+            first.set_steering_ebpf(Some(prog))?;
+        }
+        let mut pool = WorkerPool {
+            name: first.name().to_owned(),
+            workers: Vec::with_capacity(self.queues),
+        };
+        let cpus = &self.cpus;
+        let cpu = |i: usize| cpus.get(i.checked_rem(cpus.len())?).cloned();
+        pool.start(first, &callback, cpu(0))?;
+        for i in 1..self.queues {
+            // The name is fixed now, so the others attach to the same device.
+            let queue = Iface::with_flags(&pool.name, self.flags)?;
+            pool.start(queue, &callback, cpu(i))?;
+        }
+        debug!("Started {} workers on {}", self.queues, pool.name);
+        Ok(pool)
+    }
+}
+
+/// Pins the current thread to a single CPU.
+///
+/// This is what [`WorkerPoolBuilder::cpus`](struct.WorkerPoolBuilder.html#method.cpus) does to
+/// its threads, for threads reading the queues created by other means.
+///
+/// # Errors
+///
+/// If the CPU doesn't exist or the thread isn't allowed to run on it.
+///
+/// # Examples
+///
+/// ```rust,no_run
+/// # use std::thread;
+/// # use tun_tap::*;
+/// # use tun_tap::workers::pin_to_cpu;
+/// let queue = Iface::with_flags("mytun", TunFlags::TUN | TunFlags::MULTI_QUEUE)
+///     .expect("Failed to create a TUN device");
+/// thread::spawn(move || {
+///     pin_to_cpu(1).expect("Failed to pin the thread");
+///     let mut buffer = vec![0; 1504];
+///     while let Ok(size) = queue.recv(&mut buffer) {
+///         // Process the packet
+///     }
+/// });
+/// ```
+pub fn pin_to_cpu(cpu: usize) -> io::Result<()> {
+    set_affinity(unsafe { libc::pthread_self() }, cpu)
+}
+
+fn set_affinity(thread: libc::pthread_t, cpu: usize) -> io::Result<()> {
+    // CPU_SET would overflow the set (panic, actually) on too large numbers
+    if cpu >= libc::CPU_SETSIZE as usize {
+        return Err(io::Error::from_raw_os_error(libc::EINVAL));
+    }
+    let result = unsafe {
+        let mut set: libc::cpu_set_t = mem::zeroed();
+        libc::CPU_SET(cpu, &mut set);
+        libc::pthread_setaffinity_np(thread, mem::size_of::<libc::cpu_set_t>(), &set)
+    };
+    // Returns the error instead of setting errno
+    match result {
+        0 => Ok(()),
+        errno => Err(io::Error::from_raw_os_error(errno)),
+    }
+}
+
+/// Returns the index of the queue served by the current thread.
+///
+/// Called from a callback of a [`WorkerPool`](struct.WorkerPool.html), this tells which queue
+/// the packet came through (the indices go from 0 to the number of queues). Returns `None` in
+/// other threads.
+pub fn current_queue() -> Option<usize> {
+    QUEUE.with(Cell::get)
+}
+
+fn work(index: usize, queue: &Iface, callback: &Callback) {
+    QUEUE.with(|current| current.set(Some(index)));
+    // An error is already logged by the recv
+    let _ = device::pump(queue, None, |packet| {
+        callback(queue, packet);
+        true
+    });
+}
+
+#[derive(Debug)]
+struct Worker {
+    shutdown: ShutdownHandle,
+    thread: JoinHandle<()>,
+    cpu: Option<usize>,
+}
+
+/// Information about a queue of a [`WorkerPool`](struct.WorkerPool.html).
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+#[non_exhaustive]
+pub struct QueueInfo {
+    /// The index of the queue, as returned by [`current_queue`](fn.current_queue.html).
+    pub index: usize,
+    /// The thread serving the queue.
+    pub thread: ThreadId,
+    /// The CPU the thread is pinned to, if any.
+    pub cpu: Option<usize>,
+    /// If the thread is still running (it terminates on an error of the queue).
+    pub running: bool,
+}
+
+/// Threads processing the packets of a multi-queue device, one per queue.
+///
+/// Created by [`WorkerPoolBuilder::spawn`](struct.WorkerPoolBuilder.html#method.spawn). The
+/// device lives until all the threads terminate. Dropping the pool tells them to terminate, but
+/// doesn't wait for them, [`shutdown`](#method.shutdown) does.
+///
+/// A thread terminates on an error of its queue (it is logged), the others keep running.
+#[derive(Debug)]
+pub struct WorkerPool {
+    name: String,
+    workers: Vec<Worker>,
+}
+
+impl WorkerPool {
+    fn start(
+        &mut self,
+        queue: Iface,
+        callback: &Arc<Callback>,
+        cpu: Option<usize>,
+    ) -> Result<(), Error> {
+        let shutdown = queue.shutdown_handle()?;
+        let callback = Arc::clone(callback);
+        let index = self.workers.len();
+        let thread = thread::Builder::new()
+            .name(format!("{}-q{}", self.name, index))
+            .spawn(move || work(index, &queue, &*callback))?;
+        let pthread = thread.as_pthread_t();
+        // Pushed first, so the thread gets terminated if pinning fails
+        self.workers.push(Worker {
+            shutdown,
+            thread,
+            cpu,
+        });
+        if let Some(cpu) = cpu {
+            if let Err(e) = set_affinity(pthread, cpu) {
+                error!("Failed to pin the worker of {} to CPU {}: {}", self.name, cpu, e);
+                return Err(e.into());
+            }
+        }
+        Ok(())
+    }
+    /// Returns the name of the device.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Returns the number of queues (and threads).
+    pub fn queues(&self) -> usize {
+        self.workers.len()
+    }
+    /// Describes the queues, ordered by their index.
+    pub fn queue_info(&self) -> Vec<QueueInfo> {
+        self.workers
+            .iter()
+            .enumerate()
+            .map(|(index, worker)| QueueInfo {
+                index,
+                thread: worker.thread.thread().id(),
+                cpu: worker.cpu,
+                running: !worker.thread.is_finished(),
+            })
+            .collect()
+    }
+    /// Terminates the threads and waits for them.
+    ///
+    /// A packet being processed is finished first.
+    ///
+    /// # Panics
+    ///
+    /// If any of the callbacks panicked.
+    pub fn shutdown(mut self) {
+        let workers = std::mem::take(&mut self.workers);
+        for worker in &workers {
+            let _ = worker.shutdown.shutdown();
+        }
+        for worker in workers {
+            worker.thread.join().expect("Worker thread panicked");
+        }
+    }
+}
+
+impl Drop for WorkerPool {
+    fn drop(&mut self) {
+        for worker in &self.workers {
+            let _ = worker.shutdown.shutdown();
+        }
+    }
+}
@@ -0,0 +1,673 @@
+//! Receiving and sending through AF_XDP sockets (experimental).
+//!
+//! The TUN/TAP devices are convenient, but each packet costs a syscall and a copy or two. Once a
+//! prototype needs to process the traffic of a real network card at the line rate, the AF_XDP
+//! sockets are the way to go on Linux. The packets are exchanged with the kernel (or directly with
+//! the driver, in the zero-copy mode) through rings of descriptors pointing into a shared memory
+//! area (the UMEM), without a syscall for each of them.
+//!
+//! This module wraps them into the same [`Device`](../trait.Device.html) trait the TAP devices
+//! implement, so the code written for them (including the [`Async`](../async/struct.Async.html)
+//! wrapper) works on top of an AF_XDP socket too. The frames are copied between the UMEM and the
+//! buffers of the caller, which is the price of the compatible interface (but it is still a lot
+//! cheaper than a syscall per packet).
+//!
+//! Each socket serves a single queue of the interface. The packets are steered into the sockets
+//! by an XDP program, the [`XdpProgram`](struct.XdpProgram.html) attaches a minimal one (the
+//! packets of queues without a socket go on to the network stack of the kernel as usual).
+//!
+//! This needs the `xdp` feature, a kernel 5.9 or newer and the `CAP_NET_ADMIN`, `CAP_NET_RAW`
+//! and `CAP_BPF` (or `CAP_SYS_ADMIN`) capabilities. It is experimental ‒ the interface may
+//! change and only the basic use is covered.
+//!
+//! # Examples
+//!
+//! ```rust,no_run
+//! # use tun_tap::xdp::{XdpConfig, XdpIface, XdpMode, XdpProgram};
+//! let program = XdpProgram::attach("eth0", 1, XdpMode::Auto).expect("Failed to attach XDP");
+//! let iface = XdpIface::new("eth0", &program, XdpConfig::new()).expect("Failed to open");
+//! let mut buffer = vec![0; 2048];
+//! loop {
+//!     let size = iface.recv(&mut buffer).unwrap();
+//!     // Reflect the frame back
+//!     iface.send(&buffer[..size]).unwrap();
+//! }
+//! ```
+
+use std::ffi::CString;
+use std::io::{self, Error, ErrorKind, Result};
+use std::mem;
+use std::os::raw::{c_char, c_int, c_uint};
+use std::os::unix::io::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::sync::{Mutex, MutexGuard};
+
+use crate::{Device, Mode};
+
+extern "C" {
+    fn tuntap_get_mtu(name: *const c_char) -> c_int;
+    fn tuntap_xsk_map_create(entries: c_uint) -> c_int;
+    fn tuntap_xsk_map_set(map: c_int, queue: c_uint, sock: c_int) -> c_int;
+    fn tuntap_xdp_redirect_prog(map: c_int) -> c_int;
+    fn tuntap_xdp_attach(prog: c_int, ifindex: c_int, flags: c_uint) -> c_int;
+}
+
+// From linux/if_link.h
+const XDP_FLAGS_SKB_MODE: c_uint = 1 << 1;
+const XDP_FLAGS_DRV_MODE: c_uint = 1 << 2;
+
+fn check_fd(result: c_int, what: &str, name: &str) -> Result<OwnedFd> {
+    if result < 0 {
+        let error = Error::last_os_error();
+        error!("{} on {} failed: {}", what, name, error);
+        Err(error)
+    } else {
+        Ok(unsafe { OwnedFd::from_raw_fd(result) })
+    }
+}
+
+fn check(result: c_int, what: &str, name: &str) -> Result<()> {
+    if result < 0 {
+        let error = Error::last_os_error();
+        error!("{} on {} failed: {}", what, name, error);
+        Err(error)
+    } else {
+        Ok(())
+    }
+}
+
+fn index(name: &str) -> Result<c_int> {
+    let c_name = CString::new(name).map_err(|e| Error::new(ErrorKind::InvalidInput, e))?;
+    match unsafe { libc::if_nametoindex(c_name.as_ptr()) } {
+        0 => Err(Error::last_os_error()),
+        index => Ok(index as c_int),
+    }
+}
+
+/// How the XDP program is attached to the interface.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub enum XdpMode {
+    /// The driver mode if the driver supports it, the generic one otherwise.
+    #[default]
+    Auto,
+    /// The generic mode, working with any interface (but slower and without zero-copy).
+    Generic,
+    /// The driver mode, failing if the driver doesn't support XDP.
+    Driver,
+}
+
+/// An XDP program redirecting the packets of an interface into the AF_XDP sockets.
+///
+/// The program is detached once this is dropped (the sockets stop receiving then).
+#[derive(Debug)]
+pub struct XdpProgram {
+    map: OwnedFd,
+    _link: OwnedFd,
+    name: String,
+    ifindex: c_int,
+    queues: u32,
+}
+
+impl XdpProgram {
+    /// Loads the program and attaches it to the interface.
+    ///
+    /// The `queues` is the number of queues to redirect (starting from 0), usually all the
+    /// combined or rx queues of the interface (see `ethtool -l`).
+    ///
+    /// # Errors
+    ///
+    /// If the interface doesn't exist, the kernel refuses the program (eg. for the lack of
+    /// privileges) or another XDP program is already attached.
+    pub fn attach(name: &str, queues: u32, mode: XdpMode) -> Result<Self> {
+        let ifindex = index(name)?;
+        let map = check_fd(unsafe { tuntap_xsk_map_create(queues) }, "BPF_MAP_CREATE", name)?;
+        let prog = unsafe { tuntap_xdp_redirect_prog(map.as_raw_fd()) };
+        let prog = check_fd(prog, "BPF_PROG_LOAD", name)?;
+        let flags = match mode {
+            XdpMode::Auto => 0,
+            XdpMode::Generic => XDP_FLAGS_SKB_MODE,
+            XdpMode::Driver => XDP_FLAGS_DRV_MODE,
+        };
+        let link = unsafe { tuntap_xdp_attach(prog.as_raw_fd(), ifindex, flags) };
+        let link = check_fd(link, "BPF_LINK_CREATE", name)?;
+        debug!("Attached XDP to {} ({:?}, {} queues)", name, mode, queues);
+        Ok(XdpProgram {
+            map,
+            _link: link,
+            name: name.to_owned(),
+            ifindex,
+            queues,
+        })
+    }
+    /// Returns the name of the interface.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Returns the number of the redirected queues.
+    pub fn queues(&self) -> u32 {
+        self.queues
+    }
+    fn register(&self, queue: u32, sock: c_int) -> Result<()> {
+        let result = unsafe { tuntap_xsk_map_set(self.map.as_raw_fd(), queue, sock) };
+        check(result, "BPF_MAP_UPDATE_ELEM", &self.name)
+    }
+}
+
+/// The parameters of an [`XdpIface`](struct.XdpIface.html).
+///
+/// # Examples
+///
+/// ```rust
+/// # use tun_tap::xdp::XdpConfig;
+/// let config = XdpConfig::new().queue(2).ring_size(4096).zero_copy(true);
+/// ```
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct XdpConfig {
+    queue: u32,
+    ring_size: u32,
+    frame_size: u32,
+    zero_copy: Option<bool>,
+}
+
+impl XdpConfig {
+    /// The defaults: queue 0, rings of 2048 descriptors, 4096 bytes per frame and zero-copy if
+    /// possible.
+    pub fn new() -> Self {
+        XdpConfig {
+            queue: 0,
+            ring_size: 2048,
+            frame_size: 4096,
+            zero_copy: None,
+        }
+    }
+    /// Sets the queue of the interface to serve.
+    pub fn queue(self, queue: u32) -> Self {
+        XdpConfig { queue, ..self }
+    }
+    /// Sets the number of descriptors in each ring.
+    ///
+    /// It needs to be a power of two. The UMEM holds twice as many frames, half of them for each
+    /// direction.
+    pub fn ring_size(self, ring_size: u32) -> Self {
+        XdpConfig { ring_size, ..self }
+    }
+    /// Sets the size of a frame in the UMEM, the largest packet that fits.
+    ///
+    /// It needs to be a power of two, 2048 or 4096.
+    pub fn frame_size(self, frame_size: u32) -> Self {
+        XdpConfig { frame_size, ..self }
+    }
+    /// Requires (`true`) or refuses (`false`) the zero-copy mode.
+    ///
+    /// By default, the kernel uses it if the driver supports it.
+    pub fn zero_copy(self, zero_copy: bool) -> Self {
+        XdpConfig {
+            zero_copy: Some(zero_copy),
+            ..self
+        }
+    }
+}
+
+impl Default for XdpConfig {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A memory mapping, unmapped on drop.
+#[derive(Debug)]
+struct Mmap {
+    ptr: *mut u8,
+    len: usize,
+}
+
+// It's only memory, the synchronization is done by the users
+unsafe impl Send for Mmap {}
+unsafe impl Sync for Mmap {}
+
+impl Mmap {
+    fn new(len: usize, fd: Option<(RawFd, libc::off_t)>) -> Result<Self> {
+        let (flags, fd, offset) = match fd {
+            Some((fd, offset)) => (libc::MAP_SHARED | libc::MAP_POPULATE, fd, offset),
+            None => (libc::MAP_PRIVATE | libc::MAP_ANONYMOUS, -1, 0),
+        };
+        let prot = libc::PROT_READ | libc::PROT_WRITE;
+        let ptr = unsafe { libc::mmap(ptr::null_mut(), len, prot, flags, fd, offset) };
+        if ptr == libc::MAP_FAILED {
+            Err(Error::last_os_error())
+        } else {
+            Ok(Mmap {
+                ptr: ptr as *mut u8,
+                len,
+            })
+        }
+    }
+}
+
+impl Drop for Mmap {
+    fn drop(&mut self) {
+        unsafe { libc::munmap(self.ptr as *mut libc::c_void, self.len) };
+    }
+}
+
+/// One of the rings shared with the kernel.
+///
+/// The descriptors are either plain addresses (the fill and completion rings) or the `xdp_desc`
+/// (the rx and tx rings). Each ring has a single producer and a single consumer, one of them is
+/// us (holding the lock of the ring), the other the kernel.
+#[derive(Debug)]
+struct Ring {
+    map: Mmap,
+    offsets: libc::xdp_ring_offset,
+    size: u32,
+}
+
+impl Ring {
+    fn new(
+        fd: RawFd,
+        size: u32,
+        desc: usize,
+        offsets: libc::xdp_ring_offset,
+        page: u64,
+    ) -> Result<Self> {
+        let len = offsets.desc as usize + size as usize * desc;
+        let map = Mmap::new(len, Some((fd, page as libc::off_t)))?;
+        Ok(Ring { map, offsets, size })
+    }
+    fn index(&self, offset: u64) -> &AtomicU32 {
+        unsafe { &*(self.map.ptr.add(offset as usize) as *const AtomicU32) }
+    }
+    fn producer(&self) -> &AtomicU32 {
+        self.index(self.offsets.producer)
+    }
+    fn consumer(&self) -> &AtomicU32 {
+        self.index(self.offsets.consumer)
+    }
+    fn needs_wakeup(&self) -> bool {
+        self.index(self.offsets.flags).load(Ordering::Relaxed) & libc::XDP_RING_NEED_WAKEUP != 0
+    }
+    fn desc<T>(&self, position: u32) -> *mut T {
+        let i = (position & (self.size - 1)) as usize;
+        unsafe { (self.map.ptr.add(self.offsets.desc as usize) as *mut T).add(i) }
+    }
+    /// Puts a descriptor in, if there's room.
+    fn produce<T>(&self, value: T) -> bool {
+        let producer = self.producer().load(Ordering::Relaxed);
+        if producer.wrapping_sub(self.consumer().load(Ordering::Acquire)) >= self.size {
+            return false;
+        }
+        unsafe { ptr::write_volatile(self.desc(producer), value) };
+        self.producer().store(producer.wrapping_add(1), Ordering::Release);
+        true
+    }
+    /// Takes a descriptor out, if there's any.
+    fn consume<T>(&self) -> Option<T> {
+        let consumer = self.consumer().load(Ordering::Relaxed);
+        if self.producer().load(Ordering::Acquire) == consumer {
+            return None;
+        }
+        let value = unsafe { ptr::read_volatile(self.desc(consumer)) };
+        self.consumer().store(consumer.wrapping_add(1), Ordering::Release);
+        Some(value)
+    }
+}
+
+/// The receiving side.
+#[derive(Debug)]
+struct Rx {
+    rx: Ring,
+    fill: Ring,
+}
+
+/// The sending side.
+#[derive(Debug)]
+struct Tx {
+    tx: Ring,
+    completion: Ring,
+    /// The frames not in use by the kernel.
+    free: Vec<u64>,
+}
+
+impl Tx {
+    fn reclaim(&mut self) {
+        while let Some(addr) = self.completion.consume::<u64>() {
+            self.free.push(addr);
+        }
+    }
+}
+
+/// An AF_XDP socket serving one queue of an interface.
+///
+/// It looks like a TAP device without the packet info ‒ [`recv`](#method.recv) returns a whole
+/// ethernet frame and [`send`](#method.send) transmits one. Unlike a TAP device, it is attached
+/// to an existing (usually physical) interface, through an [`XdpProgram`](struct.XdpProgram.html)
+/// redirecting the packets of the queue into it. The received packets don't reach the network
+/// stack of the kernel.
+///
+/// Receiving and sending can happen at the same time from different threads, but multiple
+/// threads receiving (or sending) wait for each other.
+///
+/// See the [module documentation](index.html).
+#[derive(Debug)]
+pub struct XdpIface {
+    // Dropped first, before the memory it uses
+    fd: OwnedFd,
+    rx: Mutex<Rx>,
+    tx: Mutex<Tx>,
+    umem: Mmap,
+    name: String,
+    queue: u32,
+    frame_size: u32,
+    non_blocking: AtomicBool,
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    // Nothing panics while holding it
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl XdpIface {
+    /// Opens the socket on the queue of the interface and registers it with the program.
+    ///
+    /// # Errors
+    ///
+    /// If the configuration is invalid (eg. the queue is not covered by the program or the sizes
+    /// are not powers of two) or the kernel refuses to set up the socket (eg. the zero-copy mode
+    /// is required, but not supported).
+    pub fn new(name: &str, program: &XdpProgram, config: XdpConfig) -> Result<Self> {
+        let valid = config.ring_size.is_power_of_two()
+            && config.frame_size.is_power_of_two()
+            && config.queue < program.queues
+            && program.ifindex == index(name)?;
+        if !valid {
+            return Err(Error::new(ErrorKind::InvalidInput, "Invalid XDP configuration"));
+        }
+        let fd = unsafe { libc::socket(libc::AF_XDP, libc::SOCK_RAW | libc::SOCK_CLOEXEC, 0) };
+        let fd = check_fd(fd, "socket(AF_XDP)", name)?;
+        let raw = fd.as_raw_fd();
+        let frames = 2 * config.ring_size;
+        let umem = Mmap::new(frames as usize * config.frame_size as usize, None)?;
+        let registration = libc::xdp_umem_reg {
+            addr: umem.ptr as u64,
+            len: umem.len as u64,
+            chunk_size: config.frame_size,
+            headroom: 0,
+            flags: 0,
+            tx_metadata_len: 0,
+        };
+        set_option(raw, libc::XDP_UMEM_REG, &registration, name)?;
+        for ring in &[
+            libc::XDP_UMEM_FILL_RING,
+            libc::XDP_UMEM_COMPLETION_RING,
+            libc::XDP_RX_RING,
+            libc::XDP_TX_RING,
+        ] {
+            set_option(raw, *ring, &config.ring_size, name)?;
+        }
+        let mut offsets: libc::xdp_mmap_offsets = unsafe { mem::zeroed() };
+        let mut len = mem::size_of::<libc::xdp_mmap_offsets>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                raw,
+                libc::SOL_XDP,
+                libc::XDP_MMAP_OFFSETS,
+                &mut offsets as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        check(result, "XDP_MMAP_OFFSETS", name)?;
+        let size = config.ring_size;
+        let (addr, desc) = (mem::size_of::<u64>(), mem::size_of::<libc::xdp_desc>());
+        let fill = Ring::new(raw, size, addr, offsets.fr, libc::XDP_UMEM_PGOFF_FILL_RING)?;
+        let completion =
+            Ring::new(raw, size, addr, offsets.cr, libc::XDP_UMEM_PGOFF_COMPLETION_RING)?;
+        let rx = Ring::new(raw, size, desc, offsets.rx, libc::XDP_PGOFF_RX_RING as u64)?;
+        let tx = Ring::new(raw, size, desc, offsets.tx, libc::XDP_PGOFF_TX_RING as u64)?;
+        // The first half of the frames is for receiving, the other for sending
+        let frame_size = u64::from(config.frame_size);
+        for frame in 0..u64::from(size) {
+            fill.produce(frame * frame_size);
+        }
+        let free = (u64::from(size)..u64::from(frames))
+            .map(|frame| frame * frame_size)
+            .collect();
+        let mut address: libc::sockaddr_xdp = unsafe { mem::zeroed() };
+        address.sxdp_family = libc::AF_XDP as u16;
+        address.sxdp_ifindex = program.ifindex as u32;
+        address.sxdp_queue_id = config.queue;
+        address.sxdp_flags = libc::XDP_USE_NEED_WAKEUP
+            | match config.zero_copy {
+                Some(true) => libc::XDP_ZEROCOPY,
+                Some(false) => libc::XDP_COPY,
+                None => 0,
+            };
+        let result = unsafe {
+            libc::bind(
+                raw,
+                &address as *const _ as *const libc::sockaddr,
+                mem::size_of::<libc::sockaddr_xdp>() as libc::socklen_t,
+            )
+        };
+        check(result, "bind(AF_XDP)", name)?;
+        program.register(config.queue, raw)?;
+        debug!("Opened an AF_XDP socket on {} queue {}", name, config.queue);
+        Ok(XdpIface {
+            fd,
+            rx: Mutex::new(Rx { rx, fill }),
+            tx: Mutex::new(Tx {
+                tx,
+                completion,
+                free,
+            }),
+            umem,
+            name: name.to_owned(),
+            queue: config.queue,
+            frame_size: config.frame_size,
+            non_blocking: AtomicBool::new(false),
+        })
+    }
+    /// Returns the name of the interface.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+    /// Returns the queue served by the socket.
+    pub fn queue(&self) -> u32 {
+        self.queue
+    }
+    /// Checks if the driver exchanges the packets through the UMEM directly.
+    pub fn is_zero_copy(&self) -> Result<bool> {
+        let mut options = libc::xdp_options { flags: 0 };
+        let mut len = mem::size_of::<libc::xdp_options>() as libc::socklen_t;
+        let result = unsafe {
+            libc::getsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_XDP,
+                libc::XDP_OPTIONS,
+                &mut options as *mut _ as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        check(result, "XDP_OPTIONS", &self.name)?;
+        Ok(options.flags & libc::XDP_OPTIONS_ZEROCOPY != 0)
+    }
+    /// Returns the MTU of the interface.
+    ///
+    /// See [`Iface::mtu`](../struct.Iface.html#method.mtu).
+    pub fn mtu(&self) -> Result<usize> {
+        let name = CString::new(self.name.as_str()).map_err(io::Error::other)?;
+        let result = unsafe { tuntap_get_mtu(name.as_ptr()) };
+        if result < 0 {
+            let error = Error::last_os_error();
+            error!("SIOCGIFMTU on {} failed: {}", self.name, error);
+            Err(error)
+        } else {
+            Ok(result as usize)
+        }
+    }
+    /// Tells the kernel there are frames to fill, in case it stopped looking.
+    fn wakeup_fill(&self, fill: &Ring) {
+        if fill.needs_wakeup() {
+            let fd = self.fd.as_raw_fd();
+            // A receive wakes the RX side, fails with EAGAIN if there's nothing yet
+            unsafe {
+                libc::recvfrom(
+                    fd,
+                    ptr::null_mut(),
+                    0,
+                    libc::MSG_DONTWAIT,
+                    ptr::null_mut(),
+                    ptr::null_mut(),
+                )
+            };
+        }
+    }
+    /// Tells the kernel there are frames to send, in case it stopped looking.
+    fn wakeup_tx(&self, tx: &Ring) {
+        if tx.needs_wakeup() {
+            let fd = self.fd.as_raw_fd();
+            // Fails with EAGAIN or EBUSY if the kernel is busy, it looks again then
+            unsafe { libc::sendto(fd, ptr::null(), 0, libc::MSG_DONTWAIT, ptr::null(), 0) };
+        }
+    }
+    fn wait(&self, events: libc::c_short, timeout: c_int) -> Result<()> {
+        let mut fd = libc::pollfd {
+            fd: self.fd.as_raw_fd(),
+            events,
+            revents: 0,
+        };
+        match unsafe { libc::poll(&mut fd, 1, timeout) } {
+            -1 => match Error::last_os_error() {
+                e if e.kind() == ErrorKind::Interrupted => Ok(()),
+                e => Err(e),
+            },
+            _ => Ok(()),
+        }
+    }
+    /// Receives a single frame.
+    ///
+    /// See [`Iface::recv`](../struct.Iface.html#method.recv).
+    pub fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        loop {
+            {
+                let rx = lock(&self.rx);
+                if let Some(desc) = rx.rx.consume::<libc::xdp_desc>() {
+                    let size = (desc.len as usize).min(buf.len());
+                    let frame = unsafe {
+                        std::slice::from_raw_parts(self.umem.ptr.add(desc.addr as usize), size)
+                    };
+                    buf[..size].copy_from_slice(frame);
+                    // The fill ring has room for all the receiving frames
+                    let frame_size = u64::from(self.frame_size);
+                    rx.fill.produce(desc.addr / frame_size * frame_size);
+                    self.wakeup_fill(&rx.fill);
+                    return Ok(size);
+                }
+                self.wakeup_fill(&rx.fill);
+            }
+            if self.non_blocking.load(Ordering::Relaxed) {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            self.wait(libc::POLLIN, -1)?;
+        }
+    }
+    /// Sends a single frame.
+    ///
+    /// See [`Iface::send`](../struct.Iface.html#method.send). The frame is only queued for
+    /// sending, errors of the transmission are not reported.
+    ///
+    /// # Errors
+    ///
+    /// If the frame doesn't fit into a frame of the UMEM.
+    pub fn send(&self, buf: &[u8]) -> Result<usize> {
+        if buf.len() > self.frame_size as usize {
+            return Err(Error::new(ErrorKind::InvalidInput, "Frame too large for the UMEM"));
+        }
+        loop {
+            {
+                let mut tx = lock(&self.tx);
+                tx.reclaim();
+                if tx.free.is_empty() {
+                    // In the copy mode, the kernel sends (and completes) right away
+                    self.wakeup_tx(&tx.tx);
+                    tx.reclaim();
+                }
+                if let Some(addr) = tx.free.pop() {
+                    let frame = unsafe {
+                        std::slice::from_raw_parts_mut(self.umem.ptr.add(addr as usize), buf.len())
+                    };
+                    frame.copy_from_slice(buf);
+                    let desc = libc::xdp_desc {
+                        addr,
+                        len: buf.len() as u32,
+                        options: 0,
+                    };
+                    // The tx ring has room for all the sending frames
+                    tx.tx.produce(desc);
+                    self.wakeup_tx(&tx.tx);
+                    return Ok(buf.len());
+                }
+            }
+            if self.non_blocking.load(Ordering::Relaxed) {
+                return Err(ErrorKind::WouldBlock.into());
+            }
+            // There's no readiness for the completions, just give the driver a moment
+            self.wait(libc::POLLOUT, 1)?;
+        }
+    }
+    /// Makes [`recv`](#method.recv) and [`send`](#method.send) return
+    /// [`WouldBlock`](std::io::ErrorKind::WouldBlock) instead of waiting.
+    pub fn set_non_blocking(&self) -> Result<()> {
+        self.non_blocking.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+fn set_option<T>(fd: RawFd, option: c_int, value: &T, name: &str) -> Result<()> {
+    let result = unsafe {
+        libc::setsockopt(
+            fd,
+            libc::SOL_XDP,
+            option,
+            value as *const T as *const libc::c_void,
+            mem::size_of::<T>() as libc::socklen_t,
+        )
+    };
+    check(result, "setsockopt(SOL_XDP)", name)
+}
+
+impl AsRawFd for XdpIface {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl AsFd for XdpIface {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl Device for XdpIface {
+    fn name(&self) -> &str {
+        XdpIface::name(self)
+    }
+    fn mode(&self) -> Mode {
+        Mode::Tap
+    }
+    fn packet_info(&self) -> bool {
+        false
+    }
+    fn mtu(&self) -> Result<usize> {
+        XdpIface::mtu(self)
+    }
+    fn recv(&self, buf: &mut [u8]) -> Result<usize> {
+        XdpIface::recv(self, buf)
+    }
+    fn send(&self, buf: &[u8]) -> Result<usize> {
+        XdpIface::send(self, buf)
+    }
+    fn set_non_blocking(&self) -> Result<()> {
+        XdpIface::set_non_blocking(self)
+    }
+}
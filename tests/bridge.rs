@@ -0,0 +1,50 @@
+extern crate tun_tap;
+
+use std::io::ErrorKind;
+use std::thread;
+use std::time::Duration;
+
+use tun_tap::bridge::{self, Direction};
+use tun_tap::testing;
+
+#[test]
+fn forwards_both_ways() {
+    let (a, a_peer) = testing::pair().unwrap();
+    let (b, b_peer) = testing::pair().unwrap();
+    let bridge = bridge::bridge(a, b, bridge::forward_all).unwrap();
+    a_peer.send(b"hello").unwrap();
+    assert_eq!(b"hello", &b_peer.recv_vec().unwrap()[..]);
+    b_peer.send(b"world").unwrap();
+    assert_eq!(b"world", &a_peer.recv_vec().unwrap()[..]);
+
+    // The devices are usable again after the shutdown
+    let (a, b) = bridge.shutdown();
+    a_peer.send(b"direct").unwrap();
+    assert_eq!(b"direct", &a.recv_vec().unwrap()[..]);
+    b.send(b"back").unwrap();
+    assert_eq!(b"back", &b_peer.recv_vec().unwrap()[..]);
+}
+
+#[test]
+fn filters_and_transforms() {
+    let (a, a_peer) = testing::pair().unwrap();
+    let (b, b_peer) = testing::pair().unwrap();
+    let filter = |direction, packet: &mut Vec<u8>| match direction {
+        Direction::AToB => {
+            packet.push(b'!');
+            true
+        },
+        Direction::BToA => packet[0] != 0,
+    };
+    let _bridge = bridge::bridge(a, b, filter).unwrap();
+    a_peer.send(b"hello").unwrap();
+    assert_eq!(b"hello!", &b_peer.recv_vec().unwrap()[..]);
+    b_peer.send(&[0, 1, 2]).unwrap();
+    b_peer.send(&[1, 2, 3]).unwrap();
+    // The first one got dropped
+    assert_eq!(vec![1, 2, 3], a_peer.recv_vec().unwrap());
+    thread::sleep(Duration::from_millis(50));
+    a_peer.set_non_blocking().unwrap();
+    let mut buf = [0; 10];
+    assert_eq!(ErrorKind::WouldBlock, a_peer.recv(&mut buf).unwrap_err().kind());
+}
@@ -0,0 +1,218 @@
+#![cfg(feature = "tokio")]
+
+extern crate bytes;
+extern crate futures;
+extern crate tokio_core;
+extern crate tun_tap;
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+
+use bytes::BytesMut;
+use futures::{Sink, Stream};
+use tokio_core::reactor::Core;
+use tun_tap::r#async::Async;
+use tun_tap::codec::{
+    Decoder, Encoder, EthernetFrame, EthernetFrameCodec, TunPacket, TunPacketCodec,
+};
+use tun_tap::demux::{Demux, Key};
+use tun_tap::packet::{EthernetHeader, MacAddr};
+use tun_tap::{Device, EtherType, Mode};
+
+#[test]
+fn round_trip_with_packet_info() {
+    let mut codec = TunPacketCodec::new(true, Mode::Tun);
+    let packet = TunPacket::new(EtherType::Ipv6, &b"\x60hello"[..]);
+    let mut buf = BytesMut::new();
+    codec.encode(packet.clone(), &mut buf).unwrap();
+    assert_eq!(&buf[..], b"\0\0\x86\xdd\x60hello");
+    assert_eq!(Some(packet), codec.decode(&mut buf).unwrap());
+    assert!(buf.is_empty());
+}
+
+#[test]
+fn guesses_proto_without_packet_info() {
+    let mut codec = TunPacketCodec::new(false, Mode::Tun);
+    let mut buf = BytesMut::from(&b"\x45\0\0\x14"[..]);
+    let packet = codec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(EtherType::Ipv4, packet.proto);
+    assert_eq!(&packet.payload[..], b"\x45\0\0\x14");
+
+    let mut codec = TunPacketCodec::new(false, Mode::Tap);
+    let mut frame = vec![0xff; 12];
+    frame.extend_from_slice(&[0x08, 0x06]);
+    let mut buf = BytesMut::from(&frame[..]);
+    let packet = codec.decode(&mut buf).unwrap().unwrap();
+    assert_eq!(EtherType::Arp, packet.proto);
+}
+
+#[test]
+fn rejects_truncated_header() {
+    let mut codec = TunPacketCodec::new(true, Mode::Tun);
+    let mut buf = BytesMut::from(&b"\0\0"[..]);
+    assert!(codec.decode(&mut buf).is_err());
+}
+
+#[test]
+fn ethernet_round_trip() {
+    let mut codec = EthernetFrameCodec::new(true);
+    let frame = EthernetFrame {
+        header: EthernetHeader {
+            destination: MacAddr::BROADCAST,
+            source: MacAddr([2, 0, 0, 0, 0, 1]),
+            ether_type: EtherType::Arp,
+        },
+        payload: (&b"arp"[..]).into(),
+    };
+    let mut buf = BytesMut::new();
+    codec.encode(frame.clone(), &mut buf).unwrap();
+    assert_eq!(4 + 14 + 3, buf.len());
+    assert_eq!(&buf[2..4], b"\x08\x06");
+    assert_eq!(Some(frame), codec.decode(&mut buf).unwrap());
+}
+
+#[test]
+fn demux_dispatch() {
+    let mut demux = Demux::new();
+    let icmp = demux.stream(Key::IpProtocol(1));
+    let ipv4 = demux.stream(Key::EtherType(EtherType::Ipv4));
+    let mut ping = vec![0x45; 20];
+    ping[9] = 1;
+    let mut udp = ping.clone();
+    udp[9] = 17;
+    assert!(demux.dispatch(TunPacket::new(EtherType::Ipv4, ping)));
+    assert!(demux.dispatch(TunPacket::new(EtherType::Ipv4, udp)));
+    assert!(!demux.dispatch(TunPacket::new(EtherType::Arp, vec![0; 28])));
+    drop(demux);
+    assert_eq!(1, icmp.wait().count());
+    let ipv4 = ipv4.wait().collect::<Result<Vec<_>, _>>().unwrap();
+    assert_eq!(1, ipv4.len());
+    assert_eq!(17, ipv4[0].payload[9]);
+}
+
+/// A device backed by a datagram socket, the other end plays the kernel.
+struct MockDevice(UnixDatagram);
+
+impl AsRawFd for MockDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl Device for MockDevice {
+    fn name(&self) -> &str {
+        "mock0"
+    }
+    fn mode(&self) -> Mode {
+        Mode::Tun
+    }
+    fn packet_info(&self) -> bool {
+        true
+    }
+    fn mtu(&self) -> io::Result<usize> {
+        Ok(1500)
+    }
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.recv(buf)
+    }
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.0.send(buf)
+    }
+    fn set_non_blocking(&self) -> io::Result<()> {
+        self.0.set_nonblocking(true)
+    }
+}
+
+#[test]
+fn async_mock_device() {
+    let (device, kernel) = UnixDatagram::pair().unwrap();
+    let mut core = Core::new().unwrap();
+    let iface = Async::new(MockDevice(device), &core.handle()).unwrap();
+    assert_eq!("mock0", iface.iface().name());
+    // The MTU with the packet info
+    assert_eq!(1504, iface.recv_bufsize());
+    let (sink, stream) = iface.framed().split();
+
+    kernel.send(b"\0\0\x08\x00\x45first").unwrap();
+    kernel.send(b"\0\0\x86\xdd\x60second").unwrap();
+    let packets = core.run(stream.take(2).collect()).unwrap();
+    assert_eq!(
+        vec![
+            TunPacket::new(EtherType::Ipv4, &b"\x45first"[..]),
+            TunPacket::new(EtherType::Ipv6, &b"\x60second"[..]),
+        ],
+        packets
+    );
+
+    let packet = TunPacket::new(EtherType::Ipv4, &b"\x45reply"[..]);
+    core.run(sink.send(packet)).unwrap();
+    let mut buf = [0; 50];
+    let size = kernel.recv(&mut buf).unwrap();
+    assert_eq!(b"\0\0\x08\x00\x45reply", &buf[..size]);
+}
+
+#[test]
+fn async_channels() {
+    let (device, kernel) = tun_tap::testing::pair().unwrap();
+    let mut core = Core::new().unwrap();
+    let (sender, receiver) = Async::new(device, &core.handle())
+        .unwrap()
+        .into_channels(4);
+
+    kernel.send(b"\x45first").unwrap();
+    let (packet, receiver) = core.run(receiver.into_future()).map_err(|_| ()).unwrap();
+    assert_eq!(Some(TunPacket::new(EtherType::Ipv4, &b"\x45first"[..])), packet);
+
+    let packet = TunPacket::new(EtherType::Ipv6, &b"\x60reply"[..]);
+    core.run(sender.clone().send(packet)).unwrap();
+    // Let the sending task run
+    core.turn(Some(std::time::Duration::from_millis(10)));
+    let mut buf = [0; 20];
+    let size = kernel.recv(&mut buf).unwrap();
+    assert_eq!(b"\x60reply", &buf[..size]);
+
+    // Dropping the senders terminates the sending task, the receiving one still works.
+    drop(sender);
+    kernel.send(b"\x45second").unwrap();
+    let (packet, _) = core.run(receiver.into_future()).map_err(|_| ()).unwrap();
+    assert_eq!(Some(TunPacket::new(EtherType::Ipv4, &b"\x45second"[..])), packet);
+}
+
+#[test]
+fn async_pooled() {
+    let (device, kernel) = tun_tap::testing::pair().unwrap();
+    let pool = tun_tap::pool::PacketPool::new(100);
+    let mut core = Core::new().unwrap();
+    let packets = Async::new(device, &core.handle()).unwrap().pooled(pool.clone());
+
+    kernel.send(b"\x45first").unwrap();
+    kernel.send(b"\x45second").unwrap();
+    let (packet, packets) = core.run(packets.into_future()).map_err(|_| ()).unwrap();
+    assert_eq!(b"\x45first", &packet.unwrap()[..]);
+    let (packet, _) = core.run(packets.into_future()).map_err(|_| ()).unwrap();
+    assert_eq!(b"\x45second", &packet.unwrap()[..]);
+    // Both went back to the pool and the second reused the buffer of the first.
+    assert_eq!(1, pool.idle());
+}
+
+#[test]
+fn async_timestamped() {
+    use tun_tap::Timestamp;
+
+    let (device, kernel) = tun_tap::testing::pair().unwrap();
+    let mut core = Core::new().unwrap();
+    let packets = Async::new(device, &core.handle()).unwrap().timestamped();
+
+    let before = Timestamp::now();
+    kernel.send(b"\x45first").unwrap();
+    kernel.send(b"\x45second").unwrap();
+    let packets = core.run(packets.take(2).collect()).unwrap();
+    let after = Timestamp::now();
+    assert_eq!(b"\x45first", &packets[0].0[..]);
+    assert_eq!(b"\x45second", &packets[1].0[..]);
+    assert!(before.monotonic <= packets[0].1.monotonic);
+    assert!(packets[0].1.monotonic <= packets[1].1.monotonic);
+    assert!(packets[1].1.monotonic <= after.monotonic);
+    assert!(after.duration_since(&before) >= packets[1].1.duration_since(&packets[0].1));
+}
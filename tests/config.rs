@@ -0,0 +1,44 @@
+#![cfg(feature = "serde")]
+
+extern crate serde_json;
+extern crate tun_tap;
+
+use tun_tap::{Cleanup, IfaceConfig, Mode};
+
+#[test]
+fn config_from_json() {
+    let config: IfaceConfig =
+        serde_json::from_str(r#"{"name": "vpn%d", "mode": "tap"}"#).unwrap();
+    assert_eq!(IfaceConfig::new("vpn%d", Mode::Tap), config);
+
+    let config: IfaceConfig =
+        serde_json::from_str(r#"{"name": "vpn0", "mode": "tun", "packet_info": false}"#).unwrap();
+    assert_eq!(Mode::Tun, config.mode);
+    assert!(!config.packet_info);
+    assert!(!config.one_queue);
+
+    let config: IfaceConfig =
+        serde_json::from_str(r#"{"name": "vpn0", "mode": "tun", "one_queue": true}"#).unwrap();
+    assert!(config.one_queue);
+
+    let config: IfaceConfig =
+        serde_json::from_str(r#"{"name": "vpn0", "mode": "tun", "cleanup": "link-down"}"#).unwrap();
+    assert_eq!(Cleanup::LinkDown, config.cleanup);
+}
+
+#[test]
+fn config_round_trip() {
+    let config = IfaceConfig::new("vpn0", Mode::Tun);
+    let json = serde_json::to_string(&config).unwrap();
+    let expected = concat!(
+        r#"{"name":"vpn0","mode":"tun","packet_info":true,"#,
+        r#""one_queue":false,"cleanup":"nothing"}"#,
+    );
+    assert_eq!(expected, json);
+    assert_eq!(config, serde_json::from_str(&json).unwrap());
+}
+
+#[test]
+fn rejects_unknown_mode() {
+    assert!(serde_json::from_str::<Mode>(r#""tum""#).is_err());
+}
@@ -0,0 +1,77 @@
+extern crate tun_tap;
+
+use std::sync::{Arc, Mutex};
+
+use tun_tap::filtered::{Direction, FilteredIface};
+use tun_tap::testing;
+
+#[test]
+fn passes_without_hooks() {
+    let (left, right) = testing::pair().unwrap();
+    let left = FilteredIface::new(left);
+    assert_eq!(5, left.send(b"hello").unwrap());
+    assert_eq!(b"hello", &right.recv_vec().unwrap()[..]);
+    right.send(b"world").unwrap();
+    let mut buf = [0; 10];
+    assert_eq!(5, left.recv(&mut buf).unwrap());
+    assert_eq!(b"world", &buf[..5]);
+}
+
+#[test]
+fn drops_packets() {
+    let (left, right) = testing::pair().unwrap();
+    let left = FilteredIface::new(left).hook(|_: Direction, packet: &mut Vec<u8>| {
+        // Anything starting with x is bad
+        packet[0] != b'x'
+    });
+    assert_eq!(4, left.send(b"xbad").unwrap());
+    left.send(b"good").unwrap();
+    assert_eq!(b"good", &right.recv_vec().unwrap()[..]);
+    right.send(b"xbad").unwrap();
+    right.send(b"fine").unwrap();
+    let mut buf = [0; 10];
+    assert_eq!(4, left.recv(&mut buf).unwrap());
+    assert_eq!(b"fine", &buf[..4]);
+    assert_eq!(2, left.dropped());
+}
+
+#[test]
+fn rewrites_and_truncates() {
+    let (left, right) = testing::pair().unwrap();
+    let left = FilteredIface::new(left).hook(|_: Direction, packet: &mut Vec<u8>| {
+        packet.extend_from_slice(b"!!");
+        true
+    });
+    assert_eq!(2, left.send(b"hi").unwrap());
+    assert_eq!(b"hi!!", &right.recv_vec().unwrap()[..]);
+    right.send(b"abc").unwrap();
+    let mut buf = [0; 4];
+    assert_eq!(4, left.recv(&mut buf).unwrap());
+    assert_eq!(b"abc!", &buf);
+}
+
+#[test]
+fn stacks_like_middleware() {
+    let (left, right) = testing::pair().unwrap();
+    let log = Arc::new(Mutex::new(Vec::new()));
+    let hook = |name: &'static str| {
+        let log = Arc::clone(&log);
+        move |direction: Direction, _: &mut Vec<u8>| {
+            log.lock().unwrap().push((name, direction));
+            true
+        }
+    };
+    let left = FilteredIface::new(left).hook(hook("outer")).hook(hook("inner"));
+    left.send(b"out").unwrap();
+    right.recv_vec().unwrap();
+    right.send(b"in").unwrap();
+    let mut buf = [0; 10];
+    left.recv(&mut buf).unwrap();
+    let expected = vec![
+        ("outer", Direction::Sent),
+        ("inner", Direction::Sent),
+        ("inner", Direction::Received),
+        ("outer", Direction::Received),
+    ];
+    assert_eq!(expected, *log.lock().unwrap());
+}
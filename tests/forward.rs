@@ -0,0 +1,263 @@
+#[cfg(feature = "tokio")]
+extern crate futures;
+#[cfg(feature = "tokio")]
+extern crate tokio_core;
+extern crate tun_tap;
+
+use std::io::{Read, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::os::unix::net::UnixDatagram;
+use std::thread;
+use std::time::Duration;
+
+use tun_tap::forward::{self, tcp, udp, unix, Direction};
+use tun_tap::testing;
+
+/// Two connected sockets, the first one for the tunnel, the other one as the remote endpoint.
+fn sockets() -> (UdpSocket, UdpSocket) {
+    let local = UdpSocket::bind("127.0.0.1:0").unwrap();
+    let remote = UdpSocket::bind("127.0.0.1:0").unwrap();
+    local.connect(remote.local_addr().unwrap()).unwrap();
+    remote.connect(local.local_addr().unwrap()).unwrap();
+    remote
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    (local, remote)
+}
+
+fn recv_datagram(socket: &UdpSocket) -> Vec<u8> {
+    let mut buf = vec![0; 1500];
+    let size = socket.recv(&mut buf).unwrap();
+    buf.truncate(size);
+    buf
+}
+
+/// A toy cipher, marking which way the packet went.
+fn transform(direction: Direction, packet: &mut Vec<u8>) -> bool {
+    match direction {
+        Direction::Outbound => {
+            packet.iter_mut().for_each(|b| *b ^= 0x42);
+            packet.push(b'>');
+            true
+        },
+        Direction::Inbound => match packet.pop() {
+            Some(b'<') => {
+                packet.iter_mut().for_each(|b| *b ^= 0x42);
+                true
+            },
+            _ => false,
+        },
+    }
+}
+
+fn xor(data: &[u8]) -> Vec<u8> {
+    data.iter().map(|b| b ^ 0x42).collect()
+}
+
+#[test]
+fn udp_tunnel() {
+    let (iface, kernel) = testing::pair().unwrap();
+    let (socket, remote) = sockets();
+    let tunnel = udp::spawn(iface, socket, transform).unwrap();
+
+    kernel.send(b"hello").unwrap();
+    let mut expected = xor(b"hello");
+    expected.push(b'>');
+    assert_eq!(expected, recv_datagram(&remote));
+
+    // Failing the "authentication" and dropped
+    remote.send(b"forged").unwrap();
+    let mut datagram = xor(b"world");
+    datagram.push(b'<');
+    remote.send(&datagram).unwrap();
+    assert_eq!(b"world", &kernel.recv_vec().unwrap()[..]);
+
+    let (iface, socket) = tunnel.shutdown();
+    kernel.send(b"direct").unwrap();
+    assert_eq!(b"direct", &iface.recv_vec().unwrap()[..]);
+    remote.send(b"raw").unwrap();
+    assert_eq!(b"raw", &recv_datagram(&socket)[..]);
+}
+
+#[test]
+fn udp_tunnel_needs_connected_socket() {
+    let (iface, _kernel) = testing::pair().unwrap();
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    assert!(udp::spawn(iface, socket, forward::unchanged).is_err());
+}
+
+#[test]
+fn unix_tunnel() {
+    let (iface, kernel) = testing::pair().unwrap();
+    let (ours, theirs) = unix::seqpacket_pair().unwrap();
+    theirs
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    let tunnel = unix::spawn(iface, ours, transform).unwrap();
+
+    kernel.send(b"hello").unwrap();
+    let mut buf = [0; 10];
+    let size = theirs.recv(&mut buf).unwrap();
+    let mut expected = xor(b"hello");
+    expected.push(b'>');
+    assert_eq!(expected, &buf[..size]);
+    let mut message = xor(b"world");
+    message.push(b'<');
+    theirs.send(&message).unwrap();
+    assert_eq!(b"world", &kernel.recv_vec().unwrap()[..]);
+
+    // Closing the other side stops forwarding that way, but the device is still there
+    drop(theirs);
+    let (iface, _) = tunnel.shutdown();
+    kernel.send(b"direct").unwrap();
+    assert_eq!(b"direct", &iface.recv_vec().unwrap()[..]);
+}
+
+#[test]
+fn unix_tunnel_needs_connected_socket() {
+    let (iface, _kernel) = testing::pair().unwrap();
+    let socket = UnixDatagram::unbound().unwrap();
+    assert!(unix::spawn(iface, socket, forward::unchanged).is_err());
+}
+
+/// A connected pair of TCP streams.
+fn streams() -> (TcpStream, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let local = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (remote, _) = listener.accept().unwrap();
+    remote
+        .set_read_timeout(Some(Duration::from_secs(5)))
+        .unwrap();
+    (local, remote)
+}
+
+fn recv_frame(stream: &mut TcpStream) -> Vec<u8> {
+    let mut len = [0; 2];
+    stream.read_exact(&mut len).unwrap();
+    let mut frame = vec![0; u16::from_be_bytes(len).into()];
+    stream.read_exact(&mut frame).unwrap();
+    frame
+}
+
+#[test]
+fn tcp_tunnel() {
+    let (iface, kernel) = testing::pair().unwrap();
+    let (stream, mut remote) = streams();
+    let tunnel = tcp::spawn(iface, stream, forward::unchanged).unwrap();
+
+    kernel.send(b"hello").unwrap();
+    kernel.send(b"world").unwrap();
+    assert_eq!(b"hello", &recv_frame(&mut remote)[..]);
+    assert_eq!(b"world", &recv_frame(&mut remote)[..]);
+
+    // Two frames in one write and one split into pieces
+    remote.write_all(b"\0\x01a\0\x02bc\0").unwrap();
+    remote.flush().unwrap();
+    thread::sleep(Duration::from_millis(50));
+    remote.write_all(b"\x03de").unwrap();
+    thread::sleep(Duration::from_millis(50));
+    remote.write_all(b"f").unwrap();
+    assert_eq!(b"a", &kernel.recv_vec().unwrap()[..]);
+    assert_eq!(b"bc", &kernel.recv_vec().unwrap()[..]);
+    assert_eq!(b"def", &kernel.recv_vec().unwrap()[..]);
+
+    let (iface, mut stream) = tunnel.shutdown();
+    kernel.send(b"direct").unwrap();
+    assert_eq!(b"direct", &iface.recv_vec().unwrap()[..]);
+    remote.write_all(b"raw").unwrap();
+    let mut buf = [0; 3];
+    stream.read_exact(&mut buf).unwrap();
+    assert_eq!(b"raw", &buf);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn async_tcp_tunnel() {
+    use futures::future;
+    use futures::Future;
+    use tokio_core::net::TcpStream as AsyncStream;
+    use tokio_core::reactor::Core;
+    use tun_tap::forward::tcp::AsyncTcpTunnel;
+    use tun_tap::r#async::Async;
+
+    let (iface, kernel) = testing::pair().unwrap();
+    let (stream, mut remote) = streams();
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let iface = Async::new(iface, &handle).unwrap();
+    let stream = AsyncStream::from_stream(stream, &handle).unwrap();
+    let mut tunnel = AsyncTcpTunnel::new(iface, stream, transform);
+
+    kernel.send(b"hello").unwrap();
+    let mut frame = vec![0, 6];
+    frame.extend(xor(b"world"));
+    frame.push(b'<');
+    // Split in the middle of the length
+    remote.write_all(&frame[..1]).unwrap();
+    core.turn(Some(Duration::from_millis(50)));
+    remote.write_all(&frame[1..]).unwrap();
+    kernel.set_non_blocking().unwrap();
+    core.run(future::poll_fn(|| {
+        tunnel.poll().unwrap();
+        // Wait for the reply, but keep the tunnel running
+        let mut buf = [0; 10];
+        match kernel.recv(&mut buf) {
+            Ok(size) => Ok::<_, ()>(futures::Async::Ready(buf[..size].to_vec())),
+            Err(_) => {
+                futures::task::current().notify();
+                Ok(futures::Async::NotReady)
+            },
+        }
+    }))
+    .map(|packet| assert_eq!(b"world", &packet[..]))
+    .unwrap();
+    let mut expected = xor(b"hello");
+    expected.push(b'>');
+    assert_eq!(expected, recv_frame(&mut remote));
+
+    // Closing the stream ends the tunnel
+    drop(remote);
+    core.run(tunnel).unwrap();
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn async_udp_tunnel() {
+    use futures::future::{self, Either};
+    use futures::Future;
+    use tokio_core::net::UdpSocket as AsyncSocket;
+    use tokio_core::reactor::{Core, Timeout};
+    use tun_tap::forward::udp::AsyncUdpTunnel;
+    use tun_tap::r#async::Async;
+
+    let (iface, kernel) = testing::pair().unwrap();
+    let (socket, remote) = sockets();
+    let mut core = Core::new().unwrap();
+    let handle = core.handle();
+    let iface = Async::new(iface, &handle).unwrap();
+    let socket = AsyncSocket::from_socket(socket, &handle).unwrap();
+    let mut tunnel = AsyncUdpTunnel::new(iface, socket, transform);
+
+    kernel.send(b"hello").unwrap();
+    let mut datagram = xor(b"world");
+    datagram.push(b'<');
+    remote.send(&datagram).unwrap();
+    // Run the tunnel for a while, it doesn't end by itself
+    let timeout = Timeout::new(Duration::from_millis(100), &handle).unwrap();
+    match core.run(future::poll_fn(|| tunnel.poll()).select2(timeout)) {
+        Ok(Either::B(_)) => (),
+        _ => panic!("The tunnel terminated"),
+    }
+    let mut expected = xor(b"hello");
+    expected.push(b'>');
+    assert_eq!(expected, recv_datagram(&remote));
+    assert_eq!(b"world", &kernel.recv_vec().unwrap()[..]);
+
+    let (iface, socket) = tunnel.into_inner();
+    remote.send(b"raw").unwrap();
+    let mut buf = [0; 10];
+    core.run(future::poll_fn(|| Ok::<_, ()>(socket.poll_read()))).unwrap();
+    let size = socket.recv(&mut buf).unwrap();
+    assert_eq!(b"raw", &buf[..size]);
+    assert_eq!("fake0", iface.iface().name());
+}
@@ -0,0 +1,141 @@
+#![cfg(feature = "fragment")]
+
+extern crate tun_tap;
+
+use std::thread;
+use std::time::Duration;
+
+use tun_tap::fragment::{self, FragmentError, Reassembler};
+
+fn packet(id: u8, options: &[u8], payload_len: usize) -> Vec<u8> {
+    let header_len = 20 + options.len();
+    let total = (header_len + payload_len) as u16;
+    let mut packet = vec![0x40 | (header_len / 4) as u8, 0];
+    packet.extend_from_slice(&total.to_be_bytes());
+    packet.extend_from_slice(&[0, id, 0, 0, 64, 17, 0, 0, 10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend_from_slice(options);
+    packet.extend((0..payload_len).map(|i| i as u8));
+    packet
+}
+
+fn checksum_ok(packet: &[u8]) -> bool {
+    let header_len = usize::from(packet[0] & 0x0F) * 4;
+    let mut sum = packet[..header_len]
+        .chunks(2)
+        .map(|word| u32::from(u16::from_be_bytes([word[0], word[1]])))
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum == 0xffff
+}
+
+fn flags_offset(packet: &[u8]) -> (bool, usize) {
+    let raw = u16::from_be_bytes([packet[6], packet[7]]);
+    (raw & 0x2000 != 0, usize::from(raw & 0x1FFF) * 8)
+}
+
+#[test]
+fn small_passes() {
+    let packet = packet(1, &[], 100);
+    assert_eq!(vec![packet.clone()], fragment::fragment(&packet, 1500).unwrap());
+    assert_eq!(Some(packet.clone()), Reassembler::new().push(&packet));
+}
+
+#[test]
+fn splits_and_reassembles() {
+    let original = packet(2, &[], 3000);
+    let fragments = fragment::fragment(&original, 1280).unwrap();
+    assert_eq!(3, fragments.len());
+    let mut expected_offset = 0;
+    for (i, fragment) in fragments.iter().enumerate() {
+        assert!(fragment.len() <= 1280);
+        assert!(checksum_ok(fragment));
+        let (more, offset) = flags_offset(fragment);
+        assert_eq!(i < 2, more);
+        assert_eq!(expected_offset, offset);
+        expected_offset += fragment.len() - 20;
+    }
+    let mut reassembler = Reassembler::new();
+    assert_eq!(None, reassembler.push(&fragments[2]));
+    assert_eq!(None, reassembler.push(&fragments[0]));
+    assert_eq!(1, reassembler.pending());
+    let reassembled = reassembler.push(&fragments[1]).unwrap();
+    assert!(checksum_ok(&reassembled));
+    assert_eq!(original[..10], reassembled[..10]);
+    assert_eq!(original[12..], reassembled[12..]);
+    assert_eq!(0, reassembler.pending());
+}
+
+#[test]
+fn refragments() {
+    let original = packet(3, &[], 2000);
+    let mut reassembler = Reassembler::new();
+    let mut result = None;
+    for fragment in fragment::fragment(&original, 1000).unwrap() {
+        for smaller in fragment::fragment(&fragment, 300).unwrap() {
+            assert!(smaller.len() <= 300);
+            assert!(result.is_none());
+            result = reassembler.push(&smaller);
+        }
+    }
+    assert_eq!(original[12..], result.unwrap()[12..]);
+}
+
+#[test]
+fn copies_options() {
+    // A copied option (security, 0x82) and a not copied one (record route, 0x07), padded
+    let options = [0x82, 4, 0xAA, 0xBB, 0x07, 3, 4, 0];
+    let original = packet(4, &options, 200);
+    let fragments = fragment::fragment(&original, 100).unwrap();
+    assert_eq!(original[20..28], fragments[0][20..28]);
+    for fragment in &fragments[1..] {
+        assert_eq!(0x46, fragment[0]);
+        assert_eq!([0x82, 4, 0xAA, 0xBB], fragment[20..24]);
+        assert!(checksum_ok(fragment));
+    }
+    let mut reassembler = Reassembler::new();
+    let reassembled = fragments.iter().filter_map(|f| reassembler.push(f)).next().unwrap();
+    assert_eq!(original[12..], reassembled[12..]);
+}
+
+#[test]
+fn refuses() {
+    let mut dont = packet(5, &[], 2000);
+    dont[6] = 0x40;
+    assert_eq!(Err(FragmentError::DontFragment), fragment::fragment(&dont, 1500));
+    let big = packet(5, &[], 2000);
+    assert_eq!(Err(FragmentError::MtuTooSmall), fragment::fragment(&big, 27));
+    assert_eq!(Err(FragmentError::Malformed), fragment::fragment(&[0x60; 40], 1500));
+}
+
+#[test]
+fn drops_too_large() {
+    // The first fragment has the longest possible header, the last one fits only with a short one
+    let mut first = packet(8, &[1; 40], 8);
+    first[6] = 0x20;
+    let mut last = packet(8, &[], 65_535 - 20 - 8);
+    last[7] = 1;
+    let mut reassembler = Reassembler::new();
+    assert_eq!(None, reassembler.push(&first));
+    assert_eq!(None, reassembler.push(&last));
+    assert_eq!(0, reassembler.pending());
+}
+
+#[test]
+fn forgets_incomplete() {
+    let mut reassembler = Reassembler::new()
+        .timeout(Duration::from_millis(50))
+        .max_pending(1);
+    let first = fragment::fragment(&packet(6, &[], 2000), 1000).unwrap();
+    let second = fragment::fragment(&packet(7, &[], 2000), 1000).unwrap();
+    assert_eq!(None, reassembler.push(&first[0]));
+    // Pushes out the first one
+    assert_eq!(None, reassembler.push(&second[0]));
+    assert_eq!(1, reassembler.expired());
+    assert_eq!(None, reassembler.push(&first[1]));
+    thread::sleep(Duration::from_millis(100));
+    assert_eq!(None, reassembler.push(&second[1]));
+    assert_eq!(3, reassembler.expired());
+    assert_eq!(1, reassembler.pending());
+}
@@ -0,0 +1,160 @@
+extern crate etherparse;
+extern crate tun_tap;
+
+use etherparse::PacketBuilder;
+
+use tun_tap::gso::GsoSender;
+use tun_tap::packet::VirtioNetHeader;
+use tun_tap::testing::{self, FakeIface};
+
+fn udp(port: u16, payload: &[u8]) -> Vec<u8> {
+    let builder = PacketBuilder::ipv4([10, 0, 0, 1], [10, 0, 0, 2], 64).udp(port, 4242);
+    let mut packet = Vec::with_capacity(builder.size(payload.len()));
+    builder.write(&mut packet, payload).unwrap();
+    packet
+}
+
+fn tcp(seq: u32, payload: &[u8]) -> Vec<u8> {
+    let builder = PacketBuilder::ipv4([10, 0, 0, 1], [10, 0, 0, 2], 64)
+        .tcp(1234, 80, seq, 1000)
+        .ack(42);
+    let mut packet = Vec::with_capacity(builder.size(payload.len()));
+    builder.write(&mut packet, payload).unwrap();
+    packet
+}
+
+fn recv(iface: &FakeIface) -> (VirtioNetHeader, Vec<u8>) {
+    let mut buf = vec![0; 70_000];
+    let size = iface.recv(&mut buf).unwrap();
+    let mut header = [0; VirtioNetHeader::SIZE];
+    header.copy_from_slice(&buf[..VirtioNetHeader::SIZE]);
+    (
+        VirtioNetHeader::parse(&header),
+        buf[VirtioNetHeader::SIZE..size].to_vec(),
+    )
+}
+
+fn be16(packet: &[u8], pos: usize) -> u16 {
+    u16::from_be_bytes([packet[pos], packet[pos + 1]])
+}
+
+#[test]
+fn sends_one_by_one_without_vnet_hdr() {
+    let (left, right) = testing::pair().unwrap();
+    let mut sender = GsoSender::new(left, false);
+    let first = udp(1000, &[1; 100]);
+    let second = udp(1000, &[2; 100]);
+    sender.push(&first).unwrap();
+    assert_eq!(0, sender.pending());
+    sender.push(&second).unwrap();
+    let mut buf = vec![0; 1500];
+    let size = right.recv(&mut buf).unwrap();
+    assert_eq!(first, &buf[..size]);
+    let size = right.recv(&mut buf).unwrap();
+    assert_eq!(second, &buf[..size]);
+}
+
+#[test]
+fn glues_udp() {
+    let (left, right) = testing::pair().unwrap();
+    let mut sender = GsoSender::new(left, true);
+    sender
+        .send_batch(
+            [
+                &udp(1000, &[1; 100])[..],
+                &udp(1000, &[2; 100]),
+                &udp(1000, &[3; 50]),
+                // A shorter one ends the batch
+                &udp(1000, &[4; 50]),
+            ]
+            .iter()
+            .cloned(),
+        )
+        .unwrap();
+    assert_eq!(0, sender.pending());
+
+    let (header, packet) = recv(&right);
+    assert_eq!(VirtioNetHeader::F_NEEDS_CSUM, header.flags);
+    assert_eq!(VirtioNetHeader::GSO_UDP_L4, header.gso_type);
+    assert_eq!(28, header.hdr_len);
+    assert_eq!(100, header.gso_size);
+    assert_eq!(20, header.csum_start);
+    assert_eq!(6, header.csum_offset);
+    assert_eq!(28 + 250, packet.len());
+    // IP total length and UDP length
+    assert_eq!(278, be16(&packet, 2));
+    assert_eq!(258, be16(&packet, 24));
+    // A valid IP header sums to all ones
+    let ip_sum = (0..20)
+        .step_by(2)
+        .map(|i| u32::from(be16(&packet, i)))
+        .sum::<u32>();
+    assert_eq!(0xffff, (ip_sum & 0xffff) + (ip_sum >> 16));
+    // The pseudo-header checksum: addresses, protocol and length
+    assert_eq!(0x0a00 + 0x0001 + 0x0a00 + 0x0002 + 17 + 258, be16(&packet, 26));
+    assert_eq!(&[1; 100][..], &packet[28..128]);
+    assert_eq!(&[3; 50][..], &packet[228..]);
+
+    // The last one goes alone, without offloads
+    let (header, packet) = recv(&right);
+    assert_eq!(VirtioNetHeader::default(), header);
+    assert_eq!(udp(1000, &[4; 50]), packet);
+}
+
+#[test]
+fn separates_flows() {
+    let (left, right) = testing::pair().unwrap();
+    let mut sender = GsoSender::new(left, true);
+    sender.push(&udp(1000, &[1; 100])).unwrap();
+    sender.push(&udp(1000, &[2; 100])).unwrap();
+    assert_eq!(2, sender.pending());
+    // Another port, another flow
+    sender.push(&udp(1001, &[3; 100])).unwrap();
+    assert_eq!(1, sender.pending());
+    let (header, packet) = recv(&right);
+    assert_eq!(VirtioNetHeader::GSO_UDP_L4, header.gso_type);
+    assert_eq!(228, packet.len());
+    sender.flush().unwrap();
+    let (header, packet) = recv(&right);
+    assert_eq!(VirtioNetHeader::GSO_NONE, header.gso_type);
+    assert_eq!(udp(1001, &[3; 100]), packet);
+}
+
+#[test]
+fn glues_tcp_in_sequence() {
+    let (left, right) = testing::pair().unwrap();
+    let mut sender = GsoSender::new(left, true);
+    sender.push(&tcp(1000, &[1; 100])).unwrap();
+    sender.push(&tcp(1100, &[2; 100])).unwrap();
+    sender.push(&tcp(1200, &[3; 100])).unwrap();
+    assert_eq!(3, sender.pending());
+    // A gap in the sequence numbers
+    sender.push(&tcp(1400, &[4; 100])).unwrap();
+    assert_eq!(1, sender.pending());
+    sender.flush().unwrap();
+
+    let (header, packet) = recv(&right);
+    assert_eq!(VirtioNetHeader::GSO_TCPV4, header.gso_type);
+    assert_eq!(40, header.hdr_len);
+    assert_eq!(100, header.gso_size);
+    assert_eq!(16, header.csum_offset);
+    assert_eq!(340, packet.len());
+    // The sequence number of the first segment
+    assert_eq!(&1000u32.to_be_bytes()[..], &packet[24..28]);
+    let (header, _) = recv(&right);
+    assert_eq!(VirtioNetHeader::GSO_NONE, header.gso_type);
+}
+
+#[test]
+fn passes_other_packets() {
+    let (left, right) = testing::pair().unwrap();
+    let mut sender = GsoSender::new(left, true);
+    sender.push(b"\x45not really a packet").unwrap();
+    sender.push(b"\x45not really a packet").unwrap();
+    sender.flush().unwrap();
+    for _ in 0..2 {
+        let (header, packet) = recv(&right);
+        assert_eq!(VirtioNetHeader::default(), header);
+        assert_eq!(b"\x45not really a packet", &packet[..]);
+    }
+}
@@ -0,0 +1,82 @@
+extern crate tun_tap;
+
+use std::time::Duration;
+
+use tun_tap::hub::{Filter, Hub};
+use tun_tap::packet::{FiveTuple, IPPROTO_UDP};
+use tun_tap::testing;
+use tun_tap::EtherType;
+
+/// A UDP packet over IPv4 from 10.0.0.1 to 10.0.0.2.
+fn udp(source_port: u8, destination_port: u8) -> Vec<u8> {
+    let mut packet = vec![0x45, 0, 0, 28, 0, 0, 0, 0, 64, IPPROTO_UDP, 0, 0];
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2]);
+    packet.extend_from_slice(&[0, source_port, 0, destination_port, 0, 8, 0, 0]);
+    packet
+}
+
+const TIMEOUT: Duration = Duration::from_secs(5);
+
+#[test]
+fn fans_out_by_filter() {
+    let (iface, kernel) = testing::pair().unwrap();
+    let hub = Hub::new(iface).unwrap();
+    let all = hub.subscribe(Filter::All, 10);
+    let ipv6 = hub.subscribe(Filter::EtherType(EtherType::Ipv6), 10);
+    let flow = FiveTuple::parse(&udp(1, 2)).unwrap();
+    let flow = hub.subscribe(Filter::Flow(flow), 10);
+    assert_eq!(3, hub.subscriptions());
+
+    kernel.send(&udp(1, 2)).unwrap();
+    kernel.send(&udp(2, 1)).unwrap();
+    kernel.send(b"\x60ipv6").unwrap();
+
+    assert_eq!(udp(1, 2), all.recv_timeout(TIMEOUT).unwrap());
+    assert_eq!(udp(2, 1), all.recv_timeout(TIMEOUT).unwrap());
+    assert_eq!(b"\x60ipv6", &all.recv_timeout(TIMEOUT).unwrap()[..]);
+    assert_eq!(b"\x60ipv6", &ipv6.recv_timeout(TIMEOUT).unwrap()[..]);
+    assert_eq!(udp(1, 2), flow.recv_timeout(TIMEOUT).unwrap());
+    // Nothing else came to the filtered ones
+    assert!(ipv6.try_recv().is_none());
+    assert!(flow.try_recv().is_none());
+
+    // The unsubscribed ones are removed with the next packet
+    drop(ipv6);
+    kernel.send(b"\x60again").unwrap();
+    assert_eq!(b"\x60again", &all.recv_timeout(TIMEOUT).unwrap()[..]);
+    assert_eq!(2, hub.subscriptions());
+
+    // Sending goes straight to the device
+    hub.iface().send(b"\x45reply").unwrap();
+    let mut buf = [0; 10];
+    let size = kernel.recv(&mut buf).unwrap();
+    assert_eq!(b"\x45reply", &buf[..size]);
+
+    let iface = hub.shutdown();
+    assert_eq!(None, all.recv());
+    assert_eq!(None, flow.recv());
+    kernel.send(b"\x45direct").unwrap();
+    let size = iface.recv(&mut buf).unwrap();
+    assert_eq!(b"\x45direct", &buf[..size]);
+}
+
+#[test]
+fn slow_subscriber_loses_packets() {
+    let (iface, kernel) = testing::pair().unwrap();
+    let hub = Hub::new(iface).unwrap();
+    let slow = hub.subscribe(Filter::All, 1);
+    let fast = hub.subscribe(Filter::All, 10);
+    for i in 0..3 {
+        kernel.send(&[0x45, i]).unwrap();
+    }
+    for i in 0..3 {
+        assert_eq!(vec![0x45, i], fast.recv_timeout(TIMEOUT).unwrap());
+    }
+    assert_eq!(vec![0x45, 0], slow.recv_timeout(TIMEOUT).unwrap());
+    assert_eq!(2, slow.dropped());
+    assert_eq!(0, fast.dropped());
+
+    drop(hub);
+    // Stopped in the background
+    assert_eq!(None, fast.recv_timeout(TIMEOUT));
+}
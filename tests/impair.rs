@@ -0,0 +1,81 @@
+extern crate tun_tap;
+
+use std::io::ErrorKind;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tun_tap::impair::{ImpairedIface, Impairment};
+use tun_tap::testing;
+
+#[test]
+fn delays_sent_packets() {
+    let (left, right) = testing::pair().unwrap();
+    let delay = Impairment::new().delay(Duration::from_millis(50));
+    let left = ImpairedIface::new(left, delay, Impairment::new()).unwrap();
+    let start = Instant::now();
+    assert_eq!(5, left.send(b"first").unwrap());
+    left.send(b"second").unwrap();
+    assert_eq!(b"first", &right.recv_vec().unwrap()[..]);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(b"second", &right.recv_vec().unwrap()[..]);
+}
+
+#[test]
+fn delays_received_packets() {
+    let (left, right) = testing::pair().unwrap();
+    let delay = Impairment::new().delay(Duration::from_millis(50));
+    let left = ImpairedIface::new(left, Impairment::new(), delay).unwrap();
+    let start = Instant::now();
+    right.send(b"hello").unwrap();
+    let mut buf = [0; 10];
+    assert_eq!(5, left.recv(&mut buf).unwrap());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    assert_eq!(b"hello", &buf[..5]);
+}
+
+#[test]
+fn loses_packets() {
+    let (left, right) = testing::pair().unwrap();
+    let lossy = Impairment::new().loss(1.0);
+    let left = ImpairedIface::new(left, lossy, Impairment::new()).unwrap();
+    // Lost packets look sent
+    assert_eq!(5, left.send(b"first").unwrap());
+    assert_eq!(6, left.send(b"second").unwrap());
+    assert_eq!(2, left.lost());
+    right.set_non_blocking().unwrap();
+    let mut buf = [0; 10];
+    assert_eq!(ErrorKind::WouldBlock, right.recv(&mut buf).unwrap_err().kind());
+
+    // The loss is random, but the seed makes it reproducible
+    let (left, right) = testing::pair().unwrap();
+    let lossy = Impairment::new().loss(0.5).seed(42);
+    let left = ImpairedIface::new(left, lossy, Impairment::new()).unwrap();
+    for i in 0..100u8 {
+        left.send(&[i]).unwrap();
+    }
+    // Let the background thread deliver them
+    thread::sleep(Duration::from_millis(100));
+    right.set_non_blocking().unwrap();
+    let mut received = 0;
+    while right.recv(&mut buf).is_ok() {
+        received += 1;
+    }
+    assert!(received > 20 && received < 80);
+    assert_eq!(100, received + left.lost());
+}
+
+#[test]
+fn limits_rate() {
+    let (left, right) = testing::pair().unwrap();
+    // 10ms per packet
+    let slow = Impairment::new().rate(800_000);
+    let left = ImpairedIface::new(left, slow, Impairment::new()).unwrap();
+    let start = Instant::now();
+    for _ in 0..10 {
+        left.send(&[0; 1000]).unwrap();
+    }
+    for _ in 0..10 {
+        assert_eq!(1000, right.recv_vec().unwrap().len());
+    }
+    assert!(start.elapsed() >= Duration::from_millis(100));
+}
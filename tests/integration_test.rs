@@ -1,11 +1,21 @@
 extern crate etherparse;
+extern crate libc;
+#[cfg(feature = "metrics")]
+extern crate metrics;
 extern crate serial_test;
 extern crate tun_tap;
 
 use etherparse::{IpHeader, PacketBuilder, PacketHeaders, TransportHeader};
 use serial_test::serial;
 use std::net::{IpAddr, Ipv4Addr, UdpSocket};
-use tun_tap::{Iface, Mode};
+use std::thread;
+use tun_tap::instrumented::InstrumentedIface;
+use tun_tap::tee::{Direction, TeeIface};
+use tun_tap::testnet::TestNet;
+use tun_tap::{
+    Error, EtherType, Iface, IffFlags, Mode, OffloadFlags, PacketInfo, PendingIface, Shutdown,
+    TunFlags,
+};
 
 #[test]
 #[serial]
@@ -63,3 +73,2189 @@ fn it_receives_packets() {
     assert_eq!(source.port(), 4242);
     assert_eq!(data, &buf[..num]);
 }
+
+#[test]
+#[serial]
+fn it_splits_into_halves() {
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let (recv, send) = iface.split();
+    assert_eq!("tun10", recv.name());
+    assert_eq!("tun10", send.name());
+    assert!(!recv.packet_info());
+
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    let receiver = thread::spawn(move || {
+        let mut buf = [0; 50];
+        let size = recv.recv(&mut buf).expect("failed to receive data");
+        (recv, size)
+    });
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let (recv, size) = receiver.join().unwrap();
+    assert_eq!(38, size);
+
+    let builder = PacketBuilder::ipv4([10, 10, 10, 2], [10, 10, 10, 1], 20).udp(4242, 2424);
+    let mut packet = Vec::<u8>::with_capacity(builder.size(10));
+    builder.write(&mut packet, &[2; 10]).expect("failed to build packet");
+    send.send(&packet).expect("failed to send packet");
+    let mut buf = [0; 50];
+    let size = socket.recv(&mut buf).expect("failed to receive packet");
+    assert_eq!([2; 10], buf[..size]);
+
+    // Halves of different interfaces don't fit together
+    let other = Iface::new("tun34", Mode::Tun).expect("failed to create a TUN device");
+    let (other_recv, other_send) = other.split();
+    let (recv, other_send) = match recv.reunite(other_send) {
+        Err(halves) => halves,
+        Ok(_) => panic!("reunited halves of different interfaces"),
+    };
+    assert_eq!("tun34", other_recv.reunite(other_send).expect("failed to reunite").name());
+    let iface = recv.reunite(send).expect("failed to reunite");
+    assert_eq!("tun10", iface.name());
+    assert!(!iface.packet_info());
+}
+
+#[test]
+#[serial]
+fn it_reads_mtu() {
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    assert_eq!(iface.mtu().expect("failed to read MTU"), 1500);
+}
+
+#[test]
+#[serial]
+fn it_creates_in_two_phases() {
+    let pending = PendingIface::open().expect("failed to open /dev/net/tun");
+    match pending.finish("a_very_long_interface_name", Mode::Tun) {
+        Err(Error::NameTooLong) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    let pending = PendingIface::open().expect("failed to open /dev/net/tun");
+    let iface = pending
+        .finish_with_flags("tun10", TunFlags::TUN | TunFlags::NO_PI)
+        .expect("failed to create a TUN device");
+    assert_eq!(iface.name(), "tun10");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    assert_eq!(iface.recv(&mut buf).expect("failed to receive data"), 38);
+}
+
+#[test]
+#[serial]
+fn it_pins_workers() {
+    use std::fs;
+    use tun_tap::workers::{pin_to_cpu, WorkerPoolBuilder};
+
+    let pinned = thread::spawn(|| {
+        pin_to_cpu(0).expect("failed to pin the thread");
+        fs::read_to_string("/proc/thread-self/status").unwrap()
+    })
+    .join()
+    .unwrap();
+    assert!(pinned.contains("Cpus_allowed_list:\t0\n"));
+    assert!(pin_to_cpu(usize::MAX).is_err());
+
+    let pool = WorkerPoolBuilder::new("tun14", TunFlags::TUN | TunFlags::NO_PI)
+        .queues(2)
+        .cpus(vec![0])
+        .spawn(|_: &Iface, _: &[u8]| ())
+        .expect("failed to create the workers");
+    assert_eq!(pool.queues(), 2);
+    pool.shutdown();
+
+    // The first queue starts, but can't be pinned
+    let err = WorkerPoolBuilder::new("tun14", TunFlags::TUN | TunFlags::NO_PI)
+        .queues(2)
+        .cpus(vec![100_000])
+        .spawn(|_: &Iface, _: &[u8]| ())
+        .unwrap_err();
+    assert!(matches!(err, Error::Io(_)));
+}
+
+#[test]
+#[serial]
+fn it_runs_worker_pool() {
+    use std::collections::HashSet;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use tun_tap::workers::{self, WorkerPoolBuilder};
+
+    let received = Arc::new(AtomicUsize::new(0));
+    let threads = Arc::new(Mutex::new(HashSet::new()));
+    let pool = {
+        let received = Arc::clone(&received);
+        let threads = Arc::clone(&threads);
+        WorkerPoolBuilder::new("tun14", TunFlags::TUN | TunFlags::NO_PI)
+            .queues(3)
+            .spawn(move |_queue: &Iface, packet: &[u8]| {
+                // Ignore any IPv6 noise
+                if packet[0] >> 4 == 4 {
+                    let queue = workers::current_queue().expect("not in a worker");
+                    let name = thread::current().name().unwrap().to_owned();
+                    assert_eq!(format!("tun14-q{}", queue), name);
+                    threads.lock().unwrap().insert(name);
+                    received.fetch_add(1, Ordering::SeqCst);
+                }
+            })
+            .expect("failed to create the workers")
+    };
+    assert_eq!(pool.name(), "tun14");
+    assert_eq!(pool.queues(), 3);
+    let info = pool.queue_info();
+    assert_eq!((0..3).collect::<Vec<_>>(), info.iter().map(|q| q.index).collect::<Vec<_>>());
+    assert!(info.iter().all(|q| q.running && q.cpu.is_none()));
+    assert!(workers::current_queue().is_none());
+    {
+        // An extra queue just for the configuration, gone before any traffic
+        let flags = TunFlags::TUN | TunFlags::NO_PI | TunFlags::MULTI_QUEUE;
+        let queue = Iface::with_flags("tun14", flags).expect("failed to attach a queue");
+        assert!(queue.is_multiqueue().expect("failed to read flags"));
+        assert_eq!(4, queue.queue_count().expect("failed to count queues"));
+        queue
+            .set_ipv4(Ipv4Addr::new(10, 10, 14, 1), 24)
+            .expect("failed to set the address");
+        queue.set_up(true).expect("failed to bring the device up");
+    }
+    // Different ports make different flows, likely spread over the queues
+    for port in 0..30 {
+        let socket = UdpSocket::bind(("10.10.14.1", 3000 + port)).expect("failed to bind");
+        socket
+            .send_to(&[1; 10], "10.10.14.2:4242")
+            .expect("failed to send data");
+    }
+    let start = Instant::now();
+    while received.load(Ordering::SeqCst) < 30 {
+        assert!(start.elapsed() < Duration::from_secs(5), "packets not received");
+        thread::sleep(Duration::from_millis(10));
+    }
+    pool.shutdown();
+    assert!(threads.lock().unwrap().iter().all(|name| name.starts_with("tun14-q")));
+    // All the queues are closed, so the device is gone
+    assert!(!std::path::Path::new("/sys/class/net/tun14").exists());
+}
+
+#[test]
+#[serial]
+fn it_busy_polls() {
+    use std::time::{Duration, Instant};
+
+    let mut iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    iface.set_busy_poll(Duration::from_millis(50));
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    assert_eq!(iface.recv(&mut buf).expect("failed to receive data"), 38);
+
+    // Nothing comes, so it spins for the whole time before giving up
+    iface.set_non_blocking().expect("failed to set non-blocking");
+    let start = Instant::now();
+    let err = iface.recv(&mut buf).unwrap_err();
+    assert_eq!(err.kind(), std::io::ErrorKind::WouldBlock);
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+#[serial]
+fn it_seals() {
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let index = iface.index().expect("failed to read the index");
+    let iface = iface.seal().expect("failed to seal");
+    assert_eq!(iface.name(), "tun10");
+    assert_eq!(iface.mtu(), 1500);
+    assert_eq!(iface.index(), index);
+    assert!(iface.tun_flags().contains(TunFlags::TUN | TunFlags::NO_PI));
+    assert!(iface.iff_flags().contains(IffFlags::UP));
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    assert_eq!(iface.recv(&mut buf).expect("failed to receive data"), 38);
+}
+
+#[test]
+#[serial]
+fn it_uses_channels() {
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let (sender, receiver) = iface.into_channels(4).expect("failed to spawn threads");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let packet = receiver.recv().expect("failed to receive data");
+    assert_eq!(packet.len(), 38);
+
+    let mut packet = Vec::new();
+    PacketBuilder::ipv4([10, 10, 10, 2], [10, 10, 10, 1], 20)
+        .udp(4242, 2424)
+        .write(&mut packet, &[2; 10])
+        .expect("failed to build packet");
+    sender.send(packet).expect("failed to send packet");
+    let mut buf = [0; 50];
+    let num = socket.recv(&mut buf).expect("failed to receive packet");
+    assert_eq!(&buf[..num], &[2; 10]);
+
+    // The threads terminate and close the interface, even with no more packets coming.
+    drop(sender);
+    drop(receiver);
+    let reopened = (0..50).any(|_| {
+        thread::sleep(std::time::Duration::from_millis(10));
+        Iface::without_packet_info("tun10", Mode::Tun).is_ok()
+    });
+    assert!(reopened, "interface not closed");
+}
+
+#[test]
+#[serial]
+fn it_reads_flags() {
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let flags = iface.tun_flags().expect("failed to read flags");
+    assert!(flags.contains(TunFlags::TUN | TunFlags::NO_PI | TunFlags::PERSIST));
+    assert!(iface.is_persistent().unwrap());
+    assert_eq!(flags.mode(), Some(Mode::Tun));
+    let iff_flags = iface.iff_flags().expect("failed to read interface flags");
+    assert!(iff_flags.contains(IffFlags::UP));
+    iface
+        .set_offload(OffloadFlags::empty())
+        .expect("failed to set offload");
+    iface.set_no_checksum(true).expect("failed to turn checksums off");
+    iface.set_no_checksum(false).expect("failed to turn checksums on");
+    assert!(!iface.is_multiqueue().expect("failed to read flags"));
+    assert_eq!(1, iface.queue_count().expect("failed to count queues"));
+    drop(iface);
+
+    let iface = Iface::with_flags("tun10", TunFlags::TUN).expect("failed to create a TUN device");
+    assert!(!iface.tun_flags().unwrap().contains(TunFlags::NO_PI));
+    drop(iface);
+
+    match Iface::with_flags("tun10", TunFlags::TUN | TunFlags::TAP) {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::InvalidInput),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+#[serial]
+fn it_adopts_from_systemd() {
+    use std::env;
+    use std::os::unix::io::AsRawFd;
+
+    // Pretend systemd passed the device as the last of many file descriptors, on a high number
+    // not used by anything else.
+    const FD: i32 = 203;
+    assert!(unsafe { libc::fcntl(FD, libc::F_GETFD) } < 0);
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    assert_eq!(unsafe { libc::dup2(iface.as_raw_fd(), FD) }, FD);
+    let mut names = vec!["other"; FD as usize - 3];
+    names.push("vpn");
+    env::set_var("LISTEN_FDS", (FD - 2).to_string());
+    env::set_var("LISTEN_FDNAMES", names.join(":"));
+    let not_found = |result| match result {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+        other => panic!("unexpected result {:?}", other),
+    };
+
+    // Not for us without the PID
+    not_found(Iface::from_systemd("vpn"));
+    env::set_var("LISTEN_PID", std::process::id().to_string());
+    not_found(Iface::from_systemd("missing"));
+    let adopted = Iface::from_systemd("vpn").expect("failed to adopt the device");
+    // Only one owner of the file descriptor
+    not_found(Iface::from_systemd("vpn"));
+    for var in &["LISTEN_PID", "LISTEN_FDS", "LISTEN_FDNAMES"] {
+        env::remove_var(var);
+    }
+    assert_eq!(adopted.name(), "tun10");
+    assert_eq!(adopted.mode(), Mode::Tun);
+    assert!(!adopted.packet_info());
+    assert!(adopted.tun_flags().unwrap().contains(TunFlags::NO_PI));
+}
+
+#[test]
+#[serial]
+fn it_adopts_file_descriptors() {
+    use std::os::unix::io::AsFd;
+
+    for &packet_info in &[true, false] {
+        let iface = if packet_info {
+            Iface::new("tun10", Mode::Tun)
+        } else {
+            Iface::without_packet_info("tun10", Mode::Tun)
+        }
+        .expect("failed to create a TUN device");
+        assert_eq!(iface.packet_info(), packet_info);
+        let fd = iface.as_fd().try_clone_to_owned().unwrap();
+        let adopted = Iface::from_fd(fd).expect("failed to adopt the device");
+        assert_eq!(adopted.name(), "tun10");
+        assert_eq!(adopted.mode(), Mode::Tun);
+        assert_eq!(adopted.packet_info(), packet_info);
+    }
+
+    let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+    match Iface::from_fd(socket.into()) {
+        Err(Error::NotATunDevice) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+#[serial]
+fn it_rejects_devices_of_other_namespaces() {
+    // The network namespace is per thread, the rest of the tests stay in the original one.
+    let iface = thread::spawn(|| {
+        if unsafe { libc::unshare(libc::CLONE_NEWNET) } < 0 {
+            return None;
+        }
+        Some(Iface::new("tun36", Mode::Tun).expect("failed to create a TUN device"))
+    })
+    .join()
+    .unwrap();
+    let iface = match iface {
+        Some(iface) => iface,
+        None => {
+            eprintln!("Skipping, can't create a network namespace");
+            return;
+        },
+    };
+    match Iface::from_file(iface.into_file()) {
+        Err(Error::Io(e)) => assert_eq!(e.kind(), std::io::ErrorKind::NotFound),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+#[serial]
+fn it_converts_into_file_and_back() {
+    use std::fs::File;
+    use std::os::unix::io::AsRawFd;
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let fd = iface.as_raw_fd();
+    let file = iface.into_file();
+    assert_eq!(file.as_raw_fd(), fd);
+    let iface = Iface::from_file(file).expect("failed to reclaim the device");
+    assert_eq!(iface.as_raw_fd(), fd);
+    assert_eq!(iface.name(), "tun10");
+    assert_eq!(iface.mode(), Mode::Tun);
+    assert!(!iface.packet_info());
+
+    let file = File::open("/dev/null").unwrap();
+    match Iface::from_file(file) {
+        Err(Error::NotATunDevice) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+#[serial]
+fn it_keeps_cleanup_in_file() {
+    use std::fs;
+    use tun_tap::Cleanup;
+
+    let is_up = || {
+        let flags = fs::read_to_string("/sys/class/net/tun33/flags").expect("no flags");
+        u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).unwrap() & 1 == 1
+    };
+    let mut iface = Iface::new("tun33", Mode::Tun).expect("failed to create a TUN device");
+    iface.set_cleanup(Cleanup::LinkDown).expect("failed to set cleanup");
+    iface.set_up(true).expect("failed to bring the device up");
+    let file = iface.into_file();
+    assert!(is_up());
+    let iface = Iface::from_file(file).expect("failed to reclaim the device");
+    assert_eq!(Cleanup::LinkDown, iface.cleanup());
+    // A bare file of another handle, the cleanup stays with the original
+    let other = Iface::from_file(iface.try_clone().unwrap().into_file().into_inner())
+        .expect("failed to reclaim the device");
+    assert_eq!(Cleanup::Nothing, other.cleanup());
+    assert!(is_up());
+    drop(other);
+    // Taking the bare file out cleans up, while the file keeps the device alive
+    let file = iface.into_file().into_inner();
+    assert!(!is_up());
+    drop(file);
+}
+
+#[cfg(feature = "helper")]
+#[test]
+#[serial]
+fn it_creates_through_helper() {
+    use tun_tap::helper::Helper;
+
+    let helper = Helper::new(env!("CARGO_BIN_EXE_tun-tap-helper"));
+    let iface = helper
+        .create("tun10", TunFlags::TUN | TunFlags::NO_PI)
+        .expect("failed to create a TUN device");
+    assert_eq!(iface.name(), "tun10");
+    assert_eq!(iface.mode(), Mode::Tun);
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    assert_eq!(iface.recv(&mut buf).expect("failed to receive data"), 38);
+
+    match helper.create("a_very_long_interface_name", TunFlags::TUN) {
+        Err(Error::NameTooLong) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+#[serial]
+fn it_tells_taken_names_from_bad_flags() {
+    match Iface::new("lo", Mode::Tun) {
+        Err(Error::NameTaken) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    let iface = Iface::new("tun31", Mode::Tun).expect("failed to create a TUN device");
+    // Same kind and mode, but the kernel refuses the multi-queue mismatch
+    match Iface::with_flags("tun31", TunFlags::TUN | TunFlags::MULTI_QUEUE) {
+        Err(Error::Io(e)) => assert_eq!(e.raw_os_error(), Some(libc::EINVAL)),
+        other => panic!("unexpected result {:?}", other),
+    }
+    drop(iface);
+}
+
+#[test]
+fn it_rejects_invalid_names() {
+    let invalid = [
+        ("tun\0", Error::NameContainsNul),
+        ("tun/0", Error::NameContainsInvalidChar('/')),
+        ("tun:0", Error::NameContainsInvalidChar(':')),
+        ("tun 0", Error::NameContainsInvalidChar(' ')),
+        ("tun\t0", Error::NameContainsInvalidChar('\t')),
+        ("..", Error::InvalidName),
+        ("tun%", Error::InvalidName),
+        ("tun%s", Error::InvalidName),
+        ("tun%d%d", Error::InvalidName),
+    ];
+    for (name, expected) in &invalid {
+        match Iface::new(name, Mode::Tun) {
+            Err(ref e) if e.to_string() == expected.to_string() => (),
+            other => panic!("unexpected result {:?} for {:?}", other, name),
+        }
+    }
+}
+
+/// Checks the user (or group) ID can be used, which isn't the case in a user namespace without
+/// it mapped (see tests/netns.sh).
+#[cfg(feature = "cli")]
+fn is_mapped(map: &str, id: u32) -> bool {
+    let map = std::fs::read_to_string(format!("/proc/self/{}", map)).unwrap();
+    map.lines().any(|line| {
+        let range = line
+            .split_whitespace()
+            .map(|n| n.parse::<u64>().unwrap())
+            .collect::<Vec<_>>();
+        (range[0]..range[0] + range[2]).contains(&u64::from(id))
+    })
+}
+
+#[cfg(feature = "cli")]
+#[test]
+#[serial]
+fn it_manages_persistent_devices() {
+    use std::path::Path;
+    use std::process::Command;
+
+    let ids = [("uid_map", 1234), ("uid_map", 4321), ("gid_map", 42)];
+    if !ids.iter().all(|&(map, id)| is_mapped(map, id)) {
+        eprintln!("Skipping, the users are not mapped in this user namespace");
+        return;
+    }
+
+    let cli = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_tuntap"))
+            .args(args)
+            .output()
+            .expect("failed to run the cli");
+        assert!(output.status.success(), "{:?} failed: {:?}", args, output);
+        String::from_utf8(output.stdout).unwrap()
+    };
+
+    assert_eq!("tun12\n", cli(&["add", "tun12", "tap", "--user", "1234", "--no-pi"]));
+    // It survives the cli exiting
+    assert!(Path::new("/sys/class/net/tun12").exists());
+    let info = cli(&["show", "tun12"]);
+    assert!(info.contains("mode: Tap"), "{}", info);
+    assert!(info.contains("PERSIST"), "{}", info);
+    assert!(info.contains("NO_PI"), "{}", info);
+    assert!(info.contains("owner: 1234"), "{}", info);
+    assert!(info.contains("group: none"), "{}", info);
+    cli(&["set", "tun12", "--user", "4321", "--group", "42"]);
+    let info = cli(&["show", "tun12"]);
+    assert!(info.contains("owner: 4321"), "{}", info);
+    assert!(info.contains("group: 42"), "{}", info);
+    cli(&["del", "tun12"]);
+    assert!(!Path::new("/sys/class/net/tun12").exists());
+}
+
+#[test]
+#[serial]
+fn it_detects_missing_capability() {
+    #[repr(C)]
+    struct CapHeader {
+        version: u32,
+        pid: i32,
+    }
+    #[repr(C)]
+    #[derive(Copy, Clone)]
+    struct CapData {
+        effective: u32,
+        permitted: u32,
+        inheritable: u32,
+    }
+    const CAP_NET_ADMIN: u32 = 12;
+
+    // The capabilities are per thread, so drop it in a separate one.
+    let result = thread::spawn(|| {
+        let mut header = CapHeader {
+            version: 0x2008_0522,
+            pid: 0,
+        };
+        let mut data = [CapData {
+            effective: 0,
+            permitted: 0,
+            inheritable: 0,
+        }; 2];
+        unsafe {
+            assert_eq!(libc::syscall(libc::SYS_capget, &mut header, data.as_mut_ptr()), 0);
+            data[0].effective &= !(1 << CAP_NET_ADMIN);
+            assert_eq!(libc::syscall(libc::SYS_capset, &mut header, data.as_ptr()), 0);
+        }
+        // Attaching to tun10 is allowed to anyone (it has no owner), creating a new one is not.
+        Iface::new("tun11", Mode::Tun)
+    })
+    .join()
+    .unwrap();
+    match result {
+        Err(Error::MissingCapability) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+fn it_diagnoses() {
+    use tun_tap::doctor::{self, Status};
+
+    let report = doctor::diagnose();
+    assert!(report.is_ok(), "{}", report);
+    let features = report.features.expect("no features");
+    assert!(features.contains(TunFlags::TUN | TunFlags::TAP | TunFlags::NO_PI));
+    assert_eq!(Some(true), report.net_admin);
+    let access = report.checks.iter().find(|check| check.name == "access").unwrap();
+    assert_eq!(Status::Ok, access.status);
+    assert!(report.to_string().contains("[ok] access: can be opened"));
+}
+
+#[test]
+#[serial]
+fn it_drops_privileges() {
+    use tun_tap::privileges::DropPrivileges;
+
+    // The capabilities are per thread, so the rest of the tests keep them.
+    thread::spawn(|| {
+        let iface =
+            Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+        DropPrivileges::new()
+            .apply()
+            .expect("failed to drop privileges");
+        match Iface::new("tun11", Mode::Tun) {
+            Err(Error::MissingCapability) => (),
+            other => panic!("unexpected result {:?}", other),
+        }
+        let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+        socket
+            .send_to(&[1; 10], "10.10.10.2:4242")
+            .expect("failed to send data");
+        let mut buf = [0; 50];
+        assert_eq!(iface.recv(&mut buf).expect("failed to receive data"), 38);
+    })
+    .join()
+    .unwrap();
+}
+
+#[test]
+#[serial]
+fn it_interrupts_blocked_recv() {
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let handle = iface.shutdown_handle().expect("failed to create shutdown handle");
+    let reader = thread::spawn(move || {
+        let mut buf = [0; 1500];
+        loop {
+            if let Err(e) = iface.recv(&mut buf) {
+                return e;
+            }
+        }
+    });
+    handle.shutdown().expect("failed to shut down");
+    let error = reader.join().expect("reader panicked");
+    assert!(Shutdown::is_shutdown(&error), "unexpected error {}", error);
+}
+
+#[test]
+#[serial]
+fn it_counts_packets() {
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let iface = InstrumentedIface::new(iface);
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    let num = iface.recv(&mut buf).expect("failed to receive data");
+    assert_eq!(num, 38);
+    let counters = iface.counters();
+    assert_eq!(counters.packets_received, 1);
+    assert_eq!(counters.bytes_received, 38);
+    assert_eq!(counters.packets_sent, 0);
+    assert_eq!(counters.recv_errors, 0);
+}
+
+#[cfg(feature = "metrics")]
+#[test]
+#[serial]
+fn it_reports_metrics() {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering};
+    use std::sync::{Arc, Mutex};
+
+    use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+
+    #[derive(Default)]
+    struct TestRecorder(Mutex<HashMap<String, Arc<AtomicU64>>>);
+
+    impl Recorder for TestRecorder {
+        fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+        fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+            let label = key.labels().next().expect("missing the interface label");
+            assert_eq!(("interface", "tun10"), (label.key(), label.value()));
+            let mut counters = self.0.lock().unwrap();
+            let counter = counters.entry(key.name().to_owned()).or_default();
+            Counter::from_arc(Arc::clone(counter))
+        }
+        fn register_gauge(&self, _: &Key, _: &Metadata<'_>) -> Gauge {
+            Gauge::noop()
+        }
+        fn register_histogram(&self, _: &Key, _: &Metadata<'_>) -> Histogram {
+            Histogram::noop()
+        }
+    }
+
+    let recorder = TestRecorder::default();
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let iface = metrics::with_local_recorder(&recorder, || InstrumentedIface::new(iface));
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    let num = iface.recv(&mut buf).expect("failed to receive data");
+    assert_eq!(num, 38);
+    let counters = recorder.0.lock().unwrap();
+    let value = |name: &str| counters[name].load(Ordering::Relaxed);
+    assert_eq!(1, value("tuntap_packets_received"));
+    assert_eq!(38, value("tuntap_bytes_received"));
+    assert_eq!(0, value("tuntap_packets_sent"));
+    assert_eq!(0, value("tuntap_recv_errors"));
+}
+
+#[test]
+fn it_rejects_long_names() {
+    match Iface::new("thisnameistoolong", Mode::Tun) {
+        Err(Error::NameTooLong) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+}
+
+#[test]
+#[serial]
+fn it_sends_with_proto() {
+    let iface = Iface::new("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let data = [1; 10];
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    let builder = PacketBuilder::ipv4([10, 10, 10, 2], [10, 10, 10, 1], 20).udp(4242, 2424);
+    let mut packet = Vec::<u8>::with_capacity(builder.size(data.len()));
+    builder
+        .write(&mut packet, &data)
+        .expect("failed to build packet");
+    let sent = iface
+        .send_with_proto(EtherType::Ipv4, &packet)
+        .expect("failed to send packet");
+    assert_eq!(sent, packet.len());
+    let mut buf = [0; 50];
+    let num = socket.recv(&mut buf).expect("failed to receive packet");
+    assert_eq!(data, &buf[..num]);
+}
+
+#[test]
+#[serial]
+fn it_types_packet_info() {
+    use tun_tap::typed::{NoPi, TypedIface, WithPi};
+
+    let iface = TypedIface::<WithPi>::new("tun10", Mode::Tun).expect("failed to create a device");
+    assert!(iface.get_ref().packet_info());
+    let data = [1; 10];
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    let builder = PacketBuilder::ipv4([10, 10, 10, 2], [10, 10, 10, 1], 20).udp(4242, 2424);
+    let mut packet = Vec::<u8>::with_capacity(builder.size(data.len()));
+    builder
+        .write(&mut packet, &data)
+        .expect("failed to build packet");
+    iface
+        .send_with_proto(EtherType::Ipv4, &packet)
+        .expect("failed to send packet");
+    let mut buf = [0; 50];
+    let num = socket.recv(&mut buf).expect("failed to receive packet");
+    assert_eq!(data, &buf[..num]);
+
+    // The packet info doesn't match, the interface is given back
+    let iface = TypedIface::<NoPi>::from_iface(iface.into_inner()).unwrap_err();
+    let iface = TypedIface::<WithPi>::from_iface(iface).expect("packet info matches");
+    drop(iface);
+    let iface = TypedIface::<NoPi>::new("tun10", Mode::Tun).expect("failed to create a device");
+    assert!(!iface.get_ref().packet_info());
+    assert!(TypedIface::<WithPi>::from_iface(iface.into_inner()).is_err());
+}
+
+#[test]
+#[serial]
+fn it_types_mode() {
+    use std::ffi::CString;
+    use std::fs;
+    use std::path::Path;
+    use tun_tap::packet::MacAddr;
+    use tun_tap::typed::{TapIface, TunIface};
+
+    let tun = TunIface::new("tun18").expect("failed to create a TUN device");
+    // ARPHRD_PPP
+    tun.set_link_type(512).expect("failed to set the link type");
+    let link = fs::read_to_string("/sys/class/net/tun18/type").unwrap();
+    assert_eq!("512", link.trim());
+    assert!(TapIface::from_iface(tun.into_inner()).is_err());
+
+    let tap = TapIface::without_packet_info("tap17").expect("failed to create a TAP device");
+    let mac = MacAddr([2, 0, 0, 0, 0, 0x17]);
+    tap.set_mac_address(mac).expect("failed to set the MAC address");
+    assert_eq!(mac, tap.mac_address().unwrap());
+    let tap = TapIface::from_iface(tap.into_inner()).expect("it is a TAP device");
+
+    // The bridge is created by hand, the library doesn't manage them
+    const SIOCBRADDBR: libc::c_ulong = 0x89a0;
+    const SIOCBRDELBR: libc::c_ulong = 0x89a1;
+    let bridge = CString::new("br17").unwrap();
+    let sock = unsafe { libc::socket(libc::AF_INET, libc::SOCK_DGRAM, 0) };
+    assert!(sock >= 0);
+    if unsafe { libc::ioctl(sock, SIOCBRADDBR as _, bridge.as_ptr()) } < 0 {
+        eprintln!("Skipping bridges: {}", std::io::Error::last_os_error());
+        unsafe { libc::close(sock) };
+        return;
+    }
+    tap.attach_to_bridge("br17").expect("failed to attach to the bridge");
+    assert!(Path::new("/sys/class/net/br17/brif/tap17").exists());
+    tap.detach_from_bridge("br17").expect("failed to detach from the bridge");
+    assert!(!Path::new("/sys/class/net/br17/brif/tap17").exists());
+    assert!(tap.attach_to_bridge("nosuchbridge").is_err());
+    unsafe {
+        libc::ioctl(sock, SIOCBRDELBR as _, bridge.as_ptr());
+        libc::close(sock);
+    }
+}
+
+#[test]
+#[serial]
+fn it_sends_with_header() {
+    let iface = Iface::new("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let data = [1; 10];
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    let builder = PacketBuilder::ipv4([10, 10, 10, 2], [10, 10, 10, 1], 20).udp(4242, 2424);
+    let mut header = PacketInfo::new(EtherType::Ipv4).to_bytes().to_vec();
+    builder
+        .write(&mut header, &data)
+        .expect("failed to build packet");
+    // The payload goes from a separate buffer
+    header.truncate(header.len() - data.len());
+    let sent = iface
+        .send_with_header(&header, &data)
+        .expect("failed to send packet");
+    assert_eq!(sent, data.len());
+    let mut buf = [0; 50];
+    let num = socket.recv(&mut buf).expect("failed to receive packet");
+    assert_eq!(data, &buf[..num]);
+}
+
+#[test]
+#[serial]
+fn it_glues_packets() {
+    use tun_tap::gso::GsoSender;
+
+    let iface = Iface::with_flags("tun10", TunFlags::TUN | TunFlags::NO_PI | TunFlags::VNET_HDR)
+        .expect("failed to create a TUN device");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    let packets = (1..=3)
+        .map(|i| {
+            let builder = PacketBuilder::ipv4([10, 10, 10, 2], [10, 10, 10, 1], 20).udp(4242, 2424);
+            let mut packet = Vec::<u8>::with_capacity(builder.size(100));
+            builder
+                .write(&mut packet, &[i; 100])
+                .expect("failed to build packet");
+            packet
+        })
+        .collect::<Vec<_>>();
+    let mut sender = GsoSender::new(iface, true);
+    sender.push(&packets[0]).expect("failed to send packet");
+    sender.push(&packets[1]).expect("failed to send packet");
+    sender.push(&packets[2]).expect("failed to send packet");
+    assert_eq!(sender.pending(), 3);
+    sender.flush().expect("failed to send packets");
+    // Split back by the kernel
+    let mut buf = [0; 500];
+    for i in 1..=3 {
+        let num = socket.recv(&mut buf).expect("failed to receive packet");
+        assert_eq!(&[i; 100][..], &buf[..num]);
+    }
+}
+
+#[test]
+#[serial]
+fn it_runs_selftest() {
+    use std::time::Duration;
+    use tun_tap::selftest::SelfTest;
+    use tun_tap::tee::Direction;
+
+    let iface = Iface::new("tun10", Mode::Tun).expect("failed to create a TUN device");
+    for &direction in &[Direction::Sent, Direction::Received] {
+        let report = SelfTest::new(Ipv4Addr::new(10, 10, 10, 1), Ipv4Addr::new(10, 10, 10, 2))
+            .direction(direction)
+            .packets(100)
+            .payload_size(100)
+            .timeout(Duration::from_millis(200))
+            .run(&iface)
+            .expect("failed to run the self test");
+        assert_eq!(report.packets_sent, 100);
+        assert!(report.packets_received > 0, "nothing received {:?}", direction);
+        assert_eq!(report.bytes_received, report.packets_received * 128);
+        assert!(report.pps() > 0.0);
+        let median = report.latency(0.5).expect("no latency");
+        assert!(median <= report.latency(0.99).unwrap());
+    }
+}
+
+#[test]
+#[serial]
+fn it_answers_arp() {
+    use tun_tap::arp::ArpResponder;
+    use tun_tap::packet::{EthernetHeader, MacAddr};
+
+    let iface = Iface::without_packet_info("tap15", Mode::Tap).expect("failed to create a TAP");
+    let net = TestNet::configure(iface, Ipv4Addr::new(10, 10, 15, 1), 24)
+        .expect("failed to configure the device");
+    let iface = net.get_ref();
+    let mac = MacAddr([0x02, 0, 0, 0, 0x15, 2]);
+    let mut arp = ArpResponder::new();
+    arp.insert(Ipv4Addr::new(10, 10, 15, 2), mac);
+    let socket = UdpSocket::bind("10.10.15.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.15.2:4242")
+        .expect("failed to send data");
+    // The kernel asks first, then sends the packet to the answered address
+    let mut buf = [0; 1518];
+    loop {
+        let size = iface.recv(&mut buf).expect("failed to receive");
+        if arp.respond(iface, &buf[..size]).expect("failed to answer") {
+            continue;
+        }
+        let (header, _) = EthernetHeader::parse(&buf[..size]).unwrap();
+        if header.ether_type == EtherType::Ipv4 {
+            assert_eq!(header.destination, mac);
+            break;
+        }
+    }
+}
+
+#[test]
+#[serial]
+fn it_opens_raw_socket() {
+    use tun_tap::packet::{EthernetHeader, MacAddr};
+    use tun_tap::raw::RawIface;
+    use tun_tap::Device;
+
+    assert!(RawIface::new("nosuchiface").is_err());
+    let tap = Iface::without_packet_info("tap26", Mode::Tap).expect("failed to create a TAP");
+    tap.set_up(true).expect("failed to bring the device up");
+    let raw = RawIface::new("tap26").expect("failed to open the raw socket");
+    assert_eq!(Mode::Tap, Device::mode(&raw));
+    assert_eq!(tap.mtu().unwrap(), raw.mtu().unwrap());
+    assert_eq!(tap.index().unwrap(), raw.index());
+    raw.set_promiscuous(true).expect("failed to set promiscuous mode");
+    let header = EthernetHeader {
+        destination: MacAddr([0x02, 0, 0, 0, 0x26, 1]),
+        source: MacAddr([0x02, 0, 0, 0, 0x26, 2]),
+        ether_type: EtherType::Other(0x88B5),
+    };
+    let mut frame = header.to_bytes().to_vec();
+    frame.extend_from_slice(&[0x26; 50]);
+    // Sent into the wire, the TAP device is the other end of it
+    raw.send(&frame).expect("failed to send");
+    let mut buf = [0; 1518];
+    // The kernel may send its own (IPv6) frames through the device
+    let size = loop {
+        let size = tap.recv(&mut buf).expect("failed to receive");
+        if buf[12..14] == [0x88, 0xB5] {
+            break size;
+        }
+    };
+    assert_eq!(&frame[..], &buf[..size]);
+    // And back from the other end, the sent frame is not seen again
+    frame[14] = 0x62;
+    tap.send(&frame).expect("failed to send");
+    let size = raw.recv(&mut buf).expect("failed to receive");
+    assert_eq!(&frame[..], &buf[..size]);
+}
+
+#[test]
+#[serial]
+fn it_creates_many() {
+    use std::collections::HashSet;
+    use std::path::Path;
+
+    let ifaces = Iface::new_many("many%d", Mode::Tap, 5).expect("failed to create devices");
+    assert_eq!(5, ifaces.len());
+    let names = ifaces.iter().map(|iface| iface.name().to_owned()).collect::<HashSet<_>>();
+    assert_eq!(5, names.len());
+    for iface in &ifaces {
+        assert!(iface.name().starts_with("many"));
+        assert_eq!(Mode::Tap, iface.mode());
+    }
+    drop(ifaces);
+    for name in &names {
+        assert!(!Path::new(&format!("/sys/class/net/{}", name)).exists());
+    }
+    match Iface::new_many("many", Mode::Tap, 2) {
+        Err(Error::InvalidName) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    assert!(Iface::new_many("many%d", Mode::Tun, 0).unwrap().is_empty());
+}
+
+#[test]
+#[serial]
+fn it_cleans_up_on_drop() {
+    use std::io::ErrorKind;
+    use std::path::Path;
+    use tun_tap::Cleanup;
+
+    let mut iface = Iface::new("tun24", Mode::Tun).expect("failed to create the device");
+    assert_eq!(Cleanup::Nothing, iface.cleanup());
+    iface.set_persistent(true).expect("failed to make the device persistent");
+    iface.set_ipv4(Ipv4Addr::new(10, 10, 24, 1), 24).expect("failed to set the address");
+    iface.set_up(true).expect("failed to bring the device up");
+    iface.set_cleanup(Cleanup::FlushAddresses).unwrap();
+    assert_eq!(Cleanup::FlushAddresses, iface.cleanup());
+    // The clone keeps it configured
+    let clone = iface.try_clone().unwrap();
+    drop(iface);
+    assert!(clone.iff_flags().unwrap().contains(IffFlags::UP));
+    drop(UdpSocket::bind("10.10.24.1:2424").expect("failed to bind to address"));
+    drop(clone);
+
+    let mut iface = Iface::new("tun24", Mode::Tun).expect("failed to attach");
+    assert!(iface.is_persistent().unwrap());
+    assert!(!iface.iff_flags().unwrap().contains(IffFlags::UP));
+    let err = UdpSocket::bind("10.10.24.1:2424").unwrap_err();
+    assert_eq!(ErrorKind::AddrNotAvailable, err.kind());
+    iface.set_cleanup(Cleanup::RemovePersistence).unwrap();
+    drop(iface);
+    assert!(!Path::new("/sys/class/net/tun24").exists());
+}
+
+#[test]
+#[serial]
+fn it_reverts_configuration() {
+    use std::fs;
+    use tun_tap::ConfiguredIface;
+
+    fn routed(name: &str) -> bool {
+        // The destination 10.25.0.0 in the host byte order of the kernel
+        let destination = format!("{:08X}", u32::from_ne_bytes([10, 25, 0, 0]));
+        fs::read_to_string("/proc/net/route")
+            .unwrap()
+            .lines()
+            .any(|line| line.starts_with(name) && line.contains(&destination))
+    }
+
+    let iface = Iface::new("tun25", Mode::Tun).expect("failed to create the device");
+    iface.set_persistent(true).expect("failed to make the device persistent");
+    assert_eq!(None, iface.ipv4().unwrap());
+    let mtu = iface.mtu().unwrap();
+    let result = thread::spawn(move || {
+        let mut configured = ConfiguredIface::new(iface);
+        configured.set_ipv4(Ipv4Addr::new(10, 10, 25, 1), 24).unwrap();
+        configured.set_up(true).unwrap();
+        configured.set_mtu(1400).unwrap();
+        configured.add_route(Ipv4Addr::new(10, 25, 0, 0), 16).unwrap();
+        let iface = configured.get_ref();
+        assert_eq!(Some((Ipv4Addr::new(10, 10, 25, 1), 24)), iface.ipv4().unwrap());
+        assert_eq!(1400, iface.mtu().unwrap());
+        assert!(routed("tun25"));
+        panic!("Midway through the test");
+    })
+    .join();
+    assert!(result.is_err());
+
+    let iface = Iface::new("tun25", Mode::Tun).expect("failed to attach");
+    assert_eq!(None, iface.ipv4().unwrap());
+    assert_eq!(mtu, iface.mtu().unwrap());
+    assert!(!iface.iff_flags().unwrap().contains(IffFlags::UP));
+    assert!(!routed("tun25"));
+
+    let mut configured = ConfiguredIface::new(iface);
+    configured.set_ipv4(Ipv4Addr::new(10, 10, 25, 1), 24).unwrap();
+    let iface = configured.into_inner();
+    assert_eq!(Some((Ipv4Addr::new(10, 10, 25, 1), 24)), iface.ipv4().unwrap());
+    iface.set_persistent(false).expect("failed to remove the device");
+}
+
+#[test]
+#[serial]
+fn it_timestamps_packets() {
+    use std::time::{Duration, SystemTime};
+    use tun_tap::Timestamp;
+
+    let iface = Iface::without_packet_info("tun10", Mode::Tun).expect("failed to attach");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    let before = Timestamp::now();
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    let (size, timestamp) = iface.recv_timestamped(&mut buf).expect("failed to receive data");
+    assert_eq!(38, size);
+    assert!(timestamp.monotonic >= before.monotonic);
+    assert!(timestamp.duration_since(&before) < Duration::from_secs(5));
+    assert!(timestamp.realtime > SystemTime::UNIX_EPOCH);
+    assert_eq!(Duration::ZERO, before.duration_since(&timestamp));
+}
+
+#[test]
+#[serial]
+fn it_asks_for_one_queue() {
+    use tun_tap::IfaceConfig;
+
+    let supported = PendingIface::open()
+        .expect("failed to open /dev/net/tun")
+        .supported_flags()
+        .expect("failed to read the features");
+    assert!(supported.contains(TunFlags::NO_PI | TunFlags::MULTI_QUEUE));
+    let mut config = IfaceConfig::new("tun23", Mode::Tun);
+    config.one_queue = true;
+    let iface = config.create().expect("failed to create the device");
+    let flags = iface.tun_flags().unwrap();
+    assert_eq!(supported.contains(TunFlags::ONE_QUEUE), flags.contains(TunFlags::ONE_QUEUE));
+    assert!(iface.packet_info());
+}
+
+#[test]
+#[serial]
+fn it_configures_test_net() {
+    use std::io::ErrorKind;
+    use tun_tap::{IfaceConfig, IffFlags};
+
+    let mut config = IfaceConfig::new("tun16", Mode::Tun);
+    config.packet_info = false;
+    let net = TestNet::new(&config, Ipv4Addr::new(10, 10, 16, 1), 24)
+        .expect("failed to create the test network");
+    let iface = net.get_ref();
+    assert!(iface.iff_flags().unwrap().contains(IffFlags::UP));
+    // A persistent one, to see the configuration removed
+    assert!(!iface.is_persistent().unwrap());
+    iface.set_persistent(true).expect("failed to make the device persistent");
+    assert!(iface.is_persistent().unwrap());
+    let socket = UdpSocket::bind("10.10.16.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.16.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 1500];
+    // Skip any IPv6 noise of a freshly brought up device
+    loop {
+        let size = iface.recv(&mut buf).expect("failed to receive data");
+        if buf[0] >> 4 == 4 {
+            assert_eq!(size, 38);
+            break;
+        }
+    }
+    drop(socket);
+    drop(net);
+
+    let iface = Iface::without_packet_info("tun16", Mode::Tun).expect("failed to attach");
+    assert!(!iface.iff_flags().unwrap().contains(IffFlags::UP));
+    let err = UdpSocket::bind("10.10.16.1:2424").unwrap_err();
+    assert_eq!(ErrorKind::AddrNotAvailable, err.kind());
+    assert!(iface.is_persistent().unwrap());
+    iface.set_persistent(false).expect("failed to remove the device");
+    assert!(!iface.is_persistent().unwrap());
+}
+
+#[test]
+#[serial]
+fn it_ensures_devices() {
+    use tun_tap::Ensured;
+
+    let (iface, ensured) =
+        Iface::ensure("tun19", Mode::Tun, TunFlags::NO_PI).expect("failed to create");
+    assert_eq!(Ensured::Created, ensured);
+    iface.set_persistent(true).expect("failed to make the device persistent");
+    match Iface::ensure("tun19", Mode::Tun, TunFlags::empty()) {
+        Err(Error::DeviceBusy) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    drop(iface);
+
+    let (iface, ensured) =
+        Iface::ensure("tun19", Mode::Tun, TunFlags::empty()).expect("failed to attach");
+    assert_eq!(Ensured::Attached, ensured);
+    assert!(iface.packet_info());
+    drop(iface);
+    match Iface::ensure("tun19", Mode::Tap, TunFlags::empty()) {
+        Err(Error::NameTaken) => (),
+        other => panic!("unexpected result {:?}", other),
+    }
+    let (iface, _) = Iface::ensure("tun19", Mode::Tun, TunFlags::TUN_EXCL).expect("no attach");
+    iface.set_persistent(false).expect("failed to remove the device");
+}
+
+#[test]
+#[serial]
+fn it_restores_deleted_devices() {
+    use std::process::Command;
+    use std::sync::{Arc, Mutex};
+    use std::time::Duration;
+    use tun_tap::resilient::{Event, ResilientBuilder};
+    use tun_tap::IfaceConfig;
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let events_log = Arc::clone(&events);
+    let mut config = IfaceConfig::new("tun20", Mode::Tun);
+    config.packet_info = false;
+    let iface = ResilientBuilder::new(config)
+        .backoff(Duration::from_millis(10), Duration::from_millis(100))
+        .on_event(move |event: &Event| {
+            let kind = match event {
+                Event::Lost(_) => "lost",
+                Event::Failed { .. } => "failed",
+                Event::Restored { .. } => "restored",
+                _ => "other",
+            };
+            events_log.lock().unwrap().push(kind);
+        })
+        .create(|iface: &Iface| {
+            iface.set_ipv4(Ipv4Addr::new(10, 10, 20, 1), 24)?;
+            iface.set_up(true)
+        })
+        .expect("failed to create the device");
+
+    // The library can't delete devices, so it needs the ip command
+    let deleted = Command::new("ip").args(["link", "del", "tun20"]).status();
+    if !deleted.map(|status| status.success()).unwrap_or(false) {
+        eprintln!("Skipping, can't delete the device");
+        return;
+    }
+    thread::scope(|scope| {
+        scope.spawn(|| {
+            while iface.restorations() == 0 {
+                thread::sleep(Duration::from_millis(10));
+            }
+            let socket = UdpSocket::bind("10.10.20.1:0").expect("failed to bind to address");
+            socket
+                .send_to(&[1; 10], "10.10.20.2:4242")
+                .expect("failed to send data");
+        });
+        let mut buf = [0; 1500];
+        // Skip any IPv6 noise of a freshly brought up device
+        loop {
+            let size = iface.recv(&mut buf).expect("failed to receive data");
+            if buf[0] >> 4 == 4 {
+                assert_eq!(size, 38);
+                break;
+            }
+        }
+    });
+    assert_eq!(1, iface.restorations());
+    assert_eq!("tun20", iface.name());
+    assert_eq!(vec!["lost", "restored"], *events.lock().unwrap());
+}
+
+#[test]
+#[serial]
+fn it_does_not_restore_devices_that_are_down() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use tun_tap::resilient::{Event, ResilientBuilder};
+    use tun_tap::IfaceConfig;
+
+    let events = Arc::new(AtomicUsize::new(0));
+    let events_seen = Arc::clone(&events);
+    let mut config = IfaceConfig::new("tun32", Mode::Tun);
+    config.packet_info = false;
+    let iface = ResilientBuilder::new(config)
+        .on_event(move |_: &Event| {
+            events_seen.fetch_add(1, Ordering::SeqCst);
+        })
+        .create(|_: &Iface| Ok(()))
+        .expect("failed to create the device");
+    // Writing into a device that is down fails with EIO, but the device is still there
+    let error = iface.send(&[0x45; 20]).unwrap_err();
+    assert_eq!(Some(libc::EIO), error.raw_os_error());
+    assert_eq!(0, iface.restorations());
+    assert_eq!(0, events.load(Ordering::SeqCst));
+    assert!(iface.current().is_some());
+}
+
+/// Pings the address through a raw socket, returns the ICMP part of the reply.
+fn ping(address: Ipv4Addr) -> std::io::Result<Vec<u8>> {
+    use std::io::Error;
+    use std::mem;
+
+    unsafe {
+        let sock = libc::socket(libc::AF_INET, libc::SOCK_RAW, libc::IPPROTO_ICMP);
+        if sock < 0 {
+            return Err(Error::last_os_error());
+        }
+        let timeout = libc::timeval {
+            tv_sec: 5,
+            tv_usec: 0,
+        };
+        libc::setsockopt(
+            sock,
+            libc::SOL_SOCKET,
+            libc::SO_RCVTIMEO,
+            &timeout as *const _ as *const libc::c_void,
+            mem::size_of::<libc::timeval>() as libc::socklen_t,
+        );
+        // Echo request, identifier 42, sequence 1, with the checksum precomputed
+        let request = [8u8, 0, 0xf3, 0xce, 0, 42, 0, 1, 1, 2, 3, 4];
+        let mut addr: libc::sockaddr_in = mem::zeroed();
+        addr.sin_family = libc::AF_INET as libc::sa_family_t;
+        addr.sin_addr.s_addr = u32::from(address).to_be();
+        let sent = libc::sendto(
+            sock,
+            request.as_ptr() as *const libc::c_void,
+            request.len(),
+            0,
+            &addr as *const _ as *const libc::sockaddr,
+            mem::size_of::<libc::sockaddr_in>() as libc::socklen_t,
+        );
+        let mut buf = [0u8; 100];
+        let result = if sent < 0 {
+            Err(Error::last_os_error())
+        } else {
+            loop {
+                let size = libc::recv(sock, buf.as_mut_ptr() as *mut libc::c_void, buf.len(), 0);
+                if size < 0 {
+                    break Err(Error::last_os_error());
+                }
+                // The raw socket gets the IP header too, and our own request
+                let icmp = &buf[20..size as usize];
+                if icmp[0] == 0 {
+                    break Ok(icmp.to_vec());
+                }
+            }
+        };
+        libc::close(sock);
+        result
+    }
+}
+
+#[test]
+#[serial]
+fn it_answers_ping() {
+    use tun_tap::icmp::EchoResponder;
+
+    let iface = Iface::new("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let shutdown = iface.shutdown_handle().expect("failed to get a shutdown handle");
+    let responder = thread::spawn(move || EchoResponder::new().run(&iface));
+    let reply = ping(Ipv4Addr::new(10, 10, 10, 2));
+    shutdown.shutdown().expect("failed to shut down");
+    responder
+        .join()
+        .unwrap()
+        .expect("failed to answer the ping");
+    let reply = reply.expect("failed to ping");
+    assert_eq!(&[0, 42, 0, 1, 1, 2, 3, 4], &reply[4..]);
+}
+
+#[test]
+#[serial]
+fn it_receives_parsed() {
+    let iface = Iface::new("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    let (info, packet) = iface.recv_parsed(&mut buf).expect("failed to receive data");
+    assert_eq!(info.expect("missing packet info").proto, EtherType::Ipv4);
+    assert_eq!(packet.len(), 38);
+}
+
+#[test]
+#[serial]
+fn it_mirrors_packets() {
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let (iface, mirror) = TeeIface::new(iface, 1);
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    for _ in 0..2 {
+        socket
+            .send_to(&[1; 10], "10.10.10.2:4242")
+            .expect("failed to send data");
+        let mut buf = [0; 50];
+        iface.recv(&mut buf).expect("failed to receive data");
+    }
+    drop(iface);
+    let packets = mirror.collect::<Vec<_>>();
+    assert_eq!(packets.len(), 1);
+    assert_eq!(packets[0].direction, Direction::Received);
+    assert_eq!(packets[0].data.len(), 38);
+}
+
+#[test]
+#[serial]
+fn it_clones() {
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let clone = iface.try_clone().expect("failed to clone the interface");
+    assert_eq!(iface.name(), clone.name());
+    let handle = iface.shutdown_handle().expect("failed to create shutdown handle");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    assert_eq!(clone.recv(&mut buf).expect("failed to receive data"), 38);
+    drop(clone);
+    // The original is still alive and the queue is now empty.
+    handle.shutdown().expect("failed to shut down");
+    let err = iface.recv(&mut buf).unwrap_err();
+    assert!(Shutdown::is_shutdown(&err));
+}
+
+#[test]
+#[serial]
+fn it_polls_many() {
+    use std::time::Duration;
+    use tun_tap::poll_set::{Interest, PollSet};
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    iface.set_non_blocking().expect("failed to set non-blocking");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    let poll_set = PollSet::new().expect("failed to create poll set");
+    poll_set
+        .add(&iface, 1, Interest::Readable)
+        .expect("failed to add the interface");
+    poll_set
+        .add(&socket, 2, Interest::Readable)
+        .expect("failed to add the socket");
+    let mut events = Vec::new();
+    let cnt = poll_set
+        .wait(&mut events, Some(Duration::from_millis(10)))
+        .expect("failed to wait");
+    assert_eq!(cnt, 0);
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    poll_set
+        .wait(&mut events, Some(Duration::from_secs(1)))
+        .expect("failed to wait");
+    assert_eq!(events.len(), 1);
+    assert_eq!(events[0].token, 1);
+    assert!(events[0].readable);
+    let mut buf = [0; 50];
+    assert_eq!(iface.recv(&mut buf).expect("failed to receive data"), 38);
+}
+
+#[cfg(feature = "capture")]
+#[test]
+#[serial]
+fn it_captures_packets() {
+    use tun_tap::capture::CaptureIface;
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let iface = CaptureIface::new(iface, Vec::new()).expect("failed to write the header");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    iface.recv(&mut buf).expect("failed to receive data");
+    assert!(iface.take_error().is_none());
+    let (_, pcap) = iface.into_inner();
+
+    let word =
+        |pos: usize| u32::from_ne_bytes([pcap[pos], pcap[pos + 1], pcap[pos + 2], pcap[pos + 3]]);
+    let mut blocks = Vec::new();
+    let mut pos = 0;
+    while pos < pcap.len() {
+        let len = word(pos + 4) as usize;
+        assert_eq!(len, word(pos + len - 4) as usize);
+        blocks.push((word(pos), pos));
+        pos += len;
+    }
+    assert_eq!(pos, pcap.len());
+    let types = blocks.iter().map(|b| b.0).collect::<Vec<_>>();
+    assert_eq!(types, vec![0x0A0D_0D0A, 1, 6]);
+    let epb = blocks[2].1;
+    // Captured and original length
+    assert_eq!(word(epb + 20), 38);
+    assert_eq!(word(epb + 24), 38);
+    assert_eq!(&pcap[epb + 28 + 28..epb + 28 + 38], &[1; 10]);
+    // The epb_flags option, marking the packet as outbound
+    assert_eq!(word(epb + 28 + 40), 4 << 16 | 2);
+    assert_eq!(word(epb + 28 + 44), 2);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_waits_for_readable() {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use futures::future::poll_fn;
+    use futures::Async as FAsync;
+    use tokio_core::reactor::Core;
+    use tun_tap::r#async::Async;
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let mut core = Core::new().expect("failed to create the core");
+    let mut iface = Async::new(iface, &core.handle()).expect("failed to wrap the interface");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    for _ in 0..2 {
+        socket
+            .send_to(&[1; 10], "10.10.10.2:4242")
+            .expect("failed to send data");
+    }
+    let ready = core.run(iface.readable()).expect("failed to wait");
+    assert!(ready.is_readable());
+    let mut buf = [0; 50];
+    let sizes = core
+        .run(poll_fn(|| {
+            let mut sizes = Vec::new();
+            while let FAsync::Ready(size) = iface.poll_recv(&mut buf)? {
+                sizes.push(size);
+            }
+            Ok::<_, std::io::Error>(FAsync::Ready(sizes))
+        }))
+        .expect("failed to receive data");
+    assert_eq!(sizes, vec![38, 38]);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_reattaches_streams() {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use futures::Stream;
+    use std::process::Command;
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+    use tun_tap::r#async::{Async, StreamEvent};
+    use tun_tap::IfaceConfig;
+
+    let config = IfaceConfig::new("tun21", Mode::Tun);
+    let setup = |iface: &Iface| {
+        iface.set_ipv4(Ipv4Addr::new(10, 10, 21, 1), 24)?;
+        iface.set_up(true)
+    };
+    let iface = config.create().expect("failed to create a TUN device");
+    setup(&iface).expect("failed to configure the device");
+    let mut core = Core::new().expect("failed to create the core");
+    let mut packets = Async::new(iface, &core.handle())
+        .expect("failed to wrap the interface")
+        .reattaching(config, setup);
+
+    // The library can't delete devices, so it needs the ip command
+    let deleted = Command::new("ip").args(["link", "del", "tun21"]).status();
+    if !deleted.map(|status| status.success()).unwrap_or(false) {
+        eprintln!("Skipping, can't delete the device");
+        return;
+    }
+    let (event, rest) = core
+        .run(packets.into_future())
+        .map_err(|(e, _)| e)
+        .expect("the stream failed");
+    packets = rest;
+    match event {
+        Some(StreamEvent::Interrupted(_)) => (),
+        other => panic!("unexpected event {:?}", other),
+    }
+    let sender = thread::spawn(|| {
+        // The setup brings the device up as the last thing
+        let is_up = || {
+            let flags = std::fs::read_to_string("/sys/class/net/tun21/flags").unwrap_or_default();
+            u32::from_str_radix(flags.trim().trim_start_matches("0x"), 16).unwrap_or(0) & 1 == 1
+        };
+        while !is_up() {
+            thread::sleep(Duration::from_millis(10));
+        }
+        let socket = UdpSocket::bind("10.10.21.1:0").expect("failed to bind to address");
+        socket
+            .send_to(&[1; 10], "10.10.21.2:4242")
+            .expect("failed to send data");
+    });
+    // Skip any IPv6 noise of a freshly brought up device
+    loop {
+        let (event, rest) = core
+            .run(packets.into_future())
+            .map_err(|(e, _)| e)
+            .expect("the stream failed");
+        packets = rest;
+        match event {
+            Some(StreamEvent::Packet(packet)) if packet.proto == EtherType::Ipv4 => {
+                assert_eq!(packet.payload.len(), 38);
+                break;
+            },
+            Some(StreamEvent::Packet(_)) => (),
+            other => panic!("unexpected event {:?}", other),
+        }
+    }
+    sender.join().unwrap();
+    assert_eq!("tun21", packets.get_ref().unwrap().iface().name());
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_streams_packets() {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use futures::Stream;
+    use tokio_core::reactor::Core;
+    use tun_tap::r#async::Async;
+
+    let iface = Iface::new("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let mut core = Core::new().expect("failed to create the core");
+    let packets = Async::new(iface, &core.handle())
+        .expect("failed to wrap the interface")
+        .packets();
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let (packet, _) = core
+        .run(packets.into_future())
+        .map_err(|(e, _)| e)
+        .expect("failed to receive data");
+    let packet = packet.expect("stream ended");
+    assert_eq!(packet.proto, EtherType::Ipv4);
+    assert_eq!(packet.payload.len(), 38);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_streams_from_queues() {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use futures::Stream;
+    use tokio_core::reactor::Core;
+    use tun_tap::r#async::Async;
+
+    let mut core = Core::new().expect("failed to create the core");
+    let flags = TunFlags::TUN | TunFlags::NO_PI;
+    let queues =
+        Async::new_queues("tun35", flags, 2, &core.handle()).expect("failed to open the queues");
+    assert_eq!(2, queues.len());
+    for queue in &queues {
+        assert_eq!("tun35", queue.iface().name());
+        assert!(queue.iface().is_multiqueue().expect("failed to read the flags"));
+    }
+    assert_eq!(2, queues[0].iface().queue_count().expect("failed to count the queues"));
+    let iface = queues[0].iface();
+    iface.set_ipv4(Ipv4Addr::new(10, 10, 35, 1), 24).expect("failed to set the address");
+    iface.set_up(true).expect("failed to bring the device up");
+    let socket = UdpSocket::bind("10.10.35.1:0").expect("failed to bind to address");
+    // Different flows, so they likely go through both queues
+    for port in 4242..4262 {
+        socket
+            .send_to(&[1; 10], ("10.10.35.2", port))
+            .expect("failed to send data");
+    }
+    let mut queues = queues.into_iter().map(|queue| queue.packets());
+    let packets = queues.next().unwrap().select(queues.next().unwrap());
+    let ipv4 = packets.filter(|packet| packet.proto == EtherType::Ipv4);
+    let received = core
+        .run(ipv4.take(20).collect())
+        .expect("failed to receive data");
+    assert!(received.iter().all(|packet| packet.payload.len() == 38));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_sinks_packets() {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use futures::Sink;
+    use tokio_core::reactor::Core;
+    use tun_tap::codec::TunPacket;
+    use tun_tap::r#async::Async;
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let mut core = Core::new().expect("failed to create the core");
+    let sink = Async::new(iface, &core.handle())
+        .expect("failed to wrap the interface")
+        .packet_sink();
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    let data = [1; 10];
+    let mut packet = Vec::new();
+    PacketBuilder::ipv4([10, 10, 10, 2], [10, 10, 10, 1], 20)
+        .udp(4242, 2424)
+        .write(&mut packet, &data)
+        .expect("failed to build packet");
+    core.run(sink.send(TunPacket::new(EtherType::Ipv4, packet)))
+        .expect("failed to send data");
+    let mut buf = [0; 50];
+    let (num, source) = socket
+        .recv_from(&mut buf)
+        .expect("failed to receive packet");
+    assert_eq!(num, 10);
+    assert_eq!(source.port(), 4242);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_pools_receive_buffers() {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use futures::Stream;
+    use tokio_core::reactor::Core;
+    use tun_tap::r#async::Async;
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let mut core = Core::new().expect("failed to create the core");
+    let mut iface = Async::new(iface, &core.handle()).expect("failed to wrap the interface");
+    iface.set_recv_pool_size(4);
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    for _ in 0..2 {
+        socket
+            .send_to(&[1; 10], "10.10.10.2:4242")
+            .expect("failed to send data");
+    }
+    let packets = core
+        .run(iface.packets().take(2).collect())
+        .expect("failed to receive data");
+    // Both packets live in the same buffer, one right after the other
+    assert_eq!(packets[0].payload.len(), 38);
+    assert_eq!(
+        packets[0].payload.as_ptr() as usize + 38,
+        packets[1].payload.as_ptr() as usize
+    );
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_sizes_buffers_by_mtu() {
+    extern crate tokio_core;
+
+    use tokio_core::reactor::Core;
+    use tun_tap::r#async::Async;
+
+    // A separate device, changing the MTU disturbs the traffic of tun10 for a while.
+    let iface = Iface::new("tun13", Mode::Tap).expect("failed to create a TAP device");
+    iface.set_mtu(9000).expect("failed to set the MTU");
+    assert_eq!(iface.max_frame_size().expect("failed to read the MTU"), 9022);
+    let core = Core::new().expect("failed to create the core");
+    let iface = Async::new(iface, &core.handle()).expect("failed to wrap the interface");
+    assert_eq!(iface.recv_bufsize(), 9022);
+}
+
+/// Creates a device with a jumbo MTU and the 10.N.N.1/24 address.
+fn jumbo_iface(name: &str, mode: Mode, mtu: usize, net: u8) -> Iface {
+    let iface = Iface::new(name, mode).expect("failed to create a device");
+    iface.set_mtu(mtu).expect("failed to set the MTU");
+    iface
+        .set_ipv4(Ipv4Addr::new(10, net, net, 1), 24)
+        .expect("failed to set the address");
+    iface.set_up(true).expect("failed to bring the device up");
+    iface
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_streams_jumbo_packets() {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use futures::Stream;
+    use tokio_core::reactor::Core;
+    use tun_tap::r#async::Async;
+
+    let iface = jumbo_iface("tun28", Mode::Tun, 65_000, 28);
+    assert_eq!(iface.max_frame_size().expect("failed to read the MTU"), 65_004);
+    let mut core = Core::new().expect("failed to create the core");
+    let mut packets = Async::new(iface, &core.handle())
+        .expect("failed to wrap the interface")
+        .packets();
+    let socket = UdpSocket::bind("10.28.28.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 64_000], "10.28.28.2:4242")
+        .expect("failed to send data");
+    loop {
+        let (packet, rest) = core
+            .run(packets.into_future())
+            .map_err(|(e, _)| e)
+            .expect("failed to receive data");
+        let packet = packet.expect("stream ended");
+        // Skip whatever else the kernel sends there (eg. IPv6 router solicitations)
+        if packet.proto == EtherType::Ipv4 {
+            assert_eq!(packet.payload.len(), 64_028);
+            break;
+        }
+        packets = rest;
+    }
+}
+
+#[test]
+#[serial]
+fn it_dispatches_jumbo_frames() {
+    use std::time::Duration;
+    use tun_tap::hub::{Filter, Hub};
+
+    let iface = jumbo_iface("tap29", Mode::Tap, 9000, 29);
+    let hub = Hub::new(iface).expect("failed to start the hub");
+    let ipv4 = hub.subscribe(Filter::EtherType(EtherType::Ipv4), 4);
+    // Broadcast, to not wait for ARP
+    let socket = UdpSocket::bind("10.29.29.1:2424").expect("failed to bind to address");
+    socket.set_broadcast(true).unwrap();
+    socket
+        .send_to(&[1; 8000], "10.29.29.255:4242")
+        .expect("failed to send data");
+    let frame = ipv4
+        .recv_timeout(Duration::from_secs(5))
+        .expect("failed to receive data");
+    // The packet info, the ethernet header, IP and UDP
+    assert_eq!(frame.len(), 4 + 14 + 28 + 8000);
+}
+
+#[test]
+#[serial]
+fn it_follows_raised_mtu() {
+    let iface =
+        Iface::without_packet_info("tun30", Mode::Tun).expect("failed to create a TUN device");
+    iface
+        .set_ipv4(Ipv4Addr::new(10, 30, 30, 1), 24)
+        .expect("failed to set the address");
+    iface.set_up(true).expect("failed to bring the device up");
+    let bufsize = iface.max_frame_size().expect("failed to read the MTU");
+    let clone = iface.try_clone().expect("failed to clone the interface");
+    let (_sender, receiver) = iface.into_channels(4).expect("failed to spawn threads");
+    let socket = UdpSocket::bind("10.30.30.1:2424").expect("failed to bind to address");
+    let next_ipv4 = || loop {
+        let packet = receiver.recv().expect("failed to receive data");
+        if packet[0] >> 4 == 4 {
+            return packet;
+        }
+    };
+    // Make sure the receiving thread already runs with the old buffer
+    socket
+        .send_to(&[1; 10], "10.30.30.2:4242")
+        .expect("failed to send data");
+    assert_eq!(next_ipv4().len(), 38);
+    clone.set_mtu(9000).expect("failed to set the MTU");
+    socket
+        .send_to(&[1; 8000], "10.30.30.2:4242")
+        .expect("failed to send data");
+    // The buffer was sized by the old MTU, the first one doesn't fit
+    assert_eq!(next_ipv4().len(), bufsize);
+    socket
+        .send_to(&[1; 8000], "10.30.30.2:4242")
+        .expect("failed to send data");
+    assert_eq!(next_ipv4().len(), 8028);
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_receives_many() {
+    extern crate tokio_core;
+
+    use tokio_core::reactor::Core;
+    use tun_tap::r#async::Async;
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let mut core = Core::new().expect("failed to create the core");
+    let mut iface = Async::new(iface, &core.handle()).expect("failed to wrap the interface");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    for _ in 0..3 {
+        socket
+            .send_to(&[1; 10], "10.10.10.2:4242")
+            .expect("failed to send data");
+    }
+    let mut packets = Vec::new();
+    let cnt = core
+        .run(iface.recv_many(&mut packets, 2))
+        .expect("failed to receive data");
+    assert_eq!(cnt, 2);
+    let cnt = core
+        .run(iface.recv_many(&mut packets, 2))
+        .expect("failed to receive data");
+    assert_eq!(cnt, 1);
+    assert_eq!(packets.len(), 3);
+    assert!(packets.iter().all(|p| p.len() == 38));
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_cancels_recv() {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use futures::future::Either;
+    use futures::Future;
+    use std::time::Duration;
+    use tokio_core::reactor::{Core, Timeout};
+    use tun_tap::r#async::Async;
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let mut core = Core::new().expect("failed to create the core");
+    let mut iface = Async::new(iface, &core.handle()).expect("failed to wrap the interface");
+    let mut buf = [0; 50];
+    {
+        // Nothing to receive, so the timeout wins and the recv gets dropped mid-way.
+        let timeout = Timeout::new(Duration::from_millis(50), &core.handle())
+            .expect("failed to create timeout");
+        let result = core.run(iface.recv_packet(&mut buf).select2(timeout));
+        match result {
+            Ok(Either::B(_)) => (),
+            _ => panic!("recv should have been cancelled"),
+        }
+    }
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    let timeout =
+        Timeout::new(Duration::from_millis(50), &core.handle()).expect("failed to create timeout");
+    // Already ready, so the timeout loses and gets dropped instead.
+    let size = core
+        .run(iface.recv_packet(&mut buf).select2(timeout))
+        .map(|result| match result {
+            Either::A((size, _)) => size,
+            Either::B(_) => panic!("timed out"),
+        })
+        .unwrap_or_else(|_| panic!("failed to receive data"));
+    assert_eq!(size, 38);
+    // The cancellation left nothing behind.
+    let mut packets = Vec::new();
+    let timeout =
+        Timeout::new(Duration::from_millis(50), &core.handle()).expect("failed to create timeout");
+    match core.run(iface.recv_many(&mut packets, 10).select2(timeout)) {
+        Ok(Either::B(_)) => (),
+        _ => panic!("no more packets expected"),
+    }
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+#[serial]
+fn it_shuts_down_async() {
+    extern crate futures;
+    extern crate tokio_core;
+
+    use futures::Stream;
+    use std::time::Duration;
+    use tokio_core::reactor::Core;
+    use tun_tap::r#async::Async;
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let mut core = Core::new().expect("failed to create the core");
+    let mut iface = Async::new(iface, &core.handle()).expect("failed to wrap the interface");
+    let handle = iface.shutdown_handle().expect("failed to create shutdown handle");
+    let mut clone = iface
+        .try_clone(&core.handle())
+        .expect("failed to clone the interface");
+    let shutdown = thread::spawn(move || {
+        thread::sleep(Duration::from_millis(50));
+        handle.shutdown().expect("failed to shut down");
+    });
+    let mut buf = [0; 50];
+    let err = core.run(clone.recv_packet(&mut buf)).unwrap_err();
+    assert!(Shutdown::is_shutdown(&err));
+    let packets = core
+        .run(iface.packets().collect())
+        .expect("the stream should end cleanly");
+    assert!(packets.is_empty());
+    shutdown.join().unwrap();
+}
+
+#[cfg(feature = "futures-io")]
+#[test]
+#[serial]
+fn it_works_without_tokio() {
+    extern crate async_io;
+    extern crate futures_io;
+
+    use futures_io::{AsyncRead, AsyncWrite};
+    use std::future;
+    use std::pin::Pin;
+    use tun_tap::agnostic::AsyncIface;
+
+    let iface =
+        Iface::without_packet_info("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let mut iface = AsyncIface::new(iface).expect("failed to wrap the interface");
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    let sender = thread::spawn(move || {
+        thread::sleep(std::time::Duration::from_millis(50));
+        socket
+            .send_to(&[1; 10], "10.10.10.2:4242")
+            .expect("failed to send data");
+        socket
+    });
+    let mut buf = [0; 50];
+    let num = async_io::block_on(future::poll_fn(|cx| {
+        Pin::new(&mut iface).poll_read(cx, &mut buf)
+    }))
+    .expect("failed to receive data");
+    assert_eq!(num, 38);
+    let socket = sender.join().unwrap();
+
+    let mut packet = Vec::new();
+    PacketBuilder::ipv4([10, 10, 10, 2], [10, 10, 10, 1], 20)
+        .udp(4242, 2424)
+        .write(&mut packet, &[2; 10])
+        .expect("failed to build packet");
+    async_io::block_on(future::poll_fn(|cx| {
+        Pin::new(&mut iface).poll_write(cx, &packet)
+    }))
+    .expect("failed to send data");
+    let num = socket.recv(&mut buf).expect("failed to receive packet");
+    assert_eq!(&buf[..num], &[2; 10]);
+}
+
+#[cfg(feature = "smoltcp")]
+#[test]
+#[serial]
+fn it_runs_smoltcp() {
+    extern crate smoltcp;
+
+    use smoltcp::phy::{Device, Medium, RxToken, TxToken};
+    use smoltcp::time::Instant;
+    use tun_tap::phy::SmolIface;
+
+    let iface = Iface::new("tun10", Mode::Tun).expect("failed to create a TUN device");
+    let mut iface = SmolIface::new(iface).expect("failed to wrap the device");
+    assert_eq!(iface.capabilities().medium, Medium::Ip);
+    assert!(iface.receive(Instant::now()).is_none());
+
+    let socket = UdpSocket::bind("10.10.10.1:2424").expect("failed to bind to address");
+    socket
+        .send_to(&[1; 10], "10.10.10.2:4242")
+        .expect("failed to send data");
+    thread::sleep(std::time::Duration::from_millis(50));
+    let (rx, _tx) = iface.receive(Instant::now()).expect("no packet received");
+    // The packet info is stripped
+    assert_eq!(rx.consume(|packet| packet.len()), 38);
+
+    let mut packet = Vec::new();
+    PacketBuilder::ipv4([10, 10, 10, 2], [10, 10, 10, 1], 20)
+        .udp(4242, 2424)
+        .write(&mut packet, &[2; 10])
+        .expect("failed to build packet");
+    let tx = iface.transmit(Instant::now()).expect("can't transmit");
+    tx.consume(packet.len(), |buf| buf.copy_from_slice(&packet));
+    let mut buf = [0; 50];
+    let num = socket.recv(&mut buf).expect("failed to receive packet");
+    assert_eq!(&buf[..num], &[2; 10]);
+}
+
+#[cfg(feature = "smoltcp")]
+#[test]
+#[serial]
+fn it_runs_smoltcp_with_jumbo_frames() {
+    extern crate smoltcp;
+
+    use smoltcp::phy::{Device, RxToken};
+    use smoltcp::time::Instant;
+    use tun_tap::phy::SmolIface;
+
+    let iface = jumbo_iface("tap29", Mode::Tap, 9000, 29);
+    let mut iface = SmolIface::new(iface).expect("failed to wrap the device");
+    assert_eq!(iface.capabilities().max_transmission_unit, 9014);
+    let socket = UdpSocket::bind("10.29.29.1:2424").expect("failed to bind to address");
+    socket.set_broadcast(true).unwrap();
+    socket
+        .send_to(&[1; 8000], "10.29.29.255:4242")
+        .expect("failed to send data");
+    thread::sleep(std::time::Duration::from_millis(50));
+    let mut sizes = Vec::new();
+    while let Some((rx, _tx)) = iface.receive(Instant::now()) {
+        sizes.push(rx.consume(|packet| packet.len()));
+    }
+    // The packet info is stripped
+    assert!(sizes.contains(&(14 + 28 + 8000)), "no jumbo frame in {:?}", sizes);
+}
+
+#[cfg(feature = "smoltcp")]
+#[test]
+#[serial]
+fn it_runs_smoltcp_after_raising_mtu() {
+    extern crate smoltcp;
+
+    use smoltcp::phy::{Device, RxToken};
+    use smoltcp::time::Instant;
+    use tun_tap::phy::SmolIface;
+
+    let iface = jumbo_iface("tap36", Mode::Tap, 1500, 36);
+    let mut iface = SmolIface::new(iface).expect("failed to wrap the device");
+    assert_eq!(iface.capabilities().max_transmission_unit, 1514);
+    iface.iface().set_mtu(9000).expect("failed to set the MTU");
+    let socket = UdpSocket::bind("10.36.36.1:2424").expect("failed to bind to address");
+    socket.set_broadcast(true).unwrap();
+    socket
+        .send_to(&[1; 8000], "10.36.36.255:4242")
+        .expect("failed to send data");
+    thread::sleep(std::time::Duration::from_millis(50));
+    let mut sizes = Vec::new();
+    while let Some((rx, _tx)) = iface.receive(Instant::now()) {
+        sizes.push(rx.consume(|packet| packet.len()));
+    }
+    // Not even the first jumbo frame is lost and it updates the capabilities
+    assert!(sizes.contains(&(14 + 28 + 8000)), "no jumbo frame in {:?}", sizes);
+    assert_eq!(iface.capabilities().max_transmission_unit, 9014);
+}
+
+#[cfg(feature = "vhost")]
+#[test]
+#[serial]
+fn it_hands_devices_to_vhost() {
+    use std::io::ErrorKind;
+    use tun_tap::vhost::{self, MemoryRegion, VhostNet, VringAddresses};
+
+    let flags = TunFlags::TAP | TunFlags::NO_PI | TunFlags::VNET_HDR;
+    let iface = Iface::with_flags("tap22", flags).expect("failed to create a TAP device");
+    vhost::set_vnet_header_size(&iface, 12).expect("failed to set the header size");
+    let vhost = match VhostNet::new() {
+        Ok(vhost) => vhost,
+        Err(ref e) if e.kind() == ErrorKind::NotFound => {
+            eprintln!("No vhost-net, skipping");
+            return;
+        },
+        Err(e) => panic!("failed to open vhost-net: {}", e),
+    };
+    let features = vhost.features().expect("failed to get features");
+    vhost
+        .set_features(features & VhostNet::F_MRG_RXBUF)
+        .expect("failed to set features");
+    // A fake guest memory, identity mapped, with an (empty) queue in it
+    let memory = vec![0u64; 1024];
+    let start = memory.as_ptr() as u64;
+    let region = MemoryRegion {
+        guest_address: start,
+        size: 8192,
+        user_address: start,
+    };
+    // Safety: the memory outlives the use by the kernel, the backend is detached before the end
+    unsafe { vhost.set_mem_table(&[region]) }.expect("failed to set memory");
+    vhost.set_vring_num(VhostNet::RX, 16).expect("failed to set queue size");
+    vhost.set_vring_base(VhostNet::RX, 0).expect("failed to set queue base");
+    let addresses = VringAddresses {
+        descriptors: start,
+        available: start + 256,
+        used: start + 4096,
+        log: None,
+    };
+    unsafe { vhost.set_vring_addr(VhostNet::RX, &addresses) }
+        .expect("failed to set queue addresses");
+    vhost.set_backend(VhostNet::RX, &iface).expect("failed to set backend");
+    vhost.detach_backend(VhostNet::RX).expect("failed to detach backend");
+    assert_eq!(0, vhost.vring_base(VhostNet::RX).expect("failed to get queue base"));
+}
+
+#[cfg(feature = "xdp")]
+#[test]
+#[serial]
+fn it_receives_through_xdp() {
+    use tun_tap::packet::{EthernetHeader, MacAddr};
+    use tun_tap::xdp::{XdpConfig, XdpIface, XdpMode, XdpProgram};
+    use tun_tap::Device;
+
+    let tap = Iface::without_packet_info("tap27", Mode::Tap).expect("failed to create a TAP");
+    // No IPv6 chatter from the kernel through the device
+    let _ = std::fs::write("/proc/sys/net/ipv6/conf/tap27/disable_ipv6", "1");
+    tap.set_up(true).expect("failed to bring the device up");
+    let program = match XdpProgram::attach("tap27", 1, XdpMode::Generic) {
+        Ok(program) => program,
+        Err(e) => {
+            eprintln!("No XDP, skipping: {}", e);
+            return;
+        },
+    };
+    assert!(XdpIface::new("tap27", &program, XdpConfig::new().queue(1)).is_err());
+    let config = XdpConfig::new().ring_size(64).frame_size(2048).zero_copy(false);
+    let xdp = XdpIface::new("tap27", &program, config).expect("failed to open the socket");
+    assert!(!xdp.is_zero_copy().unwrap());
+    assert_eq!(Mode::Tap, Device::mode(&xdp));
+    let header = EthernetHeader {
+        destination: MacAddr([0x02, 0, 0, 0, 0x27, 1]),
+        source: MacAddr([0x02, 0, 0, 0, 0x27, 2]),
+        ether_type: EtherType::Other(0x88B5),
+    };
+    let mut frame = header.to_bytes().to_vec();
+    frame.extend_from_slice(&[0x27; 50]);
+    // More than the frames in the rings, to make them go around
+    for i in 0..200 {
+        frame[14] = i as u8;
+        tap.send(&frame).expect("failed to send");
+        let mut buf = [0; 2048];
+        let size = xdp.recv(&mut buf).expect("failed to receive");
+        assert_eq!(&frame[..], &buf[..size]);
+        xdp.send(&frame).expect("failed to send");
+        let size = tap.recv(&mut buf).expect("failed to receive");
+        assert_eq!(&frame[..], &buf[..size]);
+    }
+    xdp.set_non_blocking().unwrap();
+    let mut buf = [0; 2048];
+    assert_eq!(
+        std::io::ErrorKind::WouldBlock,
+        xdp.recv(&mut buf).unwrap_err().kind()
+    );
+}
@@ -0,0 +1,47 @@
+#!/bin/sh
+
+# Runs the tests without root, in a new user and network namespace with its own tun10:
+#
+#   sh tests/netns.sh [cargo test arguments]
+#
+# Cargo starts each test binary through this script (as the target runner), so each gets a fresh
+# namespace. If the kernel doesn't allow unprivileged user namespaces, the tests run directly and
+# need the device from setup.sh.
+#
+# Only the current user (as root) is mapped into the namespace, unless there's a range for it in
+# /etc/subuid and /etc/subgid. The tests needing other users skip themselves without one.
+
+set -e
+
+case "$1" in
+    --run)
+        shift
+        map=--map-root-user
+        if unshare $map --map-auto true 2>/dev/null; then
+            map="$map --map-auto"
+        fi
+        exec unshare $map --net --mount sh "$0" --inside "$@"
+        ;;
+    --inside)
+        shift
+        # The sysfs shows the devices of the namespace that mounted it
+        mount -t sysfs sysfs /sys
+        ip link set lo up
+        ip tuntap add dev tun10 mode tun
+        ip address add 10.10.10.1/24 dev tun10
+        # Router solicitations would get in the way of the tests reading the device
+        sysctl -q -w net.ipv6.conf.tun10.disable_ipv6=1
+        ip link set tun10 up
+        exec "$@"
+        ;;
+esac
+
+if ! unshare --map-root-user --net --mount true 2>/dev/null; then
+    echo "No user namespaces, running the tests directly (see tests/setup.sh)" >&2
+    exec cargo test "$@"
+fi
+
+script=$(realpath "$0")
+target=$(rustc -vV | sed -n 's/^host: //p' | tr 'a-z-' 'A-Z_')
+export "CARGO_TARGET_${target}_RUNNER=sh $script --run"
+exec cargo test "$@"
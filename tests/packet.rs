@@ -0,0 +1,136 @@
+extern crate tun_tap;
+
+use tun_tap::packet::{
+    inner_ether_type, insert_vlan_tag, strip_vlan_tag, vlan_tags, EthernetHeader, MacAddr,
+    PacketDisplay, VlanTag, ETHERTYPE_ARP, ETHERTYPE_IPV4, ETHERTYPE_IPV6, TPID_8021AD,
+};
+use tun_tap::{EtherType, PacketInfo};
+
+#[test]
+fn ether_type_round_trip() {
+    for raw in &[0x0800, 0x0806, 0x86DD, 0x8100, 0] {
+        assert_eq!(*raw, u16::from(EtherType::from(*raw)));
+    }
+    assert_eq!(EtherType::Ipv6, EtherType::from(0x86DD));
+    assert_eq!(EtherType::Other(0x8100), EtherType::from(0x8100));
+}
+
+#[test]
+fn packet_info_round_trip() {
+    let info = PacketInfo::parse(&[0, 0, 0x86, 0xDD]);
+    assert_eq!(PacketInfo::new(EtherType::Ipv6), info);
+    assert_eq!([0, 0, 0x86, 0xDD], info.to_bytes());
+    let info = PacketInfo {
+        flags: 1,
+        proto: EtherType::Other(0x1234),
+    };
+    assert_eq!(info, PacketInfo::parse(&info.to_bytes()));
+    assert!(info.is_truncated());
+    assert!(!PacketInfo::new(EtherType::Ipv4).is_truncated());
+}
+
+#[test]
+fn raw_ether_types() {
+    assert_eq!(EtherType::Ipv4, EtherType::from(ETHERTYPE_IPV4));
+    assert_eq!(EtherType::Arp, EtherType::from(ETHERTYPE_ARP));
+    assert_eq!(EtherType::Ipv6, EtherType::from(ETHERTYPE_IPV6));
+    let info = PacketInfo {
+        flags: PacketInfo::TUN_PKT_STRIP,
+        proto: EtherType::Ipv4,
+    };
+    let bytes = info.to_bytes();
+    assert_eq!(ETHERTYPE_IPV4.to_be_bytes(), bytes[2..]);
+    assert_eq!(PacketInfo::TUN_PKT_STRIP.to_ne_bytes(), bytes[..2]);
+}
+
+#[test]
+fn ethernet_header_round_trip() {
+    let header = EthernetHeader {
+        destination: MacAddr::BROADCAST,
+        source: MacAddr([2, 0, 0, 0, 0, 1]),
+        ether_type: EtherType::Ipv6,
+    };
+    let mut frame = header.to_bytes().to_vec();
+    frame.extend_from_slice(&[1, 2]);
+    let (parsed, payload) = EthernetHeader::parse(&frame).unwrap();
+    assert_eq!(header, parsed);
+    assert_eq!(&[1, 2], payload);
+    assert_eq!("02:00:00:00:00:01", header.source.to_string());
+    assert!(EthernetHeader::parse(&frame[..13]).is_none());
+}
+
+#[test]
+fn qinq_tags() {
+    let mut frame = vec![0; 12];
+    frame.extend_from_slice(&[0x86, 0xDD, 0x60]);
+    assert_eq!(0, vlan_tags(&frame).count());
+    insert_vlan_tag(&mut frame, VlanTag::new(10)).unwrap();
+    let outer = VlanTag {
+        tpid: TPID_8021AD,
+        pcp: 5,
+        dei: true,
+        vid: 4000,
+    };
+    insert_vlan_tag(&mut frame, outer).unwrap();
+    assert_eq!(12 + 8 + 3, frame.len());
+    assert_eq!(vec![outer, VlanTag::new(10)], vlan_tags(&frame).collect::<Vec<_>>());
+    assert_eq!(Some(EtherType::Ipv6), inner_ether_type(&frame));
+    assert_eq!(Some(outer), strip_vlan_tag(&mut frame));
+    assert_eq!(Some(VlanTag::new(10)), strip_vlan_tag(&mut frame));
+    assert_eq!(None, strip_vlan_tag(&mut frame));
+    assert_eq!(&frame[12..], &[0x86, 0xDD, 0x60]);
+    assert!(insert_vlan_tag(&mut vec![0; 10], VlanTag::new(1)).is_err());
+}
+
+#[test]
+fn hexdump() {
+    let packet: Vec<u8> = (b'0'..b'0' + 20).collect();
+    let expected = "\
+0000  30 31 32 33 34 35 36 37  38 39 3a 3b 3c 3d 3e 3f  |0123456789:;<=>?|
+0010  40 41 42 43                                       |@ABC|";
+    assert_eq!(expected, PacketDisplay::new(&packet).to_string());
+    assert_eq!(expected, format!("{:?}", PacketDisplay::new(&packet)));
+
+    let with_info = [0, 1, 0x86, 0xdd, 0, 0xff];
+    let expected = "\
+Packet info: Ipv6, flags 0x0100
+0000  00 ff                                             |..|";
+    if cfg!(target_endian = "little") {
+        assert_eq!(expected, PacketDisplay::with_packet_info(&with_info).to_string());
+    }
+    assert_eq!("", PacketDisplay::new(&[]).to_string());
+}
+
+#[test]
+fn five_tuple() {
+    use std::net::SocketAddr;
+    use tun_tap::packet::{FiveTuple, IPPROTO_TCP};
+
+    // TCP over IPv6, from [::1]:80 to [::2]:8080
+    let mut packet = vec![0x60, 0, 0, 0, 0, 20, IPPROTO_TCP, 64];
+    packet.extend_from_slice(&[0; 15]);
+    packet.push(1);
+    packet.extend_from_slice(&[0; 15]);
+    packet.push(2);
+    packet.extend_from_slice(&[0, 80, 0x1F, 0x90]);
+    let flow = FiveTuple::parse(&packet).unwrap();
+    assert_eq!(IPPROTO_TCP, flow.protocol);
+    assert_eq!("[::1]:80".parse::<SocketAddr>().unwrap(), flow.source);
+    assert_eq!("[::2]:8080".parse::<SocketAddr>().unwrap(), flow.destination);
+    assert_eq!(flow, flow.reversed().reversed());
+    assert_eq!(flow.source, flow.reversed().destination);
+
+    // Truncated ports
+    assert_eq!(None, FiveTuple::parse(&packet[..42]));
+    // ICMPv6
+    packet[6] = 58;
+    assert_eq!(None, FiveTuple::parse(&packet));
+    // IPv4 with options and then a non-first fragment
+    let mut packet = vec![0x46, 0, 0, 32, 0, 0, 0, 0, 64, 17, 0, 0];
+    packet.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 1, 1, 1, 1]);
+    packet.extend_from_slice(&[0, 1, 0, 2, 0, 8, 0, 0]);
+    assert_eq!(2, FiveTuple::parse(&packet).unwrap().destination.port());
+    packet[7] = 1;
+    assert_eq!(None, FiveTuple::parse(&packet));
+    assert_eq!(None, FiveTuple::parse(&[]));
+}
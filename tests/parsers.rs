@@ -0,0 +1,204 @@
+//! Property tests of the parsers.
+//!
+//! These sit directly on the data coming from the network, so they must not panic on anything
+//! and what they encode must parse back the same.
+
+extern crate proptest;
+extern crate tun_tap;
+
+use std::net::Ipv4Addr;
+
+use proptest::collection::vec;
+use proptest::prelude::*;
+use tun_tap::arp::ArpResponder;
+use tun_tap::icmp::EchoResponder;
+use tun_tap::packet::{
+    inner_ether_type, insert_vlan_tag, strip_vlan_tag, vlan_tags, EthernetHeader, MacAddr,
+    PacketDisplay, VirtioNetHeader, VlanTag,
+};
+use tun_tap::{EtherType, PacketInfo};
+
+/// Arbitrary bytes, but often starting with the `prefix` to get past the first checks.
+fn after(prefix: Vec<u8>) -> impl Strategy<Value = Vec<u8>> {
+    prop_oneof![
+        vec(any::<u8>(), 0..100),
+        vec(any::<u8>(), 0..100).prop_map(move |rest| {
+            let mut data = prefix.clone();
+            data.extend(rest);
+            data
+        }),
+    ]
+}
+
+/// Frames of the ether type, possibly VLAN tagged.
+fn frames(ether_type: u16) -> impl Strategy<Value = Vec<u8>> {
+    let mut untagged = vec![0xff; 12];
+    untagged.extend_from_slice(&ether_type.to_be_bytes());
+    let mut tagged = vec![0xff; 12];
+    tagged.extend_from_slice(&[0x81, 0, 0, 42]);
+    tagged.extend_from_slice(&ether_type.to_be_bytes());
+    prop_oneof![after(untagged), after(tagged)]
+}
+
+fn mac() -> impl Strategy<Value = MacAddr> {
+    any::<[u8; 6]>().prop_map(MacAddr)
+}
+
+proptest! {
+    #[test]
+    fn ether_type_round_trip(raw in any::<u16>()) {
+        prop_assert_eq!(raw, u16::from(EtherType::from(raw)));
+    }
+
+    #[test]
+    fn packet_info_round_trip(bytes in any::<[u8; 4]>()) {
+        let info = PacketInfo::parse(&bytes);
+        prop_assert_eq!(bytes, info.to_bytes());
+        prop_assert_eq!(info, PacketInfo::parse(&info.to_bytes()));
+    }
+
+    #[test]
+    fn virtio_net_header_round_trip(bytes in any::<[u8; 10]>()) {
+        let header = VirtioNetHeader::parse(&bytes);
+        prop_assert_eq!(bytes, header.to_bytes());
+        prop_assert_eq!(header, VirtioNetHeader::parse(&header.to_bytes()));
+    }
+
+    #[test]
+    fn ethernet_header(frame in vec(any::<u8>(), 0..40)) {
+        match EthernetHeader::parse(&frame) {
+            Some((header, rest)) => {
+                prop_assert_eq!(&frame[..EthernetHeader::SIZE], &header.to_bytes()[..]);
+                prop_assert_eq!(&frame[EthernetHeader::SIZE..], rest);
+            },
+            None => prop_assert!(frame.len() < EthernetHeader::SIZE),
+        }
+    }
+
+    #[test]
+    fn ethernet_header_round_trip(destination in mac(), source in mac(), raw in any::<u16>()) {
+        let header = EthernetHeader {
+            destination,
+            source,
+            ether_type: EtherType::from(raw),
+        };
+        let bytes = header.to_bytes();
+        prop_assert_eq!(Some((header, &[][..])), EthernetHeader::parse(&bytes));
+    }
+
+    #[test]
+    fn vlan_tag_round_trip(bytes in any::<[u8; 4]>(), vid in any::<u16>()) {
+        prop_assert_eq!(bytes, VlanTag::parse(&bytes).to_bytes());
+        let tag = VlanTag::new(vid);
+        prop_assert!(tag.vid < 4096);
+        prop_assert_eq!(tag, VlanTag::parse(&tag.to_bytes()));
+    }
+
+    #[test]
+    fn vlan_tags_malformed(frame in frames(0x8100)) {
+        let tags = vlan_tags(&frame).count();
+        prop_assert!(tags == 0 || 12 + tags * VlanTag::SIZE + 2 <= frame.len());
+        if let Some(ether_type) = inner_ether_type(&frame) {
+            prop_assert!(!matches!(u16::from(ether_type), 0x8100 | 0x88A8 | 0x9100));
+        }
+        let mut stripped = frame.clone();
+        match strip_vlan_tag(&mut stripped) {
+            Some(_) => prop_assert_eq!(frame.len() - VlanTag::SIZE, stripped.len()),
+            None => prop_assert_eq!(&frame, &stripped),
+        }
+    }
+
+    #[test]
+    fn vlan_tag_insert_strip(frame in vec(any::<u8>(), 0..40), vid in any::<u16>()) {
+        let mut tagged = frame.clone();
+        match insert_vlan_tag(&mut tagged, VlanTag::new(vid)) {
+            Ok(()) => {
+                prop_assert_eq!(Some(VlanTag::new(vid)), strip_vlan_tag(&mut tagged));
+                prop_assert_eq!(frame, tagged);
+            },
+            Err(_) => {
+                prop_assert!(frame.len() < EthernetHeader::SIZE);
+                prop_assert_eq!(frame, tagged);
+            },
+        }
+    }
+
+    #[test]
+    fn display_anything(packet in vec(any::<u8>(), 0..100)) {
+        let lines = match packet.len() {
+            len if len >= PacketInfo::SIZE => 1 + (len - PacketInfo::SIZE).div_ceil(16),
+            len => len.div_ceil(16),
+        };
+        let dump = PacketDisplay::with_packet_info(&packet).to_string();
+        prop_assert_eq!(lines, dump.lines().count());
+        let dump = PacketDisplay::new(&packet).to_string();
+        prop_assert_eq!(packet.len().div_ceil(16), dump.lines().count());
+    }
+
+    #[test]
+    fn arp_malformed(frame in frames(0x0806)) {
+        let mut arp = ArpResponder::new();
+        arp.insert(Ipv4Addr::new(10, 0, 0, 1), MacAddr([2, 0, 0, 0, 0, 1]));
+        if let Some(reply) = arp.reply(&frame) {
+            prop_assert_eq!(frame.len(), reply.len());
+        }
+    }
+
+    #[test]
+    fn ping_malformed(packet in after(vec![0x45, 0, 0, 30, 0, 0, 0, 0, 64, 1])) {
+        let echo = EchoResponder::new();
+        if let Some(reply) = echo.reply(&packet) {
+            prop_assert!(reply.len() <= packet.len());
+        }
+    }
+}
+
+#[cfg(feature = "dhcp")]
+proptest! {
+    #[test]
+    fn dhcp_malformed(frame in frames(0x0800)) {
+        use tun_tap::dhcp::DhcpServer;
+
+        let range = Ipv4Addr::new(10, 0, 0, 100)..=Ipv4Addr::new(10, 0, 0, 101);
+        let mut server = DhcpServer::new(Ipv4Addr::new(10, 0, 0, 1), MacAddr([2; 6]), range);
+        server.reply(&frame);
+    }
+}
+
+#[cfg(feature = "tokio")]
+proptest! {
+    #[test]
+    fn codecs_malformed(data in vec(any::<u8>(), 0..40), packet_info in any::<bool>()) {
+        extern crate bytes;
+        extern crate tokio_codec;
+
+        use bytes::BytesMut;
+        use tokio_codec::Decoder;
+        use tun_tap::codec::{EthernetFrameCodec, TunPacketCodec};
+        use tun_tap::Mode;
+
+        let mut codec = TunPacketCodec::new(packet_info, Mode::Tun);
+        let _ = codec.decode(&mut BytesMut::from(&data[..]));
+        let mut codec = EthernetFrameCodec::new(packet_info);
+        if let Ok(Some(frame)) = codec.decode(&mut BytesMut::from(&data[..])) {
+            prop_assert_eq!(&data[data.len() - frame.payload.len()..], &frame.payload[..]);
+        }
+    }
+
+    #[test]
+    fn codec_round_trip(raw in any::<u16>(), payload in vec(any::<u8>(), 1..40)) {
+        extern crate bytes;
+        extern crate tokio_codec;
+
+        use bytes::BytesMut;
+        use tokio_codec::{Decoder, Encoder};
+        use tun_tap::codec::{TunPacket, TunPacketCodec};
+        use tun_tap::Mode;
+
+        let mut codec = TunPacketCodec::new(true, Mode::Tun);
+        let packet = TunPacket::new(EtherType::from(raw), payload);
+        let mut buf = BytesMut::new();
+        codec.encode(packet.clone(), &mut buf).unwrap();
+        prop_assert_eq!(Some(packet), codec.decode(&mut buf).unwrap());
+    }
+}
@@ -0,0 +1,95 @@
+extern crate tun_tap;
+
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::os::unix::net::UnixDatagram;
+
+use tun_tap::pool::PacketPool;
+use tun_tap::testing;
+use tun_tap::{Device, Mode};
+
+#[test]
+fn recycles_buffers() {
+    let pool = PacketPool::new(100);
+    assert_eq!(0, pool.idle());
+    let packet = pool.get();
+    assert_eq!(100, packet.len());
+    let ptr = packet.as_ptr();
+    drop(packet);
+    assert_eq!(1, pool.idle());
+    let mut packet = pool.get();
+    assert_eq!(0, pool.idle());
+    // The same buffer, in full size again
+    assert_eq!(ptr, packet.as_ptr());
+    packet.truncate(10);
+    assert_eq!(10, packet.len());
+    drop(packet);
+    assert_eq!(100, pool.get().len());
+
+    // Detached buffers don't return
+    let packet = pool.get().into_vec();
+    assert_eq!(100, packet.len());
+    assert_eq!(0, pool.idle());
+}
+
+#[test]
+fn limits_idle_buffers() {
+    let pool = PacketPool::with_max_idle(10, 1);
+    let first = pool.get();
+    let second = pool.get();
+    drop(first);
+    drop(second);
+    assert_eq!(1, pool.idle());
+}
+
+#[test]
+fn receives_into_pool() {
+    let (left, right) = testing::pair().unwrap();
+    let pool = PacketPool::for_device(&right).unwrap();
+    assert_eq!(1500, pool.buffer_size());
+    left.send(b"\x45hello").unwrap();
+    let packet = pool.recv(&right).unwrap();
+    assert_eq!(b"\x45hello", &packet[..]);
+    drop(packet);
+    assert_eq!(1, pool.idle());
+}
+
+struct TapDevice(UnixDatagram);
+
+impl AsRawFd for TapDevice {
+    fn as_raw_fd(&self) -> RawFd {
+        self.0.as_raw_fd()
+    }
+}
+
+impl Device for TapDevice {
+    fn name(&self) -> &str {
+        "tap0"
+    }
+    fn mode(&self) -> Mode {
+        Mode::Tap
+    }
+    fn packet_info(&self) -> bool {
+        true
+    }
+    fn mtu(&self) -> io::Result<usize> {
+        Ok(9000)
+    }
+    fn recv(&self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.recv(buf)
+    }
+    fn send(&self, buf: &[u8]) -> io::Result<usize> {
+        self.0.send(buf)
+    }
+    fn set_non_blocking(&self) -> io::Result<()> {
+        self.0.set_nonblocking(true)
+    }
+}
+
+#[test]
+fn sized_by_max_frame() {
+    let (device, _) = UnixDatagram::pair().unwrap();
+    let pool = PacketPool::for_device(&TapDevice(device)).unwrap();
+    // The MTU, the ethernet header with a VLAN tag and the packet info
+    assert_eq!(9000 + 14 + 4 + 4, pool.buffer_size());
+}
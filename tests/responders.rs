@@ -0,0 +1,268 @@
+extern crate tun_tap;
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use tun_tap::arp::ArpResponder;
+use tun_tap::icmp::EchoResponder;
+use tun_tap::packet::{insert_vlan_tag, vlan_tags, EthernetHeader, MacAddr, VlanTag};
+use tun_tap::EtherType;
+
+const OURS: MacAddr = MacAddr([0x02, 0, 0, 0, 0, 2]);
+const THEIRS: MacAddr = MacAddr([0x02, 0, 0, 0, 0, 1]);
+
+fn arp_request(target: [u8; 4]) -> Vec<u8> {
+    let header = EthernetHeader {
+        destination: MacAddr::BROADCAST,
+        source: THEIRS,
+        ether_type: EtherType::Arp,
+    };
+    let mut frame = header.to_bytes().to_vec();
+    frame.extend_from_slice(&[0, 1, 0x08, 0x00, 6, 4, 0, 1]);
+    frame.extend_from_slice(&THEIRS.0);
+    frame.extend_from_slice(&[10, 0, 0, 1]);
+    frame.extend_from_slice(&[0; 6]);
+    frame.extend_from_slice(&target);
+    frame
+}
+
+#[test]
+fn answers_arp() {
+    let mut arp = ArpResponder::new();
+    arp.insert(Ipv4Addr::new(10, 0, 0, 2), OURS);
+    let reply = arp.reply(&arp_request([10, 0, 0, 2])).expect("no reply");
+    let (header, payload) = EthernetHeader::parse(&reply).unwrap();
+    assert_eq!(THEIRS, header.destination);
+    assert_eq!(OURS, header.source);
+    assert_eq!(EtherType::Arp, header.ether_type);
+    // A reply, from us to them
+    assert_eq!(&[0, 1, 0x08, 0x00, 6, 4, 0, 2], &payload[..8]);
+    assert_eq!(&OURS.0, &payload[8..14]);
+    assert_eq!(&[10, 0, 0, 2], &payload[14..18]);
+    assert_eq!(&THEIRS.0, &payload[18..24]);
+    assert_eq!(&[10, 0, 0, 1], &payload[24..28]);
+
+    // Not ours
+    assert!(arp.reply(&arp_request([10, 0, 0, 3])).is_none());
+    // Not a request
+    let mut reply_frame = arp_request([10, 0, 0, 2]);
+    reply_frame[21] = 2;
+    assert!(arp.reply(&reply_frame).is_none());
+    assert!(arp.reply(&[0; 10]).is_none());
+
+    arp.remove(Ipv4Addr::new(10, 0, 0, 2));
+    assert!(arp.reply(&arp_request([10, 0, 0, 2])).is_none());
+}
+
+#[test]
+fn answers_tagged_arp() {
+    let mut arp = ArpResponder::new();
+    arp.insert(Ipv4Addr::new(10, 0, 0, 2), OURS);
+    let mut request = arp_request([10, 0, 0, 2]);
+    insert_vlan_tag(&mut request, VlanTag::new(42)).unwrap();
+    let reply = arp.reply(&request).expect("no reply");
+    assert_eq!(vec![VlanTag::new(42)], vlan_tags(&reply).collect::<Vec<_>>());
+    assert_eq!(&OURS.0, &reply[26..32]);
+}
+
+fn sums_to_ones(data: &[u8], initial: u32) -> bool {
+    let mut sum = data
+        .chunks(2)
+        .map(|word| u32::from(word[0]) << 8 | u32::from(*word.get(1).unwrap_or(&0)))
+        .sum::<u32>()
+        + initial;
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    sum == 0xffff
+}
+
+fn ping_v4(destination: [u8; 4]) -> Vec<u8> {
+    let mut packet = vec![
+        0x45, 0, 0, 32, 0, 1, 0, 0, 5, ICMP, 0, 0, 10, 0, 0, 1, // Source
+    ];
+    packet.extend_from_slice(&destination);
+    // Echo request, the checksum is not checked
+    packet.extend_from_slice(&[8, 0, 0, 0, 0, 42, 0, 1, 1, 2, 3, 4]);
+    packet
+}
+
+const ICMP: u8 = 1;
+
+#[test]
+fn answers_ping() {
+    let echo = EchoResponder::new();
+    let reply = echo.reply(&ping_v4([10, 0, 0, 2])).expect("no reply");
+    assert_eq!(32, reply.len());
+    assert_eq!(64, reply[8]);
+    assert_eq!(&[10, 0, 0, 2], &reply[12..16]);
+    assert_eq!(&[10, 0, 0, 1], &reply[16..20]);
+    assert!(sums_to_ones(&reply[..20], 0));
+    // Echo reply, the same identifier, sequence number and data
+    assert_eq!(0, reply[20]);
+    assert_eq!(&[0, 42, 0, 1, 1, 2, 3, 4], &reply[24..]);
+    assert!(sums_to_ones(&reply[20..], 0));
+
+    // Not a request
+    assert!(echo.reply(&reply).is_none());
+    // Not to a unicast address
+    assert!(echo.reply(&ping_v4([224, 0, 0, 1])).is_none());
+
+    let mut restricted = EchoResponder::new();
+    restricted.add_address(IpAddr::V4(Ipv4Addr::new(10, 0, 0, 3)));
+    assert!(restricted.reply(&ping_v4([10, 0, 0, 2])).is_none());
+    assert!(restricted.reply(&ping_v4([10, 0, 0, 3])).is_some());
+}
+
+#[test]
+fn answers_ping_v6() {
+    let source: Ipv6Addr = "fd00::1".parse().unwrap();
+    let destination: Ipv6Addr = "fd00::2".parse().unwrap();
+    let mut packet = vec![0x60, 0, 0, 0, 0, 12, 58, 64];
+    packet.extend_from_slice(&source.octets());
+    packet.extend_from_slice(&destination.octets());
+    packet.extend_from_slice(&[128, 0, 0, 0, 0, 42, 0, 1, 1, 2, 3, 4]);
+    let reply = EchoResponder::new().reply(&packet).expect("no reply");
+    assert_eq!(&destination.octets(), &reply[8..24]);
+    assert_eq!(&source.octets(), &reply[24..40]);
+    assert_eq!(129, reply[40]);
+    // The checksum covers the pseudo-header
+    let pseudo = reply[8..40]
+        .chunks(2)
+        .map(|word| u32::from(word[0]) << 8 | u32::from(word[1]))
+        .sum::<u32>()
+        + 12
+        + 58;
+    assert!(sums_to_ones(&reply[40..], pseudo));
+}
+
+#[cfg(feature = "dhcp")]
+mod dhcp {
+    use std::net::Ipv4Addr;
+
+    use tun_tap::dhcp::DhcpServer;
+    use tun_tap::packet::{EthernetHeader, MacAddr};
+    use tun_tap::EtherType;
+
+    use super::{sums_to_ones, OURS, THEIRS};
+
+    const SERVER: Ipv4Addr = Ipv4Addr::new(10, 0, 0, 1);
+
+    fn server() -> DhcpServer {
+        let range = Ipv4Addr::new(10, 0, 0, 100)..=Ipv4Addr::new(10, 0, 0, 101);
+        DhcpServer::new(SERVER, OURS, range).router(SERVER)
+    }
+
+    /// A client message, the options without the end.
+    fn message(client: MacAddr, message_type: u8, options: &[u8]) -> Vec<u8> {
+        let header = EthernetHeader {
+            destination: MacAddr::BROADCAST,
+            source: client,
+            ether_type: EtherType::Ipv4,
+        };
+        let mut bootp = vec![1, 1, 6, 0, 1, 2, 3, 4, 0, 0, 0, 0];
+        bootp.extend_from_slice(&[0; 16]);
+        bootp.extend_from_slice(&client.0);
+        bootp.extend_from_slice(&[0; 202]);
+        bootp.extend_from_slice(&[99, 130, 83, 99, 53, 1, message_type]);
+        bootp.extend_from_slice(options);
+        bootp.push(255);
+        let total = 28 + bootp.len() as u16;
+        let mut frame = header.to_bytes().to_vec();
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&total.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0, 64, 17, 0, 0, 0, 0, 0, 0, 255, 255, 255, 255]);
+        frame.extend_from_slice(&[0, 68, 0, 67]);
+        frame.extend_from_slice(&(total - 20).to_be_bytes());
+        frame.extend_from_slice(&[0, 0]);
+        frame.extend_from_slice(&bootp);
+        frame
+    }
+
+    fn requested(address: Ipv4Addr) -> Vec<u8> {
+        let mut option = vec![50, 4];
+        option.extend_from_slice(&address.octets());
+        option
+    }
+
+    /// Checks the reply, returns its type and the offered address.
+    fn check(reply: &[u8]) -> (u8, Ipv4Addr) {
+        let (header, ip) = EthernetHeader::parse(reply).unwrap();
+        assert_eq!(OURS, header.source);
+        assert_eq!(EtherType::Ipv4, header.ether_type);
+        assert!(sums_to_ones(&ip[..20], 0));
+        assert_eq!(&SERVER.octets(), &ip[12..16]);
+        let udp = &ip[20..];
+        assert_eq!(&[0, 67, 0, 68], &udp[..4]);
+        let pseudo = ip[12..20]
+            .chunks(2)
+            .map(|word| u32::from(word[0]) << 8 | u32::from(word[1]))
+            .sum::<u32>()
+            + 17
+            + udp.len() as u32;
+        assert!(sums_to_ones(udp, pseudo));
+        let bootp = &udp[8..];
+        assert_eq!(2, bootp[0]);
+        assert_eq!(&[1, 2, 3, 4], &bootp[4..8]);
+        assert_eq!(&[99, 130, 83, 99, 53, 1], &bootp[236..242]);
+        let yiaddr = Ipv4Addr::new(bootp[16], bootp[17], bootp[18], bootp[19]);
+        (bootp[242], yiaddr)
+    }
+
+    #[test]
+    fn hands_out_addresses() {
+        let mut server = server();
+        let offer = server.reply(&message(THEIRS, 1, &[])).expect("no offer");
+        let first = Ipv4Addr::new(10, 0, 0, 100);
+        assert_eq!((2, first), check(&offer));
+        // Unicast to the offered address
+        assert_eq!(&THEIRS.0, &offer[..6]);
+        assert_eq!(&first.octets(), &offer[30..34]);
+        // The router is announced
+        assert!(offer.windows(6).any(|option| option == [3, 4, 10, 0, 0, 1]));
+
+        let mut options = requested(first);
+        options.extend_from_slice(&[54, 4, 10, 0, 0, 1]);
+        let ack = server.reply(&message(THEIRS, 3, &options)).expect("no ack");
+        assert_eq!((5, first), check(&ack));
+        assert_eq!(Some(first), server.lease(THEIRS));
+
+        // Another client gets another address, the same one keeps its own
+        let other = MacAddr([0x02, 0, 0, 0, 0, 3]);
+        let offer = server.reply(&message(other, 1, &[])).expect("no offer");
+        assert_eq!((2, Ipv4Addr::new(10, 0, 0, 101)), check(&offer));
+        let offer = server.reply(&message(THEIRS, 1, &[])).expect("no offer");
+        assert_eq!((2, first), check(&offer));
+
+        // The range is exhausted
+        let third = MacAddr([0x02, 0, 0, 0, 0, 4]);
+        assert!(server.reply(&message(third, 1, &[])).is_none());
+        // Until someone releases its address
+        assert!(server.reply(&message(THEIRS, 7, &[])).is_none());
+        assert_eq!(None, server.lease(THEIRS));
+        let offer = server.reply(&message(third, 1, &[])).expect("no offer");
+        assert_eq!((2, first), check(&offer));
+    }
+
+    #[test]
+    fn refuses_requests() {
+        let mut server = server();
+        let taken = Ipv4Addr::new(10, 0, 0, 100);
+        let ack = server.reply(&message(THEIRS, 3, &requested(taken)));
+        assert_eq!((5, taken), check(&ack.expect("no ack")));
+
+        // Somebody else's address
+        let other = MacAddr([0x02, 0, 0, 0, 0, 3]);
+        let nak = server.reply(&message(other, 3, &requested(taken))).expect("no nak");
+        assert_eq!((6, Ipv4Addr::UNSPECIFIED), check(&nak));
+        assert_eq!(&MacAddr::BROADCAST.0, &nak[..6]);
+        // Outside of the range
+        let outside = Ipv4Addr::new(192, 168, 0, 1);
+        let nak = server.reply(&message(other, 3, &requested(outside))).expect("no nak");
+        assert_eq!(6, check(&nak).0);
+        // Choosing another server
+        let mut options = requested(Ipv4Addr::new(10, 0, 0, 101));
+        options.extend_from_slice(&[54, 4, 10, 0, 0, 254]);
+        assert!(server.reply(&message(other, 3, &options)).is_none());
+        assert_eq!(None, server.lease(other));
+    }
+}
@@ -0,0 +1,88 @@
+#![cfg(feature = "testgen")]
+
+extern crate etherparse;
+extern crate tun_tap;
+
+use std::net::Ipv4Addr;
+
+use etherparse::{IpHeader, PacketHeaders, TransportHeader};
+use tun_tap::packet::{EthernetHeader, MacAddr};
+use tun_tap::testgen::PacketBuilder;
+use tun_tap::testing;
+use tun_tap::{EtherType, PacketInfo};
+
+fn builder() -> PacketBuilder {
+    PacketBuilder::ipv4(Ipv4Addr::new(10, 0, 0, 2), Ipv4Addr::new(10, 0, 0, 1)).ttl(20)
+}
+
+#[test]
+fn builds_udp() {
+    let packet = builder().udp(4242, 2424, b"hello");
+    let headers = PacketHeaders::from_ip_slice(&packet).unwrap();
+    let ip = match headers.ip {
+        Some(IpHeader::Version4(ip)) => ip,
+        other => panic!("Not IPv4: {:?}", other),
+    };
+    assert_eq!([10, 0, 0, 2], ip.source);
+    assert_eq!([10, 0, 0, 1], ip.destination);
+    assert_eq!(20, ip.time_to_live);
+    assert_eq!(ip.calc_header_checksum().unwrap(), ip.header_checksum);
+    let udp = match headers.transport {
+        Some(TransportHeader::Udp(udp)) => udp,
+        other => panic!("Not UDP: {:?}", other),
+    };
+    assert_eq!(4242, udp.source_port);
+    assert_eq!(2424, udp.destination_port);
+    assert_eq!(13, udp.length);
+    assert_eq!(udp.calc_checksum_ipv4(&ip, b"hello").unwrap(), udp.checksum);
+    assert_eq!(b"hello", headers.payload);
+}
+
+#[test]
+fn builds_icmp() {
+    let packet = builder().icmp_echo_request(42, 1, b"ping");
+    assert_eq!(20 + 8 + 4, packet.len());
+    assert_eq!(1, packet[9]);
+    let icmp = &packet[20..];
+    assert_eq!(&[8, 0], &icmp[..2]);
+    assert_eq!(&[0, 42, 0, 1], &icmp[4..8]);
+    // The checksum of a correct message sums up to zero
+    let mut sum = icmp
+        .chunks(2)
+        .map(|word| u32::from(word[0]) << 8 | u32::from(word[1]))
+        .sum::<u32>();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    assert_eq!(0xffff, sum);
+
+    let reply = builder().icmp_echo_reply(42, 1, b"ping");
+    assert_eq!(0, reply[20]);
+}
+
+#[test]
+fn builds_frames() {
+    let ours = MacAddr([0x02, 0, 0, 0, 0, 2]);
+    let theirs = MacAddr([0x02, 0, 0, 0, 0, 1]);
+    let frame = builder()
+        .ethernet(ours, theirs)
+        .packet_info(true)
+        .udp(4242, 2424, b"hello");
+    let mut info = [0; 4];
+    info.copy_from_slice(&frame[..4]);
+    assert_eq!(EtherType::Ipv4, PacketInfo::parse(&info).proto);
+    let (header, ip) = EthernetHeader::parse(&frame[4..]).unwrap();
+    assert_eq!(ours, header.source);
+    assert_eq!(theirs, header.destination);
+    assert_eq!(EtherType::Ipv4, header.ether_type);
+    assert_eq!(&builder().udp(4242, 2424, b"hello"), ip);
+
+    // A TUN device has no ethernet header nor the packet info
+    let (device, _) = testing::pair().unwrap();
+    let packet = builder()
+        .ethernet(ours, theirs)
+        .packet_info(true)
+        .for_device(&device)
+        .udp(4242, 2424, b"hello");
+    assert_eq!(builder().udp(4242, 2424, b"hello"), packet);
+}
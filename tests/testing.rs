@@ -0,0 +1,139 @@
+extern crate tun_tap;
+
+use std::io::ErrorKind;
+use std::sync::Arc;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use tun_tap::poll_set::{Interest, PollSet};
+use tun_tap::testing::{self, MockIface};
+use tun_tap::{Device, Mode};
+
+#[test]
+fn pair_passes_packets_both_ways() {
+    let (left, right) = testing::pair().unwrap();
+    assert_eq!("fake0", left.name());
+    assert_eq!("fake1", right.name());
+
+    left.send(b"first").unwrap();
+    left.send(b"second").unwrap();
+    assert_eq!(b"first", &right.recv_vec().unwrap()[..]);
+    assert_eq!(b"second", &right.recv_vec().unwrap()[..]);
+
+    right.send(b"reply").unwrap();
+    assert_eq!(b"reply", &left.recv_vec().unwrap()[..]);
+
+    left.set_non_blocking().unwrap();
+    let mut buf = [0; 10];
+    assert_eq!(ErrorKind::WouldBlock, left.recv(&mut buf).unwrap_err().kind());
+}
+
+fn echo<D: Device>(device: &D) {
+    let mut buf = vec![0; device.mtu().unwrap()];
+    let size = device.recv(&mut buf).unwrap();
+    device.send(&buf[..size]).unwrap();
+}
+
+#[test]
+fn pair_is_a_device() {
+    let (left, right) = testing::pair().unwrap();
+    left.send(b"ping").unwrap();
+    echo(&right);
+    assert_eq!(b"ping", &left.recv_vec().unwrap()[..]);
+}
+
+#[test]
+fn mock_plays_script() {
+    let iface = MockIface::new(Mode::Tap);
+    assert_eq!(Mode::Tap, iface.mode());
+    iface.push_recv(&b"first"[..]);
+    iface.push_recv_after(Duration::from_millis(50), &b"second"[..]);
+    iface.expect_send(&b"reply"[..]);
+    iface.set_non_blocking().unwrap();
+    let mut buf = [0; 10];
+
+    assert_eq!(5, iface.recv(&mut buf).unwrap());
+    assert_eq!(b"first", &buf[..5]);
+    // Not yet due
+    assert_eq!(ErrorKind::WouldBlock, iface.recv(&mut buf).unwrap_err().kind());
+    iface.send(b"reply").unwrap();
+    thread::sleep(Duration::from_millis(60));
+    assert_eq!(6, iface.recv(&mut buf).unwrap());
+    assert_eq!(ErrorKind::WouldBlock, iface.recv(&mut buf).unwrap_err().kind());
+
+    iface.send(b"extra").unwrap();
+    assert_eq!(vec![b"reply".to_vec(), b"extra".to_vec()], iface.sent());
+    iface.assert_done();
+}
+
+#[test]
+fn mock_waits_for_packets() {
+    let iface = MockIface::new(Mode::Tun);
+    iface.push_recv_after(Duration::from_millis(50), &b"late"[..]);
+    let start = Instant::now();
+    let mut buf = [0; 10];
+    assert_eq!(4, iface.recv(&mut buf).unwrap());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+}
+
+#[test]
+#[should_panic(expected = "Unexpected packet sent")]
+fn mock_rejects_unexpected_send() {
+    let iface = MockIface::new(Mode::Tun);
+    iface.expect_send(&b"expected"[..]);
+    let _ = iface.send(b"other");
+}
+
+#[test]
+#[should_panic(expected = "not received")]
+fn mock_checks_script_played() {
+    let iface = MockIface::new(Mode::Tun);
+    iface.push_recv(&b"never read"[..]);
+    iface.assert_done();
+}
+
+#[test]
+fn mock_is_a_device() {
+    let iface = MockIface::new(Mode::Tun);
+    iface.push_recv(&b"ping"[..]);
+    iface.expect_send(&b"ping"[..]);
+    echo(&iface);
+    iface.assert_done();
+}
+
+#[test]
+fn mock_signals_readiness() {
+    let start = Instant::now();
+    let iface = MockIface::new(Mode::Tun);
+    let poll = PollSet::new().unwrap();
+    poll.add(&iface, 0, Interest::Readable).unwrap();
+    let mut events = Vec::new();
+    // Nothing scripted yet
+    assert_eq!(0, poll.wait(&mut events, Some(Duration::from_millis(10))).unwrap());
+
+    // The first delay counts from the creation
+    iface.push_recv_after(Duration::from_millis(50), &b"late"[..]);
+    assert_eq!(0, poll.wait(&mut events, Some(Duration::ZERO)).unwrap());
+    assert_eq!(1, poll.wait(&mut events, Some(Duration::from_secs(5))).unwrap());
+    assert!(start.elapsed() >= Duration::from_millis(50));
+    iface.set_non_blocking().unwrap();
+    let mut buf = [0; 10];
+    assert_eq!(4, iface.recv(&mut buf).unwrap());
+    // Picked up, nothing more to read
+    assert_eq!(0, poll.wait(&mut events, Some(Duration::ZERO)).unwrap());
+}
+
+#[test]
+fn mock_waits_for_more_script() {
+    let iface = Arc::new(MockIface::new(Mode::Tun));
+    let pusher = {
+        let iface = Arc::clone(&iface);
+        thread::spawn(move || {
+            thread::sleep(Duration::from_millis(50));
+            iface.push_recv(&b"pushed"[..]);
+        })
+    };
+    let mut buf = [0; 10];
+    assert_eq!(6, iface.recv(&mut buf).unwrap());
+    pusher.join().unwrap();
+}
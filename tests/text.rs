@@ -0,0 +1,51 @@
+extern crate tun_tap;
+
+use tun_tap::{Cleanup, IffFlags, Mode, OffloadFlags, TunFlags};
+
+#[test]
+fn mode() {
+    for mode in &[Mode::Tun, Mode::Tap] {
+        assert_eq!(Ok(*mode), mode.to_string().parse());
+    }
+    assert_eq!(Ok(Mode::Tun), "TUN".parse());
+    let error = "tunnel".parse::<Mode>().unwrap_err();
+    assert_eq!("tunnel", error.input());
+    assert_eq!("Invalid mode: \"tunnel\"", error.to_string());
+}
+
+#[test]
+fn tun_flags() {
+    let flags = TunFlags::TUN | TunFlags::NO_PI | TunFlags::MULTI_QUEUE;
+    assert_eq!("TUN | MULTI_QUEUE | NO_PI", flags.to_string());
+    assert_eq!(Ok(flags), flags.to_string().parse());
+    assert_eq!(Ok(flags), "no-pi|tun | Multi_Queue".parse());
+    assert_eq!(Ok(TunFlags::empty()), "".parse());
+    assert_eq!(Ok(TunFlags::TAP | TunFlags::from_bits_retain(0x400)), "TAP | 0x400".parse());
+    let error = "TUN | NO_SUCH_FLAG".parse::<TunFlags>().unwrap_err();
+    assert_eq!("NO_SUCH_FLAG", error.input());
+    assert!("0xZZ".parse::<TunFlags>().is_err());
+}
+
+#[test]
+fn other_flags() {
+    let flags = IffFlags::UP | IffFlags::from_bits_retain(0x8000);
+    assert_eq!(Ok(flags), flags.to_string().parse());
+    let flags = OffloadFlags::CSUM | OffloadFlags::TSO4;
+    assert_eq!("CSUM | TSO4", flags.to_string());
+    assert_eq!(Ok(flags), "csum | tso4".parse());
+}
+
+#[test]
+fn cleanup() {
+    let all = [
+        Cleanup::Nothing,
+        Cleanup::LinkDown,
+        Cleanup::FlushAddresses,
+        Cleanup::RemovePersistence,
+    ];
+    for cleanup in &all {
+        assert_eq!(Ok(*cleanup), cleanup.to_string().parse());
+    }
+    assert_eq!("link-down", Cleanup::LinkDown.to_string());
+    assert!("everything".parse::<Cleanup>().is_err());
+}